@@ -0,0 +1,59 @@
+//! Compare `InputTextStream::read_to_text_string` with `read_to_string` and
+//! with a loop of small `read` calls.
+
+use clap::{ambient_authority, TryFromOsArg};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use nameless::InputTextStream;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+fn open(path: &Path) -> InputTextStream {
+    InputTextStream::try_from_os_str_arg(path.as_os_str(), ambient_authority()).unwrap()
+}
+
+fn read_text(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("input.txt");
+    let line = "The quick brown fox jumps over the lazy dog. \u{e9}\u{4e16}\u{1f980}\r\n";
+    fs::write(&path, line.repeat(16 * 1024 * 1024 / line.len())).unwrap();
+
+    let mut group = c.benchmark_group("read_text");
+    group.throughput(Throughput::Bytes(fs::metadata(&path).unwrap().len()));
+    group.sample_size(10);
+
+    group.bench_function("small_reads", |b| {
+        b.iter(|| {
+            let mut input = open(&path);
+            let mut s = String::new();
+            let mut buf = [0; 4096];
+            loop {
+                let n = input.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                s.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+            }
+            s
+        })
+    });
+    group.bench_function("read_to_string", |b| {
+        b.iter(|| {
+            let mut s = String::new();
+            open(&path).read_to_string(&mut s).unwrap();
+            s
+        })
+    });
+    group.bench_function("read_to_text_string", |b| {
+        b.iter(|| {
+            let mut s = basic_text::TextString::new();
+            open(&path).read_to_text_string(&mut s).unwrap();
+            s
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, read_text);
+criterion_main!(benches);
@@ -1,7 +1,7 @@
 //! A simple grep-like program using `kommand` and `InputTextStream`.
 //! Unlike regular grep, this grep supports URLs and gzip. Perg!
 
-use nameless::{InputTextStream, LazyOutput, MediaType, OutputTextStream};
+use nameless::{InputTextStream, LazyOutput, MediaType, OpenResults, OutputTextStream};
 use regex::Regex;
 use std::io::{BufRead, BufReader, Write};
 
@@ -11,13 +11,24 @@ use std::io::{BufRead, BufReader, Write};
 /// * `output` - Output sink
 /// * `inputs` - Input sources
 /// * `inputs_with_matches` - Print only the names of the inputs containing matches
+/// * `skip_bad_inputs` - Report inputs which fail to open, and search the rest
 #[kommand::main]
 fn main(
     pattern: Regex,
     output: LazyOutput<OutputTextStream>,
-    inputs: Vec<InputTextStream>,
+    #[kommand(collect_errors)] inputs: OpenResults<InputTextStream>,
     #[kommand(short = 'l', long)] inputs_with_matches: bool,
+    #[kommand(long)] skip_bad_inputs: bool,
 ) -> anyhow::Result<()> {
+    let inputs = if skip_bad_inputs {
+        for failure in inputs.failures() {
+            eprintln!("grep: {}", failure);
+        }
+        inputs.into_successes()
+    } else {
+        inputs.into_result()?
+    };
+
     let mut output = output.materialize(MediaType::text())?;
 
     let print_inputs = inputs.len() > 1;
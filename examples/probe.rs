@@ -0,0 +1,31 @@
+//! A simple program using `kommand` and `nameless::probe` which prints the
+//! kind, size, and media type of each of its inputs, without reading any of
+//! their contents. HTTP URLs are probed with a `HEAD` request.
+
+use nameless::{probe, OpenPolicy};
+use std::ffi::OsString;
+
+/// # Arguments
+///
+/// * `inputs` - Input sources to probe
+#[kommand::main]
+fn main(#[kommand(parse(from_os_str))] inputs: Vec<OsString>) -> anyhow::Result<()> {
+    let policy = OpenPolicy::default();
+
+    println!("{:<12} {:>12}  MEDIA TYPE", "KIND", "SIZE");
+    for input in inputs {
+        let probe = probe(&input, &policy)?;
+        let size = match probe.size() {
+            Some(size) => size.to_string(),
+            None => "-".to_owned(),
+        };
+        println!(
+            "{:<12} {:>12}  {}",
+            format!("{:?}", probe.kind()),
+            size,
+            probe.media_type().mime()
+        );
+    }
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-//! A simple REPL program using `kommand` and `InteractiveTextStream`.
+//! A simple REPL program using `kommand` and `LineProtocolServer`.
 //!
 //! Run it interactively with the process' (stdin, stdout):
 //! ```
@@ -9,9 +9,7 @@
 //! [entered "world"]
 //! ```
 //!
-//! Run it interactively with the process' tty. This works the same way, but
-//! doesn't detect terminal colors, because we need the "TERM" environment
-//! variable to do that.
+//! Run it interactively with the process' tty. This works the same way.
 //! ```
 //! $ cargo run --quiet --example repl /dev/tty
 //! prompt> hello
@@ -28,7 +26,8 @@
 //! ```
 //!
 //! Run it connected to the same program but use a socket instead of a
-//! pipe -- note that this opens a network port!
+//! pipe -- note that this opens a network port! With a socket, it serves
+//! any number of clients, until it's interrupted.
 //!
 //! ```
 //! $ cargo run --quiet --example repl accept://localhost:9999 &
@@ -38,51 +37,28 @@
 //! [entered "world"]
 //! ```
 
-use io_streams::BufReaderLineWriter;
-use layered_io::Bufferable;
-use nameless::InteractiveTextStream;
-use std::io::{self, BufRead, Write};
-use terminal_io::{TerminalColorSupport, WriteTerminal};
+use nameless::{ambient_authority, LineProtocolServer, Response};
+use std::ffi::OsString;
 
 #[kommand::main]
-fn main(io: InteractiveTextStream) -> anyhow::Result<()> {
-    let io = BufReaderLineWriter::new(io);
-    let color =
-        io.color_support() != TerminalColorSupport::Monochrome && std::env::var("NOCOLOR").is_err();
+fn main(#[kommand(parse(from_os_str))] io: OsString) -> anyhow::Result<()> {
+    LineProtocolServer::new(io)
+        .prompt("prompt> \u{34f}")
+        .on_line(|line, ctx| {
+            let line = line.trim();
+            if line == "exit" {
+                return Ok(Response::Close);
+            }
 
-    match repl(io, color) {
-        Ok(()) => Ok(()),
-        Err(e) => match e.kind() {
-            io::ErrorKind::BrokenPipe => Ok(()),
-            _ => Err(e.into()),
-        },
-    }
-}
-
-fn repl(mut io: BufReaderLineWriter<InteractiveTextStream>, color: bool) -> io::Result<()> {
-    let mut s = String::new();
-
-    loop {
-        if color {
-            write!(io, "\u{1b}[01;36mprompt>\u{1b}[0m \u{34f}")?;
-        } else {
-            write!(io, "prompt> \u{34f}")?;
-        }
-
-        if io.read_line(&mut s)? == 0 {
-            // End of stream.
-            io.abandon();
-            return Ok(());
-        }
-
-        if s.trim() == "exit" {
-            io.abandon();
-            return Ok(());
-        }
-
-        eprintln!("[logging \"{}\"]", s.trim().escape_default());
-        writeln!(io, "[received \"{}\"]", s.trim().escape_default())?;
-
-        s.clear();
-    }
+            eprintln!(
+                "[logging \"{}\" from {}]",
+                line.escape_default(),
+                ctx.peer()
+            );
+            Ok(Response::Reply(format!(
+                "[received \"{}\"]",
+                line.escape_default()
+            )))
+        })
+        .run(ambient_authority())
 }
@@ -0,0 +1,86 @@
+//! A simple RPC program using `kommand`, `InteractiveTextStream`, and
+//! `JsonLines`. Requires the "codecs" feature.
+//!
+//! Run a server piped to a client process:
+//! ```
+//! $ cargo run --quiet --features codecs --example rpc -- '$(cargo run --quiet --features codecs --example rpc -- --serve -)'
+//! sum(1, 2, 3) = 6
+//! sum() = 0
+//! error: unknown method "product"
+//! ```
+//!
+//! Or connect them with a socket -- note that this opens a network port!
+//! The argument is a `LazyInteractive`, so a mistake in the other arguments
+//! is reported before the server waits for a connection.
+//! ```
+//! $ cargo run --quiet --features codecs --example rpc -- --serve accept://localhost:9999 &
+//! ...
+//! $ cargo run --quiet --features codecs --example rpc -- connect://localhost:9999
+//! sum(1, 2, 3) = 6
+//! sum() = 0
+//! error: unknown method "product"
+//! ```
+
+use layered_io::WriteLayered;
+use nameless::{InteractiveTextStream, JsonLines, LazyInteractive};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+    method: String,
+    args: Vec<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Response {
+    Ok(i64),
+    Err(String),
+}
+
+/// # Arguments
+///
+/// * `io` - The stream to the peer
+/// * `serve` - Answer requests instead of making them
+#[kommand::main]
+fn main(
+    io: LazyInteractive<InteractiveTextStream>,
+    #[kommand(long)] serve: bool,
+) -> anyhow::Result<()> {
+    // Check the syntax up front, and only then bind, connect, or spawn.
+    io.validate()?;
+    let mut io = JsonLines::new(io.materialize()?);
+
+    if serve {
+        while let Some(request) = io.recv::<Request>()? {
+            let response = match request.method.as_str() {
+                "sum" => Response::Ok(request.args.iter().sum()),
+                other => Response::Err(format!("unknown method {:?}", other)),
+            };
+            io.send(&response)?;
+        }
+        return Ok(());
+    }
+
+    for (method, args) in [
+        ("sum", vec![1, 2, 3]),
+        ("sum", vec![]),
+        ("product", vec![2]),
+    ] {
+        io.send(&Request {
+            method: method.to_owned(),
+            args: args.clone(),
+        })?;
+        match io.recv::<Response>()? {
+            Some(Response::Ok(value)) => {
+                let args = args.iter().map(i64::to_string).collect::<Vec<_>>();
+                println!("{}({}) = {}", method, args.join(", "), value);
+            }
+            Some(Response::Err(message)) => println!("error: {}", message),
+            None => anyhow::bail!("the server hung up"),
+        }
+    }
+
+    // Let the server see the end of the requests.
+    io.get_mut().close()?;
+    Ok(())
+}
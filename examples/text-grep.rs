@@ -10,9 +10,17 @@ use std::io::{self, BufRead, BufReader, Write};
 ///
 /// * `pattern` - The regex to search for
 /// * `inputs` - Input sources, stdin if none
+/// * `summary` - Print a summary of the output to stderr
 #[kommand::main]
-fn main(pattern: Regex, mut inputs: Vec<InputTextStream>) -> anyhow::Result<()> {
+fn main(
+    pattern: Regex,
+    mut inputs: Vec<InputTextStream>,
+    #[kommand(long)] summary: bool,
+) -> anyhow::Result<()> {
     let mut output = OutputTextStream::try_from_os_str_arg("-".as_ref(), ambient_authority())?;
+    if summary {
+        output.enable_accounting();
+    }
 
     if inputs.is_empty() {
         inputs.push(InputTextStream::try_from_os_str_arg(
@@ -45,5 +53,13 @@ fn main(pattern: Regex, mut inputs: Vec<InputTextStream>) -> anyhow::Result<()>
         }
     }
 
+    if let Some(accounting) = output.accounting() {
+        eprintln!(
+            "wrote {} lines, {} bytes",
+            accounting.lines(),
+            accounting.bytes()
+        );
+    }
+
     Ok(())
 }
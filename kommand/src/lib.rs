@@ -14,7 +14,21 @@ use syn::visit_mut::{self, VisitMut};
 use syn::{parse_macro_input, parse_quote, Expr, Ident, LitStr, Pat, Stmt, Type};
 
 #[proc_macro_attribute]
-pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
+    // `#[kommand::main(plain_version)]` disables the nameless details in
+    // `--version` output.
+    let plain_version = if attr.is_empty() {
+        false
+    } else {
+        let attr = parse_macro_input!(attr as Ident);
+        if attr != "plain_version" {
+            return TokenStream::from(quote_spanned! { attr.span() =>
+                compile_error!("unsupported `#[kommand::main]` option; expected `plain_version`");
+            });
+        }
+        true
+    };
+
     let mut input = parse_macro_input!(item as syn::ItemFn);
     let ret = &input.sig.output;
     let name = &input.sig.ident;
@@ -73,9 +87,13 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
     }
 
     // Parse the `Environment Variables` information from the comment.
-    let (edited, env_info) = match parse_env_vars_from_comment(&about, name.span()) {
+    let (edited, env_info) = match parse_env_vars_from_comment(&about) {
         Ok(env_info) => env_info,
-        Err(tokenstream) => return tokenstream,
+        Err(message) => {
+            return TokenStream::from(quote_spanned! { name.span() =>
+                compile_error!(#message);
+            })
+        }
     };
 
     // Process the environment variables.
@@ -102,9 +120,13 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
     }
 
     // Parse the `Arguments` information from the comment.
-    let (edited, arg_info) = match parse_arguments_from_comment(&edited, name.span()) {
+    let (edited, arg_info) = match parse_arguments_from_comment(&edited) {
         Ok(arg_info) => arg_info,
-        Err(tokenstream) => return tokenstream,
+        Err(message) => {
+            return TokenStream::from(quote_spanned! { name.span() =>
+                compile_error!(#message);
+            })
+        }
     };
     if !edited.is_empty() {
         abouts.push(edited);
@@ -117,6 +139,7 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let mut arg_docs = Vec::new();
     let mut arg_names = Vec::new();
     let mut arg_types = Vec::new();
+    let mut kommand_args = Vec::new();
     for input in inputs {
         let arg = match input {
             syn::FnArg::Typed(arg) => arg,
@@ -155,6 +178,7 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
             }
         };
         no_mut_ident.mutability = None;
+        let field_ident = no_mut_ident.ident.clone();
 
         // Create a copy of the argument with the no-`mut` ident.
         let mut no_mut_arg = arg.clone();
@@ -168,8 +192,37 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
                     compile_error!("Main argument has unsupported attributes");
                 });
             }
-            let ident = &mut no_mut_arg.attrs[0].path.segments.first_mut().unwrap().ident;
-            *ident = Ident::new("clap", ident.span());
+
+            // `glob` and `collect_errors` are handled by kommand rather
+            // than clap: the argument is parsed as strings, which are
+            // glob-expanded if requested and then converted into streams.
+            let (flags, rest) = match take_flags(&no_mut_arg.attrs[0]) {
+                Ok(split) => split,
+                Err(err) => return err.to_compile_error().into(),
+            };
+            if flags.glob || flags.collect_errors {
+                let last_segment = match &*arg.ty {
+                    Type::Path(path) => path.path.segments.last().map(|segment| &segment.ident),
+                    _ => None,
+                };
+                if flags.collect_errors {
+                    if !matches!(last_segment, Some(ident) if ident == "OpenResults") {
+                        return TokenStream::from(quote_spanned! { arg.ty.span() =>
+                            compile_error!("`#[kommand(collect_errors)]` requires an `OpenResults` argument");
+                        });
+                    }
+                } else if !matches!(last_segment, Some(ident) if ident == "Vec") {
+                    return TokenStream::from(quote_spanned! { arg.ty.span() =>
+                        compile_error!("`#[kommand(glob)]` requires a `Vec` argument");
+                    });
+                }
+                kommand_args.push((field_ident, arg.pat.clone(), arg.ty.clone(), flags));
+                no_mut_arg.ty = parse_quote! { Vec<std::ffi::OsString> };
+                no_mut_arg.attrs = vec![parse_quote! { #[clap(parse(from_os_str) #(, #rest)*)] }];
+            } else {
+                let ident = &mut no_mut_arg.attrs[0].path.segments.first_mut().unwrap().ident;
+                *ident = Ident::new("clap", ident.span());
+            }
         }
 
         args.push(no_mut_arg);
@@ -190,6 +243,47 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
         None => quote! {},
     };
 
+    // Unless disabled, list the linked nameless version and the stream
+    // syntaxes it supports in `--version` output. This is computed at
+    // runtime so that it describes the build that's actually linked in.
+    let parse = if plain_version {
+        quote! { clap::Clap::parse() }
+    } else {
+        quote! {{
+            let long_version = nameless::long_version(env!("CARGO_PKG_VERSION"));
+            let app = <_KommandOpt as clap::IntoApp>::into_app().long_version(&*long_version);
+            <_KommandOpt as clap::FromArgMatches>::from_arg_matches(&app.get_matches())
+        }}
+    };
+
+    // Expand globs in `#[kommand(glob)]` arguments and open the results,
+    // reporting failures the way clap reports other argument errors.
+    // `#[kommand(collect_errors)]` arguments collect the failures instead,
+    // for the program to report.
+    let kommand_inits = kommand_args.iter().map(|(ident, pat, ty, flags)| {
+        if flags.collect_errors {
+            let collect = if flags.glob {
+                quote! { nameless::glob_collect_args }
+            } else {
+                quote! { nameless::collect_args }
+            };
+            quote! {
+                let #pat: #ty = #collect(#ident);
+            }
+        } else {
+            quote! {
+                let #pat: #ty = match nameless::glob_args(#ident) {
+                    Ok(streams) => streams,
+                    Err(err) => clap::Error::with_description(
+                        format!("{:#}", err),
+                        clap::ErrorKind::InvalidValue,
+                    )
+                    .exit(),
+                };
+            }
+        }
+    });
+
     // Import `nameless::clap` so that clap_derive's macro expansions can
     // use it, and our users don't need to manually import it. In theory
     // there are cleaner ways to do this, but as a macro-around-a-macro,
@@ -209,7 +303,8 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
 
         #(#attrs)*
         #asyncness fn main() #ret {
-            let _KommandOpt { #(#arg_names,)* } = clap::Clap::parse();
+            let _KommandOpt { #(#arg_names,)* } = #parse;
+            #(#kommand_inits)*
 
             let _kommand_env = _KommandEnv {
                 #(#env_inits,)*
@@ -222,6 +317,36 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
     .into()
 }
 
+/// The `#[kommand(...)]` flags which kommand handles itself.
+#[derive(Default)]
+struct KommandFlags {
+    glob: bool,
+    collect_errors: bool,
+}
+
+/// Split a `#[kommand(...)]` attribute into the flags kommand handles
+/// itself, and its other contents.
+fn take_flags(attr: &syn::Attribute) -> syn::Result<(KommandFlags, Vec<syn::NestedMeta>)> {
+    let list = match attr.parse_meta()? {
+        syn::Meta::List(list) => list,
+        meta => return Err(syn::Error::new(meta.span(), "expected `#[kommand(...)]`")),
+    };
+    let mut flags = KommandFlags::default();
+    let mut rest = Vec::new();
+    for nested in list.nested {
+        match &nested {
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("glob") => {
+                flags.glob = true
+            }
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("collect_errors") => {
+                flags.collect_errors = true
+            }
+            _ => rest.push(nested),
+        }
+    }
+    Ok((flags, rest))
+}
+
 #[derive(Default)]
 struct EnvVisitor {
     err: Option<(String, Span2)>,
@@ -323,17 +448,17 @@ impl VisitMut for EnvVisitor {
                     // [autoref specialization]: http://lukaskalbertodt.github.io/2019/12/05/generalized-autoref-based-specialization.html
                     let default = init.1.clone();
                     let pat_ident = pat.ident.clone();
-                    let initializer =
-                        generate_env_initializer(default, pat_ident.clone(), result_type);
-                    *init.1 = initializer;
-
-                    // Record the variable name so that we can check for duplicates
-                    // and undocumented errors.
                     let env_name = pat_ident
                         .to_string()
                         .to_shouty_snake_case()
                         .escape_default()
                         .to_string();
+                    let initializer =
+                        generate_env_initializer(default, pat_ident, &env_name, result_type);
+                    *init.1 = initializer;
+
+                    // Record the variable name so that we can check for duplicates
+                    // and undocumented errors.
                     if !self.vars.insert(env_name) {
                         self.err = Some((
                             "#[env_or_default] requires variable names be unique within a function"
@@ -357,128 +482,25 @@ impl VisitMut for EnvVisitor {
     }
 }
 
-fn generate_env_initializer(default: Box<Expr>, pat_ident: Ident2, result_type: Box<Type>) -> Expr {
-    let case_insensitive = false;
+fn generate_env_initializer(
+    default: Box<Expr>,
+    pat_ident: Ident2,
+    env_name: &str,
+    result_type: Box<Type>,
+) -> Expr {
+    // The parsing itself lives in `nameless::env_or_default`. The call to
+    // `parse_env` is emitted here, where `result_type` is concrete, so that
+    // method resolution can pick the parsing trait.
     parse_quote! {
-        match _kommand_env.#pat_ident {
-            Some(os_str) => match {
-                use std::convert::{Infallible, TryFrom};
-                use std::ffi::{OsStr, OsString};
-                use std::str::FromStr;
-                use std::marker::PhantomData;
-
-                struct Wrap<T>(T);
-                trait Specialize8 {
-                    type Return;
-                    fn specialized(&self) -> Self::Return;
-                }
-                impl<'a, T: clap::ArgEnum> Specialize8 for &&&&&&&&Wrap<(&'a OsStr, PhantomData<T>)> {
-                    type Return = Result<T, Result<String, OsString>>;
-                    fn specialized(&self) -> Self::Return {
-                        match self.0.0.to_str() {
-                            None => Err(Err(self.0.0.to_os_string())),
-                            Some(s) => T::from_str(s, #case_insensitive).map_err(Ok),
-                        }
-                    }
-                }
-                trait Specialize7 {
-                    type Return;
-                    fn specialized(&self) -> Self::Return;
-                }
-                impl<'a, T: clap::TryFromOsArg> Specialize7 for &&&&&&&Wrap<(&'a OsStr, PhantomData<T>)> {
-                    type Return = Result<T, Result<T::Error, OsString>>;
-                    fn specialized(&self) -> Self::Return {
-                        T::try_from_os_str_arg(
-                            self.0.0,
-                            clap::ambient_authority()
-                        ).map_err(Ok)
-                    }
-                }
-                trait Specialize6 {
-                    type Return;
-                    fn specialized(&self) -> Self::Return;
-                }
-                impl<'a, T: TryFrom<&'a OsStr>> Specialize6 for &&&&&&Wrap<(&'a OsStr, PhantomData<T>)> {
-                    type Return = Result<T, Result<T::Error, OsString>>;
-                    fn specialized(&self) -> Self::Return {
-                        T::try_from(self.0.0).map_err(Ok)
-                    }
-                }
-                trait Specialize5 {
-                    type Return;
-                    fn specialized(&self) -> Self::Return;
-                }
-                impl<T: FromStr> Specialize5 for &&&&&Wrap<(&OsStr, PhantomData<T>)> {
-                    type Return = Result<T, Result<T::Err, OsString>>;
-                    fn specialized(&self) -> Self::Return {
-                        match self.0.0.to_str() {
-                            None => Err(Err(self.0.0.to_os_string())),
-                            Some(s) => T::from_str(s).map_err(Ok),
-                        }
-                    }
-                }
-                trait Specialize4 {
-                    type Return;
-                    fn specialized(&self) -> Self::Return;
-                }
-                impl<'a, T: TryFrom<&'a str>> Specialize4 for &&&&Wrap<(&'a OsStr, PhantomData<T>)> {
-                    type Return = Result<T, Result<T::Error, OsString>>;
-                    fn specialized(&self) -> Self::Return {
-                        match self.0.0.to_str() {
-                            None => Err(Err(self.0.0.to_os_string())),
-                            Some(s) => T::try_from(s).map_err(Ok),
-                        }
-                    }
-                }
-                trait Specialize3 {
-                    type Return;
-                    fn specialized(&self) -> Self::Return;
-                }
-                impl<'a, T: From<&'a OsStr>> Specialize3 for &&&Wrap<(&'a OsStr, PhantomData<T>)> {
-                    type Return = Result<T, Result<Infallible, OsString>>;
-                    fn specialized(&self) -> Self::Return {
-                        Ok(T::from(self.0.0))
-                    }
-                }
-                trait Specialize2 {
-                    type Return;
-                    fn specialized(&self) -> Self::Return;
-                }
-                impl<'a, T: From<&'a str>> Specialize2 for &&Wrap<(&'a OsStr, PhantomData<T>)> {
-                    type Return = Result<T, Result<Infallible, OsString>>;
-                    fn specialized(&self) -> Self::Return {
-                        match self.0.0.to_str() {
-                            None => Err(Err(self.0.0.to_os_string())),
-                            Some(s) => Ok(T::from(s)),
-                        }
-                    }
-                }
-                trait Specialize1 {
-                    type Return;
-                    fn specialized(&self) -> Self::Return;
-                }
-                impl<'a, T> Specialize1 for &Wrap<(&'a OsStr, PhantomData<T>)> {
-                    type Return = Result<T, Result<String, OsString>>;
-                    fn specialized(&self) -> Self::Return {
-                        Err(Ok(format!(
-                            "Type `{}` does not implement any of the parsing traits: \
-                            `clap::ArgEnum`, `clap::TryFromOsArg`, `TryFrom<&OsStr>`, `FromStr`, \
-                            `TryFrom<&str>`, `From<&OsStr>`, or `From<&str>`",
-                            stringify!(#result_type)
-                        )))
-                    }
-                }
-                (&&&&&&&&Wrap((os_str.as_os_str(), PhantomData::<#result_type>))).specialized()
-            } {
-                Ok(value) => value,
-                Err(e) => {
-                    // TODO: Prettier errors.
-                    eprintln!("environment variable parsing error: {:?}", e);
-                    std::process::exit(3);
-                }
-            }
-            None => #default,
-        }
+        nameless::env_or_default::parse_env_or_default(
+            #env_name,
+            _kommand_env.#pat_ident,
+            |os_str| {
+                use nameless::env_or_default::*;
+                (&&&&&&&&nameless::env_or_default::EnvValue::<#result_type>::new(os_str)).parse_env()
+            },
+            || #default,
+        )
     }
 }
 
@@ -490,6 +512,10 @@ fn opts() -> Options {
         | Options::ENABLE_TASKLISTS
 }
 
+/// A doc comment with a section removed, and the names and descriptions
+/// listed in that section.
+type Section = (String, Vec<(String, String)>);
+
 /// Parse the `about` string as Markdown to find the `Arguments` section and
 /// extract the argument names and descriptions.
 ///
@@ -510,10 +536,7 @@ fn opts() -> Options {
 ///    ...
 /// }
 /// ```
-fn parse_arguments_from_comment(
-    about: &str,
-    span: Span2,
-) -> Result<(String, Vec<(String, String)>), TokenStream> {
+fn parse_arguments_from_comment(about: &str) -> Result<Section, &'static str> {
     let mut p = Parser::new_ext(&about, opts()).into_offset_iter();
     while let Some((event, start_offset)) = p.next() {
         if matches!(event, Event::Start(Tag::Heading(HeadingLevel::H1, _, _))) {
@@ -527,11 +550,9 @@ fn parse_arguments_from_comment(
                     continue;
                 }
                 if let Some((Event::Start(Tag::List(None)), _)) = p.next() {
-                    return parse_arguments_list(start_offset, p, span, about);
+                    return parse_arguments_list(start_offset, p, about);
                 }
-                return Err(TokenStream::from(quote_spanned! { span =>
-                    compile_error!("`# Arguments` section does not contain a name/description list");
-                }));
+                return Err("`# Arguments` section does not contain a name/description list");
             }
         }
     }
@@ -543,9 +564,8 @@ fn parse_arguments_from_comment(
 fn parse_arguments_list(
     start_offset: Range<usize>,
     mut p: OffsetIter,
-    span: Span2,
     about: &str,
-) -> Result<(String, Vec<(String, String)>), TokenStream> {
+) -> Result<Section, &'static str> {
     let mut arg_info = Vec::new();
 
     while let Some((Event::Start(Tag::Item), _)) = p.next() {
@@ -561,15 +581,11 @@ fn parse_arguments_list(
                         continue;
                     }
                 } else {
-                    return Err(TokenStream::from(quote_spanned! { span =>
-                        compile_error!("Argument description must start with ` - `");
-                    }));
+                    return Err("Argument description must start with ` - `");
                 }
             }
         }
-        return Err(TokenStream::from(quote_spanned! { span =>
-            compile_error!("Name/description list has unexpected contents");
-        }));
+        return Err("Name/description list has unexpected contents");
     }
 
     // We've successfully reached the end of the list.
@@ -608,10 +624,7 @@ fn parse_arguments_list(
 ///    ...
 /// }
 /// ```
-fn parse_env_vars_from_comment(
-    about: &str,
-    span: Span2,
-) -> Result<(String, Vec<(String, String)>), TokenStream> {
+fn parse_env_vars_from_comment(about: &str) -> Result<Section, &'static str> {
     let mut p = Parser::new_ext(&about, opts()).into_offset_iter();
     while let Some((event, start_offset)) = p.next() {
         if matches!(event, Event::Start(Tag::Heading(HeadingLevel::H1, _, _))) {
@@ -625,11 +638,9 @@ fn parse_env_vars_from_comment(
                     continue;
                 }
                 if let Some((Event::Start(Tag::List(None)), _)) = p.next() {
-                    return parse_env_vars_list(start_offset, p, span, about);
+                    return parse_env_vars_list(start_offset, p, about);
                 }
-                return Err(TokenStream::from(quote_spanned! { span =>
-                    compile_error!("`# Arguments` section does not contain a name/description list");
-                }));
+                return Err("`# Arguments` section does not contain a name/description list");
             }
         }
     }
@@ -641,9 +652,8 @@ fn parse_env_vars_from_comment(
 fn parse_env_vars_list(
     start_offset: Range<usize>,
     mut p: OffsetIter,
-    span: Span2,
     about: &str,
-) -> Result<(String, Vec<(String, String)>), TokenStream> {
+) -> Result<Section, &'static str> {
     let mut env_info = Vec::new();
 
     while let Some((Event::Start(Tag::Item), _)) = p.next() {
@@ -659,15 +669,11 @@ fn parse_env_vars_list(
                         continue;
                     }
                 } else {
-                    return Err(TokenStream::from(quote_spanned! { span =>
-                        compile_error!("Argument description must start with ` - `");
-                    }));
+                    return Err("Argument description must start with ` - `");
                 }
             }
         }
-        return Err(TokenStream::from(quote_spanned! { span =>
-            compile_error!("Name/description list has unexpected contents");
-        }));
+        return Err("Name/description list has unexpected contents");
     }
 
     // We've successfully reached the end of the list.
@@ -675,18 +681,7 @@ fn parse_env_vars_list(
     // Edit the `# Environment Variables` and the list out of the
     // `about` string to avoid redundant output.
 
-    let mut replacement = "ENVIRONMENT VARIABLES:\n".to_owned();
-    let longest_len = env_info.iter().fold(0, |acc, x| max(acc, x.0.len()));
-    for var in &env_info {
-        let env_name = var.0.to_shouty_snake_case().escape_default().to_string();
-        replacement.push_str(&format!(
-            "    <{}>{}   {}\n",
-            env_name,
-            " ".repeat(longest_len),
-            var.1
-        ));
-    }
-
+    let replacement = env_vars_help(&env_info);
     let mut edited = about.to_string();
     edited.replace_range(
         (
@@ -702,6 +697,23 @@ fn parse_env_vars_list(
     Ok((edited, env_info))
 }
 
+/// Format the `ENVIRONMENT VARIABLES` section of the help text, which
+/// replaces the `# Environment Variables` section of the comment.
+fn env_vars_help(env_info: &[(String, String)]) -> String {
+    let mut help = "ENVIRONMENT VARIABLES:\n".to_owned();
+    let longest_len = env_info.iter().fold(0, |acc, x| max(acc, x.0.len()));
+    for var in env_info {
+        let env_name = var.0.to_shouty_snake_case().escape_default().to_string();
+        help.push_str(&format!(
+            "    <{}>{}   {}\n",
+            env_name,
+            " ".repeat(longest_len),
+            var.1
+        ));
+    }
+    help
+}
+
 /// Replace with `ops::Bound::cloned` once that's stable:
 /// https://github.com/rust-lang/rust/issues/61356
 fn clone_bound<T: Clone>(bound: Bound<&T>) -> Bound<T> {
@@ -719,3 +731,71 @@ fn exclude<T: std::fmt::Debug>(bound: Bound<T>) -> Bound<T> {
         Bound::Unbounded => Bound::Unbounded,
     }
 }
+
+#[test]
+fn arguments_from_comment() {
+    let about = "Adds numbers.\n\n# Arguments\n\n* `x` - x marks the spot\n* `y` - why ask y\n";
+    let (edited, arg_info) = parse_arguments_from_comment(about).unwrap();
+    assert_eq!(edited, "Adds numbers.\n\n");
+    assert_eq!(
+        arg_info,
+        [
+            ("x".to_owned(), "x marks the spot".to_owned()),
+            ("y".to_owned(), "why ask y".to_owned())
+        ]
+    );
+
+    // Without an `Arguments` section, everything is left as is.
+    let (edited, arg_info) = parse_arguments_from_comment("Adds numbers.\n").unwrap();
+    assert_eq!(edited, "Adds numbers.\n");
+    assert!(arg_info.is_empty());
+
+    assert_eq!(
+        parse_arguments_from_comment("# Arguments\n\nx marks the spot\n").unwrap_err(),
+        "`# Arguments` section does not contain a name/description list"
+    );
+    assert_eq!(
+        parse_arguments_from_comment("# Arguments\n\n* `x` x marks the spot\n").unwrap_err(),
+        "Argument description must start with ` - `"
+    );
+}
+
+#[test]
+fn env_vars_from_comment() {
+    let about = "Adds numbers.\n\n# Environment Variables\n\n* `z` - z for zest\n";
+    let (edited, env_info) = parse_env_vars_from_comment(about).unwrap();
+    assert_eq!(
+        edited,
+        "Adds numbers.\n\nENVIRONMENT VARIABLES:\n    <Z>    z for zest\n"
+    );
+    assert_eq!(env_info, [("z".to_owned(), "z for zest".to_owned())]);
+}
+
+#[test]
+fn env_vars_help_format() {
+    let env_info = [
+        ("z".to_owned(), "z for zest".to_owned()),
+        ("app_w".to_owned(), "w".to_owned()),
+    ];
+    assert_eq!(
+        env_vars_help(&env_info),
+        "ENVIRONMENT VARIABLES:\n    <Z>        z for zest\n    <APP_W>        w\n"
+    );
+}
+
+#[test]
+fn env_initializer_size() {
+    let initializer = generate_env_initializer(
+        parse_quote! { 0 },
+        format_ident!("z"),
+        "Z",
+        parse_quote! { i32 },
+    );
+    let expansion = quote! { #initializer }.to_string();
+    assert!(expansion.contains("parse_env_or_default"), "{}", expansion);
+
+    // Each `#[env_or_default]` variable used to expand to the full set of
+    // parsing traits, several thousand bytes of tokens; now the parsing is
+    // in the library, and the expansion is a call to it.
+    assert!(expansion.len() < 500, "{}", expansion.len());
+}
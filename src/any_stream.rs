@@ -0,0 +1,87 @@
+use io_streams::{StreamReader, StreamWriter};
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+
+/// The reader underlying an `InputByteStream`: an ordinary stream, or with
+/// the "testing" or "codecs" features, any reader, so that wrappers can
+/// still be presented as an `InputByteStream`.
+pub(crate) enum AnyReader {
+    Stream(StreamReader),
+    #[cfg(any(feature = "testing", feature = "codecs"))]
+    Boxed(Box<dyn Read + Send>),
+}
+
+impl Read for AnyReader {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Stream(reader) => reader.read(buf),
+            #[cfg(any(feature = "testing", feature = "codecs"))]
+            Self::Boxed(reader) => reader.read(buf),
+        }
+    }
+
+    #[inline]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        match self {
+            Self::Stream(reader) => reader.read_vectored(bufs),
+            #[cfg(any(feature = "testing", feature = "codecs"))]
+            Self::Boxed(reader) => reader.read_vectored(bufs),
+        }
+    }
+
+    #[cfg(can_vector)]
+    #[inline]
+    fn is_read_vectored(&self) -> bool {
+        match self {
+            Self::Stream(reader) => reader.is_read_vectored(),
+            #[cfg(any(feature = "testing", feature = "codecs"))]
+            Self::Boxed(reader) => reader.is_read_vectored(),
+        }
+    }
+}
+
+/// The writer underlying an `OutputByteStream`, like `AnyReader`.
+pub(crate) enum AnyWriter {
+    Stream(StreamWriter),
+    #[cfg(any(feature = "testing", feature = "codecs"))]
+    Boxed(Box<dyn Write + Send>),
+}
+
+impl Write for AnyWriter {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Stream(writer) => writer.write(buf),
+            #[cfg(any(feature = "testing", feature = "codecs"))]
+            Self::Boxed(writer) => writer.write(buf),
+        }
+    }
+
+    #[inline]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        match self {
+            Self::Stream(writer) => writer.write_vectored(bufs),
+            #[cfg(any(feature = "testing", feature = "codecs"))]
+            Self::Boxed(writer) => writer.write_vectored(bufs),
+        }
+    }
+
+    #[cfg(can_vector)]
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::Stream(writer) => writer.is_write_vectored(),
+            #[cfg(any(feature = "testing", feature = "codecs"))]
+            Self::Boxed(writer) => writer.is_write_vectored(),
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Stream(writer) => writer.flush(),
+            #[cfg(any(feature = "testing", feature = "codecs"))]
+            Self::Boxed(writer) => writer.flush(),
+        }
+    }
+}
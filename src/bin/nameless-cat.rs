@@ -0,0 +1,135 @@
+//! A `cat`-like program which exercises nameless itself, for testing every
+//! supported syntax by hand or in CI.
+//!
+//! It copies each of its inputs to its output, and then prints a summary of
+//! each stream to stderr, in the form:
+//!
+//! ```text
+//! input 0: kind=File media-type=text/plain size=6 copied=6
+//! output: kind=Stdio media-type=text/plain copied=6
+//! ```
+//!
+//! Sizes which are unknown are printed as `-`. Stream names are not printed.
+//!
+//! It exits with status 66 if an input can't be opened, 73 if the output
+//! can't be opened, 74 if copying fails, and 141 if the output is a closed
+//! pipe.
+
+use clap::{ambient_authority, TryFromOsArg};
+use layered_io::WriteLayered;
+use nameless::{
+    copy, CopyError, Existence, InputByteStream, LazyOutput, MediaType, OutputByteStream,
+};
+use std::ffi::OsString;
+use std::io;
+use std::process::exit;
+
+/// An input couldn't be opened.
+const EXIT_NO_INPUT: i32 = 66;
+/// The output couldn't be opened.
+const EXIT_CANT_CREATE: i32 = 73;
+/// An error occurred while copying.
+const EXIT_IO_ERROR: i32 = 74;
+/// The output was a pipe whose reader exited. This is the status a shell
+/// reports for a process killed by `SIGPIPE`.
+const EXIT_BROKEN_PIPE: i32 = 141;
+
+/// # Arguments
+///
+/// * `output` - Output sink, stdout if not present
+/// * `inputs` - Input sources, stdin if none
+#[kommand::main]
+fn main(
+    #[kommand(short = 'o', long)] output: Option<LazyOutput<OutputByteStream>>,
+    #[kommand(parse(from_os_str))] inputs: Vec<OsString>,
+) {
+    let inputs = if inputs.is_empty() {
+        vec![OsString::from("-")]
+    } else {
+        inputs
+    };
+
+    // Open all the inputs up front, so that we don't produce partial output
+    // if any of them can't be opened.
+    let mut streams = Vec::new();
+    for input in &inputs {
+        match InputByteStream::try_from_os_str_arg(input, ambient_authority()) {
+            Ok(stream) => streams.push(stream),
+            Err(e) => {
+                eprintln!("nameless-cat: {:#}", e);
+                exit(EXIT_NO_INPUT);
+            }
+        }
+    }
+
+    let media_type = streams
+        .iter()
+        .fold(None, |acc: Option<MediaType>, stream| {
+            Some(match acc {
+                Some(acc) => acc.union(stream.media_type().clone()),
+                None => stream.media_type().clone(),
+            })
+        })
+        .unwrap_or_else(MediaType::unknown);
+    let output = match output {
+        Some(output) => output.materialize(media_type),
+        None => OutputByteStream::try_from_os_str_arg("-".as_ref(), ambient_authority()),
+    };
+    let mut output = match output {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("nameless-cat: {:#}", e);
+            exit(EXIT_CANT_CREATE);
+        }
+    };
+
+    let mut total = 0;
+    for (index, mut stream) in streams.into_iter().enumerate() {
+        let info = stream.info();
+        let copied = match copy(&mut stream, &mut output) {
+            Ok(copied) => copied,
+            Err(e) => {
+                eprintln!("nameless-cat: {}", e);
+                if e.kind() == io::ErrorKind::BrokenPipe
+                    && CopyError::of(&e) == Some(CopyError::Write)
+                {
+                    exit(EXIT_BROKEN_PIPE);
+                }
+                exit(EXIT_IO_ERROR);
+            }
+        };
+        total += copied;
+        eprintln!(
+            "input {}: kind={:?} media-type={} size={} copied={}",
+            index,
+            info.kind(),
+            info.media_type().mime(),
+            match info.initial_size() {
+                Some(size) => size.to_string(),
+                None => "-".to_owned(),
+            },
+            copied
+        );
+    }
+
+    if let Err(e) = output.close() {
+        eprintln!("nameless-cat: {}", e);
+        if e.kind() == io::ErrorKind::BrokenPipe {
+            exit(EXIT_BROKEN_PIPE);
+        }
+        exit(EXIT_IO_ERROR);
+    }
+
+    let info = output.info();
+    eprintln!(
+        "output: kind={:?} media-type={} existence={} copied={}",
+        info.kind(),
+        info.media_type().mime(),
+        match info.existence() {
+            Some(Existence::Created) => "created",
+            Some(Existence::Overwrote) => "overwrote",
+            _ => "-",
+        },
+        total
+    );
+}
@@ -0,0 +1,200 @@
+use layered_io::WriteLayered;
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A stream shared between boxed readers and writers and a `CloseHandle`.
+/// The stream is taken out when it's closed.
+type Shared<S> = Arc<Mutex<Option<S>>>;
+
+pub(crate) fn share<S>(stream: S) -> Shared<S> {
+    Arc::new(Mutex::new(Some(stream)))
+}
+
+fn lock<S>(shared: &Shared<S>) -> MutexGuard<'_, Option<S>> {
+    shared.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn closed() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "stream was closed through its CloseHandle",
+    )
+}
+
+/// A reader for a stream shared with a `CloseHandle`.
+pub(crate) struct SharedReader<S>(pub(crate) Shared<S>);
+
+impl<S: Read> Read for SharedReader<S> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut *lock(&self.0) {
+            Some(stream) => stream.read(buf),
+            None => Err(closed()),
+        }
+    }
+
+    #[inline]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        match &mut *lock(&self.0) {
+            Some(stream) => stream.read_vectored(bufs),
+            None => Err(closed()),
+        }
+    }
+}
+
+/// A writer for a stream shared with a `CloseHandle`.
+pub(crate) struct SharedWriter<S>(pub(crate) Shared<S>);
+
+impl<S: Write> Write for SharedWriter<S> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut *lock(&self.0) {
+            Some(stream) => stream.write(buf),
+            None => Err(closed()),
+        }
+    }
+
+    #[inline]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        match &mut *lock(&self.0) {
+            Some(stream) => stream.write_vectored(bufs),
+            None => Err(closed()),
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut *lock(&self.0) {
+            Some(stream) => stream.flush(),
+            None => Err(closed()),
+        }
+    }
+
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match &mut *lock(&self.0) {
+            Some(stream) => stream.write_all(buf),
+            None => Err(closed()),
+        }
+    }
+}
+
+/// A handle for closing a stream which has been converted into boxed
+/// `dyn Write` or `dyn Read` trait objects, such as with
+/// [`OutputByteStream::into_boxed_write`].
+///
+/// [`CloseHandle::close`] performs the stream's full close, including
+/// waiting for a child process to exit and reporting if it failed, which
+/// dropping the boxed objects can't do. If the handle is dropped instead,
+/// the stream is cleaned up on a best-effort basis once the boxed objects
+/// are dropped too, with errors going to the [`on_drop_error`] callback.
+///
+/// [`OutputByteStream::into_boxed_write`]: crate::OutputByteStream::into_boxed_write
+/// [`on_drop_error`]: crate::on_drop_error
+pub struct CloseHandle {
+    close: Box<dyn FnOnce() -> io::Result<()> + Send>,
+}
+
+impl CloseHandle {
+    pub(crate) fn new<S: WriteLayered + Send + 'static>(shared: Shared<S>) -> Self {
+        Self {
+            close: Box::new(move || {
+                // Take the stream out before closing it, so that the lock
+                // isn't held while waiting.
+                let stream = lock(&shared).take();
+                match stream {
+                    Some(mut stream) => stream.close(),
+                    None => Ok(()),
+                }
+            }),
+        }
+    }
+
+    /// Close the stream and return any errors. This may be called before or
+    /// after the boxed objects are dropped; after it's called, they fail
+    /// with [`io::ErrorKind::BrokenPipe`].
+    pub fn close(self) -> io::Result<()> {
+        (self.close)()
+    }
+}
+
+impl Debug for CloseHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut b = f.debug_struct("CloseHandle");
+        b.finish()
+    }
+}
+
+#[test]
+fn boxed_read() {
+    use crate::InputByteStream;
+    use clap::TryFromOsArg;
+
+    fn read_all<R: Read>(mut reader: R) -> String {
+        let mut s = String::new();
+        reader.read_to_string(&mut s).unwrap();
+        s
+    }
+
+    let input =
+        InputByteStream::try_from_os_str_arg("data:,hello".as_ref(), clap::ambient_authority())
+            .unwrap();
+    assert_eq!(read_all(input.into_boxed_read()), "hello");
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn boxed_write_close_waits_for_child() {
+    use crate::OutputByteStream;
+    use clap::TryFromOsArg;
+
+    let dir = tempfile::tempdir().unwrap();
+    let open = |script: &str| {
+        let name = format!("$(sh -c '{}')", script);
+        OutputByteStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority()).unwrap()
+    };
+
+    // The child is slow to finish, and fails.
+    let path = dir.path().join("failure");
+    let (mut writer, handle) =
+        open(&format!("cat > {}; sleep 0.2; exit 3", path.display())).into_boxed_write();
+    writer.write_all(b"hello").unwrap();
+    drop(writer);
+    let err = handle.close().unwrap_err();
+    assert!(
+        err.to_string().contains("non-success exit status"),
+        "{}",
+        err
+    );
+    assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+
+    // The child succeeds, and the handle is closed while the writer is live.
+    let path = dir.path().join("success");
+    let (mut writer, handle) = open(&format!("cat > {}", path.display())).into_boxed_write();
+    writer.write_all(b"world").unwrap();
+    handle.close().unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"world");
+    assert_eq!(
+        writer.write_all(b"more").unwrap_err().kind(),
+        io::ErrorKind::BrokenPipe
+    );
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn boxed_halves() {
+    use crate::InteractiveByteStream;
+    use clap::TryFromOsArg;
+
+    let interactive =
+        InteractiveByteStream::try_from_os_str_arg("$(cat)".as_ref(), clap::ambient_authority())
+            .unwrap();
+    let (mut reader, mut writer, handle) = interactive.into_boxed_halves();
+    writer.write_all(b"ping\n").unwrap();
+    let mut buf = [0; 5];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping\n");
+    drop((reader, writer));
+    handle.close().unwrap();
+}
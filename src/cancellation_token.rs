@@ -0,0 +1,97 @@
+use crate::OpenError;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A handle for cancelling long-running opens and transfers from another
+/// thread.
+///
+/// Tokens are cheap to clone, and all clones share the same state, so one
+/// clone can be handed to [`copy_cancellable`] or an [`OpenPolicy`] while
+/// another is kept to call [`cancel`] on.
+///
+/// Cancellation is cooperative: it's noticed between accept attempts and
+/// between buffers of a copy. A blocking read or write, such as a read from
+/// a slow file or pipe, is not interrupted, so cancellation takes effect
+/// once it returns.
+///
+/// [`copy_cancellable`]: crate::copy_cancellable
+/// [`OpenPolicy`]: crate::OpenPolicy
+/// [`cancel`]: Self::cancel
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    /// Return a new token which is not cancelled.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return a new token which is cancelled automatically once `deadline`
+    /// has passed, or earlier if [`cancel`] is called.
+    ///
+    /// [`cancel`]: Self::cancel
+    #[inline]
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                cancelled: AtomicBool::new(false),
+                deadline: Some(deadline),
+            }),
+        }
+    }
+
+    /// Cancel this token and all of its clones.
+    #[inline]
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Test whether this token has been cancelled, or its deadline has
+    /// passed.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Relaxed)
+            || matches!(self.inner.deadline, Some(deadline) if Instant::now() >= deadline)
+    }
+
+    /// Return the error for an operation stopped by a cancellation, which
+    /// is an `Interrupted` error wrapping `OpenError::Cancelled`.
+    pub(crate) fn error() -> io::Error {
+        io::Error::new(io::ErrorKind::Interrupted, OpenError::Cancelled)
+    }
+}
+
+#[test]
+fn cancel_clones() {
+    let token = CancellationToken::new();
+    let clone = token.clone();
+    assert!(!clone.is_cancelled());
+    token.cancel();
+    assert!(clone.is_cancelled());
+    let e = CancellationToken::error();
+    assert_eq!(e.kind(), io::ErrorKind::Interrupted);
+    assert!(matches!(
+        e.get_ref().unwrap().downcast_ref::<OpenError>(),
+        Some(OpenError::Cancelled)
+    ));
+}
+
+#[test]
+fn cancel_at_deadline() {
+    let token =
+        CancellationToken::with_deadline(Instant::now() + std::time::Duration::from_millis(50));
+    assert!(!token.is_cancelled());
+    std::thread::sleep(std::time::Duration::from_millis(60));
+    assert!(token.is_cancelled());
+}
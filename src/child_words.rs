@@ -0,0 +1,109 @@
+use anyhow::anyhow;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+/// Bytes which aren't valid UTF-8 are mapped to the last 256 Unicode
+/// private-use code points while the string is split, so that
+/// `shell_words` can do the quoting, and mapped back to the original bytes
+/// afterward.
+const ESCAPE_BASE: u32 = 0x10_ff00;
+
+/// Split a string of the form `$(cmd args...)` into the command and its
+/// arguments, with shell-style quoting. Arguments may contain bytes which
+/// aren't valid UTF-8, which are passed through unchanged.
+pub(crate) fn split_child(os: &OsStr) -> anyhow::Result<Vec<OsString>> {
+    let bytes = os.as_bytes();
+    assert!(bytes.starts_with(b"$("));
+    let inner = bytes[2..]
+        .strip_suffix(b")")
+        .ok_or_else(|| anyhow!("child string must end in ')'"))?;
+    let words = shell_words::split(&escape(inner)?)?;
+    if words.is_empty() {
+        return Err(anyhow!(
+            "child stream specified with '(...)' must contain a command"
+        ));
+    }
+    Ok(words.iter().map(|word| unescape(word)).collect())
+}
+
+fn escape(mut bytes: &[u8]) -> anyhow::Result<String> {
+    let mut escaped = String::with_capacity(bytes.len());
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                push_valid(&mut escaped, valid)?;
+                return Ok(escaped);
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                push_valid(&mut escaped, std::str::from_utf8(valid).unwrap())?;
+                let invalid_len = e.error_len().unwrap_or(rest.len());
+                for byte in &rest[..invalid_len] {
+                    escaped.push(char::from_u32(ESCAPE_BASE + u32::from(*byte)).unwrap());
+                }
+                bytes = &rest[invalid_len..];
+            }
+        }
+    }
+}
+
+fn push_valid(escaped: &mut String, valid: &str) -> anyhow::Result<()> {
+    if valid.chars().any(is_escape) {
+        return Err(anyhow!(
+            "child strings may not contain the code points U+10FF00 through U+10FFFF"
+        ));
+    }
+    escaped.push_str(valid);
+    Ok(())
+}
+
+fn unescape(word: &str) -> OsString {
+    let mut bytes = Vec::with_capacity(word.len());
+    for c in word.chars() {
+        if is_escape(c) {
+            bytes.push((u32::from(c) - ESCAPE_BASE) as u8);
+        } else {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+    }
+    OsString::from_vec(bytes)
+}
+
+fn is_escape(c: char) -> bool {
+    u32::from(c) >= ESCAPE_BASE
+}
+
+#[test]
+fn split_non_utf8() {
+    let split = |bytes: &[u8]| split_child(OsStr::from_bytes(bytes));
+
+    assert_eq!(
+        split(b"$(echo 'a b' c)").unwrap(),
+        vec![OsString::from("echo"), "a b".into(), "c".into()]
+    );
+    assert_eq!(
+        split(b"$(cat f\xff 'g\xfe h' \xe2\x82)").unwrap(),
+        vec![
+            OsString::from("cat"),
+            OsString::from_vec(b"f\xff".to_vec()),
+            OsString::from_vec(b"g\xfe h".to_vec()),
+            OsString::from_vec(b"\xe2\x82".to_vec()),
+        ]
+    );
+    assert!(split(b"$(echo \xff").is_err());
+    assert!(split(b"$( )").is_err());
+    assert!(split("$(echo \u{10ff41})".as_bytes()).is_err());
+}
+
+#[test]
+fn child_non_utf8_argument() {
+    use crate::InputByteStream;
+    use clap::TryFromOsArg;
+    use std::io::Read;
+
+    let name = OsStr::from_bytes(b"$(sh -c 'printf \"%s\\n\" \"$#\" \"$1\"' sh f\xff)");
+    let mut input = InputByteStream::try_from_os_str_arg(name, clap::ambient_authority()).unwrap();
+    let mut output = Vec::new();
+    input.read_to_end(&mut output).unwrap();
+    assert_eq!(output, b"1\nf\xff\n");
+}
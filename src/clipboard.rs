@@ -0,0 +1,296 @@
+use crate::drop_error::report_drop_error;
+use crate::memory_budget::BudgetTracker;
+use crate::stream_options::{parse_url_options, CLIPBOARD};
+use anyhow::anyhow;
+use std::io::{self, Write};
+use url::Url;
+
+/// The default limit on the amount of output buffered for the clipboard.
+pub(crate) const DEFAULT_CLIPBOARD_LIMIT: usize = 16 * 1024 * 1024;
+
+/// The clipboard operations nameless uses, so that the buffering logic can
+/// be tested without a real clipboard.
+pub(crate) trait Clipboard: Send {
+    /// Return the current clipboard contents as plain text.
+    fn get_text(&mut self) -> anyhow::Result<String>;
+
+    /// Replace the clipboard contents with `text`.
+    fn set_text(&mut self, text: String) -> anyhow::Result<()>;
+
+    /// Replace the clipboard contents with `html`.
+    fn set_html(&mut self, html: String) -> anyhow::Result<()>;
+}
+
+struct SystemClipboard(arboard::Clipboard);
+
+impl Clipboard for SystemClipboard {
+    fn get_text(&mut self) -> anyhow::Result<String> {
+        Ok(self.0.get_text()?)
+    }
+
+    fn set_text(&mut self, text: String) -> anyhow::Result<()> {
+        Ok(self.0.set_text(text)?)
+    }
+
+    fn set_html(&mut self, html: String) -> anyhow::Result<()> {
+        Ok(self.0.set_html(html, None)?)
+    }
+}
+
+/// Connect to the system clipboard, failing cleanly on systems without one,
+/// such as headless servers.
+pub(crate) fn system_clipboard() -> anyhow::Result<Box<dyn Clipboard>> {
+    let clipboard =
+        arboard::Clipboard::new().map_err(|e| anyhow!("no clipboard available: {}", e))?;
+    Ok(Box::new(SystemClipboard(clipboard)))
+}
+
+/// Options from the query of a `clipboard:` URL.
+pub(crate) struct ClipboardOptions {
+    /// Whether the contents are HTML rather than plain text.
+    pub(crate) html: bool,
+    /// The maximum number of bytes of output to buffer.
+    pub(crate) limit: usize,
+}
+
+impl ClipboardOptions {
+    pub(crate) fn parse(url: &Url) -> anyhow::Result<Self> {
+        if !url.path().is_empty() || url.fragment().is_some() {
+            return Err(anyhow!("clipboard URL should only contain options"));
+        }
+        let options = parse_url_options(url, &CLIPBOARD)?;
+        Ok(Self {
+            html: options.get("type") == Some("text/html"),
+            limit: match options.get("limit") {
+                Some(_) => options
+                    .integer("limit")
+                    .ok_or_else(|| anyhow!("clipboard limit is too large"))?,
+                None => DEFAULT_CLIPBOARD_LIMIT,
+            },
+        })
+    }
+}
+
+/// Read the clipboard contents for a `clipboard:` input.
+pub(crate) fn read_clipboard(
+    clipboard: &mut dyn Clipboard,
+    options: &ClipboardOptions,
+) -> anyhow::Result<String> {
+    if options.html {
+        return Err(anyhow!("reading HTML from the clipboard is not supported"));
+    }
+    clipboard.get_text()
+}
+
+/// A writer which buffers its output and places it on the clipboard when
+/// it's dropped, because the clipboard holds one complete value rather than
+/// a stream of bytes.
+pub(crate) struct ClipboardWriter {
+    clipboard: Box<dyn Clipboard>,
+    buf: Vec<u8>,
+    html: bool,
+    limit: usize,
+    budget: BudgetTracker,
+    finished: bool,
+}
+
+impl ClipboardWriter {
+    pub(crate) fn new(clipboard: Box<dyn Clipboard>, options: &ClipboardOptions) -> Self {
+        Self {
+            clipboard,
+            buf: Vec::new(),
+            html: options.html,
+            limit: options.limit,
+            budget: BudgetTracker::new("clipboard output"),
+            finished: false,
+        }
+    }
+
+    /// Place the buffered output on the clipboard.
+    fn finish(&mut self) -> io::Result<()> {
+        self.finished = true;
+        let contents = String::from_utf8(std::mem::take(&mut self.buf)).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "clipboard output isn't UTF-8")
+        })?;
+        let result = if self.html {
+            self.clipboard.set_html(contents)
+        } else {
+            self.clipboard.set_text(contents)
+        };
+        result.map_err(|e| io::Error::other(e.to_string()))
+    }
+}
+
+impl Write for ClipboardWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buf.len() + buf.len() > self.limit {
+            return Err(io::Error::other(format!(
+                "clipboard output exceeds the limit of {} bytes",
+                self.limit
+            )));
+        }
+        self.budget.check_io(self.buf.len() + buf.len())?;
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Nothing reaches the clipboard until the stream is closed.
+        Ok(())
+    }
+}
+
+impl Drop for ClipboardWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(e) = self.finish() {
+                report_drop_error(io::Error::new(
+                    e.kind(),
+                    format!("unable to set the clipboard: {}", e),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct MockClipboard(std::sync::Arc<std::sync::Mutex<Option<(bool, String)>>>);
+
+#[cfg(test)]
+impl Clipboard for MockClipboard {
+    fn get_text(&mut self) -> anyhow::Result<String> {
+        match &*self.0.lock().unwrap() {
+            Some((_, text)) => Ok(text.clone()),
+            None => Err(anyhow!("empty clipboard")),
+        }
+    }
+
+    fn set_text(&mut self, text: String) -> anyhow::Result<()> {
+        *self.0.lock().unwrap() = Some((false, text));
+        Ok(())
+    }
+
+    fn set_html(&mut self, html: String) -> anyhow::Result<()> {
+        *self.0.lock().unwrap() = Some((true, html));
+        Ok(())
+    }
+}
+
+#[test]
+fn clipboard_set_on_close() {
+    let mock = MockClipboard::default();
+    let options = ClipboardOptions::parse(&Url::parse("clipboard:").unwrap()).unwrap();
+    let mut writer = ClipboardWriter::new(Box::new(mock.clone()), &options);
+    writer.write_all(b"hello ").unwrap();
+    writer.flush().unwrap();
+    writer.write_all(b"world").unwrap();
+
+    // Nothing is visible until the writer is closed.
+    assert!(mock.0.lock().unwrap().is_none());
+    drop(writer);
+    assert_eq!(
+        *mock.0.lock().unwrap(),
+        Some((false, "hello world".to_owned()))
+    );
+}
+
+#[test]
+fn clipboard_read() {
+    let mut mock = MockClipboard::default();
+    let options = ClipboardOptions::parse(&Url::parse("clipboard:").unwrap()).unwrap();
+    assert!(read_clipboard(&mut mock, &options).is_err());
+    mock.set_text("copied".to_owned()).unwrap();
+    assert_eq!(read_clipboard(&mut mock, &options).unwrap(), "copied");
+
+    let options =
+        ClipboardOptions::parse(&Url::parse("clipboard:?type=text/html").unwrap()).unwrap();
+    assert!(read_clipboard(&mut mock, &options).is_err());
+}
+
+#[test]
+fn clipboard_html_and_limit() {
+    let mock = MockClipboard::default();
+    let url = Url::parse("clipboard:?type=text/html&limit=8").unwrap();
+    let options = ClipboardOptions::parse(&url).unwrap();
+    let mut writer = ClipboardWriter::new(Box::new(mock.clone()), &options);
+    writer.write_all(b"<b>hi</b").unwrap();
+    assert!(writer.write_all(b">").is_err());
+    drop(writer);
+    assert_eq!(*mock.0.lock().unwrap(), Some((true, "<b>hi</b".to_owned())));
+
+    assert!(ClipboardOptions::parse(&Url::parse("clipboard:?type=image/png").unwrap()).is_err());
+    assert!(ClipboardOptions::parse(&Url::parse("clipboard:?color=red").unwrap()).is_err());
+}
+
+#[test]
+fn clipboard_memory_budget() {
+    use crate::memory_budget::{test_budget, with_test_budget};
+    use crate::OpenError;
+
+    let options = ClipboardOptions::parse(&Url::parse("clipboard:").unwrap()).unwrap();
+    let (err, _) = with_test_budget(test_budget(8), || {
+        let mut writer = ClipboardWriter::new(Box::new(MockClipboard::default()), &options);
+        writer.write_all(b"12345678").unwrap();
+        writer.write_all(b"9").unwrap_err()
+    });
+    assert!(matches!(
+        OpenError::of(&err),
+        Some(OpenError::MemoryBudgetExceeded {
+            feature: "clipboard output",
+            needed: 9,
+            budget: 8
+        })
+    ));
+}
+
+#[test]
+fn clipboard_drop_error() {
+    use crate::drop_error::DropErrorRecorder;
+
+    let recorder = DropErrorRecorder::install();
+    let mock = MockClipboard::default();
+    let options = ClipboardOptions::parse(&Url::parse("clipboard:").unwrap()).unwrap();
+    let mut writer = ClipboardWriter::new(Box::new(mock.clone()), &options);
+    writer.write_all(b"\xff not UTF-8").unwrap();
+
+    // Dropping reports the failure rather than panicking.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(writer)));
+    assert!(result.is_ok());
+    assert_eq!(recorder.take(), vec![io::ErrorKind::InvalidData]);
+    assert!(mock.0.lock().unwrap().is_none());
+}
+
+/// Exercise the real clipboard. This needs a desktop session, so it only
+/// runs when `NAMELESS_CLIPBOARD_TESTS` is set.
+#[test]
+fn clipboard_round_trip() {
+    use crate::{InputByteStream, OutputByteStream};
+    use clap::TryFromOsArg;
+    use std::io::Read;
+
+    if std::env::var_os("NAMELESS_CLIPBOARD_TESTS").is_none() {
+        return;
+    }
+
+    let mut output =
+        OutputByteStream::try_from_os_str_arg("clipboard:".as_ref(), clap::ambient_authority())
+            .unwrap();
+    output.write_all(b"from nameless").unwrap();
+    drop(output);
+
+    // The clipboard is set from the output's writer thread, so give it a
+    // moment to finish.
+    for _ in 0..100 {
+        let mut s = String::new();
+        InputByteStream::try_from_os_str_arg("clipboard:".as_ref(), clap::ambient_authority())
+            .unwrap()
+            .read_to_string(&mut s)
+            .unwrap();
+        if s == "from nameless" {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    panic!("clipboard contents were not set");
+}
@@ -0,0 +1,732 @@
+use crate::drop_error::report_drop_error;
+use crate::memory_budget::{read_line_within, BudgetTracker};
+use crate::{InteractiveByteStream, InteractiveTextStream};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// Settings for [`Framed`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct FrameFormat {
+    /// The largest frame, in bytes, which may be sent or received.
+    pub max_frame_size: u32,
+}
+
+impl Default for FrameFormat {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_frame_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// A stream of frames, each consisting of a 32-bit big-endian length
+/// followed by that many bytes, over an interactive stream.
+///
+/// Errors from the framing itself are `io::Error`s wrapping a
+/// [`CodecError`], which can be obtained with [`io::Error::get_ref`] and
+/// `downcast_ref`.
+pub struct Framed<S = InteractiveByteStream> {
+    stream: S,
+    format: FrameFormat,
+}
+
+impl<S: Read + Write> Framed<S> {
+    /// Construct a new `Framed` over `stream`.
+    #[inline]
+    pub fn new(stream: S, format: FrameFormat) -> Self {
+        Self { stream, format }
+    }
+
+    /// Send `frame`, and flush the stream.
+    ///
+    /// Frames larger than the format's `max_frame_size` fail with
+    /// [`CodecError::FrameTooLarge`] without sending anything, so the
+    /// stream remains usable.
+    pub fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        let len = match u32::try_from(frame.len()) {
+            Ok(len) if len <= self.format.max_frame_size => len,
+            _ => {
+                return Err(CodecError::FrameTooLarge {
+                    size: frame.len() as u64,
+                    limit: self.format.max_frame_size,
+                }
+                .into_io())
+            }
+        };
+        self.stream.write_all(&len.to_be_bytes())?;
+        self.stream.write_all(frame)?;
+        self.stream.flush()
+    }
+
+    /// Receive a frame, returning `None` if the stream ended cleanly
+    /// between frames.
+    ///
+    /// A frame larger than the format's `max_frame_size` is read and
+    /// discarded, and fails with [`CodecError::FrameTooLarge`], so the next
+    /// frame can still be received. Likewise, a frame larger than the
+    /// [`MemoryBudget`] allows fails with
+    /// [`OpenError::MemoryBudgetExceeded`]. A stream which ends within a frame
+    /// fails with [`CodecError::Truncated`].
+    ///
+    /// [`MemoryBudget`]: crate::MemoryBudget
+    /// [`OpenError::MemoryBudgetExceeded`]: crate::OpenError::MemoryBudgetExceeded
+    pub fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut header = [0; 4];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.stream.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(CodecError::Truncated.into_io()),
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let len = u32::from_be_bytes(header);
+        let rejection = if len > self.format.max_frame_size {
+            Some(
+                CodecError::FrameTooLarge {
+                    size: len.into(),
+                    limit: self.format.max_frame_size,
+                }
+                .into_io(),
+            )
+        } else {
+            BudgetTracker::new("frame")
+                .check_io(usize::try_from(len).unwrap_or(usize::MAX))
+                .err()
+        };
+        if let Some(err) = rejection {
+            let skipped = io::copy(&mut (&mut self.stream).take(len.into()), &mut io::sink())?;
+            if skipped != u64::from(len) {
+                return Err(CodecError::Truncated.into_io());
+            }
+            return Err(err);
+        }
+
+        let mut frame = vec![0; len as usize];
+        self.stream.read_exact(&mut frame).map_err(|err| {
+            if err.kind() == io::ErrorKind::UnexpectedEof {
+                CodecError::Truncated.into_io()
+            } else {
+                err
+            }
+        })?;
+        Ok(Some(frame))
+    }
+
+    /// Return a reference to the underlying stream.
+    #[inline]
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Return a mutable reference to the underlying stream.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consume `self` and return the underlying stream.
+    #[inline]
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+/// A stream of newline-delimited JSON values over an interactive stream.
+///
+/// Errors from the encoding itself are `io::Error`s wrapping a
+/// [`CodecError`], which can be obtained with [`io::Error::get_ref`] and
+/// `downcast_ref`.
+pub struct JsonLines<S = InteractiveTextStream> {
+    stream: BufReader<S>,
+    line: Vec<u8>,
+    budget: BudgetTracker,
+}
+
+impl<S: Read + Write> JsonLines<S> {
+    /// Construct a new `JsonLines` over `stream`.
+    #[inline]
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+            line: Vec::new(),
+            budget: BudgetTracker::new("JSON Lines record"),
+        }
+    }
+
+    /// Send `value` as a line of JSON, and flush the stream.
+    pub fn send<T: Serialize + ?Sized>(&mut self, value: &T) -> io::Result<()> {
+        // JSON serialization escapes newlines within strings, so the value
+        // is always a single line.
+        let mut line =
+            serde_json::to_vec(value).map_err(|err| CodecError::InvalidJson(err).into_io())?;
+        line.push(b'\n');
+        let stream = self.stream.get_mut();
+        stream.write_all(&line)?;
+        stream.flush()
+    }
+
+    /// Receive a value, returning `None` if the stream ended cleanly
+    /// between lines. Blank lines are skipped.
+    ///
+    /// A line which doesn't parse as a `T` fails with
+    /// [`CodecError::InvalidJson`], and a line longer than the
+    /// [`MemoryBudget`] allows fails with
+    /// [`OpenError::MemoryBudgetExceeded`]. Either way, the line is
+    /// consumed, so the next value can still be received.
+    ///
+    /// [`MemoryBudget`]: crate::MemoryBudget
+    /// [`OpenError::MemoryBudgetExceeded`]: crate::OpenError::MemoryBudgetExceeded
+    pub fn recv<T: DeserializeOwned>(&mut self) -> io::Result<Option<T>> {
+        loop {
+            self.line.clear();
+            if read_line_within(&mut self.stream, &mut self.line, &mut self.budget)? == 0 {
+                return Ok(None);
+            }
+            if self.line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            return serde_json::from_slice(&self.line)
+                .map(Some)
+                .map_err(|err| CodecError::InvalidJson(err).into_io());
+        }
+    }
+
+    /// Return a reference to the underlying stream.
+    #[inline]
+    pub fn get_ref(&self) -> &S {
+        self.stream.get_ref()
+    }
+
+    /// Return a mutable reference to the underlying stream.
+    ///
+    /// Reading from the stream directly may skip data which `JsonLines` has
+    /// buffered.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut S {
+        self.stream.get_mut()
+    }
+}
+
+/// An error from [`Framed`] or [`JsonLines`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CodecError {
+    /// A frame was larger than the format's `max_frame_size`.
+    FrameTooLarge {
+        /// The size of the frame, in bytes.
+        size: u64,
+        /// The maximum frame size.
+        limit: u32,
+    },
+    /// The stream ended within a frame.
+    Truncated,
+    /// A value couldn't be encoded as JSON, or a line couldn't be decoded.
+    InvalidJson(serde_json::Error),
+    /// A base64 JSON Lines record had a sequence number other than the
+    /// next one, because records were dropped or reordered.
+    RecordSequence {
+        /// The sequence number of the next record.
+        expected: u64,
+        /// The sequence number of the record which was found.
+        found: u64,
+    },
+    /// A base64 JSON Lines record was missing a field, or its data wasn't
+    /// valid base64.
+    InvalidRecord(String),
+}
+
+impl CodecError {
+    fn into_io(self) -> io::Error {
+        let kind = match self {
+            Self::Truncated => io::ErrorKind::UnexpectedEof,
+            Self::FrameTooLarge { .. }
+            | Self::InvalidJson(_)
+            | Self::RecordSequence { .. }
+            | Self::InvalidRecord(_) => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, self)
+    }
+
+    /// If `e` was produced by [`Framed`] or [`JsonLines`] because of the
+    /// encoding, rather than by the underlying stream, return the reason.
+    pub fn of(e: &io::Error) -> Option<&Self> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<Self>())
+    }
+}
+
+impl Error for CodecError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InvalidJson(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FrameTooLarge { size, limit } => write!(
+                f,
+                "frame of {} bytes exceeds the limit of {} bytes",
+                size, limit
+            ),
+            Self::Truncated => write!(f, "stream ended within a frame"),
+            Self::InvalidJson(err) => write!(f, "invalid JSON: {}", err),
+            Self::RecordSequence { expected, found } => write!(
+                f,
+                "expected record {}, but found record {}",
+                expected, found
+            ),
+            Self::InvalidRecord(message) => write!(f, "invalid record: {}", message),
+        }
+    }
+}
+
+/// A writer which encodes its output as JSON Lines records of the form
+/// `{"seq":0,"data":"<base64>"}`, each holding up to `record_size` bytes.
+pub(crate) struct JsonlBase64Writer<W: Write> {
+    inner: W,
+    record_size: usize,
+    pending: Vec<u8>,
+    seq: u64,
+}
+
+impl<W: Write> JsonlBase64Writer<W> {
+    pub(crate) fn new(inner: W, record_size: usize) -> Self {
+        assert!(record_size > 0, "record_size must be nonzero");
+        Self {
+            inner,
+            record_size,
+            pending: Vec::with_capacity(record_size),
+            seq: 0,
+        }
+    }
+
+    /// Write the pending bytes, if any, as a record.
+    fn emit(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let line = format!(
+            "{{\"seq\":{},\"data\":\"{}\"}}\n",
+            self.seq,
+            BASE64.encode(&self.pending)
+        );
+        self.inner.write_all(line.as_bytes())?;
+        self.seq += 1;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for JsonlBase64Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            // Emit full records before accepting more bytes, so that a
+            // failure doesn't lose bytes we've reported as written.
+            if self.pending.len() == self.record_size {
+                if let Err(err) = self.emit() {
+                    return if written == 0 { Err(err) } else { Ok(written) };
+                }
+            }
+            let n = (self.record_size - self.pending.len()).min(buf.len() - written);
+            self.pending.extend_from_slice(&buf[written..written + n]);
+            written += n;
+        }
+        Ok(written)
+    }
+
+    /// Emit any pending bytes as a record, which may be shorter than
+    /// `record_size`, and flush the underlying stream.
+    fn flush(&mut self) -> io::Result<()> {
+        self.emit()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for JsonlBase64Writer<W> {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            report_drop_error(err);
+        }
+    }
+}
+
+/// A reader which decodes JSON Lines records written by
+/// `JsonlBase64Writer`, checking that they're in sequence.
+pub(crate) struct JsonlBase64Reader<R: BufRead> {
+    inner: R,
+    line: Vec<u8>,
+    data: Vec<u8>,
+    pos: usize,
+    next_seq: u64,
+    budget: BudgetTracker,
+}
+
+impl<R: BufRead> JsonlBase64Reader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: Vec::new(),
+            data: Vec::new(),
+            pos: 0,
+            next_seq: 0,
+            budget: BudgetTracker::new("base64 JSON Lines record"),
+        }
+    }
+
+    /// Read the next record into `self.data`, returning `false` at the end
+    /// of the stream.
+    fn next_record(&mut self) -> io::Result<bool> {
+        loop {
+            self.line.clear();
+            if read_line_within(&mut self.inner, &mut self.line, &mut self.budget)? == 0 {
+                return Ok(false);
+            }
+            if !self.line.iter().all(u8::is_ascii_whitespace) {
+                break;
+            }
+        }
+
+        let record: serde_json::Value = serde_json::from_slice(&self.line)
+            .map_err(|err| CodecError::InvalidJson(err).into_io())?;
+        let seq = record["seq"]
+            .as_u64()
+            .ok_or_else(|| CodecError::InvalidRecord("missing \"seq\"".to_owned()).into_io())?;
+        if seq != self.next_seq {
+            return Err(CodecError::RecordSequence {
+                expected: self.next_seq,
+                found: seq,
+            }
+            .into_io());
+        }
+        let data = record["data"]
+            .as_str()
+            .ok_or_else(|| CodecError::InvalidRecord("missing \"data\"".to_owned()).into_io())?;
+        self.data = BASE64
+            .decode(data)
+            .map_err(|err| CodecError::InvalidRecord(err.to_string()).into_io())?;
+        self.pos = 0;
+        self.next_seq += 1;
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Read for JsonlBase64Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.data.len() {
+            if buf.is_empty() || !self.next_record()? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.data.len() - self.pos);
+        buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// An in-memory duplex stream which reads from `incoming` and writes to
+/// `outgoing`, at most `chunk` bytes at a time.
+#[cfg(test)]
+struct Trickle {
+    incoming: io::Cursor<Vec<u8>>,
+    outgoing: Vec<u8>,
+    chunk: usize,
+}
+
+#[cfg(test)]
+impl Trickle {
+    fn new(incoming: Vec<u8>, chunk: usize) -> Self {
+        Self {
+            incoming: io::Cursor::new(incoming),
+            outgoing: Vec::new(),
+            chunk,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.chunk);
+        self.incoming.read(&mut buf[..len])
+    }
+}
+
+#[cfg(test)]
+impl Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.chunk);
+        self.outgoing.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn framed_round_trip() {
+    let format = FrameFormat {
+        max_frame_size: 64,
+        ..FrameFormat::default()
+    };
+
+    // Send through a stream which accepts one byte per write.
+    let mut sender = Framed::new(Trickle::new(Vec::new(), 1), format.clone());
+    sender.send(b"hello").unwrap();
+    sender.send(b"").unwrap();
+    let err = sender.send(&[0; 65]).unwrap_err();
+    assert!(matches!(
+        CodecError::of(&err),
+        Some(CodecError::FrameTooLarge {
+            size: 65,
+            limit: 64
+        })
+    ));
+    sender.send(&[7; 64]).unwrap();
+    let mut sent = sender.into_inner().outgoing;
+
+    // Splice in an oversized frame, which is skipped on receipt.
+    sent.extend_from_slice(&100_u32.to_be_bytes());
+    sent.extend_from_slice(&[0; 100]);
+    sent.extend_from_slice(&3_u32.to_be_bytes());
+    sent.extend_from_slice(b"end");
+
+    // Receive through a stream which returns one byte per read.
+    let mut receiver = Framed::new(Trickle::new(sent, 1), format);
+    assert_eq!(receiver.recv().unwrap(), Some(b"hello".to_vec()));
+    assert_eq!(receiver.recv().unwrap(), Some(Vec::new()));
+    assert_eq!(receiver.recv().unwrap(), Some(vec![7; 64]));
+    let err = receiver.recv().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(matches!(
+        CodecError::of(&err),
+        Some(CodecError::FrameTooLarge { size: 100, .. })
+    ));
+    assert_eq!(receiver.recv().unwrap(), Some(b"end".to_vec()));
+    assert_eq!(receiver.recv().unwrap(), None);
+}
+
+#[test]
+fn framed_truncated() {
+    for incoming in [vec![0, 0], vec![0, 0, 0, 5, b'a', b'b']] {
+        let mut receiver = Framed::new(Trickle::new(incoming, 3), FrameFormat::default());
+        let err = receiver.recv().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(matches!(CodecError::of(&err), Some(CodecError::Truncated)));
+    }
+}
+
+#[test]
+fn json_lines_round_trip() {
+    let mut sender = JsonLines::new(Trickle::new(Vec::new(), 2));
+    sender.send(&vec!["a\nb", "c"]).unwrap();
+    sender.send(&42).unwrap();
+    let mut sent = sender.get_mut().outgoing.clone();
+    sent.extend_from_slice(b"\n{not json}\n\"after\"");
+
+    let mut receiver = JsonLines::new(Trickle::new(sent, 1));
+    assert_eq!(
+        receiver.recv::<Vec<String>>().unwrap(),
+        Some(vec!["a\nb".to_owned(), "c".to_owned()])
+    );
+    assert_eq!(receiver.recv::<u32>().unwrap(), Some(42));
+    let err = receiver.recv::<u32>().unwrap_err();
+    assert!(matches!(
+        CodecError::of(&err),
+        Some(CodecError::InvalidJson(_))
+    ));
+    assert_eq!(receiver.recv::<String>().unwrap(), Some("after".to_owned()));
+    assert_eq!(receiver.recv::<String>().unwrap(), None);
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn codecs_over_child() {
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+
+    let stream =
+        InteractiveByteStream::try_from_os_str_arg("$(cat)".as_ref(), clap::ambient_authority())
+            .unwrap();
+    let mut framed = Framed::new(stream, FrameFormat::default());
+    framed.send(b"ping").unwrap();
+    assert_eq!(framed.recv().unwrap(), Some(b"ping".to_vec()));
+    framed.into_inner().close().unwrap();
+
+    let stream =
+        InteractiveTextStream::try_from_os_str_arg("$(cat)".as_ref(), clap::ambient_authority())
+            .unwrap();
+    let mut json = JsonLines::new(stream);
+    json.send(&[1, 2, 3]).unwrap();
+    assert_eq!(json.recv::<Vec<u8>>().unwrap(), Some(vec![1, 2, 3]));
+    json.get_mut().close().unwrap();
+}
+
+#[test]
+fn jsonl_base64_round_trip() {
+    let bytes = (0..=255)
+        .chain([0, 0, 0xff, 0xfe, b'\n'])
+        .collect::<Vec<u8>>();
+
+    let mut encoded = Vec::new();
+    let mut writer = JsonlBase64Writer::new(&mut encoded, 7);
+    writer.write_all(&bytes[..100]).unwrap();
+    writer.write_all(&bytes[100..]).unwrap();
+    drop(writer);
+
+    let text = String::from_utf8(encoded.clone()).unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), bytes.len().div_ceil(7));
+    assert!(lines[0].starts_with("{\"seq\":0,\"data\":\""));
+    assert!(lines[1].starts_with("{\"seq\":1,"));
+
+    let mut decoded = Vec::new();
+    JsonlBase64Reader::new(encoded.as_slice())
+        .read_to_end(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, bytes);
+
+    // Flushing emits a short record.
+    let mut encoded = Vec::new();
+    let mut writer = JsonlBase64Writer::new(&mut encoded, 4);
+    writer.write_all(b"ab").unwrap();
+    writer.flush().unwrap();
+    writer.write_all(b"cdefg").unwrap();
+    drop(writer);
+    let text = String::from_utf8(encoded).unwrap();
+    assert_eq!(
+        text,
+        "{\"seq\":0,\"data\":\"YWI=\"}\n\
+         {\"seq\":1,\"data\":\"Y2RlZg==\"}\n\
+         {\"seq\":2,\"data\":\"Zw==\"}\n"
+    );
+}
+
+#[test]
+fn jsonl_base64_corruption() {
+    let mut encoded = Vec::new();
+    let mut writer = JsonlBase64Writer::new(&mut encoded, 2);
+    writer.write_all(b"\0\xffabcdef").unwrap();
+    drop(writer);
+    let text = String::from_utf8(encoded).unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+
+    let decode = |lines: &[&str]| {
+        let text = lines.join("\n");
+        let mut decoded = Vec::new();
+        JsonlBase64Reader::new(text.as_bytes())
+            .read_to_end(&mut decoded)
+            .map(|_| decoded)
+    };
+    assert_eq!(decode(&lines).unwrap(), b"\0\xffabcdef");
+
+    // A dropped line.
+    let err = decode(&[lines[0], lines[2], lines[3]]).unwrap_err();
+    assert!(matches!(
+        CodecError::of(&err),
+        Some(CodecError::RecordSequence {
+            expected: 1,
+            found: 2
+        })
+    ));
+
+    // Reordered lines.
+    let err = decode(&[lines[1], lines[0]]).unwrap_err();
+    assert!(matches!(
+        CodecError::of(&err),
+        Some(CodecError::RecordSequence {
+            expected: 0,
+            found: 1
+        })
+    ));
+
+    // Edited lines.
+    let edited = lines[1].replace("\"data\":\"", "\"data\":\"!");
+    let err = decode(&[lines[0], edited.as_str()]).unwrap_err();
+    assert!(matches!(
+        CodecError::of(&err),
+        Some(CodecError::InvalidRecord(_))
+    ));
+    let err = decode(&[lines[0], &lines[1][1..]]).unwrap_err();
+    assert!(matches!(
+        CodecError::of(&err),
+        Some(CodecError::InvalidJson(_))
+    ));
+}
+
+#[test]
+fn jsonl_base64_streams() {
+    use crate::{InputByteStream, OutputByteStream};
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("records.jsonl");
+    let output =
+        OutputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    let mut output = output.into_jsonl_base64(3);
+    output.write_all(b"\0binary\xc0\0").unwrap();
+    output.close().unwrap();
+
+    let input =
+        InputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    let mut input = input.from_jsonl_base64();
+    let mut decoded = Vec::new();
+    input.read_to_end(&mut decoded).unwrap();
+    assert_eq!(decoded, b"\0binary\xc0\0");
+}
+
+#[test]
+fn codecs_memory_budget() {
+    use crate::memory_budget::{test_budget, with_test_budget};
+    use crate::OpenError;
+
+    let feature = |err: io::Error| match OpenError::of(&err) {
+        Some(OpenError::MemoryBudgetExceeded { feature, .. }) => *feature,
+        _ => panic!("unexpected error: {}", err),
+    };
+
+    let ((), _) = with_test_budget(test_budget(16), || {
+        // An oversized line is skipped, like a line of invalid JSON.
+        let incoming = b"\"a long string value\"\n42\n".to_vec();
+        let mut receiver = JsonLines::new(Trickle::new(incoming, 3));
+        assert_eq!(
+            feature(receiver.recv::<String>().unwrap_err()),
+            "JSON Lines record"
+        );
+        assert_eq!(receiver.recv::<u32>().unwrap(), Some(42));
+
+        let mut incoming = Vec::new();
+        incoming.extend_from_slice(&20_u32.to_be_bytes());
+        incoming.extend_from_slice(&[0; 20]);
+        incoming.extend_from_slice(&1_u32.to_be_bytes());
+        incoming.push(7);
+        let mut receiver = Framed::new(Trickle::new(incoming, 4), FrameFormat::default());
+        assert_eq!(feature(receiver.recv().unwrap_err()), "frame");
+        assert_eq!(receiver.recv().unwrap(), Some(vec![7]));
+
+        let incoming = b"{\"seq\":0,\"data\":\"aGVsbG8gd29ybGQ=\"}\n";
+        let mut reader = JsonlBase64Reader::new(BufReader::new(&incoming[..]));
+        assert_eq!(
+            feature(reader.read(&mut [0; 8]).unwrap_err()),
+            "base64 JSON Lines record"
+        );
+    });
+}
@@ -0,0 +1,53 @@
+use terminal_io::TerminalColorSupport;
+
+/// Whether text streams pass ANSI color escape sequences through to their
+/// output.
+///
+/// Text streams normally strip escape sequences from their output, so that
+/// they can't corrupt terminals or files. With color output enabled, color
+/// sequences are passed through.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ColorChoice {
+    /// Pass color sequences through when the output is a terminal which
+    /// supports color.
+    Auto,
+    /// Always pass color sequences through.
+    Always,
+    /// Never pass color sequences through.
+    Never,
+}
+
+impl Default for ColorChoice {
+    #[inline]
+    fn default() -> Self {
+        Self::Auto
+    }
+}
+
+impl ColorChoice {
+    /// Decide whether to pass color sequences through, for an output with
+    /// the given terminal state.
+    pub(crate) fn use_ansi_color(
+        self,
+        is_output_terminal: bool,
+        color_support: TerminalColorSupport,
+    ) -> bool {
+        match self {
+            Self::Auto => is_output_terminal && color_support != TerminalColorSupport::Monochrome,
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+#[test]
+fn color_choices() {
+    use TerminalColorSupport::{Classic8, Monochrome, TrueColor};
+
+    assert!(ColorChoice::Auto.use_ansi_color(true, Classic8));
+    assert!(ColorChoice::Auto.use_ansi_color(true, TrueColor));
+    assert!(!ColorChoice::Auto.use_ansi_color(true, Monochrome));
+    assert!(!ColorChoice::Auto.use_ansi_color(false, TrueColor));
+    assert!(ColorChoice::Always.use_ansi_color(false, Monochrome));
+    assert!(!ColorChoice::Never.use_ansi_color(true, TrueColor));
+}
@@ -0,0 +1,54 @@
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// The number of compressed bytes a decompressing input stream has consumed
+/// from its underlying file, shared between the decoder, which may be on
+/// another thread, and the stream itself.
+#[derive(Clone, Debug)]
+pub(crate) struct CompressedProgress {
+    consumed: Arc<AtomicU64>,
+    size: u64,
+}
+
+impl CompressedProgress {
+    /// Start tracking a compressed file which is `size` bytes long.
+    pub(crate) fn new(size: u64) -> Self {
+        Self {
+            consumed: Arc::new(AtomicU64::new(0)),
+            size,
+        }
+    }
+
+    /// Wrap `inner`, counting the bytes read from it.
+    pub(crate) fn reader<R: Read>(&self, inner: R) -> CountingReader<R> {
+        CountingReader {
+            inner,
+            consumed: Arc::clone(&self.consumed),
+        }
+    }
+
+    /// Return the number of compressed bytes consumed so far.
+    pub(crate) fn consumed(&self) -> u64 {
+        self.consumed.load(Ordering::Relaxed)
+    }
+
+    /// Return the size of the compressed file.
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// A reader which counts the bytes read from its inner reader.
+pub(crate) struct CountingReader<R> {
+    inner: R,
+    consumed: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.consumed.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
@@ -0,0 +1,480 @@
+use crate::{CancellationToken, Sink, Source};
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Read, Write};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+/// The size of the buffer used by [`copy`]. This is larger than the buffer
+/// used by `std::io::copy`, as streams are often pipes or network
+/// connections, where fewer, larger transfers are faster.
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Copy the entire contents of `reader` into `writer`, returning the number
+/// of bytes copied.
+///
+/// This is similar to [`std::io::copy`], but uses a larger buffer. Unlike
+/// `std::io::copy`, when the copy fails, the returned error says whether it
+/// was the read or the write that failed, with [`CopyError`], which can be
+/// obtained from the `io::Error` with [`io::Error::get_ref`] and
+/// `downcast_ref`.
+pub fn copy<R: Read + ?Sized, W: Write + ?Sized>(
+    reader: &mut R,
+    writer: &mut W,
+) -> io::Result<u64> {
+    copy_with(reader, writer, CopyOptions::default()).map(CopyOutcome::copied)
+}
+
+/// Like [`copy`], but stop early if `token` is cancelled.
+///
+/// The token is checked between buffers, so a copy which is blocked in a
+/// read or write stops once that read or write returns. On cancellation,
+/// everything read so far has been written to `writer`, and the error is an
+/// [`io::ErrorKind::Interrupted`] error wrapping
+/// [`OpenError::Cancelled`], which can be obtained with
+/// [`io::Error::get_ref`] and `downcast_ref`.
+///
+/// [`OpenError::Cancelled`]: crate::OpenError::Cancelled
+pub fn copy_cancellable<R: Read + ?Sized, W: Write + ?Sized>(
+    reader: &mut R,
+    writer: &mut W,
+    token: &CancellationToken,
+) -> io::Result<u64> {
+    let options = CopyOptions {
+        cancel_token: Some(token.clone()),
+        ..CopyOptions::default()
+    };
+    match copy_with(reader, writer, options)? {
+        CopyOutcome::Completed(copied) => Ok(copied),
+        CopyOutcome::Cancelled(_) => Err(CancellationToken::error()),
+    }
+}
+
+/// Progress reported to the [`CopyOptions::progress`] callback.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct CopyProgress {
+    /// The number of bytes copied so far.
+    pub copied: u64,
+    /// The time since the copy started.
+    pub elapsed: Duration,
+    /// The [`CopyOptions::size_hint`], for computing percentages.
+    pub size_hint: Option<u64>,
+}
+
+/// The type of the [`CopyOptions::progress`] callback.
+pub type CopyProgressCallback<'a> = Box<dyn FnMut(CopyProgress) -> ControlFlow<()> + 'a>;
+
+/// Settings for [`copy_with`].
+#[non_exhaustive]
+pub struct CopyOptions<'a> {
+    /// The size of the buffer to copy through.
+    pub buffer_size: usize,
+
+    /// A function to call with the progress of the copy. Returning
+    /// `ControlFlow::Break` stops the copy, after flushing what's been
+    /// written, and `copy_with` returns [`CopyOutcome::Cancelled`].
+    pub progress: Option<CopyProgressCallback<'a>>,
+
+    /// The callback is called once at least this many bytes have been
+    /// copied since it was last called, or [`Self::progress_interval`] has
+    /// passed, whichever is first, and once more at the end.
+    pub progress_bytes: u64,
+
+    /// See [`Self::progress_bytes`].
+    pub progress_interval: Duration,
+
+    /// The size of the input, if known, such as from
+    /// [`InputByteStream::initial_size`], which is passed on to the
+    /// callback.
+    ///
+    /// [`InputByteStream::initial_size`]: crate::InputByteStream::initial_size
+    pub size_hint: Option<u64>,
+
+    /// A token which stops the copy when it's cancelled, or when its
+    /// deadline passes, as with [`CancellationToken::with_deadline`]. It's
+    /// checked between buffers.
+    pub cancel_token: Option<CancellationToken>,
+}
+
+impl Default for CopyOptions<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            buffer_size: COPY_BUFFER_SIZE,
+            progress: None,
+            progress_bytes: 1024 * 1024,
+            progress_interval: Duration::from_millis(100),
+            size_hint: None,
+            cancel_token: None,
+        }
+    }
+}
+
+impl Debug for CopyOptions<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut b = f.debug_struct("CopyOptions");
+        b.field("buffer_size", &self.buffer_size);
+        b.field("progress", &self.progress.as_ref().map(|_| ".."));
+        b.field("progress_bytes", &self.progress_bytes);
+        b.field("progress_interval", &self.progress_interval);
+        b.field("size_hint", &self.size_hint);
+        b.field("cancel_token", &self.cancel_token);
+        b.finish()
+    }
+}
+
+/// How a [`copy_with`] ended, with the number of bytes copied.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CopyOutcome {
+    /// The whole input was copied.
+    Completed(u64),
+    /// The copy was stopped by the progress callback or the cancellation
+    /// token. Everything read was written and flushed.
+    Cancelled(u64),
+}
+
+impl CopyOutcome {
+    /// Return the number of bytes copied.
+    #[inline]
+    pub fn copied(self) -> u64 {
+        match self {
+            Self::Completed(copied) | Self::Cancelled(copied) => copied,
+        }
+    }
+}
+
+/// Like [`copy`], with progress reporting and cancellation configured by
+/// `options`.
+///
+/// ```rust,no_run
+/// use nameless::{copy_with, CopyOptions, InputByteStream, OutputByteStream};
+/// use std::ops::ControlFlow;
+///
+/// # fn example(mut input: InputByteStream, mut output: OutputByteStream) -> std::io::Result<()> {
+/// let mut options = CopyOptions::default();
+/// options.size_hint = input.initial_size();
+/// options.progress = Some(Box::new(|progress| {
+///     if let Some(size) = progress.size_hint {
+///         eprint!("\r{}%", progress.copied * 100 / size.max(1));
+///     }
+///     ControlFlow::Continue(())
+/// }));
+/// copy_with(&mut input, &mut output, options)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn copy_with<R: Read + ?Sized, W: Write + ?Sized>(
+    reader: &mut R,
+    writer: &mut W,
+    mut options: CopyOptions<'_>,
+) -> io::Result<CopyOutcome> {
+    let start = Instant::now();
+    let mut buf = vec![0; options.buffer_size.max(1)];
+    let mut total = 0;
+    let mut reported = (0, start);
+    let outcome = loop {
+        if let Some(token) = &options.cancel_token {
+            if token.is_cancelled() {
+                break CopyOutcome::Cancelled(total);
+            }
+        }
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break CopyOutcome::Completed(total),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(CopyError::wrap(CopyError::Read, e)),
+        };
+        writer
+            .write_all(&buf[..n])
+            .map_err(|e| CopyError::wrap(CopyError::Write, e))?;
+        total += n as u64;
+
+        if let Some(progress) = &mut options.progress {
+            let now = Instant::now();
+            if total - reported.0 >= options.progress_bytes
+                || now.duration_since(reported.1) >= options.progress_interval
+            {
+                reported = (total, now);
+                let flow = progress(CopyProgress {
+                    copied: total,
+                    elapsed: now.duration_since(start),
+                    size_hint: options.size_hint,
+                });
+                if flow.is_break() {
+                    break CopyOutcome::Cancelled(total);
+                }
+            }
+        }
+    };
+    writer
+        .flush()
+        .map_err(|e| CopyError::wrap(CopyError::Write, e))?;
+
+    // Report the final count, unless the callback has already seen it.
+    if let (CopyOutcome::Completed(_), Some(progress)) = (outcome, &mut options.progress) {
+        if reported.0 != total || total == 0 {
+            let _ = progress(CopyProgress {
+                copied: total,
+                elapsed: start.elapsed(),
+                size_hint: options.size_hint,
+            });
+        }
+    }
+    Ok(outcome)
+}
+
+/// Like [`copy_with`], for a [`Source`] and a [`Sink`], using the source's
+/// metadata: if `options` has no [`CopyOptions::size_hint`], the source's
+/// [`Source::size_hint`] is used.
+///
+/// The sink is flushed, but not closed, so that more can be written to it;
+/// call [`Sink::close_boxed`] when done.
+pub fn copy_stream<S: Source + ?Sized, K: Sink + ?Sized>(
+    source: &mut S,
+    sink: &mut K,
+    mut options: CopyOptions<'_>,
+) -> io::Result<CopyOutcome> {
+    if options.size_hint.is_none() {
+        options.size_hint = source.size_hint();
+    }
+    copy_with(source, sink, options)
+}
+
+/// Which side of a [`copy`] failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CopyError {
+    /// Reading from the input failed.
+    Read,
+    /// Writing to the output failed.
+    Write,
+}
+
+impl CopyError {
+    fn wrap(self, e: io::Error) -> io::Error {
+        io::Error::new(
+            e.kind(),
+            CopyErrorSource {
+                side: self,
+                error: e,
+            },
+        )
+    }
+
+    /// If `e` is an error returned from [`copy`], return which side of the
+    /// copy failed.
+    pub fn of(e: &io::Error) -> Option<Self> {
+        e.get_ref()
+            .and_then(|inner| inner.downcast_ref::<CopyErrorSource>())
+            .map(|source| source.side)
+    }
+}
+
+#[derive(Debug)]
+struct CopyErrorSource {
+    side: CopyError,
+    error: io::Error,
+}
+
+impl std::error::Error for CopyErrorSource {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl std::fmt::Display for CopyErrorSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.side {
+            CopyError::Read => write!(f, "error reading input: {}", self.error),
+            CopyError::Write => write!(f, "error writing output: {}", self.error),
+        }
+    }
+}
+
+#[test]
+fn copy_bytes() {
+    let input = vec![7_u8; COPY_BUFFER_SIZE * 2 + 3];
+    let mut output = Vec::new();
+    assert_eq!(
+        copy(&mut input.as_slice(), &mut output).unwrap(),
+        input.len() as u64
+    );
+    assert_eq!(output, input);
+}
+
+#[test]
+fn copy_errors() {
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("read failed"))
+        }
+    }
+
+    impl Write for Failing {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let e = copy(&mut Failing, &mut Vec::new()).unwrap_err();
+    assert_eq!(CopyError::of(&e), Some(CopyError::Read));
+
+    let e = copy(&mut b"hello".as_ref(), &mut Failing).unwrap_err();
+    assert_eq!(CopyError::of(&e), Some(CopyError::Write));
+    assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+
+    assert_eq!(CopyError::of(&io::ErrorKind::Other.into()), None);
+}
+
+#[test]
+fn copy_cancelled() {
+    use crate::OpenError;
+    use std::time::{Duration, Instant};
+
+    /// A source which produces a small chunk every few milliseconds, and
+    /// never ends.
+    struct Slow;
+
+    impl Read for Slow {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            std::thread::sleep(Duration::from_millis(5));
+            let n = buf.len().min(4);
+            buf[..n].copy_from_slice(&b"abcd"[..n]);
+            Ok(n)
+        }
+    }
+
+    let token = CancellationToken::new();
+    let canceller = {
+        let token = token.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            token.cancel();
+        })
+    };
+
+    let start = Instant::now();
+    let mut output = Vec::new();
+    let e = copy_cancellable(&mut Slow, &mut output, &token).unwrap_err();
+    canceller.join().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(e.kind(), io::ErrorKind::Interrupted);
+    assert!(matches!(
+        e.get_ref().unwrap().downcast_ref::<OpenError>(),
+        Some(OpenError::Cancelled)
+    ));
+    assert_eq!(CopyError::of(&e), None);
+
+    // Everything read before the cancellation was written.
+    assert!(!output.is_empty());
+    assert_eq!(output.len() % 4, 0);
+    assert!(output.chunks(4).all(|chunk| chunk == b"abcd"));
+}
+
+#[test]
+fn copy_with_progress() {
+    let input = (0..1024 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+    let size = input.len() as u64;
+
+    // Stop halfway through, from the callback.
+    let mut reports = Vec::new();
+    let mut output = Vec::new();
+    let options = CopyOptions {
+        buffer_size: 16 * 1024,
+        progress_bytes: 64 * 1024,
+        progress_interval: Duration::from_secs(3600),
+        size_hint: Some(size),
+        progress: Some(Box::new(|progress: CopyProgress| {
+            assert_eq!(progress.size_hint, Some(size));
+            reports.push(progress.copied);
+            if progress.copied * 2 >= size {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })),
+        ..CopyOptions::default()
+    };
+    let outcome = copy_with(&mut input.as_slice(), &mut output, options).unwrap();
+    assert_eq!(outcome, CopyOutcome::Cancelled(size / 2));
+    assert_eq!(reports, [1, 2, 3, 4, 5, 6, 7, 8].map(|i| i * 64 * 1024));
+    assert_eq!(output, &input[..output.len()]);
+    assert_eq!(output.len() as u64, outcome.copied());
+
+    // Run to completion, with a final report at the end.
+    let mut reports = Vec::new();
+    let mut output = Vec::new();
+    let options = CopyOptions {
+        progress_bytes: 300 * 1024,
+        progress_interval: Duration::from_secs(3600),
+        progress: Some(Box::new(|progress: CopyProgress| {
+            reports.push(progress.copied);
+            ControlFlow::Continue(())
+        })),
+        ..CopyOptions::default()
+    };
+    let outcome = copy_with(&mut input.as_slice(), &mut output, options).unwrap();
+    assert_eq!(outcome, CopyOutcome::Completed(size));
+    assert_eq!(output, input);
+    assert_eq!(reports, [320 * 1024, 640 * 1024, 960 * 1024, size]);
+}
+
+#[test]
+fn copy_with_cancel_token() {
+    let token = CancellationToken::new();
+    token.cancel();
+    let options = CopyOptions {
+        cancel_token: Some(token),
+        ..CopyOptions::default()
+    };
+    let mut output = Vec::new();
+    let outcome = copy_with(&mut b"hello".as_ref(), &mut output, options).unwrap();
+    assert_eq!(outcome, CopyOutcome::Cancelled(0));
+    assert!(output.is_empty());
+}
+
+#[test]
+fn copy_stream_size_hint() {
+    use crate::{InputByteStream, PlainSink, PlainSource};
+    use clap::TryFromOsArg;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.bin");
+    std::fs::write(&path, [1_u8; 1000]).unwrap();
+
+    // The size hint comes from the source's metadata, when it has any.
+    let mut hints = Vec::new();
+    let mut input =
+        InputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    let mut output = PlainSink::new(Vec::new());
+    let options = CopyOptions {
+        progress_interval: Duration::from_secs(3600),
+        progress: Some(Box::new(|progress: CopyProgress| {
+            hints.push(progress.size_hint);
+            ControlFlow::Continue(())
+        })),
+        ..CopyOptions::default()
+    };
+    let outcome = copy_stream(&mut input, &mut output, options).unwrap();
+    assert_eq!(outcome, CopyOutcome::Completed(1000));
+    assert_eq!(output.get_ref().len(), 1000);
+    assert_eq!(hints, [Some(1000)]);
+
+    let mut hints = Vec::new();
+    let mut input = PlainSource::new(&[1_u8; 10][..]);
+    let options = CopyOptions {
+        progress_interval: Duration::from_secs(3600),
+        progress: Some(Box::new(|progress: CopyProgress| {
+            hints.push(progress.size_hint);
+            ControlFlow::Continue(())
+        })),
+        ..CopyOptions::default()
+    };
+    copy_stream(&mut input, &mut PlainSink::new(Vec::new()), options).unwrap();
+    assert_eq!(hints, [None]);
+}
@@ -0,0 +1,114 @@
+//! Best-effort explanations for failures to open paths.
+
+use anyhow::anyhow;
+use std::io;
+use std::path::Path;
+#[cfg(unix)]
+use {
+    rustix::fs::{accessat, Access, AtFlags, CWD},
+    rustix::io::Errno,
+    std::fs,
+    std::os::unix::fs::MetadataExt,
+};
+
+/// Describe a failure to open `path`, adding hints about why it failed when
+/// they can be determined. Gathering the hints is best-effort; any hint
+/// which can't be determined is left out.
+pub(crate) fn open_error(path: &Path, err: io::Error) -> anyhow::Error {
+    #[allow(unused_mut)]
+    let mut message = format!("{}: {}", path.display(), err);
+
+    #[cfg(unix)]
+    for hint in hints(path, &err) {
+        message += "; ";
+        message += &hint;
+    }
+
+    anyhow!(message)
+}
+
+#[cfg(unix)]
+fn hints(path: &Path, err: &io::Error) -> Vec<String> {
+    let mut hints = Vec::new();
+
+    if err.kind() == io::ErrorKind::PermissionDenied {
+        hints.push(format!(
+            "running as uid {}, gid {}",
+            rustix::process::geteuid().as_raw(),
+            rustix::process::getegid().as_raw()
+        ));
+
+        if let Ok(metadata) = fs::metadata(path) {
+            hints.push(format!(
+                "{} has mode {:04o}, owned by uid {}, gid {}",
+                path.display(),
+                metadata.mode() & 0o7777,
+                metadata.uid(),
+                metadata.gid()
+            ));
+        }
+
+        if let Some(dir) = untraversable_ancestor(path) {
+            hints.push(format!("cannot search directory {}", dir.display()));
+        }
+    }
+
+    match err.raw_os_error() {
+        Some(errno) if errno == Errno::ROFS.raw_os_error() => {
+            hints.push("the filesystem is read-only".to_owned())
+        }
+        Some(errno) if errno == Errno::NOSPC.raw_os_error() => {
+            hints.push("the device is out of space".to_owned())
+        }
+        _ => {}
+    }
+
+    hints
+}
+
+/// Find the outermost directory containing `path` which the process isn't
+/// permitted to search.
+#[cfg(unix)]
+fn untraversable_ancestor(path: &Path) -> Option<&Path> {
+    let ancestors: Vec<&Path> = path
+        .ancestors()
+        .skip(1)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect();
+    ancestors
+        .into_iter()
+        .rev()
+        .find(|dir| accessat(CWD, *dir, Access::EXEC_OK, AtFlags::EACCESS).is_err())
+}
+
+#[cfg(unix)]
+#[test]
+fn permission_denied_names_blocking_directory() {
+    use std::fs::{File, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+
+    // Permissions don't apply to root.
+    if rustix::process::geteuid().is_root() {
+        return;
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let blocked = dir.path().join("blocked");
+    fs::create_dir_all(blocked.join("inner")).unwrap();
+    fs::write(blocked.join("inner").join("file.txt"), "hello").unwrap();
+    fs::set_permissions(&blocked, Permissions::from_mode(0o000)).unwrap();
+
+    let path = blocked.join("inner").join("file.txt");
+    let err = File::open(&path).unwrap_err();
+    let message = open_error(&path, err).to_string();
+
+    // Restore the permissions so that the tempdir can be cleaned up.
+    fs::set_permissions(&blocked, Permissions::from_mode(0o755)).unwrap();
+
+    assert!(
+        message.contains(&format!("cannot search directory {}", blocked.display())),
+        "{}",
+        message
+    );
+    assert!(message.contains("running as uid"), "{}", message);
+}
@@ -0,0 +1,203 @@
+use layered_io::WriteLayered;
+use std::io::{self, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, PoisonError, RwLock};
+
+type DropErrorCallback = Arc<dyn Fn(&io::Error) + Send + Sync>;
+
+static CALLBACK: RwLock<Option<DropErrorCallback>> = RwLock::new(None);
+
+/// Set the function to call when a stream encounters an error while it's
+/// being dropped, such as failing to flush buffered output or to wait for
+/// a helper process.
+///
+/// `drop` can't return errors, so by default they're printed to stderr,
+/// ignoring any failure to print. To handle errors instead, close streams
+/// explicitly before dropping them, with [`WriteLayered::close`], which
+/// returns them.
+///
+/// The callback is also passed errors which a stream recovers from without
+/// failing the write that encountered them, such as failing to rotate a
+/// file output opened with a `?rotate` option.
+///
+/// The callback may be called from any thread, and panics in it are
+/// caught and ignored, so that dropping a stream never panics.
+///
+/// [`WriteLayered::close`]: https://docs.rs/layered-io/latest/layered_io/trait.WriteLayered.html#tymethod.close
+pub fn on_drop_error<F: Fn(&io::Error) + Send + Sync + 'static>(callback: F) {
+    *CALLBACK.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(callback));
+}
+
+/// Report an error encountered in a `drop`, without panicking.
+pub(crate) fn report_drop_error(error: io::Error) {
+    // Don't hold the lock while calling the callback, so that it can call
+    // `on_drop_error` itself.
+    let callback = CALLBACK
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let _ = catch_unwind(AssertUnwindSafe(|| match callback {
+        Some(callback) => callback(&error),
+        None => {
+            // Unlike `eprintln`, this doesn't panic if stderr is closed.
+            let _ = writeln!(io::stderr(), "{}", error);
+        }
+    }));
+}
+
+/// Close a stream's writer, or its duplexer, for a `drop`, abandoning it if
+/// closing fails, and report any error. One which has already ended,
+/// because the stream was closed or a write to it failed, is left as it is.
+pub(crate) fn close_on_drop(writer: &mut impl WriteLayered) {
+    if let Err(error) = writer.close() {
+        writer.abandon();
+        if !has_ended(&error) {
+            report_drop_error(error);
+        }
+    }
+}
+
+/// Test whether `error` is layered-io's error for using a stream which has
+/// already ended.
+fn has_ended(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::BrokenPipe && error.to_string() == "stream has already ended"
+}
+
+/// Serializes tests which install callbacks.
+#[cfg(test)]
+static TEST_CALLBACK_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// A callback installed by a test, which records the kinds of drop errors
+/// on the test's thread, and is removed when the recorder is dropped.
+#[cfg(test)]
+pub(crate) struct DropErrorRecorder {
+    _guard: std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+thread_local! {
+    static RECORDED: std::cell::RefCell<Vec<io::ErrorKind>> =
+        const { std::cell::RefCell::new(Vec::new()) };
+}
+
+#[cfg(test)]
+impl DropErrorRecorder {
+    pub(crate) fn install() -> Self {
+        let guard = TEST_CALLBACK_LOCK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        RECORDED.with(|recorded| recorded.borrow_mut().clear());
+        on_drop_error(|error| RECORDED.with(|recorded| recorded.borrow_mut().push(error.kind())));
+        Self { _guard: guard }
+    }
+
+    pub(crate) fn take(&self) -> Vec<io::ErrorKind> {
+        RECORDED.with(|recorded| recorded.borrow_mut().drain(..).collect())
+    }
+}
+
+#[cfg(test)]
+impl Drop for DropErrorRecorder {
+    fn drop(&mut self) {
+        *CALLBACK.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+#[test]
+fn drop_error_callback() {
+    let recorder = DropErrorRecorder::install();
+    let result = catch_unwind(|| {
+        report_drop_error(io::Error::new(io::ErrorKind::BrokenPipe, "closed"));
+    });
+    assert!(result.is_ok());
+    assert_eq!(recorder.take(), vec![io::ErrorKind::BrokenPipe]);
+}
+
+#[test]
+fn drop_error_callback_panics_are_contained() {
+    let _guard = TEST_CALLBACK_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    on_drop_error(|_error| panic!("callback panic"));
+    let result = catch_unwind(|| {
+        report_drop_error(io::Error::other("oops"));
+    });
+    *CALLBACK.write().unwrap_or_else(PoisonError::into_inner) = None;
+    assert!(result.is_ok());
+}
+
+#[test]
+fn dropping_unclosed_outputs() {
+    use crate::{OutputByteStream, OutputTextStream};
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use std::fs;
+
+    let recorder = DropErrorRecorder::install();
+    let dir = tempfile::tempdir().unwrap();
+    let bytes = dir.path().join("bytes.txt");
+    let text = dir.path().join("text.txt");
+    let closed = dir.path().join("closed.txt");
+    let partial = dir.path().join("partial.txt");
+    let result = catch_unwind(|| {
+        let open_bytes = |path: &std::path::Path| {
+            OutputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority())
+                .unwrap()
+        };
+        let open_text = |path: &std::path::Path| {
+            OutputTextStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority())
+                .unwrap()
+        };
+
+        let mut output = open_bytes(&bytes);
+        output.write_all(b"bytes\n").unwrap();
+        drop(output);
+
+        let mut output = open_text(&text);
+        output.write_all(b"text\n").unwrap();
+        drop(output);
+
+        // Streams which were closed aren't closed again.
+        let mut output = open_bytes(&closed);
+        output.close().unwrap();
+        drop(output);
+
+        // A text stream which doesn't end in a newline can't be closed, so
+        // it's abandoned.
+        let mut output = open_text(&partial);
+        output.write_all(b"partial").unwrap();
+        drop(output);
+    });
+    assert!(result.is_ok());
+    assert_eq!(fs::read_to_string(&bytes).unwrap(), "bytes\n");
+    assert_eq!(fs::read_to_string(&text).unwrap(), "text\n");
+    assert_eq!(recorder.take(), vec![io::ErrorKind::InvalidData]);
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn dropping_unclosed_interactive_streams() {
+    use crate::{InteractiveByteStream, InteractiveTextStream};
+    use clap::TryFromOsArg;
+
+    let recorder = DropErrorRecorder::install();
+    let result = catch_unwind(|| {
+        let mut stream = InteractiveByteStream::try_from_os_str_arg(
+            "$(cat)".as_ref(),
+            clap::ambient_authority(),
+        )
+        .unwrap();
+        stream.write_all(b"bytes\n").unwrap();
+        drop(stream);
+
+        let mut stream = InteractiveTextStream::try_from_os_str_arg(
+            "$(cat)".as_ref(),
+            clap::ambient_authority(),
+        )
+        .unwrap();
+        stream.write_all(b"text\n").unwrap();
+        drop(stream);
+    });
+    assert!(result.is_ok());
+    assert_eq!(recorder.take(), vec![]);
+}
@@ -0,0 +1,270 @@
+//! Opening lists of inputs which may name the same input more than once,
+//! according to [`OpenPolicy::duplicate_arguments`].
+
+use crate::diagnose::open_error;
+use crate::open_input::{open_input, open_shared_file, Input};
+use crate::stream_options::{split_options, FILE_INPUT};
+use crate::syntax::{classify_with_policy, split_path_fragment};
+use crate::telemetry::{traced_open, Telemetry};
+use crate::{DuplicateArguments, OpenError, OpenPolicy, SyntaxKind};
+use clap::AmbientAuthority;
+use std::collections::hash_map::{Entry, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// What identifies a file, for finding arguments which name the same file
+/// with different spellings.
+#[cfg(unix)]
+type FileId = (u64, u64);
+#[cfg(not(unix))]
+type FileId = PathBuf;
+
+/// What identifies the input an argument names.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Identity {
+    /// A plain path, without options or a fragment, to an existing file.
+    File(FileId),
+    /// Anything else, which is identified by its spelling.
+    Name(OsString),
+}
+
+/// Return the identity of the input `name` names, and for plain paths, the
+/// path.
+fn identity(name: &OsStr, policy: &OpenPolicy) -> (Identity, Option<PathBuf>) {
+    if let Ok(SyntaxKind::Path) = classify_with_policy(name, policy) {
+        let (path, fragment) = split_path_fragment(name);
+        let (path, query) = split_options(path, &FILE_INPUT);
+        if fragment.is_none() && query.is_none() {
+            if let Some(id) = file_id(Path::new(path)) {
+                return (Identity::File(id), Some(PathBuf::from(path)));
+            }
+        }
+    }
+    (Identity::Name(name.to_owned()), None)
+}
+
+#[cfg(unix)]
+fn file_id(path: &Path) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path).ok()?;
+    metadata.is_file().then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(path: &Path) -> Option<FileId> {
+    if !fs::metadata(path).ok()?.is_file() {
+        return None;
+    }
+    fs::canonicalize(path).ok()
+}
+
+/// Open each of `names`, returning each input with its occurrence number,
+/// which counts repetitions of the same string from 1.
+pub(crate) fn open_input_list(
+    names: Vec<OsString>,
+    policy: &OpenPolicy,
+    ambient_authority: AmbientAuthority,
+) -> anyhow::Result<Vec<(Input, Telemetry, usize)>> {
+    let identities = names
+        .iter()
+        .map(|name| identity(name, policy))
+        .collect::<Vec<_>>();
+
+    let mut first_index = HashMap::new();
+    let mut counts = HashMap::<_, usize>::new();
+    for (index, (identity, _path)) in identities.iter().enumerate() {
+        if let Some(&first) = first_index.get(identity) {
+            if policy.duplicate_arguments == DuplicateArguments::Error {
+                return Err(OpenError::DuplicateArgument {
+                    name: names[index].to_string_lossy().into_owned(),
+                    first,
+                    second: index,
+                }
+                .into());
+            }
+        } else {
+            first_index.insert(identity, index);
+        }
+        *counts.entry(identity).or_default() += 1;
+    }
+
+    let mut occurrences = HashMap::<&OsStr, usize>::new();
+    let mut shared = HashMap::new();
+    let mut inputs = Vec::with_capacity(names.len());
+    for (name, (identity, path)) in names.iter().zip(&identities) {
+        let occurrence = occurrences.entry(name.as_os_str()).or_default();
+        *occurrence += 1;
+
+        // Gzip files are decoded as they're read, so they aren't shared.
+        let dedupe = policy.duplicate_arguments == DuplicateArguments::Dedupe
+            && counts[identity] > 1
+            && path.as_deref().is_some_and(|path| !is_gz(path));
+        let (input, telemetry) = match (dedupe, path) {
+            (true, Some(path)) => {
+                let file = match shared.entry(identity) {
+                    Entry::Occupied(entry) => Arc::clone(entry.get()),
+                    Entry::Vacant(entry) => {
+                        let file = File::open(path).map_err(|err| open_error(path, err))?;
+                        Arc::clone(entry.insert(Arc::new(file)))
+                    }
+                };
+                traced_open(name, || open_shared_file(path, file))?
+            }
+            _ => open_input(name, policy, ambient_authority)?,
+        };
+        inputs.push((input, telemetry, *occurrence));
+    }
+    Ok(inputs)
+}
+
+fn is_gz(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("gz"))
+}
+
+#[cfg(test)]
+fn open_names(
+    names: &[&OsStr],
+    duplicate_arguments: DuplicateArguments,
+) -> anyhow::Result<Vec<crate::InputByteStream>> {
+    let policy = OpenPolicy {
+        duplicate_arguments,
+        ..OpenPolicy::default()
+    };
+    crate::InputByteStream::open_list(
+        names.iter().map(|name| name.to_os_string()),
+        &policy,
+        clap::ambient_authority(),
+    )
+}
+
+#[cfg(test)]
+fn name_of(path: &std::path::Path) -> String {
+    crate::path_to_name::path_to_name("file", path).unwrap()
+}
+
+#[cfg(test)]
+fn read_all(stream: &mut crate::InputByteStream) -> String {
+    use std::io::Read;
+
+    let mut s = String::new();
+    stream.read_to_string(&mut s).unwrap();
+    s
+}
+
+#[test]
+fn duplicate_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.csv");
+    let other = dir.path().join("other.csv");
+    fs::write(&path, "a,b\n1,2\n").unwrap();
+    fs::write(&other, "c\n").unwrap();
+    let names = [path.as_os_str(), other.as_os_str(), path.as_os_str()];
+
+    for policy in [DuplicateArguments::Allow, DuplicateArguments::Dedupe] {
+        let mut streams = open_names(&names, policy).unwrap();
+        let pseudonyms = streams
+            .iter()
+            .map(|stream| stream.pseudonym().display_name().into_owned())
+            .collect::<Vec<_>>();
+        let path = name_of(&path);
+        assert_eq!(
+            pseudonyms,
+            [
+                path.clone(),
+                name_of(&other),
+                format!("{} (2nd occurrence)", path)
+            ]
+        );
+
+        // The streams over the same file are independent.
+        let mut first = [0; 2];
+        std::io::Read::read_exact(&mut streams[0], &mut first).unwrap();
+        assert_eq!(read_all(&mut streams[2]), "a,b\n1,2\n");
+        assert_eq!(read_all(&mut streams[0]), "b\n1,2\n");
+        assert_eq!(read_all(&mut streams[1]), "c\n");
+    }
+
+    let err = open_names(&names, DuplicateArguments::Error).unwrap_err();
+    match err.downcast_ref::<OpenError>() {
+        Some(OpenError::DuplicateArgument {
+            name,
+            first: 0,
+            second: 2,
+        }) => assert_eq!(name, &path.display().to_string()),
+        _ => panic!("unexpected error: {}", err),
+    }
+    assert!(err.to_string().starts_with("argument 3 ("));
+    assert!(err
+        .to_string()
+        .ends_with(") names the same input as argument 1"));
+}
+
+#[test]
+fn duplicate_spellings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("a.txt");
+    let dotted = dir.path().join(".").join("a.txt");
+    fs::write(&path, "contents\n").unwrap();
+    let names = [path.as_os_str(), dotted.as_os_str()];
+
+    // Different spellings are different names, so neither has an
+    // occurrence count.
+    for policy in [DuplicateArguments::Allow, DuplicateArguments::Dedupe] {
+        let mut streams = open_names(&names, policy).unwrap();
+        assert_eq!(streams[1].pseudonym().display_name(), name_of(&dotted));
+        assert_eq!(read_all(&mut streams[0]), "contents\n");
+        assert_eq!(read_all(&mut streams[1]), "contents\n");
+    }
+
+    let err = open_names(&names, DuplicateArguments::Error).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<OpenError>(),
+        Some(OpenError::DuplicateArgument {
+            first: 0,
+            second: 1,
+            ..
+        })
+    ));
+
+    // Options make a name more than a plain path, so it's compared by its
+    // spelling.
+    let with_options = format!("{}?gzip=single", path.display());
+    let names = [path.as_os_str(), OsStr::new(&with_options)];
+    assert_eq!(
+        open_names(&names, DuplicateArguments::Error).unwrap().len(),
+        2
+    );
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn duplicate_commands() {
+    use crate::StreamKind;
+
+    let names = [OsStr::new("$(echo hello)"), OsStr::new("$(echo hello)")];
+
+    // Commands are run once per argument, even with `Dedupe`.
+    for policy in [DuplicateArguments::Allow, DuplicateArguments::Dedupe] {
+        let mut streams = open_names(&names, policy).unwrap();
+        assert_eq!(streams[0].info().kind(), StreamKind::Child);
+        assert_eq!(
+            streams[1].pseudonym().display_name(),
+            "$(echo hello) (2nd occurrence)"
+        );
+        assert_eq!(read_all(&mut streams[0]), "hello\n");
+        assert_eq!(read_all(&mut streams[1]), "hello\n");
+    }
+
+    let err = open_names(&names, DuplicateArguments::Error).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<OpenError>(),
+        Some(OpenError::DuplicateArgument {
+            first: 0,
+            second: 1,
+            ..
+        })
+    ));
+}
@@ -0,0 +1,285 @@
+use layered_io::Status;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+
+/// How an input stream ended, as reported by `end_status` on the input
+/// stream types.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum EndStatus {
+    /// The stream ended cleanly.
+    CleanEnd,
+    /// The stream ended before all its data arrived, such as an HTTP body
+    /// shorter than its `Content-Length` or a gzip stream missing its
+    /// trailer.
+    Truncated {
+        /// The number of bytes the source said it would produce, if known.
+        expected: Option<u64>,
+        /// The number of bytes the source actually produced.
+        got: u64,
+    },
+    /// The producer of the stream failed, such as a child process exiting
+    /// with a non-success status.
+    ProducerFailed(String),
+}
+
+/// The end status of a stream, shared between the reader on the producing
+/// side, which may be on another thread, and the stream itself.
+#[derive(Clone, Default)]
+pub(crate) struct EndState(Arc<Mutex<Shared>>);
+
+#[derive(Default)]
+struct Shared {
+    status: Option<EndStatus>,
+    /// The error which ended the stream on the producing side, held for
+    /// the consuming side to return once it reaches the end.
+    error: Option<io::Error>,
+}
+
+impl EndState {
+    /// Record `status`, unless a status has already been recorded.
+    fn set(&self, status: EndStatus) {
+        let mut guard = self.0.lock().unwrap();
+        if guard.status.is_none() {
+            guard.status = Some(status);
+        }
+    }
+
+    /// Record the error which ended the stream.
+    fn fail(&self, status: EndStatus, error: io::Error) {
+        let mut guard = self.0.lock().unwrap();
+        if guard.status.is_none() {
+            guard.status = Some(status);
+            guard.error = Some(error);
+        }
+    }
+
+    /// Return the end status, for a stream whose end has been observed.
+    pub(crate) fn get(&self) -> EndStatus {
+        self.0
+            .lock()
+            .unwrap()
+            .status
+            .clone()
+            .unwrap_or(EndStatus::CleanEnd)
+    }
+
+    /// Take the error which ended the stream, if there was one.
+    fn take_error(&self) -> Option<io::Error> {
+        self.0.lock().unwrap().error.take()
+    }
+}
+
+/// A reader which records how its inner reader ended in an `EndState`.
+///
+/// These readers run on the other side of a `StreamReader::piped_thread`,
+/// which would lose errors, so a failure ends the stream here like a clean
+/// end, and the error is held in the `EndState` for the [`EndObserver`] on
+/// the consuming side to return when it reaches the end.
+pub(crate) struct TrackedReader<R> {
+    inner: R,
+    state: EndState,
+    expected: Option<u64>,
+    got: u64,
+}
+
+impl<R: Read> TrackedReader<R> {
+    /// Wrap `inner`, which is expected to produce `expected` bytes if known.
+    pub(crate) fn new(inner: R, state: EndState, expected: Option<u64>) -> Self {
+        Self {
+            inner,
+            state,
+            expected,
+            got: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for TrackedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.read(buf) {
+            Ok(0) if !buf.is_empty() => {
+                match self.expected {
+                    Some(expected) if self.got < expected => self.state.set(EndStatus::Truncated {
+                        expected: self.expected,
+                        got: self.got,
+                    }),
+                    _ => self.state.set(EndStatus::CleanEnd),
+                }
+                Ok(0)
+            }
+            Ok(n) => {
+                self.got += n as u64;
+                Ok(n)
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Err(e),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                let status = EndStatus::Truncated {
+                    expected: self.expected,
+                    got: self.got,
+                };
+                self.state.fail(status, e);
+                Ok(0)
+            }
+            Err(e) => {
+                self.state.fail(EndStatus::ProducerFailed(e.to_string()), e);
+                Ok(0)
+            }
+        }
+    }
+}
+
+/// Tracks whether the consuming side of a stream has observed its end, by
+/// observing the results of its reads.
+pub(crate) struct EndObserver {
+    state: EndState,
+    ended: bool,
+}
+
+impl EndObserver {
+    pub(crate) fn new(state: EndState) -> Self {
+        Self {
+            state,
+            ended: false,
+        }
+    }
+
+    /// Return the end status, if the end of the stream has been observed.
+    pub(crate) fn status(&self) -> Option<EndStatus> {
+        if self.ended {
+            Some(self.state.get())
+        } else {
+            None
+        }
+    }
+
+    /// Observe the result of a read into a buffer of `len` bytes.
+    pub(crate) fn read(&mut self, result: io::Result<usize>, len: usize) -> io::Result<usize> {
+        match &result {
+            Ok(0) if len != 0 => return self.end(result),
+            Err(e) => self.error(e),
+            _ => {}
+        }
+        result
+    }
+
+    /// Observe the result of a read which reports a `Status`.
+    pub(crate) fn read_with_status<T>(
+        &mut self,
+        result: io::Result<(T, Status)>,
+    ) -> io::Result<(T, Status)> {
+        match &result {
+            Ok((_, Status::End)) => return self.end(result),
+            Err(e) => self.error(e),
+            _ => {}
+        }
+        result
+    }
+
+    /// Observe the result of a read which fails if it doesn't fill its
+    /// buffer.
+    pub(crate) fn read_exact<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        match &result {
+            // The producer's error explains why the stream ended early.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => self.end(result),
+            Err(e) => {
+                self.error(e);
+                result
+            }
+            Ok(_) => result,
+        }
+    }
+
+    /// Observe the result of a read which fails if it doesn't fill its
+    /// buffer, and which reports a `Status`.
+    pub(crate) fn read_exact_with_status(
+        &mut self,
+        result: io::Result<Status>,
+    ) -> io::Result<Status> {
+        match &result {
+            Ok(Status::End) => self.end(result),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => self.end(result),
+            Err(e) => {
+                self.error(e);
+                result
+            }
+            Ok(_) => result,
+        }
+    }
+
+    /// Observe the result of a read which reads until the end.
+    pub(crate) fn read_to_end<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        match &result {
+            Ok(_) => self.end(result),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => self.end(result),
+            Err(_) => {
+                self.ended = true;
+                result
+            }
+        }
+    }
+
+    /// Note that the end was reached, and if the producer failed, return
+    /// its error in place of `result`.
+    fn end<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        self.ended = true;
+        match self.state.take_error() {
+            Some(e) => Err(e),
+            None => result,
+        }
+    }
+
+    fn error(&mut self, e: &io::Error) {
+        if e.kind() != io::ErrorKind::Interrupted {
+            self.ended = true;
+        }
+    }
+}
+
+#[test]
+fn tracked_reader() {
+    let state = EndState::default();
+    let mut reader = TrackedReader::new(&b"hello"[..], state.clone(), Some(5));
+    io::copy(&mut reader, &mut io::sink()).unwrap();
+    assert_eq!(state.get(), EndStatus::CleanEnd);
+
+    let state = EndState::default();
+    let mut reader = TrackedReader::new(&b"hello"[..], state.clone(), Some(10));
+    io::copy(&mut reader, &mut io::sink()).unwrap();
+    assert_eq!(
+        state.get(),
+        EndStatus::Truncated {
+            expected: Some(10),
+            got: 5
+        }
+    );
+}
+
+#[test]
+fn tracked_reader_error() {
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("producer failed"))
+        }
+    }
+
+    // The producing side ends cleanly, so a piped thread doesn't fail...
+    let state = EndState::default();
+    let mut reader = TrackedReader::new(Failing, state.clone(), None);
+    io::copy(&mut reader, &mut io::sink()).unwrap();
+    assert_eq!(
+        state.get(),
+        EndStatus::ProducerFailed("producer failed".to_owned())
+    );
+
+    // ...and the consuming side gets the error at the end.
+    let mut end = EndObserver::new(state);
+    let err = end.read(Ok(0), 1).unwrap_err();
+    assert_eq!(err.to_string(), "producer failed");
+    assert_eq!(
+        end.status(),
+        Some(EndStatus::ProducerFailed("producer failed".to_owned()))
+    );
+}
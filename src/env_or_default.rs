@@ -0,0 +1,289 @@
+//! Runtime support for `kommand`'s `#[env_or_default]` variables.
+//!
+//! An environment variable is parsed with the most specific parsing trait
+//! its variable's type implements, using [autoref specialization]. The
+//! choice is made by method resolution, which needs the concrete type, so
+//! `kommand` emits the `(&&&&&&&&EnvValue::<T>::new(os)).parse_env()` call
+//! at each variable, with the traits here in scope, and passes it to
+//! [`parse_env_or_default`].
+//!
+//! The traits are tried in this order:
+//!  - `clap::ArgEnum`
+//!  - `clap::TryFromOsArg`
+//!  - `TryFrom<&OsStr>`
+//!  - `FromStr`
+//!  - `TryFrom<&str>`
+//!  - `From<&OsStr>`
+//!  - `From<&str>`
+//!
+//! and if the type implements none of them, parsing fails with a message
+//! saying so.
+//!
+//! [autoref specialization]: http://lukaskalbertodt.github.io/2019/12/05/generalized-autoref-based-specialization.html
+
+use clap::{ArgEnum, TryFromOsArg};
+use std::any::type_name;
+use std::convert::{Infallible, TryFrom};
+use std::ffi::{OsStr, OsString};
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::process::exit;
+use std::str::FromStr;
+
+/// The value of an environment variable, to be parsed as a `T`.
+pub struct EnvValue<'a, T>(&'a OsStr, PhantomData<T>);
+
+impl<'a, T> EnvValue<'a, T> {
+    /// Wrap `os` for parsing as a `T`.
+    #[inline]
+    pub fn new(os: &'a OsStr) -> Self {
+        Self(os, PhantomData)
+    }
+
+    /// Return the value as a `str`, or fail with the value itself if it
+    /// isn't valid Unicode.
+    fn to_str<E>(&self) -> Result<&'a str, Result<E, OsString>> {
+        self.0.to_str().ok_or_else(|| Err(self.0.to_os_string()))
+    }
+}
+
+/// Parse with `clap::ArgEnum`.
+pub trait ParseArgEnum {
+    type Return;
+    fn parse_env(&self) -> Self::Return;
+}
+
+impl<'a, T: ArgEnum> ParseArgEnum for &&&&&&&&EnvValue<'a, T> {
+    type Return = Result<T, Result<String, OsString>>;
+
+    fn parse_env(&self) -> Self::Return {
+        <T as ArgEnum>::from_str(self.to_str::<String>()?, false).map_err(Ok)
+    }
+}
+
+/// Parse with `clap::TryFromOsArg`.
+pub trait ParseTryFromOsArg {
+    type Return;
+    fn parse_env(&self) -> Self::Return;
+}
+
+impl<'a, T: TryFromOsArg> ParseTryFromOsArg for &&&&&&&EnvValue<'a, T> {
+    type Return = Result<T, Result<T::Error, OsString>>;
+
+    fn parse_env(&self) -> Self::Return {
+        T::try_from_os_str_arg(self.0, clap::ambient_authority()).map_err(Ok)
+    }
+}
+
+/// Parse with `TryFrom<&OsStr>`.
+pub trait ParseTryFromOsStr {
+    type Return;
+    fn parse_env(&self) -> Self::Return;
+}
+
+impl<'a, T: TryFrom<&'a OsStr>> ParseTryFromOsStr for &&&&&&EnvValue<'a, T> {
+    type Return = Result<T, Result<T::Error, OsString>>;
+
+    fn parse_env(&self) -> Self::Return {
+        T::try_from(self.0).map_err(Ok)
+    }
+}
+
+/// Parse with `FromStr`.
+pub trait ParseFromStr {
+    type Return;
+    fn parse_env(&self) -> Self::Return;
+}
+
+impl<'a, T: FromStr> ParseFromStr for &&&&&EnvValue<'a, T> {
+    type Return = Result<T, Result<T::Err, OsString>>;
+
+    fn parse_env(&self) -> Self::Return {
+        T::from_str(self.to_str::<T::Err>()?).map_err(Ok)
+    }
+}
+
+/// Parse with `TryFrom<&str>`.
+pub trait ParseTryFromStr {
+    type Return;
+    fn parse_env(&self) -> Self::Return;
+}
+
+impl<'a, T: TryFrom<&'a str>> ParseTryFromStr for &&&&EnvValue<'a, T> {
+    type Return = Result<T, Result<T::Error, OsString>>;
+
+    fn parse_env(&self) -> Self::Return {
+        T::try_from(self.to_str::<T::Error>()?).map_err(Ok)
+    }
+}
+
+/// Parse with `From<&OsStr>`.
+pub trait ParseFromOsStr {
+    type Return;
+    fn parse_env(&self) -> Self::Return;
+}
+
+impl<'a, T: From<&'a OsStr>> ParseFromOsStr for &&&EnvValue<'a, T> {
+    type Return = Result<T, Result<Infallible, OsString>>;
+
+    fn parse_env(&self) -> Self::Return {
+        Ok(T::from(self.0))
+    }
+}
+
+/// Parse with `From<&str>`.
+pub trait ParseFromStrRef {
+    type Return;
+    fn parse_env(&self) -> Self::Return;
+}
+
+impl<'a, T: From<&'a str>> ParseFromStrRef for &&EnvValue<'a, T> {
+    type Return = Result<T, Result<Infallible, OsString>>;
+
+    fn parse_env(&self) -> Self::Return {
+        Ok(T::from(self.to_str::<Infallible>()?))
+    }
+}
+
+/// Fail, for types which implement none of the parsing traits.
+pub trait ParseUnsupported {
+    type Return;
+    fn parse_env(&self) -> Self::Return;
+}
+
+impl<'a, T> ParseUnsupported for &EnvValue<'a, T> {
+    type Return = Result<T, Result<String, OsString>>;
+
+    fn parse_env(&self) -> Self::Return {
+        Err(Ok(format!(
+            "Type `{}` does not implement any of the parsing traits: \
+            `clap::ArgEnum`, `clap::TryFromOsArg`, `TryFrom<&OsStr>`, `FromStr`, \
+            `TryFrom<&str>`, `From<&OsStr>`, or `From<&str>`",
+            type_name::<T>()
+        )))
+    }
+}
+
+/// Return the value of the environment variable `name`, whose value, if it
+/// was set, is `os_value`, parsed with `parse`, or if it wasn't set, the
+/// value returned by `default_fn`.
+///
+/// If parsing fails, this prints an error and exits the process.
+pub fn parse_env_or_default<T, E: Debug>(
+    name: &str,
+    os_value: Option<OsString>,
+    parse: impl FnOnce(&OsStr) -> Result<T, E>,
+    default_fn: impl FnOnce() -> T,
+) -> T {
+    match os_value {
+        Some(os_value) => match parse(&os_value) {
+            Ok(value) => value,
+            Err(e) => {
+                // TODO: Prettier errors.
+                eprintln!("environment variable parsing error: {}: {:?}", name, e);
+                exit(3);
+            }
+        },
+        None => default_fn(),
+    }
+}
+
+#[test]
+fn parse_arg_enum() {
+    #[derive(clap_derive::ArgEnum, Debug, PartialEq)]
+    enum Level {
+        Low,
+        High,
+    }
+
+    let parsed = (&&&&&&&&EnvValue::<Level>::new(OsStr::new("high"))).parse_env();
+    assert_eq!(parsed.unwrap(), Level::High);
+    let parsed = (&&&&&&&&EnvValue::<Level>::new(OsStr::new("medium"))).parse_env();
+    assert!(matches!(parsed, Err(Ok(_))));
+}
+
+#[test]
+fn parse_try_from_os_arg() {
+    use crate::InputByteStream;
+    use std::io::Read;
+
+    let parsed = (&&&&&&&&EnvValue::<InputByteStream>::new(OsStr::new("data:,hello"))).parse_env();
+    let mut s = String::new();
+    parsed.unwrap().read_to_string(&mut s).unwrap();
+    assert_eq!(s, "hello");
+
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.txt");
+    let parsed = (&&&&&&&&EnvValue::<InputByteStream>::new(missing.as_os_str())).parse_env();
+    assert!(matches!(parsed, Err(Ok(_))));
+}
+
+#[test]
+fn parse_from_str() {
+    let parsed = (&&&&&&&&EnvValue::<i32>::new(OsStr::new("-7"))).parse_env();
+    assert_eq!(parsed.unwrap(), -7);
+    let parsed = (&&&&&&&&EnvValue::<i32>::new(OsStr::new("seven"))).parse_env();
+    assert!(matches!(parsed, Err(Ok(_))));
+}
+
+#[test]
+fn parse_try_from_str() {
+    #[derive(Debug, PartialEq)]
+    struct Short(String);
+
+    impl TryFrom<&str> for Short {
+        type Error = &'static str;
+
+        fn try_from(s: &str) -> Result<Self, Self::Error> {
+            if s.len() <= 4 {
+                Ok(Self(s.to_owned()))
+            } else {
+                Err("too long")
+            }
+        }
+    }
+
+    let parsed = (&&&&&&&&EnvValue::<Short>::new(OsStr::new("abc"))).parse_env();
+    assert_eq!(parsed.unwrap(), Short("abc".to_owned()));
+    let parsed = (&&&&&&&&EnvValue::<Short>::new(OsStr::new("abcdef"))).parse_env();
+    assert_eq!(parsed.unwrap_err(), Ok("too long"));
+}
+
+#[test]
+fn parse_unsupported() {
+    struct Opaque;
+
+    let parsed = (&&&&&&&&EnvValue::<Opaque>::new(OsStr::new("x"))).parse_env();
+    match parsed {
+        Err(Ok(message)) => {
+            assert!(message.starts_with("Type `"), "{}", message);
+            assert!(message.contains("Opaque"), "{}", message);
+        }
+        _ => panic!("expected an error"),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn parse_non_unicode() {
+    use std::os::unix::ffi::OsStrExt;
+
+    // `FromStr` needs Unicode, so the value itself is returned.
+    let os = OsStr::from_bytes(b"\xff");
+    let parsed = (&&&&&&&&EnvValue::<i32>::new(os)).parse_env();
+    assert_eq!(parsed.unwrap_err(), Err(os.to_os_string()));
+
+    // Parsing as an `OsString` doesn't.
+    let parsed = (&&&&&&&&EnvValue::<OsString>::new(os)).parse_env();
+    assert_eq!(parsed.unwrap(), os);
+}
+
+#[test]
+fn parse_env_or_default_value() {
+    let parse = |os: &OsStr| (&&&&&&&&EnvValue::<u8>::new(os)).parse_env();
+    assert_eq!(
+        parse_env_or_default("N", Some("42".into()), parse, || 0),
+        42
+    );
+    assert_eq!(parse_env_or_default("N", None, parse, || 7), 7);
+}
@@ -0,0 +1,10 @@
+/// Whether an output file was newly created or already existed, as
+/// reported by the `existence` functions on the output stream types.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum Existence {
+    /// The file didn't exist, and was created.
+    Created,
+    /// The file already existed, and was truncated.
+    Overwrote,
+}
@@ -0,0 +1,377 @@
+use crate::{InputTextStream, MediaType, OpenError};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Mutex;
+
+/// A way of selecting a named section of an input text stream, for inputs
+/// named with a `#fragment`, such as `config.yaml#2` or `notes.md#usage`.
+///
+/// Resolvers are chosen by the input's media type. nameless has resolvers
+/// for line ranges in any text, YAML documents, and Markdown headings, and
+/// applications can add their own with [`register_fragment_resolver`].
+pub trait FragmentResolver {
+    /// Return a stream which reads just the section of `stream` named by
+    /// `fragment`, which doesn't include the `#`. Resolvers can use
+    /// [`InputTextStream::into_section`] to do this.
+    fn resolve(stream: InputTextStream, fragment: &str) -> Result<InputTextStream, OpenError>;
+}
+
+type Resolve = fn(InputTextStream, &str) -> Result<InputTextStream, OpenError>;
+
+/// Resolvers registered with `register_fragment_resolver`, with the essences
+/// of the media types they're registered for.
+static RESOLVERS: Mutex<Vec<(String, Resolve)>> = Mutex::new(Vec::new());
+
+/// Use `R` to resolve the fragments of inputs with the given media type, in
+/// place of any built-in resolver. If several resolvers are registered for
+/// a type, the last one is used.
+pub fn register_fragment_resolver<R: FragmentResolver>(media_type: &MediaType) {
+    let essence = media_type.mime().essence_str().to_owned();
+    RESOLVERS.lock().unwrap().push((essence, R::resolve));
+}
+
+/// Resolve `fragment` using the resolver for `stream`'s media type.
+pub(crate) fn resolve_fragment(
+    stream: InputTextStream,
+    fragment: &str,
+) -> Result<InputTextStream, OpenError> {
+    let essence = stream.media_type().mime().essence_str().to_owned();
+    let registered = RESOLVERS
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        .find(|(registered, _)| *registered == essence)
+        .map(|(_, resolve)| *resolve);
+    if let Some(resolve) = registered {
+        return resolve(stream, fragment);
+    }
+
+    // Line ranges work for any text.
+    if parse_line_range(fragment).is_some() {
+        return LineRanges::resolve(stream, fragment);
+    }
+
+    match essence.as_str() {
+        "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => {
+            YamlDocuments::resolve(stream, fragment)
+        }
+        "text/markdown" | "text/x-markdown" => MarkdownHeadings::resolve(stream, fragment),
+        _ => LineRanges::resolve(stream, fragment),
+    }
+}
+
+/// Resolves fragments of the form `L10` or `L10-L20`, selecting lines by
+/// number, starting at 1. Ranges include both of their ends. This works for
+/// any text, whatever its media type.
+pub struct LineRanges;
+
+impl FragmentResolver for LineRanges {
+    fn resolve(stream: InputTextStream, fragment: &str) -> Result<InputTextStream, OpenError> {
+        stream.into_section(|text| {
+            let starts = lines(text).map(|(offset, _)| offset).collect::<Vec<_>>();
+            match parse_line_range(fragment) {
+                Some((first, last)) if last <= starts.len() => {
+                    Ok(starts[first - 1]..starts.get(last).copied().unwrap_or(text.len()))
+                }
+                _ => Err(unknown_fragment(
+                    fragment,
+                    match starts.len() {
+                        0 => Vec::new(),
+                        len => vec![format!("L1-L{}", len)],
+                    },
+                )),
+            }
+        })
+    }
+}
+
+/// Resolves fragments which are document numbers, starting at 1, in YAML
+/// streams containing multiple documents separated by `---` lines, such as
+/// `config.yaml#2` for the second document.
+pub struct YamlDocuments;
+
+impl FragmentResolver for YamlDocuments {
+    fn resolve(stream: InputTextStream, fragment: &str) -> Result<InputTextStream, OpenError> {
+        stream.into_section(|text| {
+            let documents = yaml_documents(text);
+            fragment
+                .parse::<usize>()
+                .ok()
+                .filter(|index| (1..=documents.len()).contains(index))
+                .map(|index| documents[index - 1].clone())
+                .ok_or_else(|| {
+                    unknown_fragment(
+                        fragment,
+                        (1..=documents.len())
+                            .map(|index| index.to_string())
+                            .collect(),
+                    )
+                })
+        })
+    }
+}
+
+/// Resolves fragments naming headings in Markdown documents, selecting the
+/// heading and everything under it, up to the next heading of the same or a
+/// higher level. Fragments may be the heading's text or its anchor as GitHub
+/// computes it, such as `getting-started` for `## Getting Started`. Only
+/// headings written with `#` are recognized.
+pub struct MarkdownHeadings;
+
+impl FragmentResolver for MarkdownHeadings {
+    fn resolve(stream: InputTextStream, fragment: &str) -> Result<InputTextStream, OpenError> {
+        stream.into_section(|text| {
+            let headings = markdown_headings(text);
+            let index = headings
+                .iter()
+                .position(|heading| heading.anchor == fragment || heading.title == fragment)
+                .ok_or_else(|| {
+                    unknown_fragment(
+                        fragment,
+                        headings
+                            .iter()
+                            .map(|heading| heading.anchor.clone())
+                            .collect(),
+                    )
+                })?;
+            let heading = &headings[index];
+            let end = headings[index + 1..]
+                .iter()
+                .find(|next| next.level <= heading.level)
+                .map_or(text.len(), |next| next.offset);
+            Ok(heading.offset..end)
+        })
+    }
+}
+
+fn unknown_fragment(fragment: &str, available: Vec<String>) -> OpenError {
+    OpenError::UnknownFragment {
+        fragment: fragment.to_owned(),
+        available,
+    }
+}
+
+/// Iterate over the lines of `text`, including their newlines, with their
+/// byte offsets.
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        Some((start, line))
+    })
+}
+
+/// Parse `L10` or `L10-L20` into a pair of line numbers.
+fn parse_line_range(fragment: &str) -> Option<(usize, usize)> {
+    let line = |s: &str| {
+        s.strip_prefix('L')?
+            .parse::<usize>()
+            .ok()
+            .filter(|line| *line != 0)
+    };
+    let (first, last) = match fragment.split_once('-') {
+        Some((first, last)) => (line(first)?, line(last)?),
+        None => (line(fragment)?, line(fragment)?),
+    };
+    (first <= last).then_some((first, last))
+}
+
+/// Return the byte ranges of the documents in a YAML stream, including their
+/// `---` lines. Text before the first `---` is a document only if it has
+/// something other than blank lines, comments, and directives.
+fn yaml_documents(text: &str) -> Vec<Range<usize>> {
+    let mut documents = Vec::new();
+    let mut start = None;
+    let mut preamble = false;
+    for (offset, line) in lines(text) {
+        let line = line.trim_end();
+        if line == "---" || line.starts_with("--- ") || line.starts_with("---\t") {
+            match start {
+                Some(start) => documents.push(start..offset),
+                None if preamble => documents.push(0..offset),
+                None => {}
+            }
+            start = Some(offset);
+        } else if start.is_none()
+            && !line.is_empty()
+            && !line.starts_with('#')
+            && !line.starts_with('%')
+        {
+            preamble = true;
+        }
+    }
+    match start {
+        Some(start) => documents.push(start..text.len()),
+        None if preamble => documents.push(0..text.len()),
+        None => {}
+    }
+    documents
+}
+
+struct Heading {
+    level: usize,
+    title: String,
+    anchor: String,
+    offset: usize,
+}
+
+/// Find the `#` headings in a Markdown document, outside of fenced code
+/// blocks.
+fn markdown_headings(text: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut anchors = HashMap::new();
+    let mut fence = None;
+    for (offset, line) in lines(text) {
+        let line = line.trim_start_matches(' ');
+        if let Some(marker) = fence {
+            if line.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if line.starts_with("```") {
+            fence = Some("```");
+            continue;
+        }
+        if line.starts_with("~~~") {
+            fence = Some("~~~");
+            continue;
+        }
+
+        let level = line.bytes().take_while(|b| *b == b'#').count();
+        let rest = &line[level..];
+        if !(1..=6).contains(&level) || !(rest.trim().is_empty() || rest.starts_with([' ', '\t'])) {
+            continue;
+        }
+
+        // Remove any closing sequence of `#`s.
+        let title = rest.trim();
+        let unclosed = title.trim_end_matches('#');
+        let title = if unclosed.is_empty() || unclosed.ends_with([' ', '\t']) {
+            unclosed.trim_end()
+        } else {
+            title
+        };
+
+        // Compute the anchor the way GitHub does, disambiguating repeated
+        // headings with numeric suffixes.
+        let mut anchor = String::new();
+        for c in title.chars() {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                anchor.extend(c.to_lowercase());
+            } else if c == ' ' {
+                anchor.push('-');
+            }
+        }
+        let count = anchors.entry(anchor.clone()).or_insert(0);
+        if *count != 0 {
+            anchor = format!("{}-{}", anchor, count);
+        }
+        *count += 1;
+
+        headings.push(Heading {
+            level,
+            title: title.to_owned(),
+            anchor,
+            offset,
+        });
+    }
+    headings
+}
+
+#[test]
+fn line_range_syntax() {
+    assert_eq!(parse_line_range("L3"), Some((3, 3)));
+    assert_eq!(parse_line_range("L10-L20"), Some((10, 20)));
+    assert_eq!(parse_line_range("L0"), None);
+    assert_eq!(parse_line_range("L5-L4"), None);
+    assert_eq!(parse_line_range("10-20"), None);
+    assert_eq!(parse_line_range("usage"), None);
+}
+
+#[test]
+fn line_ranges_end_to_end() {
+    use clap::TryFromOsArg;
+    use std::io::Read;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("lines.txt");
+    std::fs::write(&path, "one\ntwo\nthree\nfour\n").unwrap();
+
+    let read = |name: &str| {
+        let mut s = String::new();
+        InputTextStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority())?
+            .read_to_string(&mut s)?;
+        Ok::<_, anyhow::Error>(s)
+    };
+    let name = path.to_str().unwrap();
+    assert_eq!(read(&format!("{}#L2-L3", name)).unwrap(), "two\nthree\n");
+    assert_eq!(read(&format!("{}#L4", name)).unwrap(), "four\n");
+
+    let err = read(&format!("{}#L3-L9", name)).unwrap_err();
+    match err.downcast_ref::<OpenError>() {
+        Some(OpenError::UnknownFragment {
+            fragment,
+            available,
+        }) => {
+            assert_eq!(fragment, "L3-L9");
+            assert_eq!(available, &["L1-L4"]);
+        }
+        _ => panic!("unexpected error: {:?}", err),
+    }
+
+    // Byte streams don't have sections.
+    assert!(crate::InputByteStream::try_from_os_str_arg(
+        format!("{}#L2", name).as_ref(),
+        clap::ambient_authority()
+    )
+    .is_err());
+
+    // A path containing `#` can be named with a `file:` URL, which can have
+    // a fragment of its own.
+    let path = dir.path().join("a#b.txt");
+    std::fs::write(&path, "alpha\nbeta\n").unwrap();
+    let mut url = url::Url::from_file_path(&path).unwrap();
+    assert_eq!(read(url.as_str()).unwrap(), "alpha\nbeta\n");
+    url.set_fragment(Some("L2"));
+    assert_eq!(read(url.as_str()).unwrap(), "beta\n");
+}
+
+#[test]
+fn yaml_document_ranges() {
+    let text = "a: 1\n---\nb: 2\n--- # third\nc: 3\n";
+    let documents = yaml_documents(text)
+        .into_iter()
+        .map(|range| &text[range])
+        .collect::<Vec<_>>();
+    assert_eq!(documents, ["a: 1\n", "---\nb: 2\n", "--- # third\nc: 3\n"]);
+
+    // Directives aren't part of the document.
+    let text = "%YAML 1.2\n# comment\n---\na: 1\n";
+    let documents = yaml_documents(text);
+    assert_eq!(documents.len(), 1);
+    assert_eq!(&text[documents[0].clone()], "---\na: 1\n");
+}
+
+#[test]
+fn markdown_sections() {
+    let text =
+        "# Title\nintro\n## Usage\n```\n# not a heading\n```\n### Flags\n## Usage ##\n# C#\n";
+    let headings = markdown_headings(text);
+    let anchors = headings
+        .iter()
+        .map(|heading| (heading.level, heading.anchor.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        anchors,
+        [
+            (1, "title"),
+            (2, "usage"),
+            (3, "flags"),
+            (2, "usage-1"),
+            (1, "c")
+        ]
+    );
+    assert_eq!(headings[4].title, "C#");
+}
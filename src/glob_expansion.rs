@@ -0,0 +1,280 @@
+use crate::open_results::OpenFailure;
+use crate::stream_options::{split_options, FILE_INPUT};
+use crate::{classify, OpenError, OpenResults, SyntaxKind};
+use anyhow::anyhow;
+use clap::TryFromOsArg;
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+use url::Url;
+
+/// Settings for [`expand_globs`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct GlobPolicy {
+    /// Match letters case-sensitively.
+    pub case_sensitive: bool,
+    /// Let wildcards match names beginning with `.`.
+    pub include_hidden: bool,
+    /// Fail if a pattern matches more than this many paths, to catch
+    /// runaway patterns.
+    pub max_matches: usize,
+    /// If a pattern matches nothing, pass it through literally instead of
+    /// failing.
+    pub pass_through_unmatched: bool,
+}
+
+impl Default for GlobPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            case_sensitive: true,
+            include_hidden: false,
+            max_matches: 10_000,
+            pass_through_unmatched: false,
+        }
+    }
+}
+
+/// Expand glob patterns in a stream name, returning the matching names in
+/// sorted order.
+///
+/// Only names in the path namespace, which are plain paths and `file:` URLs,
+/// are expanded; other names, such as other URLs, `-`, and child processes,
+/// are returned unchanged, as are paths without any of the glob
+/// metacharacters `*`, `?`, and `[`. `**` matches any number of directories.
+/// Matches of a `file:` URL are returned as `file:` URLs with the same
+/// options, and matches of a plain path with options, such as
+/// `*.log?lock=shared`, are returned with the same options.
+///
+/// A pattern which matches nothing fails with [`OpenError::GlobNoMatches`],
+/// as with bash's `failglob`, unless `policy.pass_through_unmatched` is set.
+/// A pattern matching more than `policy.max_matches` paths fails with
+/// [`OpenError::GlobTooManyMatches`].
+pub fn expand_globs(os: &OsStr, policy: &GlobPolicy) -> anyhow::Result<Vec<OsString>> {
+    match classify(os) {
+        SyntaxKind::Path => {
+            // Carry any options over to the matches.
+            let (pattern, query) = split_options(os, &FILE_INPUT);
+            // Non-UTF-8 patterns aren't supported by the glob matcher.
+            let pattern = match pattern.to_str() {
+                Some(pattern) if is_glob(pattern) => pattern,
+                _ => return Ok(vec![os.to_owned()]),
+            };
+            let paths = match expand(pattern, policy)? {
+                Some(paths) => paths,
+                None => return Ok(vec![os.to_owned()]),
+            };
+            Ok(paths
+                .into_iter()
+                .map(|path| {
+                    let mut name = path.into_os_string();
+                    if let Some(query) = query {
+                        name.push("?");
+                        name.push(query);
+                    }
+                    name
+                })
+                .collect())
+        }
+        SyntaxKind::Url(scheme) if scheme == "file" => {
+            let mut url = Url::parse(os.to_str().unwrap())?;
+            // Carry the options over to the matches.
+            let query = url.query().map(str::to_owned);
+            url.set_query(None);
+            let path = url
+                .to_file_path()
+                .map_err(|_: ()| anyhow!("unknown file URL weirdness"))?;
+            let pattern = match path.to_str() {
+                Some(pattern) if is_glob(pattern) => pattern.to_owned(),
+                _ => return Ok(vec![os.to_owned()]),
+            };
+            let paths = match expand(&pattern, policy)? {
+                Some(paths) => paths,
+                None => return Ok(vec![os.to_owned()]),
+            };
+            paths
+                .into_iter()
+                .map(|path| {
+                    let mut url = Url::from_file_path(&path)
+                        .map_err(|()| anyhow!("{} can't be a file URL", path.display()))?;
+                    url.set_query(query.as_deref());
+                    Ok(OsString::from(url.as_str()))
+                })
+                .collect()
+        }
+        _ => Ok(vec![os.to_owned()]),
+    }
+}
+
+fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+/// Expand `pattern`, returning `None` if it matches nothing and the policy
+/// says to pass it through.
+fn expand(pattern: &str, policy: &GlobPolicy) -> anyhow::Result<Option<Vec<PathBuf>>> {
+    let options = glob::MatchOptions {
+        case_sensitive: policy.case_sensitive,
+        require_literal_separator: true,
+        require_literal_leading_dot: !policy.include_hidden,
+    };
+    let mut paths = Vec::new();
+    for path in glob::glob_with(pattern, options)? {
+        paths.push(path?);
+        if paths.len() > policy.max_matches {
+            return Err(OpenError::GlobTooManyMatches {
+                pattern: pattern.to_owned(),
+                limit: policy.max_matches,
+            }
+            .into());
+        }
+    }
+    if paths.is_empty() {
+        if policy.pass_through_unmatched {
+            return Ok(None);
+        }
+        return Err(OpenError::GlobNoMatches(pattern.to_owned()).into());
+    }
+    paths.sort();
+    Ok(Some(paths))
+}
+
+/// Expand globs in `args` with the default policy, and convert the results
+/// to streams. This is used by `kommand` for `#[kommand(glob)]` arguments.
+#[doc(hidden)]
+pub fn glob_args<T: TryFromOsArg<Error = anyhow::Error>>(
+    args: Vec<OsString>,
+) -> anyhow::Result<Vec<T>> {
+    let policy = GlobPolicy::default();
+    let mut streams = Vec::new();
+    for arg in args {
+        for name in expand_globs(&arg, &policy)? {
+            streams.push(T::try_from_os_str_arg(&name, clap::ambient_authority())?);
+        }
+    }
+    Ok(streams)
+}
+
+/// Expand globs in `args` with the default policy, and open the results,
+/// collecting the failures. This is used by `kommand` for arguments with
+/// both `#[kommand(glob)]` and `#[kommand(collect_errors)]`.
+#[doc(hidden)]
+pub fn glob_collect_args<T: TryFromOsArg<Error = anyhow::Error>>(
+    args: Vec<OsString>,
+) -> OpenResults<T> {
+    let policy = GlobPolicy::default();
+    let mut entries = Vec::new();
+    for (index, arg) in args.into_iter().enumerate() {
+        match expand_globs(&arg, &policy) {
+            Ok(names) => {
+                for name in names {
+                    entries.push(
+                        T::try_from_os_str_arg(&name, clap::ambient_authority())
+                            .map_err(|error| OpenFailure::new(index, name, error)),
+                    );
+                }
+            }
+            Err(error) => entries.push(Err(OpenFailure::new(index, arg, error))),
+        }
+    }
+    OpenResults { entries }
+}
+
+#[cfg(test)]
+fn touch(dir: &std::path::Path, names: &[&str]) {
+    for name in names {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, name).unwrap();
+    }
+}
+
+#[test]
+fn glob_txt() {
+    let dir = tempfile::tempdir().unwrap();
+    touch(
+        dir.path(),
+        &["b.txt", "a.txt", "c.md", ".hidden.txt", "C.TXT"],
+    );
+    let pattern = dir.path().join("*.txt");
+    let names = expand_globs(pattern.as_os_str(), &GlobPolicy::default()).unwrap();
+    assert_eq!(
+        names,
+        vec![
+            dir.path().join("a.txt").into_os_string(),
+            dir.path().join("b.txt").into_os_string(),
+        ]
+    );
+
+    let policy = GlobPolicy {
+        include_hidden: true,
+        case_sensitive: false,
+        ..GlobPolicy::default()
+    };
+    let names = expand_globs(pattern.as_os_str(), &policy).unwrap();
+    assert_eq!(names.len(), 4);
+}
+
+#[test]
+fn glob_recursive_cap() {
+    let dir = tempfile::tempdir().unwrap();
+    touch(
+        dir.path(),
+        &["a.txt", "x/b.txt", "x/y/c.txt", "x/y/z/d.txt"],
+    );
+    let pattern = dir.path().join("**/*.txt");
+    let names = expand_globs(pattern.as_os_str(), &GlobPolicy::default()).unwrap();
+    assert_eq!(names.len(), 4);
+
+    let policy = GlobPolicy {
+        max_matches: 3,
+        ..GlobPolicy::default()
+    };
+    let err = expand_globs(pattern.as_os_str(), &policy).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<OpenError>(),
+        Some(OpenError::GlobTooManyMatches { limit: 3, .. })
+    ));
+}
+
+#[test]
+fn glob_no_matches() {
+    let dir = tempfile::tempdir().unwrap();
+    let pattern = dir.path().join("*.txt");
+    let err = expand_globs(pattern.as_os_str(), &GlobPolicy::default()).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<OpenError>(),
+        Some(OpenError::GlobNoMatches(_))
+    ));
+
+    let policy = GlobPolicy {
+        pass_through_unmatched: true,
+        ..GlobPolicy::default()
+    };
+    assert_eq!(
+        expand_globs(pattern.as_os_str(), &policy).unwrap(),
+        vec![pattern.into_os_string()]
+    );
+}
+
+#[test]
+fn glob_urls() {
+    let dir = tempfile::tempdir().unwrap();
+    touch(dir.path(), &["a.txt", "b.txt"]);
+
+    // Only `file:` URLs are expanded.
+    for name in ["https://example.com/*.txt", "data:,*", "-", "$(ls *.txt)"] {
+        assert_eq!(
+            expand_globs(name.as_ref(), &GlobPolicy::default()).unwrap(),
+            vec![OsString::from(name)]
+        );
+    }
+
+    let mut url = Url::from_file_path(dir.path().join("*.txt")).unwrap();
+    url.set_query(Some("lock=shared"));
+    let names = expand_globs(url.as_str().as_ref(), &GlobPolicy::default()).unwrap();
+    let mut expected = Url::from_file_path(dir.path().join("a.txt")).unwrap();
+    expected.set_query(Some("lock=shared"));
+    assert_eq!(names.len(), 2);
+    assert_eq!(names[0], OsString::from(expected.as_str()));
+}
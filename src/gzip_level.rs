@@ -0,0 +1,110 @@
+use crate::MediaType;
+
+/// The gzip compression level used when none is requested.
+pub(crate) const DEFAULT_GZIP_LEVEL: u32 = 6;
+
+/// Media types whose contents are already compressed, so compressing them
+/// again gains almost nothing.
+const ALREADY_COMPRESSED: &[&str] = &[
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/zstd",
+    "application/zip",
+    "application/x-7z-compressed",
+    "application/vnd.rar",
+    "application/x-rar-compressed",
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "audio/mpeg",
+    "audio/ogg",
+    "audio/aac",
+    "audio/flac",
+    "font/woff",
+    "font/woff2",
+];
+
+/// Test whether `media_type` describes content which is already compressed.
+pub(crate) fn is_already_compressed(media_type: &MediaType) -> bool {
+    let mime = media_type.mime();
+    mime.type_() == mime::VIDEO || ALREADY_COMPRESSED.contains(&mime.essence_str())
+}
+
+/// Choose the gzip compression level for content of type `inner`. An
+/// explicitly `requested` level is always used; otherwise, content which is
+/// already compressed is stored without compression, which is still valid
+/// gzip.
+pub(crate) fn gzip_level(inner: &MediaType, requested: Option<u32>) -> u32 {
+    match requested {
+        Some(level) => level,
+        None if is_already_compressed(inner) => 0,
+        None => DEFAULT_GZIP_LEVEL,
+    }
+}
+
+#[test]
+fn levels_by_media_type() {
+    let ext = |ext: &str| MediaType::from_extension(Some(ext.as_ref()));
+    assert_eq!(gzip_level(&ext("png"), None), 0);
+    assert_eq!(gzip_level(&ext("jpg"), None), 0);
+    assert_eq!(gzip_level(&ext("mp4"), None), 0);
+    assert_eq!(gzip_level(&ext("zip"), None), 0);
+    assert_eq!(gzip_level(&ext("txt"), None), DEFAULT_GZIP_LEVEL);
+    assert_eq!(gzip_level(&MediaType::unknown(), None), DEFAULT_GZIP_LEVEL);
+    assert_eq!(gzip_level(&ext("png"), Some(9)), 9);
+    assert_eq!(gzip_level(&ext("txt"), Some(1)), 1);
+}
+
+#[test]
+fn stored_and_compressed_outputs() {
+    use crate::{InputByteStream, OutputByteStream};
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use std::io::{Read, Write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let write = |name: &str, contents: &[u8]| {
+        let path = dir.path().join(name);
+        let mut output =
+            OutputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority())
+                .unwrap();
+        let level = output.info().compression_level();
+        output.write_all(contents).unwrap();
+        output.close().unwrap();
+
+        // The output is valid gzip either way.
+        let mut input =
+            InputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority())
+                .unwrap();
+        let mut round_trip = Vec::new();
+        input.read_to_end(&mut round_trip).unwrap();
+        assert_eq!(round_trip, contents);
+
+        (level, std::fs::metadata(&path).unwrap().len())
+    };
+
+    // Stand-ins for already-compressed image data, and for text.
+    let mut state = 1_u32;
+    let png = (0..65536)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect::<Vec<_>>();
+    let text = "a line of very compressible text\n".repeat(2048);
+
+    let (level, size) = write("image.png.gz", &png);
+    assert_eq!(level, Some(0));
+    assert!(size >= png.len() as u64);
+
+    let (level, size) = write("notes.txt.gz", text.as_bytes());
+    assert_eq!(level, Some(DEFAULT_GZIP_LEVEL));
+    assert!(size < text.len() as u64 / 10);
+
+    let (level, _) = write("plain.txt", text.as_bytes());
+    assert_eq!(level, None);
+}
@@ -0,0 +1,575 @@
+//! An opt-in on-disk cache for HTTP inputs, set with [`set_http_cache`].
+//!
+//! Each cached response is a single `<key>.entry` file in the cache
+//! directory, holding a header with the URL, media type, validators, and
+//! freshness, followed by the body. Entries are written to a temporary file
+//! and renamed into place while holding an advisory lock on `<key>.lock`,
+//! so that processes sharing the directory never see partial entries.
+//! Recently used entries have their modification times updated, which
+//! orders eviction.
+
+// HTTP isn't supported on WASI, but the settings are still public there.
+#![cfg_attr(target_os = "wasi", allow(dead_code))]
+
+use crate::lock::{lock, LockOptions};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The first line of every entry file, identifying the format.
+const ENTRY_MAGIC: &str = "nameless-http-cache 1";
+
+/// How long to wait for another process to finish with an entry's lock.
+const ENTRY_LOCK_WAIT: Duration = Duration::from_secs(5);
+
+/// Settings for caching the bodies of HTTP inputs on disk, set with
+/// [`set_http_cache`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct HttpCache {
+    /// The directory to keep cached responses in. It's created if it
+    /// doesn't exist, and may be shared by several processes.
+    pub dir: PathBuf,
+
+    /// The maximum total size of the entries in the directory, in bytes.
+    /// The least recently used entries are evicted to stay under it, and
+    /// responses bigger than it aren't stored.
+    pub max_bytes: u64,
+}
+
+impl HttpCache {
+    /// Settings which cache responses in `dir`, using up to `max_bytes`.
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self { dir, max_bytes }
+    }
+}
+
+/// How an HTTP input was served from the cache set with
+/// [`set_http_cache`], returned by [`StreamInfo::cache_status`].
+///
+/// [`StreamInfo::cache_status`]: crate::StreamInfo::cache_status
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum CacheStatus {
+    /// The response was fetched from the server, because it wasn't cached
+    /// or the cached copy had changed. It's stored if the server allows it.
+    Miss,
+    /// The cached copy was still fresh according to the `Cache-Control:
+    /// max-age` it was stored with, and was served without contacting the
+    /// server.
+    Hit,
+    /// The server confirmed with `304 Not Modified` that the cached copy is
+    /// current, and it was served.
+    Revalidated,
+}
+
+static CACHE: Mutex<Option<HttpCache>> = Mutex::new(None);
+
+/// Set a cache for the bodies of HTTP and HTTPS inputs, for every stream
+/// opened after this call, in any thread, or disable caching with `None`.
+/// Caching is disabled by default.
+///
+/// Responses with an `ETag` or `Last-Modified` validator are revalidated
+/// with a conditional request, and the cached body is served if the server
+/// responds with `304 Not Modified`. Responses with `Cache-Control:
+/// max-age` are served without contacting the server until they expire.
+/// Responses with `Cache-Control: no-store` aren't stored. Errors in
+/// reading or writing the cache are ignored, falling back to the network.
+///
+/// Entries are only stored on platforms with advisory file locks, which
+/// currently excludes Windows.
+pub fn set_http_cache(cache: Option<HttpCache>) {
+    *CACHE.lock().unwrap_or_else(PoisonError::into_inner) = cache;
+}
+
+/// Return the current cache settings, if caching is enabled.
+pub(crate) fn http_cache() -> Option<HttpCache> {
+    CACHE.lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// The metadata stored with a cached body.
+#[derive(Debug, Default)]
+pub(crate) struct EntryHeader {
+    url: String,
+    pub(crate) media_type: String,
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+    /// When the entry stops being fresh, in seconds since the Unix epoch.
+    fresh_until: Option<u64>,
+}
+
+impl EntryHeader {
+    /// Build the header for a response to `url`, or return `None` if the
+    /// response shouldn't be stored.
+    #[cfg(not(target_os = "wasi"))]
+    pub(crate) fn from_response(url: &str, response: &ureq::Response) -> Option<Self> {
+        let cache_control = response.header("Cache-Control").unwrap_or_default();
+        let directives = cache_control
+            .split(',')
+            .map(|directive| directive.trim().to_ascii_lowercase())
+            .collect::<Vec<_>>();
+        if directives.iter().any(|directive| directive == "no-store") {
+            return None;
+        }
+        let fresh_until = if directives.iter().any(|directive| directive == "no-cache") {
+            None
+        } else {
+            directives
+                .iter()
+                .find_map(|directive| directive.strip_prefix("max-age="))
+                .and_then(|max_age| max_age.parse::<u64>().ok())
+                .map(|max_age| unix_now().saturating_add(max_age))
+        };
+        let header = Self {
+            url: url.to_owned(),
+            media_type: response.content_type().to_owned(),
+            etag: response.header("ETag").map(str::to_owned),
+            last_modified: response.header("Last-Modified").map(str::to_owned),
+            fresh_until,
+        };
+
+        // An entry which can't be revalidated and is never fresh would
+        // never be used.
+        let usable =
+            header.etag.is_some() || header.last_modified.is_some() || fresh_until.is_some();
+        // Each value is stored on a line of its own.
+        let single_lines = [&header.url, &header.media_type]
+            .into_iter()
+            .chain(&header.etag)
+            .chain(&header.last_modified)
+            .all(|value| !value.contains(['\r', '\n']));
+        if usable && single_lines {
+            Some(header)
+        } else {
+            None
+        }
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.fresh_until.is_some_and(|until| unix_now() < until)
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{}", ENTRY_MAGIC)?;
+        writeln!(out, "url: {}", self.url)?;
+        writeln!(out, "media-type: {}", self.media_type)?;
+        if let Some(etag) = &self.etag {
+            writeln!(out, "etag: {}", etag)?;
+        }
+        if let Some(last_modified) = &self.last_modified {
+            writeln!(out, "last-modified: {}", last_modified)?;
+        }
+        if let Some(fresh_until) = self.fresh_until {
+            writeln!(out, "fresh-until: {}", fresh_until)?;
+        }
+        writeln!(out)
+    }
+
+    /// Read a header written by `write_to`, returning `None` if it's not in
+    /// the expected format.
+    fn read_from(input: &mut impl BufRead) -> io::Result<Option<Self>> {
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        if line.trim_end() != ENTRY_MAGIC {
+            return Ok(None);
+        }
+        let mut header = Self::default();
+        loop {
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim_end_matches('\n');
+            if line.is_empty() {
+                return Ok(Some(header));
+            }
+            let (key, value) = match line.split_once(": ") {
+                Some(pair) => pair,
+                None => return Ok(None),
+            };
+            match key {
+                "url" => header.url = value.to_owned(),
+                "media-type" => header.media_type = value.to_owned(),
+                "etag" => header.etag = Some(value.to_owned()),
+                "last-modified" => header.last_modified = Some(value.to_owned()),
+                "fresh-until" => header.fresh_until = value.parse().ok(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A cached response which has been found in the cache.
+pub(crate) struct CachedEntry {
+    pub(crate) header: EntryHeader,
+    /// The entry file, positioned at the start of the body.
+    file: File,
+    pub(crate) body_len: u64,
+}
+
+impl CachedEntry {
+    /// Test whether the entry can be served without contacting the server.
+    pub(crate) fn is_fresh(&self) -> bool {
+        self.header.is_fresh()
+    }
+
+    /// Mark the entry as recently used, and return the file to read the body
+    /// from.
+    pub(crate) fn into_body(self) -> File {
+        // This only affects eviction order, so errors are ignored.
+        let _ = self.file.set_modified(SystemTime::now());
+        self.file
+    }
+}
+
+/// Look up `url` in `cache`. Any problem reading the entry is treated as a
+/// miss.
+pub(crate) fn lookup(cache: &HttpCache, url: &str) -> Option<CachedEntry> {
+    let file = File::open(entry_path(cache, url)).ok()?;
+    let len = file.metadata().ok()?.len();
+    let mut reader = BufReader::new(file);
+    let header = EntryHeader::read_from(&mut reader).ok()??;
+    // The key is a hash of the URL, so check for collisions.
+    if header.url != url {
+        return None;
+    }
+    let body_start = reader.stream_position().ok()?;
+    let mut file = reader.into_inner();
+    file.seek(SeekFrom::Start(body_start)).ok()?;
+    Some(CachedEntry {
+        header,
+        file,
+        body_len: len - body_start,
+    })
+}
+
+/// A reader which copies everything it reads into a new cache entry, and
+/// stores the entry if the body is read to the end.
+pub(crate) struct StoringReader<R> {
+    inner: R,
+    pending: Option<PendingEntry>,
+}
+
+struct PendingEntry {
+    cache: HttpCache,
+    url: String,
+    temp_path: PathBuf,
+    temp: File,
+    expected: Option<u64>,
+    body_len: u64,
+}
+
+impl<R: Read> StoringReader<R> {
+    /// Wrap `inner`, a response body which is expected to be `expected`
+    /// bytes long if known, storing it with `header` in `cache`. If the
+    /// entry can't be created, this just reads `inner`.
+    pub(crate) fn new(
+        inner: R,
+        cache: HttpCache,
+        header: &EntryHeader,
+        expected: Option<u64>,
+    ) -> Self {
+        let pending = PendingEntry::create(cache, header, expected).ok();
+        Self { inner, pending }
+    }
+}
+
+impl PendingEntry {
+    fn create(cache: HttpCache, header: &EntryHeader, expected: Option<u64>) -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        if expected.is_some_and(|expected| expected > cache.max_bytes) {
+            return Err(io::Error::other("response too big"));
+        }
+        fs::create_dir_all(&cache.dir)?;
+        let temp_path = cache.dir.join(format!(
+            "{}.{}.{}.tmp",
+            entry_key(&header.url),
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut temp = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        let result = header.write_to(&mut temp);
+        let pending = Self {
+            url: header.url.clone(),
+            cache,
+            temp_path,
+            temp,
+            expected,
+            body_len: 0,
+        };
+        // If writing the header failed, dropping `pending` removes the file.
+        result.map(|()| pending)
+    }
+
+    fn append(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.body_len += buf.len() as u64;
+        if self.body_len > self.cache.max_bytes {
+            return Err(io::Error::other("response too big"));
+        }
+        self.temp.write_all(buf)
+    }
+
+    /// Move the completed entry into place, and evict old entries.
+    fn commit(self) -> io::Result<()> {
+        if self
+            .expected
+            .is_some_and(|expected| expected != self.body_len)
+        {
+            return Err(io::Error::other("response cut short"));
+        }
+        self.temp.sync_data()?;
+        {
+            let _lock = lock_entry(&self.cache, &self.url)?;
+            fs::rename(&self.temp_path, entry_path(&self.cache, &self.url))?;
+        }
+        evict(&self.cache)
+    }
+}
+
+impl Drop for PendingEntry {
+    fn drop(&mut self) {
+        // After a commit, this fails harmlessly.
+        let _ = fs::remove_file(&self.temp_path);
+    }
+}
+
+impl<R: Read> Read for StoringReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(pending) = &mut self.pending {
+            // Caching is best-effort; on any error, just stop storing.
+            let result = if n == 0 && !buf.is_empty() {
+                self.pending.take().unwrap().commit()
+            } else {
+                pending.append(&buf[..n])
+            };
+            if result.is_err() {
+                self.pending = None;
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Take the advisory lock for `url`'s entry, which is held while the entry
+/// is replaced or evicted. It's released when the returned file is closed.
+fn lock_entry(cache: &HttpCache, url: &str) -> io::Result<File> {
+    lock_key(cache, &entry_key(url))
+}
+
+fn lock_key(cache: &HttpCache, key: &str) -> io::Result<File> {
+    let path = cache.dir.join(format!("{}.lock", key));
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+    lock(&file, &path, LockOptions::exclusive(Some(ENTRY_LOCK_WAIT))).map_err(io::Error::other)?;
+    Ok(file)
+}
+
+/// Remove the least recently used entries until the cache is within its
+/// size limit.
+fn evict(cache: &HttpCache) -> io::Result<()> {
+    let mut entries = Vec::new();
+    let mut total = 0;
+    for dir_entry in fs::read_dir(&cache.dir)? {
+        let dir_entry = dir_entry?;
+        let path = dir_entry.path();
+        let key = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => match name.strip_suffix(".entry") {
+                Some(key) => key.to_owned(),
+                None => continue,
+            },
+            None => continue,
+        };
+        // Entries may be evicted by other processes concurrently.
+        let metadata = match dir_entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        total += metadata.len();
+        entries.push((metadata.modified()?, metadata.len(), key, path));
+    }
+
+    entries.sort();
+    for (_modified, len, key, path) in entries {
+        if total <= cache.max_bytes {
+            break;
+        }
+        let _lock = lock_key(cache, &key)?;
+        if fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+    Ok(())
+}
+
+fn entry_path(cache: &HttpCache, url: &str) -> PathBuf {
+    cache.dir.join(format!("{}.entry", entry_key(url)))
+}
+
+/// The file name stem for `url`'s entry: a 64-bit FNV-1a hash, which is
+/// stable across processes and versions, unlike `DefaultHasher`. Entries
+/// record their URL, so collisions are detected.
+fn entry_key(url: &str) -> String {
+    let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn http_cache_lifecycle() {
+    use crate::test_server::{response, TestServer};
+    use crate::InputByteStream;
+    use clap::TryFromOsArg;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    let read = |url: String| {
+        let mut input =
+            InputByteStream::try_from_os_str_arg(url.as_ref(), clap::ambient_authority()).unwrap();
+        let mut s = String::new();
+        input.read_to_string(&mut s).unwrap();
+        (s, input.info().cache_status().unwrap())
+    };
+
+    // Record each request's path and `If-None-Match` header.
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let version = Arc::new(AtomicUsize::new(1));
+    let server = TestServer::start({
+        let requests = Arc::clone(&requests);
+        let version = Arc::clone(&version);
+        move |request| {
+            let if_none_match = request.header("If-None-Match").map(str::to_owned);
+            requests
+                .lock()
+                .unwrap()
+                .push((request.path.clone(), if_none_match.clone()));
+            let text = ("Content-Type", "text/plain");
+            match request.path.as_str() {
+                "/etag" => {
+                    let version = version.load(Ordering::SeqCst);
+                    let etag = format!("\"v{}\"", version);
+                    let headers = [text, ("ETag", etag.as_str())];
+                    if if_none_match.as_ref() == Some(&etag) {
+                        response("304 Not Modified", &headers, b"")
+                    } else {
+                        let body = format!("version {}", version);
+                        response("200 OK", &headers, body.as_bytes())
+                    }
+                }
+                "/nostore" => response(
+                    "200 OK",
+                    &[text, ("ETag", "\"x\""), ("Cache-Control", "no-store")],
+                    b"private",
+                ),
+                "/fresh" => response(
+                    "200 OK",
+                    &[text, ("Cache-Control", "public, max-age=3600")],
+                    b"fresh",
+                ),
+                path => {
+                    let headers = [text, ("ETag", "\"x\"")];
+                    if if_none_match.is_some() {
+                        response("304 Not Modified", &headers, b"")
+                    } else {
+                        response("200 OK", &headers, path.repeat(250).as_bytes())
+                    }
+                }
+            }
+        }
+    });
+    let sent = |path: &str| {
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(p, _)| p == path)
+            .map(|(_, if_none_match)| if_none_match.clone())
+            .collect::<Vec<_>>()
+    };
+
+    // This is the only test which sets a cache, and other tests use other
+    // servers, so their URLs don't collide with these.
+    let dir = tempfile::tempdir().unwrap();
+    set_http_cache(Some(HttpCache::new(dir.path().join("cache"), 1 << 20)));
+
+    // A miss is stored, and revalidated with its `ETag`.
+    let (body, status) = read(server.url("/etag"));
+    assert_eq!((body.as_str(), status), ("version 1", CacheStatus::Miss));
+    let (body, status) = read(server.url("/etag"));
+    assert_eq!(
+        (body.as_str(), status),
+        ("version 1", CacheStatus::Revalidated)
+    );
+
+    // Changed content is fetched, and replaces the entry.
+    version.store(2, Ordering::SeqCst);
+    let (body, status) = read(server.url("/etag"));
+    assert_eq!((body.as_str(), status), ("version 2", CacheStatus::Miss));
+    let (body, status) = read(server.url("/etag"));
+    assert_eq!(
+        (body.as_str(), status),
+        ("version 2", CacheStatus::Revalidated)
+    );
+    let v1 = Some("\"v1\"".to_owned());
+    let v2 = Some("\"v2\"".to_owned());
+    assert_eq!(sent("/etag"), [None, v1.clone(), v1, v2]);
+
+    // `no-store` responses aren't stored.
+    assert_eq!(read(server.url("/nostore")).1, CacheStatus::Miss);
+    assert_eq!(read(server.url("/nostore")).1, CacheStatus::Miss);
+    assert_eq!(sent("/nostore"), [None, None]);
+
+    // Fresh responses are served without a request.
+    assert_eq!(read(server.url("/fresh")).1, CacheStatus::Miss);
+    let (body, status) = read(server.url("/fresh"));
+    assert_eq!((body.as_str(), status), ("fresh", CacheStatus::Hit));
+    assert_eq!(sent("/fresh"), [None]);
+
+    // In a cache with room for two of these 1500-byte responses, the least
+    // recently used is evicted. Modification times may be coarse, so pause
+    // between uses.
+    set_http_cache(Some(HttpCache::new(dir.path().join("small"), 4000)));
+    let pause = || thread::sleep(Duration::from_millis(50));
+    assert_eq!(read(server.url("/big/a")).1, CacheStatus::Miss);
+    pause();
+    assert_eq!(read(server.url("/big/b")).1, CacheStatus::Miss);
+    pause();
+    assert_eq!(read(server.url("/big/a")).1, CacheStatus::Revalidated);
+    pause();
+    assert_eq!(read(server.url("/big/c")).1, CacheStatus::Miss);
+    pause();
+    assert_eq!(read(server.url("/big/a")).1, CacheStatus::Revalidated);
+    assert_eq!(read(server.url("/big/c")).1, CacheStatus::Revalidated);
+    let (body, status) = read(server.url("/big/b"));
+    assert_eq!((body, status), ("/big/b".repeat(250), CacheStatus::Miss));
+
+    // No temporary files are left behind.
+    let mut names = fs::read_dir(dir.path().join("small"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| !name.ends_with(".lock"))
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names.len(), 2);
+    assert!(names.iter().all(|name| name.ends_with(".entry")));
+
+    set_http_cache(None);
+}
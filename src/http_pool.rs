@@ -0,0 +1,189 @@
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+#[cfg(not(target_os = "wasi"))]
+use {
+    crate::telemetry::{count_http_connection, count_http_request},
+    std::io,
+    std::net::{SocketAddr, ToSocketAddrs},
+    std::time::Instant,
+};
+
+/// Settings for reusing HTTP connections between streams, set with
+/// [`set_http_pool`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct HttpPool {
+    /// The maximum number of idle connections to keep open, across all
+    /// hosts. Zero disables reuse.
+    pub max_idle: usize,
+
+    /// The maximum number of idle connections to keep open to each host.
+    /// Zero disables reuse.
+    pub max_idle_per_host: usize,
+
+    /// How long the pool may go unused before its idle connections are
+    /// closed rather than reused, since servers tend to close idle
+    /// connections themselves.
+    pub idle_timeout: Duration,
+}
+
+impl HttpPool {
+    /// Settings which open a new connection for every stream.
+    pub fn disabled() -> Self {
+        Self {
+            max_idle: 0,
+            max_idle_per_host: 0,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for HttpPool {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_idle: 100,
+            max_idle_per_host: 1,
+            idle_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The pool settings, and the agent which holds the pooled connections,
+/// which is created on first use.
+#[cfg_attr(target_os = "wasi", allow(dead_code))]
+struct PoolState {
+    config: HttpPool,
+    #[cfg(not(target_os = "wasi"))]
+    agent: Option<(ureq::Agent, Instant)>,
+}
+
+impl PoolState {
+    fn new(config: HttpPool) -> Self {
+        Self {
+            config,
+            #[cfg(not(target_os = "wasi"))]
+            agent: None,
+        }
+    }
+}
+
+static POOL: Mutex<Option<PoolState>> = Mutex::new(None);
+
+/// Set how HTTP and HTTPS streams reuse connections, for every stream
+/// opened after this call, in any thread.
+///
+/// By default, connections are kept open after a response has been read
+/// to the end, and reused by later streams to the same host, which avoids
+/// repeating connection and TLS setup. Setting the pool closes any idle
+/// connections.
+///
+/// Request headers are set per request, so nothing a stream sends, such as
+/// a `Range` header when probing, carries over to later streams which
+/// reuse its connection.
+pub fn set_http_pool(config: HttpPool) {
+    *POOL.lock().unwrap_or_else(PoisonError::into_inner) = Some(PoolState::new(config));
+}
+
+/// Return the agent for making an HTTP request, with the shared pool of
+/// connections.
+#[cfg(not(target_os = "wasi"))]
+pub(crate) fn http_agent() -> ureq::Agent {
+    count_http_request();
+
+    let mut pool = POOL.lock().unwrap_or_else(PoisonError::into_inner);
+    let pool = pool.get_or_insert_with(|| PoolState::new(HttpPool::default()));
+    let now = Instant::now();
+    match &mut pool.agent {
+        Some((agent, last_used)) if now.duration_since(*last_used) <= pool.config.idle_timeout => {
+            *last_used = now;
+            agent.clone()
+        }
+        // Replacing the agent drops its idle connections.
+        agent => {
+            let new_agent = ureq::AgentBuilder::new()
+                .max_idle_connections(pool.config.max_idle)
+                .max_idle_connections_per_host(pool.config.max_idle_per_host)
+                .resolver(resolve)
+                .build();
+            *agent = Some((new_agent.clone(), now));
+            new_agent
+        }
+    }
+}
+
+/// Resolve addresses the same way `ureq` does by default. `ureq` only
+/// resolves addresses when it can't reuse a connection, so this also counts
+/// new connections.
+#[cfg(not(target_os = "wasi"))]
+fn resolve(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    count_http_connection();
+    netloc.to_socket_addrs().map(Iterator::collect)
+}
+
+#[test]
+fn http_pool_reuse() {
+    use crate::test_server::{response, TestServer};
+    use crate::InputByteStream;
+    use clap::TryFromOsArg;
+    use std::io::Read;
+
+    let read = |url: String| {
+        let mut input =
+            InputByteStream::try_from_os_str_arg(url.as_ref(), clap::ambient_authority()).unwrap();
+        let mut s = String::new();
+        input.read_to_string(&mut s).unwrap();
+        s
+    };
+
+    // This is the only test which changes the pool settings, so other tests
+    // running concurrently don't disturb it.
+    let server = TestServer::start(|_request| response("200 OK", &[], b"hello"));
+    assert_eq!(read(server.url("/a")), "hello");
+    assert_eq!(read(server.url("/b")), "hello");
+    assert_eq!(server.connections(), 1);
+
+    set_http_pool(HttpPool::disabled());
+    let server = TestServer::start(|_request| response("200 OK", &[], b"hello"));
+    assert_eq!(read(server.url("/a")), "hello");
+    assert_eq!(read(server.url("/b")), "hello");
+    assert_eq!(server.connections(), 2);
+
+    set_http_pool(HttpPool::default());
+}
+
+#[test]
+fn http_pool_headers_dont_leak() {
+    use crate::test_server::{response, TestServer};
+    use crate::{probe, InputByteStream, OpenPolicy};
+    use clap::TryFromOsArg;
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let server = TestServer::start({
+        let ranges = Arc::clone(&ranges);
+        move |request| {
+            if request.method == "HEAD" {
+                return response("405 Method Not Allowed", &[], b"");
+            }
+            ranges
+                .lock()
+                .unwrap()
+                .push(request.header("Range").map(str::to_owned));
+            response("200 OK", &[], b"a")
+        }
+    });
+
+    // Probing falls back to a `GET` with a `Range` header.
+    probe(server.url("/").as_ref(), &OpenPolicy::default()).unwrap();
+    let mut input =
+        InputByteStream::try_from_os_str_arg(server.url("/").as_ref(), clap::ambient_authority())
+            .unwrap();
+    input.read_to_end(&mut Vec::new()).unwrap();
+
+    assert_eq!(
+        *ranges.lock().unwrap(),
+        [Some("bytes=0-0".to_owned()), None]
+    );
+}
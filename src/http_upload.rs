@@ -0,0 +1,404 @@
+//! Streaming `multipart/form-data` uploads, for `http:` and `https:`
+//! outputs with a `nameless.multipart=<field>` option.
+
+// HTTP isn't supported on WASI, but `UploadStatus` is still part of the
+// output stream types there.
+#![cfg_attr(target_os = "wasi", allow(dead_code))]
+
+use crate::drop_error::report_drop_error;
+#[cfg(not(target_os = "wasi"))]
+use crate::http_pool::http_agent;
+use crate::{MediaType, StreamOptions};
+use anyhow::anyhow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+#[cfg(not(target_os = "wasi"))]
+use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::{Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+#[cfg(not(target_os = "wasi"))]
+use std::thread;
+use std::thread::JoinHandle;
+
+/// How many written chunks may be queued for the request thread before
+/// writes wait for it, so that the body is streamed rather than buffered.
+const QUEUED_CHUNKS: usize = 4;
+
+/// The multipart field to upload as, from a `nameless.multipart=` option,
+/// and the filename to give it, from `nameless.filename=`.
+pub(crate) struct MultipartOptions {
+    field: String,
+    filename: Option<String>,
+}
+
+impl MultipartOptions {
+    pub(crate) fn from_options(options: &StreamOptions) -> anyhow::Result<Self> {
+        let field = options.get("multipart").ok_or_else(|| {
+            anyhow!("output to HTTP requires a `nameless.multipart=<field>` option")
+        })?;
+        if field.is_empty() {
+            return Err(anyhow!("multipart field name is empty"));
+        }
+        check_header_param("multipart field name", field)?;
+        let filename = options.get("filename");
+        if let Some(filename) = filename {
+            check_header_param("multipart filename", filename)?;
+        }
+        Ok(Self {
+            field: field.to_owned(),
+            filename: filename.map(str::to_owned),
+        })
+    }
+}
+
+/// Check that `value` can appear in a quoted `Content-Disposition`
+/// parameter.
+fn check_header_param(what: &str, value: &str) -> anyhow::Result<()> {
+    if value.contains(|c: char| c == '"' || c == '\\' || c.is_control()) {
+        return Err(anyhow!(
+            "{} contains a quote, backslash, or control character",
+            what
+        ));
+    }
+    Ok(())
+}
+
+/// The state shared between a [`MultipartWriter`] and its
+/// [`UploadStatus`].
+struct UploadState {
+    /// The outcome of the request, once it's finished.
+    result: Mutex<Option<io::Result<()>>>,
+    finished: Condvar,
+}
+
+/// The outcome of an upload by a [`MultipartWriter`], which is available
+/// once the writer has been dropped and the response received.
+pub(crate) struct UploadStatus {
+    state: Arc<UploadState>,
+}
+
+impl UploadStatus {
+    /// Wait for the response, and fail if the upload failed or the server
+    /// responded with a non-2xx status.
+    pub(crate) fn wait(self) -> io::Result<()> {
+        let mut result = self
+            .state
+            .result
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        loop {
+            match result.take() {
+                Some(result) => return result,
+                None => {
+                    result = self
+                        .state
+                        .finished
+                        .wait(result)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        }
+    }
+}
+
+/// A writer which uploads everything written to it as the single file
+/// field of a `multipart/form-data` POST request, sent with the chunked
+/// transfer encoding as it's written. The closing boundary is sent, and the
+/// response waited for, when the writer is dropped.
+pub(crate) struct MultipartWriter {
+    sender: Option<SyncSender<Vec<u8>>>,
+    request: Option<JoinHandle<io::Result<()>>>,
+    closing: Vec<u8>,
+    state: Arc<UploadState>,
+}
+
+impl MultipartWriter {
+    #[cfg(not(target_os = "wasi"))]
+    pub(crate) fn new(
+        url: &str,
+        options: &MultipartOptions,
+        media_type: &MediaType,
+    ) -> anyhow::Result<Self> {
+        let boundary = boundary();
+        let filename = match &options.filename {
+            Some(filename) => filename.clone(),
+            None => match media_type.preferred_extension() {
+                Some(extension) => format!("upload.{}", extension),
+                None => "upload".to_owned(),
+            },
+        };
+        let content_type = if *media_type.mime() == mime::STAR_STAR {
+            mime::APPLICATION_OCTET_STREAM
+        } else {
+            media_type.mime().clone()
+        };
+        let preamble = format!(
+            "--{}\r\n\
+             Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+             Content-Type: {}\r\n\
+             \r\n",
+            boundary, options.field, filename, content_type
+        );
+
+        let (sender, receiver) = sync_channel(QUEUED_CHUNKS);
+        sender.send(preamble.into_bytes()).unwrap();
+        let url = url.to_owned();
+        let request_content_type = format!("multipart/form-data; boundary={}", boundary);
+        let request = thread::Builder::new()
+            .name("nameless multipart upload".to_owned())
+            .spawn(move || {
+                let body = ChannelReader {
+                    receiver,
+                    chunk: Vec::new(),
+                    pos: 0,
+                };
+                let response = http_agent()
+                    .post(&url)
+                    .set("Content-Type", &request_content_type)
+                    .send(body);
+                match response {
+                    Ok(response) if (200..300).contains(&response.status()) => Ok(()),
+                    Ok(response) | Err(ureq::Error::Status(_, response)) => {
+                        Err(io::Error::other(format!(
+                            "HTTP upload to {} failed: {} {}",
+                            url,
+                            response.status(),
+                            response.status_text()
+                        )))
+                    }
+                    Err(e) => Err(io::Error::other(format!(
+                        "HTTP error uploading to {}: {}",
+                        url, e
+                    ))),
+                }
+            })?;
+
+        Ok(Self {
+            sender: Some(sender),
+            request: Some(request),
+            closing: format!("\r\n--{}--\r\n", boundary).into_bytes(),
+            state: Arc::new(UploadState {
+                result: Mutex::new(None),
+                finished: Condvar::new(),
+            }),
+        })
+    }
+
+    /// Return a handle for waiting for the outcome of the upload once this
+    /// writer is dropped.
+    pub(crate) fn status(&self) -> UploadStatus {
+        UploadStatus {
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    fn send(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.sender
+            .as_ref()
+            .unwrap()
+            .send(buf.to_vec())
+            // The request ended early. Its error is reported by
+            // `UploadStatus::wait`.
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "HTTP upload ended before the output was finished",
+                )
+            })
+    }
+}
+
+impl Write for MultipartWriter {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            self.send(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        // Chunks are sent as soon as they're written.
+        Ok(())
+    }
+}
+
+impl Drop for MultipartWriter {
+    fn drop(&mut self) {
+        // Send the closing boundary and end the body. If the request has
+        // already ended, its own error is the one to report.
+        let closing = std::mem::take(&mut self.closing);
+        let _ = self.send(&closing);
+        drop(self.sender.take());
+        let result = match self.request.take().unwrap().join() {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::other("HTTP upload thread panicked")),
+        };
+
+        // If nothing is waiting for the outcome, report errors here.
+        if Arc::strong_count(&self.state) == 1 {
+            if let Err(e) = result {
+                report_drop_error(e);
+            }
+            return;
+        }
+        *self
+            .state
+            .result
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(result);
+        self.state.finished.notify_all();
+    }
+}
+
+/// A reader for the request body, which reads the chunks sent by a
+/// [`MultipartWriter`], and ends when the writer's sender is dropped.
+struct ChannelReader {
+    receiver: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Generate a multipart boundary. It's random, so that it's vanishingly
+/// unlikely to appear in the content, which is streamed and so can't be
+/// checked in advance.
+fn boundary() -> String {
+    let random = || RandomState::new().build_hasher().finish();
+    format!("nameless-{:016x}{:016x}", random(), random())
+}
+
+/// Open an output for `url` on a test server which records the request and
+/// responds with `status`.
+#[cfg(all(test, not(target_os = "wasi")))]
+fn upload_to(
+    status: &'static str,
+    path: &str,
+) -> (
+    crate::OutputByteStream,
+    Arc<Mutex<Option<crate::test_server::Request>>>,
+    crate::test_server::TestServer,
+) {
+    use crate::test_server::{response, TestServer};
+    use clap::TryFromOsArg;
+
+    let received = Arc::new(Mutex::new(None));
+    let server = TestServer::start({
+        let received = Arc::clone(&received);
+        move |request| {
+            *received.lock().unwrap() = Some(request.clone());
+            response(status, &[], b"")
+        }
+    });
+    let output = crate::OutputByteStream::try_from_os_str_arg(
+        server.url(path).as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    (output, received, server)
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[test]
+fn multipart_upload() {
+    use layered_io::WriteLayered;
+
+    let (mut output, received, _server) = upload_to(
+        "200 OK",
+        "/upload?token=abc&nameless.multipart=attachment&nameless.filename=data.bin",
+    );
+    let payload = (0..4 << 20)
+        .map(|i: u32| (i * 7 + i / 251) as u8)
+        .collect::<Vec<_>>();
+    for chunk in payload.chunks(64 << 10) {
+        output.write_all(chunk).unwrap();
+    }
+    output.close().unwrap();
+
+    let request = received.lock().unwrap().take().unwrap();
+    assert_eq!(request.method, "POST");
+    // Only nameless' own options are removed from the query.
+    assert_eq!(request.path, "/upload?token=abc");
+    assert_eq!(request.header("Transfer-Encoding"), Some("chunked"));
+    let boundary = request
+        .header("Content-Type")
+        .unwrap()
+        .strip_prefix("multipart/form-data; boundary=")
+        .unwrap();
+
+    let head = format!(
+        "--{}\r\n\
+         Content-Disposition: form-data; name=\"attachment\"; filename=\"data.bin\"\r\n\
+         Content-Type: application/octet-stream\r\n\
+         \r\n",
+        boundary
+    );
+    let tail = format!("\r\n--{}--\r\n", boundary);
+    let body = &request.body;
+    assert!(body.starts_with(head.as_bytes()));
+    assert!(body.ends_with(tail.as_bytes()));
+    assert!(body[head.len()..body.len() - tail.len()] == payload[..]);
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[test]
+fn multipart_upload_failure() {
+    use layered_io::WriteLayered;
+
+    let (mut output, received, _server) = upload_to(
+        "500 Internal Server Error",
+        "/upload?nameless.multipart=file",
+    );
+    output.write_all(b"contents").unwrap();
+    let err = output.close().unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("failed: 500 Internal Server Error"));
+
+    // The default filename is used without a `nameless.filename` option.
+    let request = received.lock().unwrap().take().unwrap();
+    let body = String::from_utf8(request.body).unwrap();
+    assert!(body.contains("name=\"file\"; filename=\"upload\"\r\n"));
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[test]
+fn multipart_options() {
+    use crate::stream_options::{parse_options, HTTP_OUTPUT};
+
+    let parse = |query| {
+        MultipartOptions::from_options(&parse_options(Some(query), &HTTP_OUTPUT)?)
+            .map(|options| (options.field, options.filename))
+    };
+    assert_eq!(
+        parse("multipart=file&filename=a.txt").unwrap(),
+        ("file".to_owned(), Some("a.txt".to_owned()))
+    );
+    assert_eq!(
+        parse("filename=a.txt").unwrap_err().to_string(),
+        "output to HTTP requires a `nameless.multipart=<field>` option"
+    );
+    assert!(parse("multipart=").is_err());
+    assert!(parse("multipart=a%22b").is_err());
+    assert!(parse("multipart=file&filename=a%0Db").is_err());
+    assert!(parse("multipart=file&filname=a").is_err());
+}
@@ -1,11 +1,25 @@
+use crate::any_stream::AnyReader;
+use crate::compressed_progress::CompressedProgress;
+use crate::duplicate_arguments::open_input_list;
+use crate::end_status::EndObserver;
 use crate::open_input::{open_input, Input};
-use crate::{MediaType, Pseudonym};
+#[cfg(all(feature = "poll", unix))]
+use crate::poll::PollHandle;
+use crate::source_sink::Source;
+use crate::telemetry::Telemetry;
+#[cfg(feature = "testing")]
+use crate::testing::FaultyInput;
+use crate::{
+    CacheStatus, EndStatus, MediaType, OpenPolicy, Pseudonym, StreamInfo, StreamKind, StreamOptions,
+};
+use anyhow::anyhow;
 use clap::{AmbientAuthority, TryFromOsArg};
-use io_streams::StreamReader;
 use layered_io::{Bufferable, LayeredReader, ReadLayered, Status};
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Debug, Formatter};
 use std::io::{self, IoSliceMut, Read};
+#[cfg(all(feature = "poll", unix))]
+use std::os::fd::BorrowedFd;
 use terminal_io::NeverTerminalReader;
 
 /// An input stream for binary input.
@@ -31,14 +45,53 @@ use terminal_io::NeverTerminalReader;
 ///  - "-" is interpreted as standard input.
 ///  - "(...)" runs a command with a pipe from the child process' stdout, on
 ///    platforms whch support it.
+///  - Names of the form `input | cmd | cmd2` pipe the input named before
+///    the first `|` through the commands which follow it, on platforms
+///    which support it. Each command is split into words as in `$(...)`,
+///    and may also be written as one. A path containing ` | ` can be
+///    named with a `file:` URL.
+///  - With the "clipboard" feature, `clipboard:` reads the text on the
+///    system clipboard.
 ///  - Names which don't parse as URLs are interpreted as plain local
 ///    filesystem paths. To force a string to be interpreted as a plain local
 ///    path, arrange for it to begin with `./` or `/`.
+///  - Paths ending in `.gz` are decompressed, including all the members of
+///    concatenated gzip files. To stop after the first member, use a `file:`
+///    URL with a `?gzip=single` option.
+///  - `file:` URLs with a `?lock=shared` option take an advisory lock on
+///    the file, failing if another process holds a conflicting lock, or
+///    waiting up to a given time with `?lock=shared,wait=10s`. The lock is
+///    released when the stream is closed. Advisory locks only exclude other
+///    programs which also take locks.
+///  - Plain paths accept the same options as `file:` URLs, as in
+///    `data.gz?gzip=single`. Unrecognized options are rejected, with a
+///    suggestion if they look like a misspelling. To name a path containing
+///    `?`, begin it with `./`.
+///  - Plain paths containing `#` are split into a path and a `#fragment`,
+///    which only [`InputTextStream`] supports. To name a path containing
+///    `#`, begin it with `./`.
+///
+/// [`InputTextStream`]: crate::InputTextStream
 pub struct InputByteStream {
     name: String,
-    reader: LayeredReader<NeverTerminalReader<StreamReader>>,
+    /// Which occurrence of `name` in a list of arguments this is, from 1.
+    occurrence: usize,
+    kind: StreamKind,
+    #[cfg(all(feature = "poll", unix))]
+    poll: PollHandle,
+    reader: LayeredReader<NeverTerminalReader<AnyReader>>,
     media_type: MediaType,
     initial_size: Option<u64>,
+    end: EndObserver,
+    compressed: Option<CompressedProgress>,
+    options: StreamOptions,
+    cache_status: Option<CacheStatus>,
+    telemetry: Telemetry,
+    /// Faults injected by `FaultyInput`. These are applied here rather than
+    /// in a wrapped reader because `LayeredReader` ends the stream at the
+    /// first error, and injected errors can be followed by more data.
+    #[cfg(feature = "testing")]
+    faults: Option<FaultyInput>,
 }
 
 impl InputByteStream {
@@ -66,19 +119,220 @@ impl InputByteStream {
     /// `OutputByteStream` while otherwise remaining entirely opaque.
     #[inline]
     pub fn pseudonym(&self) -> Pseudonym {
-        Pseudonym::new(self.name.clone())
+        Pseudonym::with_occurrence(self.name.clone(), self.occurrence)
+    }
+
+    /// Return a summary of this stream's metadata.
+    #[inline]
+    pub fn info(&self) -> StreamInfo {
+        StreamInfo {
+            kind: self.kind,
+            media_type: self.media_type.clone(),
+            initial_size: self.initial_size,
+            compression_level: None,
+            existence: None,
+            options: self.options.clone(),
+            cache_status: self.cache_status,
+        }
+    }
+
+    /// Return the file descriptor this stream reads from, for waiting on it
+    /// with `poll` or similar alongside other file descriptors.
+    ///
+    /// Readiness means a read won't block waiting for the source, not that
+    /// any particular amount of input is available; a read may return less
+    /// than a full line or record, or report the end of the stream. Before
+    /// waiting, check [`Self::has_buffered_input`], because input the stream
+    /// has already taken from the file descriptor won't make it ready.
+    ///
+    /// This returns `None` when the stream has no single file descriptor to
+    /// poll. That includes sources which are produced by a thread inside the
+    /// stream, such as HTTP bodies and decompressed `.gz` files; for those,
+    /// use [`Self::notification_handle`].
+    ///
+    /// This requires the "poll" feature, and is only available on Unix-family
+    /// platforms.
+    #[cfg(all(feature = "poll", unix))]
+    #[inline]
+    pub fn as_poll_handle(&self) -> Option<BorrowedFd<'_>> {
+        self.poll.direct()
+    }
+
+    /// For a stream whose input is produced by a thread inside the stream,
+    /// such as an HTTP body or a decompressed `.gz` file, return a file
+    /// descriptor which becomes readable when the thread has produced input,
+    /// or has finished. It's only for waiting on; read from the stream.
+    ///
+    /// This returns `None` for streams with an [`Self::as_poll_handle`].
+    ///
+    /// This requires the "poll" feature, and is only available on Unix-family
+    /// platforms.
+    #[cfg(all(feature = "poll", unix))]
+    #[inline]
+    pub fn notification_handle(&self) -> Option<BorrowedFd<'_>> {
+        self.poll.notification()
+    }
+
+    /// Return whether this stream may hold input it has already taken from
+    /// its source, which waiting on its file descriptor wouldn't see.
+    /// `InputByteStream` doesn't buffer input, so this is always false.
+    ///
+    /// This requires the "poll" feature, and is only available on Unix-family
+    /// platforms.
+    #[cfg(all(feature = "poll", unix))]
+    #[inline]
+    pub fn has_buffered_input(&self) -> bool {
+        false
+    }
+
+    /// Consume `self` and return it as a boxed `dyn Read`, for passing to
+    /// APIs which take one. Reads behave the same as on `self`.
+    #[inline]
+    pub fn into_boxed_read(self) -> Box<dyn Read + Send> {
+        Box::new(self)
+    }
+
+    /// Once the end of the stream has been reached, or a read has failed,
+    /// return how the stream ended. This distinguishes a clean end from a
+    /// source which was cut short, such as an HTTP body shorter than its
+    /// `Content-Length`, a gzip file missing its trailer, or a child process
+    /// which failed.
+    #[inline]
+    pub fn end_status(&self) -> Option<EndStatus> {
+        self.end.status()
+    }
+
+    /// For a stream which is being decompressed, such as a `.gz` file, return
+    /// the number of compressed bytes consumed from the underlying file so
+    /// far. Together with [`Self::compressed_size`], this is suitable for
+    /// reporting progress. Decompression may run ahead of the data returned
+    /// by reads by a small amount.
+    #[inline]
+    pub fn compressed_consumed(&self) -> Option<u64> {
+        self.compressed.as_ref().map(CompressedProgress::consumed)
+    }
+
+    /// For a stream which is being decompressed, such as a `.gz` file, return
+    /// the size of the underlying compressed file.
+    #[inline]
+    pub fn compressed_size(&self) -> Option<u64> {
+        self.compressed.as_ref().map(CompressedProgress::size)
+    }
+
+    /// Open each of `names`, as if each were a command-line argument,
+    /// handling names which appear more than once according to the
+    /// policy's [`duplicate_arguments`].
+    ///
+    /// This is for programs which accept a list of inputs, such as
+    /// `-i data.csv -i data.csv`. They can parse the list as `OsString`s
+    /// and open it with this, rather than parsing it as a
+    /// `Vec<InputByteStream>`, which opens each element on its own.
+    ///
+    /// [`duplicate_arguments`]: OpenPolicy::duplicate_arguments
+    pub fn open_list<I: IntoIterator<Item = OsString>>(
+        names: I,
+        policy: &OpenPolicy,
+        ambient_authority: AmbientAuthority,
+    ) -> anyhow::Result<Vec<Self>> {
+        let names = names.into_iter().collect();
+        open_input_list(names, policy, ambient_authority)?
+            .into_iter()
+            .map(|(input, telemetry, occurrence)| {
+                if input.fragment.is_some() {
+                    return Err(anyhow!("fragments are only supported for text inputs"));
+                }
+                let mut stream = Self::from_input((input, telemetry));
+                stream.occurrence = occurrence;
+                Ok(stream)
+            })
+            .collect()
     }
 
-    fn from_input(input: Input) -> Self {
-        let reader = NeverTerminalReader::new(input.reader);
+    fn from_input((input, telemetry): (Input, Telemetry)) -> Self {
+        #[cfg(all(feature = "poll", unix))]
+        let poll = PollHandle::new(&input.reader, input.piped_thread);
+        let reader = NeverTerminalReader::new(AnyReader::Stream(input.reader));
         let reader = LayeredReader::new(reader);
         Self {
             name: input.name,
+            occurrence: 1,
+            kind: input.kind,
+            #[cfg(all(feature = "poll", unix))]
+            poll,
             reader,
             media_type: input.media_type,
             initial_size: input.initial_size,
+            end: EndObserver::new(input.end_state),
+            compressed: input.compressed,
+            options: input.options,
+            cache_status: input.cache_status,
+            telemetry,
+            #[cfg(feature = "testing")]
+            faults: None,
+        }
+    }
+
+    /// Consume `self` and return a stream which decodes [JSON Lines]
+    /// records written by [`OutputByteStream::into_jsonl_base64`].
+    ///
+    /// Reads fail with [`CodecError::RecordSequence`] if records are missing
+    /// or out of order, and with [`CodecError::InvalidJson`] or
+    /// [`CodecError::InvalidRecord`] if a record is corrupt, inside an
+    /// `io::Error`.
+    ///
+    /// This requires the "codecs" feature.
+    ///
+    /// [JSON Lines]: https://jsonlines.org/
+    /// [`OutputByteStream::into_jsonl_base64`]: crate::OutputByteStream::into_jsonl_base64
+    /// [`CodecError::RecordSequence`]: crate::CodecError::RecordSequence
+    /// [`CodecError::InvalidJson`]: crate::CodecError::InvalidJson
+    /// [`CodecError::InvalidRecord`]: crate::CodecError::InvalidRecord
+    #[cfg(feature = "codecs")]
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_jsonl_base64(self) -> Self {
+        self.wrap_boxed(|input| {
+            Box::new(crate::codecs::JsonlBase64Reader::new(io::BufReader::new(
+                input,
+            )))
+        })
+    }
+
+    /// Wrap this stream in another reader, presented as an
+    /// `InputByteStream` with the same metadata.
+    #[cfg(feature = "codecs")]
+    pub(crate) fn wrap_boxed(self, wrap: impl FnOnce(Self) -> Box<dyn Read + Send>) -> Self {
+        let name = self.name.clone();
+        let occurrence = self.occurrence;
+        let kind = self.kind;
+        let media_type = self.media_type.clone();
+        let initial_size = self.initial_size;
+        let options = self.options.clone();
+        let cache_status = self.cache_status;
+        let reader = NeverTerminalReader::new(AnyReader::Boxed(wrap(self)));
+        Self {
+            name,
+            occurrence,
+            kind,
+            #[cfg(all(feature = "poll", unix))]
+            poll: PollHandle::None,
+            reader: LayeredReader::new(reader),
+            media_type,
+            initial_size,
+            end: EndObserver::new(Default::default()),
+            compressed: None,
+            options,
+            cache_status,
+            telemetry: Telemetry::default(),
+            #[cfg(feature = "testing")]
+            faults: None,
         }
     }
+
+    /// Inject faults into this stream's reads, for `FaultyInput`.
+    #[cfg(feature = "testing")]
+    pub(crate) fn inject_faults(&mut self, faults: FaultyInput) {
+        self.faults = Some(faults);
+    }
 }
 
 /// Implement `TryFromOsArg` so that `clap_derive` can parse InputByteStream`
@@ -95,14 +349,44 @@ impl TryFromOsArg for InputByteStream {
         os: &OsStr,
         ambient_authority: AmbientAuthority,
     ) -> anyhow::Result<Self> {
-        open_input(os, ambient_authority).map(Self::from_input)
+        let (input, telemetry) = open_input(os, &OpenPolicy::default(), ambient_authority)?;
+        if input.fragment.is_some() {
+            return Err(anyhow!("fragments are only supported for text inputs"));
+        }
+        Ok(Self::from_input((input, telemetry)))
+    }
+}
+
+impl Source for InputByteStream {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        Some(&self.media_type)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<u64> {
+        self.initial_size
+    }
+
+    #[inline]
+    fn kind(&self) -> StreamKind {
+        self.kind
     }
 }
 
 impl ReadLayered for InputByteStream {
     #[inline]
     fn read_with_status(&mut self, buf: &mut [u8]) -> io::Result<(usize, Status)> {
-        self.reader.read_with_status(buf)
+        #[cfg(feature = "testing")]
+        if let Some(mut faults) = self.faults.take() {
+            let result = faults.read(buf, |buf| self.read_with_status(buf));
+            self.faults = Some(faults);
+            return result;
+        }
+
+        let result = self.reader.read_with_status(buf);
+        let result = self.telemetry.transfer_with_status(result);
+        self.end.read_with_status(result)
     }
 
     #[inline]
@@ -110,19 +394,46 @@ impl ReadLayered for InputByteStream {
         &mut self,
         bufs: &mut [IoSliceMut<'_>],
     ) -> io::Result<(usize, Status)> {
-        self.reader.read_vectored_with_status(bufs)
+        #[cfg(feature = "testing")]
+        if self.faults.is_some() {
+            return match bufs.iter_mut().find(|buf| !buf.is_empty()) {
+                Some(buf) => self.read_with_status(buf),
+                None => Ok((0, Status::active())),
+            };
+        }
+
+        let result = self.reader.read_vectored_with_status(bufs);
+        let result = self.telemetry.transfer_with_status(result);
+        self.end.read_with_status(result)
     }
 }
 
 impl Read for InputByteStream {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.reader.read(buf)
+        #[cfg(feature = "testing")]
+        if let Some(mut faults) = self.faults.take() {
+            let result = faults.read(buf, |buf| self.read(buf).map(|n| (n, ())));
+            self.faults = Some(faults);
+            return result.map(|(n, ())| n);
+        }
+
+        let result = self.reader.read(buf);
+        let result = self.telemetry.transfer(result);
+        self.end.read(result, buf.len())
     }
 
     #[inline]
     fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
-        self.reader.read_vectored(bufs)
+        #[cfg(feature = "testing")]
+        if self.faults.is_some() {
+            return ReadOnly(self).read_vectored(bufs);
+        }
+
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        let result = self.reader.read_vectored(bufs);
+        let result = self.telemetry.transfer(result);
+        self.end.read(result, len)
     }
 
     #[cfg(can_vector)]
@@ -133,17 +444,39 @@ impl Read for InputByteStream {
 
     #[inline]
     fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
-        self.reader.read_to_end(buf)
+        // `LayeredReader::read_to_end` can pass an empty buffer to `read`
+        // once its reads add up to its buffer size, and loop forever, so use
+        // the standard implementation on top of `read` instead.
+        ReadOnly(self).read_to_end(buf)
     }
 
     #[inline]
     fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
-        self.reader.read_to_string(buf)
+        // As in `read_to_end`.
+        ReadOnly(self).read_to_string(buf)
     }
 
     #[inline]
     fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
-        self.reader.read_exact(buf)
+        #[cfg(feature = "testing")]
+        if self.faults.is_some() {
+            return ReadOnly(self).read_exact(buf);
+        }
+
+        let result = self.reader.read_exact(buf);
+        let result = self.telemetry.transfer_all(result, buf.len());
+        self.end.read_exact(result)
+    }
+}
+
+/// Present only the `read` of a stream, so that the provided `Read` methods,
+/// such as `read_to_end`, are built on it.
+struct ReadOnly<'a>(&'a mut InputByteStream);
+
+impl Read for ReadOnly<'_> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
     }
 }
 
@@ -189,3 +522,189 @@ fn data_url_base64() {
     .unwrap();
     assert_eq!(s, "Hello, World!");
 }
+
+#[test]
+fn read_to_end_large_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("large.bin");
+    let contents = (0..100_000).map(|i| i as u8).collect::<Vec<_>>();
+    std::fs::write(&path, &contents).unwrap();
+
+    let mut input =
+        InputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    let mut buf = Vec::new();
+    assert_eq!(input.read_to_end(&mut buf).unwrap(), contents.len());
+    assert!(buf == contents);
+    assert_eq!(input.end_status(), Some(EndStatus::CleanEnd));
+}
+
+#[test]
+fn end_status_clean() {
+    let mut input =
+        InputByteStream::try_from_os_str_arg("data:,hello".as_ref(), clap::ambient_authority())
+            .unwrap();
+    assert_eq!(input.end_status(), None);
+    let mut buf = Vec::new();
+    input.read_to_end(&mut buf).unwrap();
+    assert_eq!(input.end_status(), Some(EndStatus::CleanEnd));
+}
+
+#[test]
+fn end_status_short_http_body() {
+    use crate::test_server::{response, TestServer};
+
+    let server = TestServer::start(|_request| {
+        response(
+            "200 OK",
+            &[("Content-Length", "100"), ("Connection", "close")],
+            b"hello",
+        )
+    });
+
+    let mut input =
+        InputByteStream::try_from_os_str_arg(server.url("/").as_ref(), clap::ambient_authority())
+            .unwrap();
+    let mut buf = Vec::new();
+    let _ = input.read_to_end(&mut buf);
+    assert_eq!(
+        input.end_status(),
+        Some(EndStatus::Truncated {
+            expected: Some(100),
+            got: 5
+        })
+    );
+}
+
+#[test]
+fn end_status_truncated_gzip() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::Write;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"hello world").unwrap();
+    let mut compressed = encoder.finish().unwrap();
+    compressed.truncate(compressed.len() - 4);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("truncated.txt.gz");
+    fs::write(&path, &compressed).unwrap();
+
+    let mut input =
+        InputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    let mut buf = Vec::new();
+    let _ = input.read_to_end(&mut buf);
+    assert!(matches!(
+        input.end_status(),
+        Some(EndStatus::Truncated { expected: None, .. })
+    ));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn end_status_killed_child() {
+    let mut input = InputByteStream::try_from_os_str_arg(
+        "$(sh -c 'echo hello; kill -9 $$')".as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    let mut buf = Vec::new();
+    let err = input.read_to_end(&mut buf).unwrap_err();
+    assert!(err.to_string().contains("signal: 9"), "{}", err);
+    assert_eq!(buf, b"hello\n");
+    assert!(matches!(
+        input.end_status(),
+        Some(EndStatus::ProducerFailed(_))
+    ));
+}
+
+#[cfg(test)]
+fn two_member_gzip(dir: &std::path::Path) -> std::path::PathBuf {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::Write;
+
+    // Like `gzip -c a >> x.gz; gzip -c b >> x.gz`.
+    let mut compressed = Vec::new();
+    for member in [&b"first member\n"[..], &b"second member\n"[..]] {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(member).unwrap();
+        compressed.extend(encoder.finish().unwrap());
+    }
+
+    let path = dir.join("members.txt.gz");
+    fs::write(&path, &compressed).unwrap();
+    path
+}
+
+#[test]
+fn gzip_multiple_members() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = two_member_gzip(dir.path());
+
+    let mut s = String::new();
+    InputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority())
+        .unwrap()
+        .read_to_string(&mut s)
+        .unwrap();
+    assert_eq!(s, "first member\nsecond member\n");
+
+    let mut url = url::Url::from_file_path(&path).unwrap();
+    url.set_query(Some("gzip=single"));
+    let mut s = String::new();
+    InputByteStream::try_from_os_str_arg(url.as_str().as_ref(), clap::ambient_authority())
+        .unwrap()
+        .read_to_string(&mut s)
+        .unwrap();
+    assert_eq!(s, "first member\n");
+}
+
+#[test]
+fn gzip_compressed_progress() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::Write;
+
+    // Use data that doesn't compress well, so that decoding takes many reads
+    // of the underlying file.
+    let data = (0..1_000_000_u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+        .collect::<Vec<_>>();
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&data).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("progress.bin.gz");
+    fs::write(&path, &compressed).unwrap();
+
+    let mut input =
+        InputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    assert_eq!(input.compressed_size(), Some(compressed.len() as u64));
+
+    let mut buf = [0; 4096];
+    let mut total = 0;
+    let mut last = 0;
+    loop {
+        let n = input.read(&mut buf).unwrap();
+        let consumed = input.compressed_consumed().unwrap();
+        assert!(consumed >= last);
+        assert!(consumed <= compressed.len() as u64);
+        last = consumed;
+        if n == 0 {
+            break;
+        }
+        total += n;
+    }
+    assert_eq!(total, data.len());
+    assert_eq!(last, compressed.len() as u64);
+
+    let input =
+        InputByteStream::try_from_os_str_arg("data:,hello".as_ref(), clap::ambient_authority())
+            .unwrap();
+    assert_eq!(input.compressed_consumed(), None);
+    assert_eq!(input.compressed_size(), None);
+}
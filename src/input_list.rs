@@ -0,0 +1,423 @@
+use crate::memory_budget::{read_until_within, BudgetTracker};
+use crate::{classify, InputByteStream, SyntaxKind};
+use anyhow::{anyhow, Context};
+use clap::{AmbientAuthority, TryFromOsArg};
+use io_streams::StreamReader;
+use std::cell::Cell;
+use std::ffi::{OsStr, OsString};
+use std::io::{BufRead, BufReader};
+use std::slice;
+use std::vec;
+
+thread_local! {
+    /// Whether an `InputList`'s entries are being converted on this thread,
+    /// to reject nested lists.
+    static IN_LIST: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks this thread as converting a list's entries until it's dropped, so
+/// that the mark is cleared even if the conversion panics.
+struct InListGuard;
+
+impl InListGuard {
+    fn enter() -> Self {
+        IN_LIST.with(|in_list| in_list.set(true));
+        Self
+    }
+}
+
+impl Drop for InListGuard {
+    fn drop(&mut self) {
+        IN_LIST.with(|in_list| in_list.set(false));
+    }
+}
+
+/// A list of streams or other arguments read from a stream, such as a file
+/// with one path per line, for lists too long to pass on the command line,
+/// like `tar`'s and `rsync`'s `--files-from`.
+///
+/// The list's own name may be any input syntax, such as a path, a URL, or
+/// `-` for standard input. Each line of the list is converted to a `T` as
+/// if it were a command-line argument. Empty lines are skipped. A `?nul`
+/// suffix on the name, such as `list.txt?nul`, separates entries with NUL
+/// bytes instead of newlines, for names which may contain newlines, as
+/// produced by `find -print0`.
+///
+/// Errors name the line of the list, or the entry with `?nul`, where the
+/// conversion failed. When the list is read from standard input, it holds
+/// standard input until it's dropped or consumed, so neither its entries
+/// nor other arguments can also be `-`. Lists can't be nested.
+///
+/// ```rust,ignore
+/// #[kommand::main]
+/// fn main(#[kommand(long)] input_list: Option<InputList<InputByteStream>>) {
+///     for input in input_list.into_iter().flatten() {
+///         // ...
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct InputList<T> {
+    entries: Vec<T>,
+    /// When the list is read from standard input, a claim on it, since the
+    /// list's own stream releases it once it's read to the end.
+    _stdin: Option<StreamReader>,
+}
+
+impl<T> InputList<T> {
+    /// Return the number of entries.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Test whether there are no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over the entries, in the order they appear in the list.
+    #[inline]
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.entries.iter()
+    }
+
+    /// Consume `self` and return the entries.
+    #[inline]
+    pub fn into_vec(self) -> Vec<T> {
+        self.entries
+    }
+}
+
+impl<T> IntoIterator for InputList<T> {
+    type Item = T;
+    type IntoIter = vec::IntoIter<T>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a InputList<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+/// Implement `TryFromOsArg` so that `clap_derive` can parse `InputList`
+/// arguments automatically.
+///
+/// This is hidden from the documentation as it opens resources from
+/// strings using ambient authorities.
+#[doc(hidden)]
+impl<T: TryFromOsArg<Error = anyhow::Error>> TryFromOsArg for InputList<T> {
+    type Error = anyhow::Error;
+
+    fn try_from_os_str_arg(
+        os: &OsStr,
+        ambient_authority: AmbientAuthority,
+    ) -> anyhow::Result<Self> {
+        if IN_LIST.with(Cell::get) {
+            return Err(anyhow!("input lists can't be nested"));
+        }
+
+        let (name, separator) = split_separator(os);
+        let list_name = name.to_string_lossy();
+        let input = InputByteStream::try_from_os_str_arg(name, ambient_authority)
+            .with_context(|| format!("reading input list {}", list_name))?;
+        let from_stdin = classify(name) == SyntaxKind::Stdio;
+        let entries = parse_entries(
+            &list_name,
+            &mut BufReader::new(input),
+            separator,
+            from_stdin,
+            |entry| T::try_from_os_str_arg(entry, ambient_authority),
+        )?;
+        let stdin = from_stdin
+            .then(StreamReader::stdin)
+            .transpose()
+            .with_context(|| format!("reading input list {}", list_name))?;
+        Ok(Self {
+            entries,
+            _stdin: stdin,
+        })
+    }
+}
+
+/// Split a `?nul` suffix off of a list name, and return the name and the
+/// entry separator.
+fn split_separator(os: &OsStr) -> (&OsStr, u8) {
+    match os.to_str().and_then(|s| s.strip_suffix("?nul")) {
+        Some(name) => (OsStr::new(name), b'\0'),
+        None => (os, b'\n'),
+    }
+}
+
+/// Convert each entry read from `reader` with `convert`, reporting the
+/// position of the first one which fails.
+fn parse_entries<T>(
+    list_name: &str,
+    reader: &mut impl BufRead,
+    separator: u8,
+    from_stdin: bool,
+    mut convert: impl FnMut(&OsStr) -> anyhow::Result<T>,
+) -> anyhow::Result<Vec<T>> {
+    let unit = if separator == b'\n' { "line" } else { "entry" };
+    let mut budget = BudgetTracker::new("input list");
+    let mut entries = Vec::new();
+    let mut entry = Vec::new();
+    for index in 0.. {
+        let position = || format!("{} {} of input list {}", unit, index + 1, list_name);
+
+        entry.clear();
+        if read_until_within(reader, separator, &mut entry, &mut budget).with_context(position)?
+            == 0
+        {
+            break;
+        }
+        if entry.last() == Some(&separator) {
+            entry.pop();
+        }
+        if separator == b'\n' && entry.last() == Some(&b'\r') {
+            entry.pop();
+        }
+        if entry.is_empty() {
+            continue;
+        }
+
+        let entry = bytes_to_os_string(&entry).with_context(position)?;
+        if from_stdin && classify(&entry) == SyntaxKind::Stdio {
+            return Err(anyhow!(
+                "`-` can't be used in an input list which is read from standard input"
+            ))
+            .with_context(position);
+        }
+
+        let converted = {
+            let _in_list = InListGuard::enter();
+            convert(&entry)
+        };
+        let converted =
+            converted.with_context(|| format!("{}: {}", position(), entry.to_string_lossy()))?;
+        entries.push(converted);
+    }
+    Ok(entries)
+}
+
+#[cfg(unix)]
+fn bytes_to_os_string(bytes: &[u8]) -> anyhow::Result<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(OsString::from_vec(bytes.to_vec()))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_os_string(bytes: &[u8]) -> anyhow::Result<OsString> {
+    std::str::from_utf8(bytes)
+        .map(OsString::from)
+        .map_err(|_| anyhow!("input list entry isn't valid UTF-8"))
+}
+
+#[test]
+fn input_list_file() {
+    use std::io::Read;
+
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let b = dir.path().join("b.txt");
+    std::fs::write(&a, "apple").unwrap();
+    std::fs::write(&b, "banana").unwrap();
+    let list = dir.path().join("list.txt");
+    std::fs::write(&list, format!("{}\n\n{}\r\n", a.display(), b.display())).unwrap();
+
+    let inputs = InputList::<InputByteStream>::try_from_os_str_arg(
+        list.as_os_str(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    assert_eq!(inputs.len(), 2);
+    let contents = inputs
+        .into_iter()
+        .map(|mut input| {
+            let mut s = String::new();
+            input.read_to_string(&mut s).unwrap();
+            s
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(contents, ["apple", "banana"]);
+}
+
+#[test]
+fn input_list_nul_data_url() {
+    use std::io::Read;
+
+    let inputs = InputList::<InputByteStream>::try_from_os_str_arg(
+        "data:,data:,one%00data:,two%00?nul".as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    let contents = inputs
+        .into_iter()
+        .map(|mut input| {
+            let mut s = String::new();
+            input.read_to_string(&mut s).unwrap();
+            s
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(contents, ["one", "two"]);
+}
+
+#[test]
+fn input_list_panic_resets_nesting() {
+    use std::io::Cursor;
+    use std::panic::catch_unwind;
+
+    let result = catch_unwind(|| {
+        parse_entries(
+            "test",
+            &mut Cursor::new("entry\n"),
+            b'\n',
+            false,
+            |_entry| -> anyhow::Result<()> {
+                assert!(IN_LIST.with(Cell::get));
+                panic!("conversion panic")
+            },
+        )
+    });
+    assert!(result.is_err());
+    assert!(!IN_LIST.with(Cell::get));
+}
+
+#[test]
+fn input_list_bad_entry() {
+    let err = InputList::<InputByteStream>::try_from_os_str_arg(
+        "data:,data:,ok%0Anosuchscheme:x%0A".as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "line 2 of input list data:,data:,ok%0Anosuchscheme:x%0A: nosuchscheme:x"
+    );
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn input_list_stdin_conflict() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    // Lists named `-` read this process' standard input, so the cases run in
+    // copies of this test with the list piped in.
+    let case = match std::env::var("NAMELESS_INPUT_LIST_CASE") {
+        Ok(case) => case,
+        Err(_) => {
+            for (case, list) in [
+                ("entry", "data:,a\n-\n"),
+                ("after", "data:,a\n"),
+                ("before", ""),
+            ] {
+                let mut child = Command::new(std::env::current_exe().unwrap())
+                    .args(["--exact", "input_list::input_list_stdin_conflict"])
+                    .env("NAMELESS_INPUT_LIST_CASE", case)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .spawn()
+                    .unwrap();
+                let mut stdin = child.stdin.take().unwrap();
+                stdin.write_all(list.as_bytes()).unwrap();
+                drop(stdin);
+                assert!(child.wait().unwrap().success(), "case {}", case);
+            }
+            return;
+        }
+    };
+
+    let open_list = || {
+        InputList::<InputByteStream>::try_from_os_str_arg("-".as_ref(), clap::ambient_authority())
+    };
+    let open_stdin =
+        || InputByteStream::try_from_os_str_arg("-".as_ref(), clap::ambient_authority());
+    match case.as_str() {
+        // The list's entries can't be `-`.
+        "entry" => {
+            let err = open_list().unwrap_err();
+            assert_eq!(err.to_string(), "line 2 of input list -");
+            assert!(format!("{:#}", err).contains("standard input"));
+        }
+        // Nor can arguments after the list.
+        "after" => {
+            let list = open_list().unwrap();
+            assert_eq!(list.len(), 1);
+            assert!(format!("{:#}", open_stdin().unwrap_err()).contains("stdin"));
+            drop(list);
+            open_stdin().unwrap();
+        }
+        // Nor arguments before it.
+        "before" => {
+            let _stdin = open_stdin().unwrap();
+            let err = open_list().unwrap_err();
+            assert_eq!(err.to_string(), "reading input list -");
+            assert!(format!("{:#}", err).contains("stdin"));
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn input_list_dash_entry() {
+    struct Arg(OsString);
+
+    impl TryFromOsArg for Arg {
+        type Error = anyhow::Error;
+
+        fn try_from_os_str_arg(os: &OsStr, _: AmbientAuthority) -> anyhow::Result<Self> {
+            Ok(Self(os.to_owned()))
+        }
+    }
+
+    // From anywhere but standard input, `-` is allowed.
+    let entries = InputList::<Arg>::try_from_os_str_arg(
+        "data:,a.txt%0A-%0A".as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap()
+    .into_iter()
+    .map(|arg| arg.0)
+    .collect::<Vec<_>>();
+    assert_eq!(entries, [OsString::from("a.txt"), OsString::from("-")]);
+}
+
+#[test]
+fn input_list_nested() {
+    let err = InputList::<InputList<InputByteStream>>::try_from_os_str_arg(
+        "data:,data:,x".as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap_err();
+    assert!(format!("{:#}", err).contains("input lists can't be nested"));
+}
+
+#[test]
+fn input_list_memory_budget() {
+    use crate::memory_budget::{exceeded_feature, test_budget, with_test_budget};
+
+    let dir = tempfile::tempdir().unwrap();
+    let list = dir.path().join("list.txt");
+    // The list is read an entry at a time, so the budget applies to each
+    // entry.
+    std::fs::write(&list, "data:,a\ndata:,b\ndata:,an-overly-long-entry\n").unwrap();
+    let (result, _) = with_test_budget(test_budget(16), || {
+        InputList::<InputByteStream>::try_from_os_str_arg(
+            list.as_os_str(),
+            clap::ambient_authority(),
+        )
+        .map(|_| ())
+    });
+    assert_eq!(exceeded_feature(&result.unwrap_err()), Some("input list"));
+}
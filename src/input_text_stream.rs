@@ -1,15 +1,38 @@
+use crate::compressed_progress::CompressedProgress;
+use crate::duplicate_arguments::open_input_list;
+use crate::end_status::{EndObserver, EndState};
+use crate::fragment::resolve_fragment;
+use crate::memory_budget::{into_open_error, BudgetTracker};
 use crate::open_input::{open_input, Input};
-use crate::{MediaType, Pseudonym};
-use basic_text::{ReadText, ReadTextLayered, TextReader, TextSubstr};
+#[cfg(all(feature = "poll", unix))]
+use crate::poll::PollHandle;
+use crate::poll::ReadAhead;
+use crate::source_sink::Source;
+use crate::telemetry::Telemetry;
+use crate::text_accounting::Accountant;
+use crate::{
+    CacheStatus, EndStatus, MediaType, OpenError, OpenPolicy, Pseudonym, StreamInfo, StreamKind,
+    StreamOptions, TextAccounting,
+};
+use basic_text::{ReadText, ReadTextLayered, TextReader, TextStr, TextString, TextSubstr};
 use clap::{AmbientAuthority, TryFromOsArg};
 use io_streams::StreamReader;
 use layered_io::{Bufferable, LayeredReader, ReadLayered, Status};
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Debug, Formatter};
 use std::io::{self, IoSliceMut, Read};
+use std::ops::Range;
+#[cfg(all(feature = "poll", unix))]
+use std::os::fd::BorrowedFd;
 use terminal_io::TerminalReader;
 use utf8_io::{ReadStr, ReadStrLayered, Utf8Reader};
 
+/// The smallest chunk size used by the bulk reading functions.
+const MIN_BULK_CHUNK_SIZE: usize = 16 * 1024;
+
+/// The largest chunk size used by the bulk reading functions.
+const MAX_BULK_CHUNK_SIZE: usize = 256 * 1024;
+
 /// In input stream for plain text input.
 ///
 /// An `InputTextStream` implements `Read` so it supports `read`,
@@ -20,6 +43,21 @@ use utf8_io::{ReadStr, ReadStrLayered, Utf8Reader};
 /// it in a [`std::io::BufReader`] is recommended for performance and
 /// ease of use.
 ///
+/// Input is converted to [Basic Text] as it's read:
+///  - CRLF and lone CR line endings become LF, and a final LF is added to
+///    non-empty text which doesn't end with one.
+///  - A byte order mark at the start is removed.
+///  - Text is put in Normalization Form C.
+///  - Other sequences which Basic Text excludes, such as escape sequences,
+///    are replaced as Basic Text specifies. This includes U+0085 (NEL),
+///    U+2028 (LS), and U+2029 (PS), which aren't treated as line breaks
+///    consistently enough to be relied on. Only LF ends a line.
+///
+/// The conversion is idempotent: reading text which was already read
+/// gives the same text. Since [`OutputTextStream`] writes Basic Text
+/// unchanged, copying an `InputTextStream` to an `OutputTextStream` is
+/// stable: copying the result again produces identical bytes.
+///
 /// The primary way to construct an `InputTextStream` is to use it as
 /// a type in a `kommand` argument or `clap_derive` struct. Command-line
 /// arguments will then be automatically converted into input streams.
@@ -33,14 +71,59 @@ use utf8_io::{ReadStr, ReadStrLayered, Utf8Reader};
 ///  - "-" is interpreted as standard input.
 ///  - "(...)" runs a command with a pipe from the child process' stdout, on
 ///    platforms whch support it.
+///  - Names of the form `input | cmd | cmd2` pipe the input named before
+///    the first `|` through the commands which follow it, on platforms
+///    which support it. Each command is split into words as in `$(...)`,
+///    and may also be written as one. A path containing ` | ` can be
+///    named with a `file:` URL.
+///  - With the "clipboard" feature, `clipboard:` reads the text on the
+///    system clipboard.
 ///  - Names which don't parse as URLs are interpreted as plain local
 ///    filesystem paths. To force a string to be interpreted as a plain local
 ///    path, arrange for it to begin with `./` or `/`.
+///  - Paths ending in `.gz` are decompressed, including all the members of
+///    concatenated gzip files. To stop after the first member, use a `file:`
+///    URL with a `?gzip=single` option.
+///  - `file:` URLs with a `?lock=shared` option take an advisory lock on
+///    the file, failing if another process holds a conflicting lock, or
+///    waiting up to a given time with `?lock=shared,wait=10s`. The lock is
+///    released when the stream is closed. Advisory locks only exclude other
+///    programs which also take locks.
+///  - Plain paths accept the same options as `file:` URLs, as in
+///    `data.gz?gzip=single`. Unrecognized options are rejected, with a
+///    suggestion if they look like a misspelling. To name a path containing
+///    `?`, begin it with `./`.
+///  - A `#fragment` after a plain path or a `file:` URL selects a section of
+///    the input, such as `notes.txt#L10-L20` for a range of lines,
+///    `config.yaml#2` for the second document of a YAML stream, or
+///    `notes.md#usage` for the section under a Markdown heading. See
+///    [`FragmentResolver`]. To name a path containing `#`, begin it with
+///    `./`.
+///
+/// [`FragmentResolver`]: crate::FragmentResolver
+/// [Basic Text]: https://docs.rs/basic-text
+/// [`OutputTextStream`]: crate::OutputTextStream
 pub struct InputTextStream {
     name: String,
-    reader: TextReader<Utf8Reader<LayeredReader<TerminalReader<StreamReader>>>>,
+    /// Which occurrence of `name` in a list of arguments this is, from 1.
+    occurrence: usize,
+    kind: StreamKind,
+    #[cfg(all(feature = "poll", unix))]
+    poll: PollHandle,
+    /// The source, until anything is read through the text layers.
+    source: Option<TerminalReader<StreamReader>>,
+    /// The text layers, created over `source` on the first read which needs
+    /// them.
+    reader: Option<TextReader<Utf8Reader<LayeredReader<TerminalReader<StreamReader>>>>>,
+    read_ahead: ReadAhead,
     media_type: MediaType,
     initial_size: Option<u64>,
+    end: EndObserver,
+    compressed: Option<CompressedProgress>,
+    accountant: Option<Accountant>,
+    options: StreamOptions,
+    cache_status: Option<CacheStatus>,
+    telemetry: Telemetry,
 }
 
 impl InputTextStream {
@@ -53,6 +136,13 @@ impl InputTextStream {
         &self.media_type
     }
 
+    /// Return the media type of the stream.
+    #[deprecated(note = "use `media_type` instead")]
+    #[inline]
+    pub fn type_(&self) -> &MediaType {
+        self.media_type()
+    }
+
     /// Return the initial size of the stream, in bytes. This is strictly based
     /// on available metadata, and not on examining any of the contents of the
     /// stream, and the stream could end up being shorter or longer if the
@@ -66,18 +156,322 @@ impl InputTextStream {
     /// its filesystem path or its URL). This allows it to be written to an
     /// `OutputByteStream` while otherwise remaining entirely opaque.
     pub fn pseudonym(&self) -> Pseudonym {
-        Pseudonym::new(self.name.clone())
+        Pseudonym::with_occurrence(self.name.clone(), self.occurrence)
+    }
+
+    /// Return a summary of this stream's metadata.
+    #[inline]
+    pub fn info(&self) -> StreamInfo {
+        StreamInfo {
+            kind: self.kind,
+            media_type: self.media_type.clone(),
+            initial_size: self.initial_size,
+            compression_level: None,
+            existence: None,
+            options: self.options.clone(),
+            cache_status: self.cache_status,
+        }
+    }
+
+    /// Read all the remaining text in the stream and append it to `out`,
+    /// returning the number of bytes appended.
+    ///
+    /// This is equivalent to `read_to_string`, but if nothing has been read
+    /// from the stream yet, it reads the source in bulk and validates and
+    /// converts it in a single pass at the end, rather than passing it
+    /// through the text layers piece by piece. Input which is already Basic
+    /// Text becomes `out`'s contents without being copied when `out` is
+    /// empty. As with `read_to_string`, if an error occurs, `out` is left as
+    /// it was.
+    pub fn read_to_text_string(&mut self, out: &mut TextString) -> io::Result<usize> {
+        if let Some(initial_size) = self.initial_size {
+            // Fail before reading anything if the stream is known to be too
+            // big.
+            let initial_size = usize::try_from(initial_size).unwrap_or(usize::MAX);
+            BudgetTracker::new("read_to_text_string").check_io(initial_size)?;
+        }
+
+        let start = out.len();
+        let result = match &mut self.source {
+            // Nothing has been read through the text layers yet, so read the
+            // source directly and convert it all at once.
+            Some(source) => read_source_to_text(source, out, self.initial_size),
+            None => {
+                if let Some(initial_size) = self.initial_size {
+                    // Add a little for the newline the text layers may
+                    // append.
+                    out.reserve(usize::try_from(initial_size).unwrap_or(0).saturating_add(1));
+                }
+                self.text().read_to_text_string(out)
+            }
+        };
+        let result = self.telemetry.transfer(result);
+        let result = self.end.read_to_end(result);
+        let result = self.read_ahead.read_to_end(result);
+        if result.is_ok() {
+            self.account(&out.as_bytes()[start..]);
+        }
+        result
+    }
+
+    /// Like [`read_to_text_string`], but append the text to a plain byte
+    /// buffer.
+    ///
+    /// [`read_to_text_string`]: Self::read_to_text_string
+    pub fn read_to_text_bytes(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.read_bulk(buf, &mut BudgetTracker::new("read_to_text_bytes"))
+    }
+
+    /// Implement `read_to_text_bytes`, with the buffer charged to `tracker`.
+    fn read_bulk(&mut self, buf: &mut Vec<u8>, tracker: &mut BudgetTracker) -> io::Result<usize> {
+        let start = buf.len();
+        let chunk_size = bulk_chunk_size(self.initial_size);
+        if let Some(initial_size) = self.initial_size {
+            // Fail before reading anything if the stream is known to be too
+            // big.
+            let initial_size = usize::try_from(initial_size).unwrap_or(usize::MAX);
+            tracker.check_io(initial_size)?;
+            // Add a little for the newline the text layers may append.
+            buf.reserve(initial_size.saturating_add(1));
+        }
+
+        loop {
+            let len = buf.len();
+            buf.resize(len + chunk_size, 0);
+            let result = self.text().read(&mut buf[len..]);
+            let result = self.telemetry.transfer(result);
+            let result = self.end.read(result, chunk_size);
+            match self.read_ahead.read(result, chunk_size) {
+                Ok(0) => {
+                    buf.truncate(len);
+                    break;
+                }
+                Ok(n) => {
+                    if let Err(e) = tracker.check_io(len + n - start) {
+                        buf.truncate(len);
+                        return Err(e);
+                    }
+                    buf.truncate(len + n);
+                    self.account(&buf[len..]);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => buf.truncate(len),
+                Err(e) => {
+                    buf.truncate(len);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(buf.len() - start)
+    }
+
+    /// Return the text layers, creating them over the source if nothing has
+    /// been read through them yet.
+    fn text(&mut self) -> &mut TextReader<Utf8Reader<LayeredReader<TerminalReader<StreamReader>>>> {
+        if let Some(source) = self.source.take() {
+            self.reader = Some(TextReader::new(source));
+        }
+        // Exactly one of `source` and `reader` is present, so this can't
+        // fail.
+        self.reader.as_mut().unwrap()
+    }
+
+    /// Return the file descriptor this stream reads from, for waiting on it
+    /// with `poll` or similar. Readiness doesn't imply that a full line is
+    /// available.
+    ///
+    /// See [`InputByteStream::as_poll_handle`] for details.
+    ///
+    /// This requires the "poll" feature, and is only available on Unix-family
+    /// platforms.
+    ///
+    /// [`InputByteStream::as_poll_handle`]: crate::InputByteStream::as_poll_handle
+    #[cfg(all(feature = "poll", unix))]
+    #[inline]
+    pub fn as_poll_handle(&self) -> Option<BorrowedFd<'_>> {
+        self.poll.direct()
+    }
+
+    /// For a stream whose input is produced by a thread inside the stream,
+    /// return a file descriptor which becomes readable when the thread has
+    /// produced input.
+    ///
+    /// See [`InputByteStream::notification_handle`] for details.
+    ///
+    /// This requires the "poll" feature, and is only available on Unix-family
+    /// platforms.
+    ///
+    /// [`InputByteStream::notification_handle`]: crate::InputByteStream::notification_handle
+    #[cfg(all(feature = "poll", unix))]
+    #[inline]
+    pub fn notification_handle(&self) -> Option<BorrowedFd<'_>> {
+        self.poll.notification()
     }
 
-    fn from_input(input: Input) -> Self {
-        let reader = TerminalReader::with_handle(input.reader);
-        let reader = TextReader::new(reader);
+    /// Return whether this stream may hold decoded input which waiting on
+    /// its file descriptor wouldn't see, so that an event loop should read
+    /// again before waiting.
+    ///
+    /// The text decoding layers hold input back, for example to complete a
+    /// UTF-8 sequence or when a read's buffer is too small, and don't say
+    /// how much. So this is conservative: it's true after a read which
+    /// filled its buffer, and false after a read which returned less than
+    /// was asked for, which leaves nothing ready behind.
+    ///
+    /// This requires the "poll" feature, and is only available on Unix-family
+    /// platforms.
+    #[cfg(all(feature = "poll", unix))]
+    #[inline]
+    pub fn has_buffered_input(&self) -> bool {
+        self.read_ahead.buffered()
+    }
+
+    /// Once the end of the stream has been reached, or a read has failed,
+    /// return how the stream ended. This distinguishes a clean end from a
+    /// source which was cut short, such as an HTTP body shorter than its
+    /// `Content-Length`, a gzip file missing its trailer, or a child process
+    /// which failed.
+    #[inline]
+    pub fn end_status(&self) -> Option<EndStatus> {
+        self.end.status()
+    }
+
+    /// For a stream which is being decompressed, such as a `.gz` file, return
+    /// the number of compressed bytes consumed from the underlying file so
+    /// far. Together with [`Self::compressed_size`], this is suitable for
+    /// reporting progress. Decompression may run ahead of the data returned
+    /// by reads by a small amount.
+    #[inline]
+    pub fn compressed_consumed(&self) -> Option<u64> {
+        self.compressed.as_ref().map(CompressedProgress::consumed)
+    }
+
+    /// For a stream which is being decompressed, such as a `.gz` file, return
+    /// the size of the underlying compressed file.
+    #[inline]
+    pub fn compressed_size(&self) -> Option<u64> {
+        self.compressed.as_ref().map(CompressedProgress::size)
+    }
+
+    /// Start counting the bytes, lines, and line lengths of the text read
+    /// from this stream, to be reported by [`Self::accounting`].
+    #[inline]
+    pub fn enable_accounting(&mut self) {
+        self.accountant.get_or_insert_with(Accountant::default);
+    }
+
+    /// If accounting has been enabled with [`Self::enable_accounting`],
+    /// return the counts of the text read since then.
+    #[inline]
+    pub fn accounting(&self) -> Option<TextAccounting> {
+        self.accountant.as_ref().map(Accountant::accounting)
+    }
+
+    #[inline]
+    fn account(&mut self, bytes: &[u8]) {
+        if let Some(accountant) = &mut self.accountant {
+            accountant.account(bytes);
+        }
+    }
+
+    /// Read the rest of this stream, and return a stream which reads just
+    /// the section of it selected by `select`, which is passed the text and
+    /// returns a byte range within it. The new stream has this stream's
+    /// metadata, with the size of the section. This is used by
+    /// [`FragmentResolver`]s.
+    ///
+    /// [`FragmentResolver`]: crate::FragmentResolver
+    ///
+    /// # Panics
+    ///
+    /// This panics if the range returned by `select` doesn't lie on
+    /// character boundaries of the text.
+    pub fn into_section(
+        mut self,
+        select: impl FnOnce(&str) -> Result<Range<usize>, OpenError>,
+    ) -> Result<Self, OpenError> {
+        let mut bytes = Vec::new();
+        self.read_bulk(&mut bytes, &mut BudgetTracker::new("fragment resolution"))
+            .map_err(|e| into_open_error(e).unwrap_or_else(OpenError::FragmentRead))?;
+        // The text layers only produce valid text, so this can't fail.
+        let text = String::from_utf8(bytes).unwrap();
+        let section = &text[select(&text)?];
+
+        let reader = StreamReader::bytes(section.as_bytes()).map_err(OpenError::FragmentRead)?;
+        #[cfg(all(feature = "poll", unix))]
+        let poll = PollHandle::new(&reader, false);
+        let source = TerminalReader::with_handle(reader);
+        Ok(Self {
+            name: self.name,
+            occurrence: self.occurrence,
+            kind: self.kind,
+            #[cfg(all(feature = "poll", unix))]
+            poll,
+            source: Some(source),
+            reader: None,
+            read_ahead: ReadAhead::default(),
+            media_type: self.media_type,
+            initial_size: Some(section.len().try_into().unwrap()),
+            end: EndObserver::new(EndState::default()),
+            compressed: None,
+            accountant: self.accountant.map(|_| Accountant::default()),
+            options: self.options,
+            cache_status: self.cache_status,
+            telemetry: self.telemetry,
+        })
+    }
+
+    /// Open each of `names`, as if each were a command-line argument,
+    /// handling names which appear more than once according to the
+    /// policy's [`duplicate_arguments`].
+    ///
+    /// This is the `InputTextStream` counterpart of
+    /// [`InputByteStream::open_list`].
+    ///
+    /// [`duplicate_arguments`]: OpenPolicy::duplicate_arguments
+    /// [`InputByteStream::open_list`]: crate::InputByteStream::open_list
+    pub fn open_list<I: IntoIterator<Item = OsString>>(
+        names: I,
+        policy: &OpenPolicy,
+        ambient_authority: AmbientAuthority,
+    ) -> anyhow::Result<Vec<Self>> {
+        let names = names.into_iter().collect();
+        open_input_list(names, policy, ambient_authority)?
+            .into_iter()
+            .map(|(mut input, telemetry, occurrence)| {
+                let fragment = input.fragment.take();
+                let mut stream = Self::from_input((input, telemetry));
+                stream.occurrence = occurrence;
+                match fragment {
+                    Some(fragment) => Ok(resolve_fragment(stream, &fragment)?),
+                    None => Ok(stream),
+                }
+            })
+            .collect()
+    }
+
+    fn from_input((input, telemetry): (Input, Telemetry)) -> Self {
+        #[cfg(all(feature = "poll", unix))]
+        let poll = PollHandle::new(&input.reader, input.piped_thread);
+        let source = TerminalReader::with_handle(input.reader);
         let media_type = input.media_type.union(MediaType::text());
         Self {
             name: input.name,
-            reader,
+            occurrence: 1,
+            kind: input.kind,
+            #[cfg(all(feature = "poll", unix))]
+            poll,
+            source: Some(source),
+            reader: None,
+            read_ahead: ReadAhead::default(),
             media_type,
             initial_size: input.initial_size,
+            end: EndObserver::new(input.end_state),
+            compressed: input.compressed,
+            accountant: None,
+            options: input.options,
+            cache_status: input.cache_status,
+            telemetry,
         }
     }
 }
@@ -96,14 +490,108 @@ impl TryFromOsArg for InputTextStream {
         os: &OsStr,
         ambient_authority: AmbientAuthority,
     ) -> anyhow::Result<Self> {
-        open_input(os, ambient_authority).map(Self::from_input)
+        let (mut input, telemetry) = open_input(os, &OpenPolicy::default(), ambient_authority)?;
+        let fragment = input.fragment.take();
+        let stream = Self::from_input((input, telemetry));
+        match fragment {
+            Some(fragment) => Ok(resolve_fragment(stream, &fragment)?),
+            None => Ok(stream),
+        }
+    }
+}
+
+/// Read all of `source` in bulk, then convert it to Basic Text in one pass
+/// and append it to `out`, returning the number of bytes appended.
+///
+/// Input which is already Basic Text, apart from perhaps a missing final
+/// newline, is appended as is. Anything else is converted as the text layers
+/// would have converted it.
+fn read_source_to_text(
+    source: &mut TerminalReader<StreamReader>,
+    out: &mut TextString,
+    initial_size: Option<u64>,
+) -> io::Result<usize> {
+    let mut tracker = BudgetTracker::new("read_to_text_string");
+    let chunk_size = bulk_chunk_size(initial_size);
+    let mut raw = Vec::new();
+    if let Some(initial_size) = initial_size {
+        raw.reserve(usize::try_from(initial_size).unwrap_or(0));
+    }
+    loop {
+        let len = raw.len();
+        raw.resize(len + chunk_size, 0);
+        match source.read(&mut raw[len..]) {
+            Ok(0) => {
+                raw.truncate(len);
+                break;
+            }
+            Ok(n) => {
+                raw.truncate(len + n);
+                tracker.check_io(raw.len())?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => raw.truncate(len),
+            Err(e) => return Err(e),
+        }
+    }
+
+    let start = out.len();
+    match TextString::from_text_vec(raw) {
+        Ok(text) => {
+            let missing_newline = !text.as_str().is_empty() && !text.as_str().ends_with('\n');
+            if out.as_str().is_empty() {
+                *out = text;
+            } else {
+                out.push_text(&text);
+            }
+            if missing_newline {
+                out.push_text(TextStr::from_text("\n").unwrap());
+            }
+        }
+        Err(e) => {
+            TextReader::new(e.into_bytes().as_slice()).read_to_text_string(out)?;
+        }
+    }
+    Ok(out.len() - start)
+}
+
+/// Choose the chunk size for bulk reads, given the stream's initial size.
+fn bulk_chunk_size(initial_size: Option<u64>) -> usize {
+    match initial_size {
+        Some(size) => usize::try_from(size)
+            .unwrap_or(MAX_BULK_CHUNK_SIZE)
+            .clamp(MIN_BULK_CHUNK_SIZE, MAX_BULK_CHUNK_SIZE),
+        None => MAX_BULK_CHUNK_SIZE,
+    }
+}
+
+impl Source for InputTextStream {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        Some(&self.media_type)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<u64> {
+        self.initial_size
+    }
+
+    #[inline]
+    fn kind(&self) -> StreamKind {
+        self.kind
     }
 }
 
 impl ReadLayered for InputTextStream {
     #[inline]
     fn read_with_status(&mut self, buf: &mut [u8]) -> io::Result<(usize, Status)> {
-        self.reader.read_with_status(buf)
+        let result = self.text().read_with_status(buf);
+        let result = self.telemetry.transfer_with_status(result);
+        let result = self.end.read_with_status(result);
+        let result = self.read_ahead.read_with_status(result, buf.len());
+        if let Ok((n, _status)) = &result {
+            self.account(&buf[..*n]);
+        }
+        result
     }
 
     #[inline]
@@ -111,73 +599,149 @@ impl ReadLayered for InputTextStream {
         &mut self,
         bufs: &mut [IoSliceMut<'_>],
     ) -> io::Result<(usize, Status)> {
-        self.reader.read_vectored_with_status(bufs)
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        let result = self.text().read_vectored_with_status(bufs);
+        let result = self.telemetry.transfer_with_status(result);
+        let result = self.end.read_with_status(result);
+        let result = self.read_ahead.read_with_status(result, len);
+        if let (Ok((n, _status)), Some(accountant)) = (&result, &mut self.accountant) {
+            accountant.account_vectored(bufs, *n);
+        }
+        result
     }
 }
 
 impl Read for InputTextStream {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.reader.read(buf)
+        let result = self.text().read(buf);
+        let result = self.telemetry.transfer(result);
+        let result = self.end.read(result, buf.len());
+        let result = self.read_ahead.read(result, buf.len());
+        if let Ok(n) = result {
+            self.account(&buf[..n]);
+        }
+        result
     }
 
     #[inline]
     fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
-        self.reader.read_vectored(bufs)
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        let result = self.text().read_vectored(bufs);
+        let result = self.telemetry.transfer(result);
+        let result = self.end.read(result, len);
+        let result = self.read_ahead.read(result, len);
+        if let (Ok(n), Some(accountant)) = (&result, &mut self.accountant) {
+            accountant.account_vectored(bufs, *n);
+        }
+        result
     }
 
     #[cfg(can_vector)]
     #[inline]
     fn is_read_vectored(&self) -> bool {
-        self.reader.is_read_vectored()
+        self.reader
+            .as_ref()
+            .is_some_and(|reader| reader.is_read_vectored())
     }
 
     #[inline]
     fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
-        self.reader.read_to_end(buf)
+        let start = buf.len();
+        let result = self.text().read_to_end(buf);
+        let result = self.telemetry.transfer(result);
+        let result = self.end.read_to_end(result);
+        let result = self.read_ahead.read_to_end(result);
+        if result.is_ok() {
+            self.account(&buf[start..]);
+        }
+        result
     }
 
     #[inline]
     fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
-        self.reader.read_to_string(buf)
+        let start = buf.len();
+        let result = self.text().read_to_string(buf);
+        let result = self.telemetry.transfer(result);
+        let result = self.end.read_to_end(result);
+        let result = self.read_ahead.read_to_end(result);
+        if result.is_ok() {
+            self.account(&buf.as_bytes()[start..]);
+        }
+        result
     }
 
     #[inline]
     fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
-        self.reader.read_exact(buf)
+        let result = self.text().read_exact(buf);
+        let result = self.telemetry.transfer_all(result, buf.len());
+        let result = self.end.read_exact(result);
+        let result = self.read_ahead.read_exact(result);
+        if result.is_ok() {
+            self.account(buf);
+        }
+        result
     }
 }
 
 impl Bufferable for InputTextStream {
     #[inline]
     fn abandon(&mut self) {
-        self.reader.abandon()
+        self.text().abandon()
     }
 }
 
 impl ReadStr for InputTextStream {
     #[inline]
     fn read_str(&mut self, buf: &mut str) -> io::Result<usize> {
-        self.reader.read_str(buf)
+        let result = self.text().read_str(buf);
+        let result = self.telemetry.transfer(result);
+        let result = self.end.read(result, buf.len());
+        let result = self.read_ahead.read(result, buf.len());
+        if let Ok(n) = result {
+            self.account(&buf.as_bytes()[..n]);
+        }
+        result
     }
 }
 
 impl ReadStrLayered for InputTextStream {
     #[inline]
     fn read_str_with_status(&mut self, buf: &mut str) -> io::Result<(usize, Status)> {
-        self.reader.read_str_with_status(buf)
+        let result = self.text().read_str_with_status(buf);
+        let result = self.telemetry.transfer_with_status(result);
+        let result = self.end.read_with_status(result);
+        let result = self.read_ahead.read_with_status(result, buf.len());
+        if let Ok((n, _status)) = &result {
+            self.account(&buf.as_bytes()[..*n]);
+        }
+        result
     }
 }
 
 impl ReadText for InputTextStream {
     #[inline]
     fn read_text_substr(&mut self, buf: &mut TextSubstr) -> io::Result<usize> {
-        self.reader.read_text_substr(buf)
+        let result = self.text().read_text_substr(buf);
+        let result = self.telemetry.transfer(result);
+        let result = self.end.read(result, buf.len());
+        let result = self.read_ahead.read(result, buf.len());
+        if let Ok(n) = result {
+            self.account(&buf.as_str().as_bytes()[..n]);
+        }
+        result
     }
 
     #[inline]
     fn read_exact_text_substr(&mut self, buf: &mut TextSubstr) -> io::Result<()> {
-        self.reader.read_exact_text_substr(buf)
+        let result = self.text().read_exact_text_substr(buf);
+        let result = self.telemetry.transfer_all(result, buf.len());
+        let result = self.end.read_exact(result);
+        let result = self.read_ahead.read_exact(result);
+        if result.is_ok() {
+            self.account(buf.as_str().as_bytes());
+        }
+        result
     }
 }
 
@@ -187,12 +751,26 @@ impl ReadTextLayered for InputTextStream {
         &mut self,
         buf: &mut TextSubstr,
     ) -> io::Result<(usize, Status)> {
-        self.reader.read_text_substr_with_status(buf)
+        let result = self.text().read_text_substr_with_status(buf);
+        let result = self.telemetry.transfer_with_status(result);
+        let result = self.end.read_with_status(result);
+        let result = self.read_ahead.read_with_status(result, buf.len());
+        if let Ok((n, _status)) = &result {
+            self.account(&buf.as_str().as_bytes()[..*n]);
+        }
+        result
     }
 
     #[inline]
     fn read_exact_text_substr_using_status(&mut self, buf: &mut TextSubstr) -> io::Result<Status> {
-        self.reader.read_exact_text_substr_using_status(buf)
+        let result = self.text().read_exact_text_substr_using_status(buf);
+        let result = self.telemetry.transfer_all(result, buf.len());
+        let result = self.end.read_exact_with_status(result);
+        let result = self.read_ahead.read_exact_with_status(result);
+        if result.is_ok() {
+            self.account(buf.as_str().as_bytes());
+        }
+        result
     }
 }
 
@@ -231,3 +809,168 @@ fn data_url_base64() {
     .unwrap();
     assert_eq!(s, "Hello, World!\n");
 }
+
+#[test]
+fn read_to_text_string_boundaries() {
+    use std::fs;
+
+    // Place a multi-byte scalar value straddling the first chunk boundary,
+    // a CRLF straddling the second, and a CR at the end of the third.
+    let mut contents = Vec::new();
+    contents.resize(MAX_BULK_CHUNK_SIZE - 1, b'a');
+    contents.extend_from_slice("\u{e9}\u{4e16}".as_bytes());
+    contents.resize(2 * MAX_BULK_CHUNK_SIZE - 1, b'b');
+    contents.extend_from_slice(b"\r\n");
+    contents.resize(3 * MAX_BULK_CHUNK_SIZE - 1, b'c');
+    contents.extend_from_slice(b"\rd\n");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("boundaries.txt");
+    fs::write(&path, &contents).unwrap();
+
+    let open = || {
+        InputTextStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap()
+    };
+
+    let mut expected = String::new();
+    open().read_to_string(&mut expected).unwrap();
+
+    let mut bytes = Vec::new();
+    let n = open().read_to_text_bytes(&mut bytes).unwrap();
+    assert_eq!(n, expected.len());
+    assert!(bytes == expected.as_bytes());
+
+    let mut text = TextString::new();
+    let n = open().read_to_text_string(&mut text).unwrap();
+    assert_eq!(n, expected.len());
+    assert!(text.as_str() == expected);
+
+    // Text is appended after what's already in `out`.
+    let n = open().read_to_text_string(&mut text).unwrap();
+    assert_eq!(n, expected.len());
+    assert!(text.as_str() == expected.repeat(2));
+}
+
+#[test]
+fn read_to_text_string_bypasses_layers() {
+    use std::fs;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bypass.txt");
+    let open = || {
+        InputTextStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap()
+    };
+
+    for contents in [
+        &b""[..],
+        b"hello\nworld\n",
+        b"no final newline",
+        b"\xef\xbb\xbfbyte order mark\n",
+        b"crlf\r\nline endings\r\n",
+        b"invalid \xff utf-8\n",
+        b"\x1b[31mescape sequences\x1b[0m\n",
+        b"\xcc\x81leading combining mark\n",
+        b"not nfc: e\xcc\x81\n",
+    ] {
+        fs::write(&path, contents).unwrap();
+
+        let mut expected = String::new();
+        open().read_to_string(&mut expected).unwrap();
+
+        let mut input = open();
+        let mut text = TextString::new();
+        let n = input.read_to_text_string(&mut text).unwrap();
+        assert_eq!(n, expected.len());
+        assert!(text.as_str() == expected);
+        // The text layers were never created.
+        assert!(input.reader.is_none());
+
+        // After a read through the layers, the rest comes through them too.
+        let mut input = open();
+        let mut first = [0; basic_text::NORMALIZATION_BUFFER_SIZE];
+        let mut text = TextString::new();
+        let n = loop {
+            match input.read(&mut first) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result.unwrap(),
+            }
+        };
+        input.read_to_text_string(&mut text).unwrap();
+        assert!(input.reader.is_some());
+        assert!([&first[..n], text.as_bytes()].concat() == expected.as_bytes());
+    }
+}
+
+#[test]
+fn bulk_chunk_sizes() {
+    assert_eq!(bulk_chunk_size(None), MAX_BULK_CHUNK_SIZE);
+    assert_eq!(bulk_chunk_size(Some(0)), MIN_BULK_CHUNK_SIZE);
+    assert_eq!(bulk_chunk_size(Some(100_000)), 100_000);
+    assert_eq!(bulk_chunk_size(Some(u64::MAX)), MAX_BULK_CHUNK_SIZE);
+}
+
+#[test]
+fn accounting() {
+    use std::fs;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("accounting.txt");
+    let contents = "hello\nworld\nlonger line\n".repeat(20) + "end";
+    fs::write(&path, &contents).unwrap();
+
+    let mut input =
+        InputTextStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    assert_eq!(input.accounting(), None);
+    input.enable_accounting();
+
+    // Read in small pieces, so that lines straddle reads.
+    let mut buf = [0; basic_text::NORMALIZATION_BUFFER_SIZE];
+    while input.read(&mut buf).unwrap() != 0 {}
+
+    // The text layers add a newline at the end.
+    let accounting = input.accounting().unwrap();
+    assert_eq!(accounting.bytes(), contents.len() as u64 + 1);
+    assert_eq!(accounting.lines(), 61);
+    assert_eq!(accounting.max_line_len(), 11);
+}
+
+#[test]
+fn text_memory_budget() {
+    use crate::memory_budget::{exceeded_feature, test_budget, with_test_budget};
+
+    let dir = tempfile::tempdir().unwrap();
+    let small = dir.path().join("small.txt");
+    let big = dir.path().join("big.txt");
+    std::fs::write(&small, "line\n".repeat(8)).unwrap();
+    std::fs::write(&big, "line\n".repeat(100)).unwrap();
+    let open =
+        |name: &str| InputTextStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority());
+    let small = small.to_str().unwrap();
+    let big = big.to_str().unwrap();
+
+    let ((small_read, (big_read, big_text), fragment), warnings) =
+        with_test_budget(test_budget(64), || {
+            let mut bytes = Vec::new();
+            let small_read = open(small).unwrap().read_to_text_bytes(&mut bytes);
+            let big_read = open(big).unwrap().read_to_text_bytes(&mut Vec::new());
+            let big_text = open(big)
+                .unwrap()
+                .read_to_text_string(&mut TextString::new());
+            let fragment = open(&format!("{}#L2", big)).map(|_| ());
+            (small_read, (big_read, big_text), fragment)
+        });
+    assert_eq!(small_read.unwrap(), 40);
+    assert_eq!(warnings, ["read_to_text_bytes"]);
+    assert_eq!(
+        exceeded_feature(&big_read.unwrap_err().into()),
+        Some("read_to_text_bytes")
+    );
+    assert_eq!(
+        exceeded_feature(&big_text.unwrap_err().into()),
+        Some("read_to_text_string")
+    );
+    assert_eq!(
+        exceeded_feature(&fragment.unwrap_err()),
+        Some("fragment resolution")
+    );
+}
@@ -1,15 +1,26 @@
-use crate::open_interactive::{open_interactive, Interactive};
-use crate::Pseudonym;
+use crate::boxed::{share, CloseHandle, SharedReader, SharedWriter};
+use crate::drop_error::close_on_drop;
+use crate::lazy_interactive::FromLazyInteractive;
+use crate::open_interactive::{open_interactive, pty_read_result, Interactive, InteractiveChild};
+#[cfg(all(feature = "poll", unix))]
+use crate::poll::PollHandle;
+use crate::source_sink::{Sink, Source};
+use crate::unit::read_unit;
+use crate::{MediaType, OpenPolicy, Pseudonym, StreamKind, UnitEnd};
 use clap::{AmbientAuthority, TryFromOsArg};
 use duplex::Duplex;
+#[cfg(all(feature = "poll", unix))]
+use io_extras::os::rustix::AsReadWriteFd;
 use io_streams::StreamDuplexer;
 use layered_io::{
     default_read, default_read_to_end, default_read_to_string, default_read_vectored, Bufferable,
     LayeredDuplexer, ReadLayered, Status, WriteLayered,
 };
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Arguments, Debug, Formatter};
 use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+#[cfg(all(feature = "poll", unix))]
+use std::os::fd::BorrowedFd;
 use terminal_io::{
     DuplexTerminal, NeverTerminalDuplexer, ReadTerminal, Terminal, TerminalColorSupport,
     WriteTerminal,
@@ -25,13 +36,36 @@ use terminal_io::{
 ///  - Names starting with `connect:` or `accept:`, which are interpreted as
 ///    socket addresses to connect to or accept from. Socket addresses may
 ///    contain host:port pairs or, on platforms which support it, filesystem
-///    paths to Unix-domain sockets.
+///    paths to Unix-domain sockets. `accept:` names may have an
+///    `accept_timeout` option, such as `accept://127.0.0.1:7070?accept_timeout=5s`,
+///    after which opening fails, or with `fallback=stdio` as well, opens
+///    the pair (stdin, stdout) instead, under a name ending in
+///    `(fell back to stdio)`.
 ///  - "-" is interpreted as the pair (stdin, stdout).
 ///  - "(...)" runs a command with pipes to and from the child process' (stdin,
 ///    stdout), on platforms whch support it.
+///  - "$(...)?pty" runs a command with a pseudo-terminal as its stdin,
+///    stdout, and stderr, on Unix-family platforms, for programs which
+///    behave differently when they aren't run in a terminal. The terminal
+///    echoes input and translates "\n" to "\r\n" in output, as terminals
+///    do. When the child exits, reads report the end of the stream.
+///
+/// Opening an `accept:` name binds a port and waits for a connection, so
+/// programs which shouldn't do that while parsing their arguments can take
+/// a [`LazyInteractive`] argument instead, and open the stream with
+/// [`LazyInteractive::materialize`] when they're ready.
+///
+/// [`LazyInteractive`]: crate::LazyInteractive
+/// [`LazyInteractive::materialize`]: crate::LazyInteractive::materialize
 pub struct InteractiveByteStream {
     name: String,
+    kind: StreamKind,
+    #[cfg(all(feature = "poll", unix))]
+    poll: PollHandle,
     duplexer: LayeredDuplexer<NeverTerminalDuplexer<StreamDuplexer>>,
+    // This is declared after `duplexer` so that the master side of the
+    // pseudo-terminal is closed before the child is reaped.
+    child: Option<InteractiveChild>,
 }
 
 impl InteractiveByteStream {
@@ -42,12 +76,90 @@ impl InteractiveByteStream {
         Pseudonym::new(self.name.clone())
     }
 
+    /// Consume `self` and return its reading and writing halves as boxed
+    /// `dyn Read` and `dyn Write`, for passing to APIs which take them,
+    /// along with a [`CloseHandle`] for closing the stream.
+    ///
+    /// The halves share the underlying stream, so a read which is waiting
+    /// for input holds up writes through the other half until it
+    /// completes. They work best used from one thread, alternating writes
+    /// and reads.
+    ///
+    /// Call [`CloseHandle::close`] once the halves are no longer needed, to
+    /// close the stream and get any errors. Dropping the halves and the
+    /// handle without calling `close` only cleans up on a best-effort basis.
+    pub fn into_boxed_halves(self) -> (Box<dyn Read + Send>, Box<dyn Write + Send>, CloseHandle) {
+        let shared = share(self);
+        let handle = CloseHandle::new(shared.clone());
+        (
+            Box::new(SharedReader(shared.clone())),
+            Box::new(SharedWriter(shared)),
+            handle,
+        )
+    }
+
+    /// Return the file descriptor this stream reads from, for waiting on it
+    /// with `poll` or similar alongside other file descriptors.
+    ///
+    /// Readiness means a read won't block, not that any particular amount
+    /// of input is available; a read may return less than a full message,
+    /// or report the end of the stream. `InteractiveByteStream` doesn't
+    /// buffer input, so [`Self::has_buffered_input`] is always false, but
+    /// an event loop which is also used with [`InteractiveTextStream`]s can
+    /// check it uniformly before waiting.
+    ///
+    /// This requires the "poll" feature, and is only available on Unix-family
+    /// platforms.
+    ///
+    /// [`InteractiveTextStream`]: crate::InteractiveTextStream
+    #[cfg(all(feature = "poll", unix))]
+    #[inline]
+    pub fn as_poll_handle(&self) -> Option<BorrowedFd<'_>> {
+        self.poll.direct()
+    }
+
+    /// Return whether this stream may hold input it has already taken from
+    /// its source, which waiting on its file descriptor wouldn't see. This
+    /// is always false.
+    ///
+    /// This requires the "poll" feature, and is only available on Unix-family
+    /// platforms.
+    #[cfg(all(feature = "poll", unix))]
+    #[inline]
+    pub fn has_buffered_input(&self) -> bool {
+        false
+    }
+
+    /// Read until the end of a unit, appending to `buf`, and return whether
+    /// the unit ended with a push or with the end of the stream.
+    ///
+    /// A unit ends where [`ReadLayered::read_with_status`] reports a push
+    /// or the end of the stream. Sockets, pipes, and child processes don't
+    /// carry pushes, so over them a unit is the rest of the stream; to
+    /// exchange several messages over them, use a framing such as `Framed`,
+    /// with the "codecs" feature.
+    ///
+    /// A unit of more than `max` bytes is read and discarded, leaving `buf`
+    /// as it was, and fails with [`UnitTooLarge`].
+    ///
+    /// [`UnitTooLarge`]: crate::UnitTooLarge
+    #[inline]
+    pub fn read_unit(&mut self, buf: &mut Vec<u8>, max: usize) -> io::Result<UnitEnd> {
+        read_unit(self, buf, max)
+    }
+
     fn from_interactive(interactive: Interactive) -> Self {
+        #[cfg(all(feature = "poll", unix))]
+        let poll = PollHandle::new(interactive.duplexer.as_read_fd(), false);
         let duplexer = NeverTerminalDuplexer::new(interactive.duplexer);
         let duplexer = LayeredDuplexer::new(duplexer);
         Self {
             name: interactive.name,
+            kind: interactive.kind,
+            #[cfg(all(feature = "poll", unix))]
+            poll,
             duplexer,
+            child: interactive.child,
         }
     }
 }
@@ -66,14 +178,56 @@ impl TryFromOsArg for InteractiveByteStream {
         os: &OsStr,
         ambient_authority: AmbientAuthority,
     ) -> anyhow::Result<Self> {
-        open_interactive(os, ambient_authority).map(Self::from_interactive)
+        open_interactive(os, &OpenPolicy::default(), ambient_authority).map(Self::from_interactive)
+    }
+}
+
+impl FromLazyInteractive for InteractiveByteStream {
+    type Err = anyhow::Error;
+
+    #[inline]
+    fn from_lazy_interactive(
+        name: OsString,
+        policy: &OpenPolicy,
+        ambient_authority: AmbientAuthority,
+    ) -> anyhow::Result<Self> {
+        open_interactive(&name, policy, ambient_authority).map(Self::from_interactive)
+    }
+}
+
+impl Source for InteractiveByteStream {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        None
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<u64> {
+        None
+    }
+
+    #[inline]
+    fn kind(&self) -> StreamKind {
+        self.kind
+    }
+}
+
+impl Sink for InteractiveByteStream {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        None
+    }
+
+    #[inline]
+    fn close_boxed(&mut self) -> io::Result<()> {
+        self.close()
     }
 }
 
 impl ReadLayered for InteractiveByteStream {
     #[inline]
     fn read_with_status(&mut self, buf: &mut [u8]) -> io::Result<(usize, Status)> {
-        self.duplexer.read_with_status(buf)
+        pty_read_result(&self.child, self.duplexer.read_with_status(buf))
     }
 
     #[inline]
@@ -81,7 +235,7 @@ impl ReadLayered for InteractiveByteStream {
         &mut self,
         bufs: &mut [IoSliceMut<'_>],
     ) -> io::Result<(usize, Status)> {
-        self.duplexer.read_vectored_with_status(bufs)
+        pty_read_result(&self.child, self.duplexer.read_vectored_with_status(bufs))
     }
 }
 
@@ -201,6 +355,16 @@ impl DuplexTerminal for InteractiveByteStream {}
 
 impl Duplex for InteractiveByteStream {}
 
+impl Drop for InteractiveByteStream {
+    fn drop(&mut self) {
+        // Close the duplexer, flushing any buffered output. We can't return
+        // `Err` from a `drop` function, so errors are reported with
+        // `report_drop_error`. Callers should use `close()` to end the stream
+        // if they wish to handle these errors.
+        close_on_drop(&mut self.duplexer);
+    }
+}
+
 impl Debug for InteractiveByteStream {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         // Don't print the name here, as that's an implementation detail.
@@ -208,3 +372,20 @@ impl Debug for InteractiveByteStream {
         b.finish()
     }
 }
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn child_pty() {
+    let run = |name: &str| {
+        let mut stream =
+            InteractiveByteStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority())
+                .unwrap();
+        let mut output = String::new();
+        stream.read_to_string(&mut output).unwrap();
+        output
+    };
+
+    let script = "$(sh -c 'if [ -t 0 ]; then echo tty; else echo notty; fi')";
+    assert_eq!(run(script), "notty\n");
+    assert_eq!(run(&format!("{}?pty", script)), "tty\r\n");
+}
@@ -1,13 +1,24 @@
-use crate::open_interactive::{open_interactive, Interactive};
-use crate::Pseudonym;
+use crate::drop_error::close_on_drop;
+use crate::lazy_interactive::FromLazyInteractive;
+use crate::open_interactive::{open_interactive, pty_read_result, Interactive, InteractiveChild};
+#[cfg(all(feature = "poll", unix))]
+use crate::poll::PollHandle;
+use crate::poll::ReadAhead;
+use crate::source_sink::{Sink, Source};
+use crate::unit::read_unit;
+use crate::{ColorChoice, MediaType, OpenPolicy, Pseudonym, StreamKind, UnitEnd};
 use basic_text::TextDuplexer;
 use clap::{AmbientAuthority, TryFromOsArg};
 use duplex::Duplex;
+#[cfg(not(any(windows, target_os = "wasi")))]
+use io_extras::os::rustix::AsReadWriteFd;
 use io_streams::StreamDuplexer;
 use layered_io::{Bufferable, LayeredDuplexer, ReadLayered, Status, WriteLayered};
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Arguments, Debug, Formatter};
 use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+#[cfg(not(any(windows, target_os = "wasi")))]
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
 use terminal_io::{
     DuplexTerminal, ReadTerminal, Terminal, TerminalColorSupport, TerminalDuplexer, WriteTerminal,
 };
@@ -23,20 +34,56 @@ use utf8_io::{ReadStr, ReadStrLayered, Utf8Duplexer, WriteStr};
 ///  - Names starting with `connect:` or `accept:`, which are interpreted as
 ///    socket addresses to connect to or accept from. Socket addresses may
 ///    contain host:port pairs or, on platforms which support it, filesystem
-///    paths to Unix-domain sockets.
+///    paths to Unix-domain sockets. `accept:` names may have an
+///    `accept_timeout` option, such as `accept://127.0.0.1:7070?accept_timeout=5s`,
+///    after which opening fails, or with `fallback=stdio` as well, opens
+///    the pair (stdin, stdout) instead, under a name ending in
+///    `(fell back to stdio)`.
 ///  - "-" is interpreted as the pair (stdin, stdout).
 ///  - "(...)" runs a command with pipes to and from the child process' (stdin,
 ///    stdout), on platforms whch support it.
+///  - "$(...)?pty" runs a command with a pseudo-terminal as its stdin,
+///    stdout, and stderr, on Unix-family platforms, for programs which
+///    behave differently when they aren't run in a terminal. When the child
+///    exits, reads report the end of the stream.
+///
+/// Opening an `accept:` name binds a port and waits for a connection, so
+/// programs which shouldn't do that while parsing their arguments can take
+/// a [`LazyInteractive`] argument instead, and open the stream with
+/// [`LazyInteractive::materialize`] when they're ready.
+///
+/// Whatever the syntax, ANSI color escape sequences in the output are passed
+/// through when the output is a terminal which supports color, and stripped
+/// otherwise.
+///
+/// To prompt for a password or other secret without echoing it, use
+/// [`ReadSecret`].
+///
+/// [`ReadSecret`]: crate::ReadSecret
+/// [`LazyInteractive`]: crate::LazyInteractive
+/// [`LazyInteractive::materialize`]: crate::LazyInteractive::materialize
 pub struct InteractiveTextStream {
     name: String,
+    kind: StreamKind,
+    #[cfg(all(feature = "poll", unix))]
+    poll: PollHandle,
+    /// When the stream reads from a terminal, a duplicate of its file
+    /// descriptor, for changing the terminal's settings while the stream is
+    /// in use.
+    #[cfg(not(any(windows, target_os = "wasi")))]
+    input_terminal: Option<OwnedFd>,
     duplexer: TextDuplexer<Utf8Duplexer<LayeredDuplexer<TerminalDuplexer<StreamDuplexer>>>>,
+    read_ahead: ReadAhead,
+    // This is declared after `duplexer` so that the master side of the
+    // pseudo-terminal is closed before the child is reaped.
+    child: Option<InteractiveChild>,
 }
 
 impl InteractiveTextStream {
     /// Write the given `Pseudonym` to the output stream.
     #[inline]
     pub fn write_pseudonym(&mut self, pseudonym: &Pseudonym) -> io::Result<()> {
-        Write::write_all(self, pseudonym.name.as_bytes())
+        Write::write_all(self, pseudonym.display_name().as_bytes())
     }
 
     /// Return a `Pseudonym` which encapsulates this stream's name (typically
@@ -47,17 +94,97 @@ impl InteractiveTextStream {
         Pseudonym::new(self.name.clone())
     }
 
-    fn from_interactive(interactive: Interactive) -> Self {
+    /// Return the file descriptor this stream reads from, for waiting on it
+    /// with `poll` or similar alongside other file descriptors. Readiness
+    /// doesn't imply that a full line is available.
+    ///
+    /// Before waiting, check [`Self::has_buffered_input`], because input the
+    /// stream has already taken from the file descriptor won't make it ready.
+    ///
+    /// This requires the "poll" feature, and is only available on Unix-family
+    /// platforms.
+    #[cfg(all(feature = "poll", unix))]
+    #[inline]
+    pub fn as_poll_handle(&self) -> Option<BorrowedFd<'_>> {
+        self.poll.direct()
+    }
+
+    /// Return whether this stream may hold decoded input which waiting on
+    /// its file descriptor wouldn't see, so that an event loop should read
+    /// again before waiting.
+    ///
+    /// See [`InputTextStream::has_buffered_input`] for details.
+    ///
+    /// This requires the "poll" feature, and is only available on Unix-family
+    /// platforms.
+    ///
+    /// [`InputTextStream::has_buffered_input`]: crate::InputTextStream::has_buffered_input
+    #[cfg(all(feature = "poll", unix))]
+    #[inline]
+    pub fn has_buffered_input(&self) -> bool {
+        self.read_ahead.buffered()
+    }
+
+    /// Read until the end of a unit, appending to `buf`, and return whether
+    /// the unit ended with a push or with the end of the stream.
+    ///
+    /// This is like [`InteractiveByteStream::read_unit`], with `max`
+    /// limiting the number of bytes of text in the unit.
+    ///
+    /// [`InteractiveByteStream::read_unit`]: crate::InteractiveByteStream::read_unit
+    pub fn read_unit_to_string(&mut self, buf: &mut String, max: usize) -> io::Result<UnitEnd> {
+        let mut bytes = Vec::new();
+        let end = read_unit(self, &mut bytes, max)?;
+        let unit =
+            String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        buf.push_str(&unit);
+        Ok(end)
+    }
+
+    /// Return the file descriptor this stream reads from, if it's a
+    /// terminal.
+    #[cfg(not(any(windows, target_os = "wasi")))]
+    pub(crate) fn input_terminal(&self) -> Option<BorrowedFd<'_>> {
+        self.input_terminal.as_ref().map(OwnedFd::as_fd)
+    }
+
+    fn from_interactive(interactive: Interactive, color: ColorChoice) -> Self {
+        #[cfg(all(feature = "poll", unix))]
+        let poll = PollHandle::new(interactive.duplexer.as_read_fd(), false);
+        #[cfg(not(any(windows, target_os = "wasi")))]
+        let input_terminal = {
+            let fd = interactive.duplexer.as_read_fd();
+            if rustix::termios::isatty(fd) {
+                rustix::io::fcntl_dupfd_cloexec(fd, 0).ok()
+            } else {
+                None
+            }
+        };
         let duplexer = TerminalDuplexer::with_handle(interactive.duplexer);
-        let duplexer = TextDuplexer::new(duplexer);
+        // Decide on color output the same way for every syntax, from the
+        // terminal state.
+        let use_ansi_color =
+            color.use_ansi_color(duplexer.is_output_terminal(), duplexer.color_support());
+        let duplexer = if use_ansi_color {
+            TextDuplexer::with_ansi_color_output(Utf8Duplexer::new(LayeredDuplexer::new(duplexer)))
+        } else {
+            TextDuplexer::new(duplexer)
+        };
         Self {
             name: interactive.name,
+            kind: interactive.kind,
+            #[cfg(all(feature = "poll", unix))]
+            poll,
+            #[cfg(not(any(windows, target_os = "wasi")))]
+            input_terminal,
             duplexer,
+            read_ahead: ReadAhead::default(),
+            child: interactive.child,
         }
     }
 }
 
-/// Implement `FromStr` so that `clap_derive` can parse `InteractiveTextStream`
+/// Implement `TryFromOsArg` so that `clap_derive` can parse `InteractiveTextStream`
 /// arguments automatically.
 ///
 /// This is hidden from the documentation as it opens resources from
@@ -71,14 +198,60 @@ impl TryFromOsArg for InteractiveTextStream {
         os: &OsStr,
         ambient_authority: AmbientAuthority,
     ) -> anyhow::Result<Self> {
-        open_interactive(os, ambient_authority).map(Self::from_interactive)
+        let policy = OpenPolicy::default();
+        open_interactive(os, &policy, ambient_authority)
+            .map(|interactive| Self::from_interactive(interactive, policy.color))
+    }
+}
+
+impl FromLazyInteractive for InteractiveTextStream {
+    type Err = anyhow::Error;
+
+    #[inline]
+    fn from_lazy_interactive(
+        name: OsString,
+        policy: &OpenPolicy,
+        ambient_authority: AmbientAuthority,
+    ) -> anyhow::Result<Self> {
+        open_interactive(&name, policy, ambient_authority)
+            .map(|interactive| Self::from_interactive(interactive, policy.color))
+    }
+}
+
+impl Source for InteractiveTextStream {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        None
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<u64> {
+        None
+    }
+
+    #[inline]
+    fn kind(&self) -> StreamKind {
+        self.kind
+    }
+}
+
+impl Sink for InteractiveTextStream {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        None
+    }
+
+    #[inline]
+    fn close_boxed(&mut self) -> io::Result<()> {
+        self.close()
     }
 }
 
 impl ReadLayered for InteractiveTextStream {
     #[inline]
     fn read_with_status(&mut self, buf: &mut [u8]) -> io::Result<(usize, Status)> {
-        self.duplexer.read_with_status(buf)
+        let result = pty_read_result(&self.child, self.duplexer.read_with_status(buf));
+        self.read_ahead.read_with_status(result, buf.len())
     }
 
     #[inline]
@@ -86,19 +259,24 @@ impl ReadLayered for InteractiveTextStream {
         &mut self,
         bufs: &mut [IoSliceMut<'_>],
     ) -> io::Result<(usize, Status)> {
-        self.duplexer.read_vectored_with_status(bufs)
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        let result = pty_read_result(&self.child, self.duplexer.read_vectored_with_status(bufs));
+        self.read_ahead.read_with_status(result, len)
     }
 }
 
 impl Read for InteractiveTextStream {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.duplexer.read(buf)
+        let result = self.duplexer.read(buf);
+        self.read_ahead.read(result, buf.len())
     }
 
     #[inline]
     fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
-        self.duplexer.read_vectored(bufs)
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        let result = self.duplexer.read_vectored(bufs);
+        self.read_ahead.read(result, len)
     }
 
     #[cfg(can_vector)]
@@ -109,17 +287,20 @@ impl Read for InteractiveTextStream {
 
     #[inline]
     fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
-        self.duplexer.read_to_end(buf)
+        let result = self.duplexer.read_to_end(buf);
+        self.read_ahead.read_to_end(result)
     }
 
     #[inline]
     fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
-        self.duplexer.read_to_string(buf)
+        let result = self.duplexer.read_to_string(buf);
+        self.read_ahead.read_to_end(result)
     }
 
     #[inline]
     fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
-        self.duplexer.read_exact(buf)
+        let result = self.duplexer.read_exact(buf);
+        self.read_ahead.read_exact(result)
     }
 }
 
@@ -221,24 +402,38 @@ impl Bufferable for InteractiveTextStream {
 impl ReadStr for InteractiveTextStream {
     #[inline]
     fn read_str(&mut self, buf: &mut str) -> io::Result<usize> {
-        self.duplexer.read_str(buf)
+        let result = self.duplexer.read_str(buf);
+        self.read_ahead.read(result, buf.len())
     }
 
     #[inline]
     fn read_exact_str(&mut self, buf: &mut str) -> io::Result<()> {
-        self.duplexer.read_exact_str(buf)
+        let result = self.duplexer.read_exact_str(buf);
+        self.read_ahead.read_exact(result)
     }
 }
 
 impl ReadStrLayered for InteractiveTextStream {
     #[inline]
     fn read_str_with_status(&mut self, buf: &mut str) -> io::Result<(usize, Status)> {
-        self.duplexer.read_str_with_status(buf)
+        let result = self.duplexer.read_str_with_status(buf);
+        self.read_ahead.read_with_status(result, buf.len())
     }
 
     #[inline]
     fn read_exact_str_using_status(&mut self, buf: &mut str) -> io::Result<Status> {
-        self.duplexer.read_exact_str_using_status(buf)
+        let result = self.duplexer.read_exact_str_using_status(buf);
+        self.read_ahead.read_exact_with_status(result)
+    }
+}
+
+impl Drop for InteractiveTextStream {
+    fn drop(&mut self) {
+        // Close the duplexer, flushing any buffered output. We can't return
+        // `Err` from a `drop` function, so errors are reported with
+        // `report_drop_error`. Callers should use `close()` to end the stream
+        // if they wish to handle these errors.
+        close_on_drop(&mut self.duplexer);
     }
 }
 
@@ -250,3 +445,46 @@ impl Debug for InteractiveTextStream {
         b.finish()
     }
 }
+
+#[test]
+fn stdin_stdout_color() {
+    // This doesn't panic whether or not stdin and stdout are terminals, and
+    // color is only enabled for terminals.
+    let mut io =
+        InteractiveTextStream::try_from_os_str_arg("-".as_ref(), clap::ambient_authority())
+            .unwrap();
+    if !io.is_output_terminal() {
+        assert_eq!(io.color_support(), TerminalColorSupport::Monochrome);
+    }
+    io.abandon();
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn child_color() {
+    let mut io =
+        InteractiveTextStream::try_from_os_str_arg("$(cat)".as_ref(), clap::ambient_authority())
+            .unwrap();
+    assert!(!io.is_output_terminal());
+    assert_eq!(io.color_support(), TerminalColorSupport::Monochrome);
+    assert!(!io.color_preference());
+    io.close().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn socket_color() {
+    use std::os::unix::net::UnixListener;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("socket");
+    let _listener = UnixListener::bind(&path).unwrap();
+    let mut io = InteractiveTextStream::try_from_os_str_arg(
+        format!("connect:{}", path.display()).as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    assert!(!io.is_output_terminal());
+    assert_eq!(io.color_support(), TerminalColorSupport::Monochrome);
+    io.close().unwrap();
+}
@@ -0,0 +1,220 @@
+use crate::lazy_output::Never;
+use crate::open_interactive::validate_interactive;
+use crate::OpenPolicy;
+use clap::{AmbientAuthority, TryFromOsArg};
+use std::ffi::{OsStr, OsString};
+use std::marker::PhantomData;
+
+#[doc(hidden)]
+pub trait FromLazyInteractive {
+    type Err;
+
+    fn from_lazy_interactive(
+        name: OsString,
+        policy: &OpenPolicy,
+        ambient_authority: AmbientAuthority,
+    ) -> Result<Self, Self::Err>
+    where
+        Self: Sized;
+}
+
+/// A placeholder for an interactive stream which is opened lazily. It is
+/// opened when `materialize` is called.
+///
+/// Opening an interactive stream can bind a port and wait for a connection,
+/// as with `accept://localhost:9999`, which is better done once a program
+/// is ready to serve, rather than while it's parsing its arguments. Using
+/// `LazyInteractive<InteractiveByteStream>` or
+/// `LazyInteractive<InteractiveTextStream>` as an argument type defers
+/// this to [`materialize`].
+///
+/// [`materialize`]: Self::materialize
+pub struct LazyInteractive<T: FromLazyInteractive> {
+    name: OsString,
+    policy: OpenPolicy,
+    ambient_authority: AmbientAuthority,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: FromLazyInteractive> LazyInteractive<T> {
+    /// Construct a placeholder for an interactive stream with the given
+    /// name.
+    #[inline]
+    pub fn new(name: OsString, ambient_authority: AmbientAuthority) -> Self {
+        Self {
+            name,
+            policy: OpenPolicy::default(),
+            ambient_authority,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Use `policy` to validate and materialize the stream, instead of the
+    /// default policy.
+    #[inline]
+    pub fn with_policy(mut self, policy: OpenPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Consume `self` and materialize an interactive stream, binding,
+    /// accepting, connecting, spawning, or opening as its name says.
+    #[inline]
+    pub fn materialize(self) -> Result<T, T::Err> {
+        T::from_lazy_interactive(self.name, &self.policy, self.ambient_authority)
+    }
+
+    /// Check whether the name is a supported interactive stream syntax,
+    /// without binding, connecting, spawning, or opening anything.
+    ///
+    /// This checks the syntax and options of `accept:` and `connect:` URLs
+    /// and `$(...)` commands, and that the syntax is permitted. It doesn't
+    /// check whether addresses are available or paths exist, so
+    /// materializing may still fail after a successful validation.
+    #[inline]
+    pub fn validate(&self) -> anyhow::Result<()> {
+        validate_interactive(&self.name, &self.policy)
+    }
+}
+
+impl<T: FromLazyInteractive> TryFromOsArg for LazyInteractive<T> {
+    type Error = Never;
+
+    #[inline]
+    fn try_from_os_str_arg(os: &OsStr, ambient_authority: AmbientAuthority) -> Result<Self, Never> {
+        Ok(Self::new(os.to_owned(), ambient_authority))
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[test]
+fn lazy_accept_does_not_bind() {
+    use crate::InteractiveByteStream;
+    use layered_io::WriteLayered;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    // Find a free port.
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let name = format!("accept://127.0.0.1:{}", port);
+
+    let lazy = LazyInteractive::<InteractiveByteStream>::try_from_os_str_arg(
+        name.as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    lazy.validate().unwrap();
+
+    // Neither constructing nor validating binds the port.
+    drop(TcpListener::bind(("127.0.0.1", port)).unwrap());
+
+    let client = thread::spawn(move || loop {
+        match TcpStream::connect(("127.0.0.1", port)) {
+            Ok(mut stream) => {
+                stream.write_all(b"hello").unwrap();
+                return;
+            }
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    });
+    let mut stream = lazy.materialize().unwrap();
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    client.join().unwrap();
+    stream.close().unwrap();
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[test]
+fn lazy_connect_text() {
+    use crate::InteractiveTextStream;
+    use layered_io::WriteLayered;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let name = format!("connect://{}", listener.local_addr().unwrap());
+    let lazy =
+        LazyInteractive::<InteractiveTextStream>::new(name.into(), clap::ambient_authority());
+    lazy.validate().unwrap();
+    let mut stream = lazy.materialize().unwrap();
+    let (mut peer, _addr) = listener.accept().unwrap();
+    writeln!(stream, "hello").unwrap();
+    stream.close().unwrap();
+    drop(stream);
+
+    let mut s = String::new();
+    std::io::Read::read_to_string(&mut peer, &mut s).unwrap();
+    assert_eq!(s, "hello\n");
+}
+
+#[test]
+fn lazy_validate_errors() {
+    use crate::InteractiveByteStream;
+
+    let validate = |name: &str| {
+        LazyInteractive::<InteractiveByteStream>::new(name.into(), clap::ambient_authority())
+            .validate()
+            .map_err(|err| err.to_string())
+    };
+
+    assert_eq!(validate("-"), Ok(()));
+    assert_eq!(
+        validate("https://example.com/"),
+        Err("non-interactive URL scheme \"https\"".to_owned())
+    );
+    assert_eq!(
+        validate("gopher://example.com/"),
+        Err("unsupported URL scheme \"gopher\"".to_owned())
+    );
+    #[cfg(not(target_os = "wasi"))]
+    {
+        assert_eq!(
+            validate("accept://127.0.0.1"),
+            Err("accept URL should have a port".to_owned())
+        );
+        assert_eq!(
+            validate("accept://127.0.0.1:9999?fallback=stdio"),
+            Err("accept fallback requires an accept_timeout".to_owned())
+        );
+        assert_eq!(
+            validate("connect://127.0.0.1"),
+            Err("TCP connect URL should have a port".to_owned())
+        );
+        validate("accept://127.0.0.1:9999?accept_timeout=5s").unwrap();
+    }
+    #[cfg(not(any(windows, target_os = "wasi")))]
+    {
+        validate("$(cat)?pty").unwrap();
+        assert!(validate("$(cat)?ptty").is_err());
+    }
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn lazy_policy() {
+    use crate::InteractiveTextStream;
+
+    let policy = OpenPolicy {
+        allow_exec: false,
+        ..OpenPolicy::default()
+    };
+    let lazy =
+        LazyInteractive::<InteractiveTextStream>::new("$(cat)".into(), clap::ambient_authority())
+            .with_policy(policy);
+    assert_eq!(
+        lazy.validate().unwrap_err().to_string(),
+        "child processes are disabled by policy"
+    );
+    assert_eq!(
+        lazy.materialize().err().unwrap().to_string(),
+        "child processes are disabled by policy"
+    );
+}
@@ -1,4 +1,5 @@
-use crate::MediaType;
+use crate::open_output::validate_output;
+use crate::{MediaType, OpenError, OpenPolicy, OutputValidation};
 use clap::{AmbientAuthority, TryFromOsArg};
 use std::error::Error;
 use std::ffi::{OsStr, OsString};
@@ -24,6 +25,16 @@ pub trait FromLazyOutput {
     fn from_lazy_output(
         name: OsString,
         media_type: MediaType,
+        policy: &OpenPolicy,
+        ambient_authority: AmbientAuthority,
+    ) -> Result<Self, Self::Err>
+    where
+        Self: Sized;
+
+    fn from_lazy_output_dry_run(
+        name: OsString,
+        media_type: MediaType,
+        policy: &OpenPolicy,
         ambient_authority: AmbientAuthority,
     ) -> Result<Self, Self::Err>
     where
@@ -34,15 +45,70 @@ pub trait FromLazyOutput {
 /// when `materialize` is called.
 pub struct LazyOutput<T: FromLazyOutput> {
     name: OsString,
+    policy: OpenPolicy,
     ambient_authority: AmbientAuthority,
     _phantom: PhantomData<T>,
 }
 
 impl<T: FromLazyOutput> LazyOutput<T> {
+    /// Construct a placeholder for an output stream with the given name,
+    /// such as one derived from an input with
+    /// [`Pseudonym::with_extension`].
+    ///
+    /// [`Pseudonym::with_extension`]: crate::Pseudonym::with_extension
+    #[inline]
+    pub fn new(name: OsString, ambient_authority: AmbientAuthority) -> Self {
+        Self {
+            name,
+            policy: OpenPolicy::default(),
+            ambient_authority,
+            _phantom: PhantomData::default(),
+        }
+    }
+
+    /// Use `policy` to validate and materialize the stream, instead of the
+    /// default policy.
+    #[inline]
+    pub fn with_policy(mut self, policy: OpenPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
     /// Consume `self` and materialize an output stream.
     #[inline]
     pub fn materialize(self, media_type: MediaType) -> Result<T, T::Err> {
-        T::from_lazy_output(self.name, media_type, self.ambient_authority)
+        T::from_lazy_output(self.name, media_type, &self.policy, self.ambient_authority)
+    }
+
+    /// Check whether the output could be opened, without creating,
+    /// truncating, or writing anything.
+    ///
+    /// For files, this checks that the file, or if it doesn't exist its
+    /// parent directory, is writable, and that the filesystem isn't full.
+    /// For other syntaxes, it checks that the syntax is supported and
+    /// permitted.
+    ///
+    /// The filesystem can change between validating and materializing, so
+    /// materializing may still fail after a successful validation.
+    ///
+    /// Failures are reported as [`OpenError::ValidationFailed`], unless they
+    /// are another [`OpenError`].
+    pub fn validate(&self) -> Result<OutputValidation, OpenError> {
+        validate_output(&self.name, &self.policy).map_err(|err| match err.downcast::<OpenError>() {
+            Ok(err) => err,
+            Err(err) => OpenError::ValidationFailed(err),
+        })
+    }
+
+    /// Consume `self` and materialize an output stream for a dry run. The
+    /// output is validated as with [`validate`], and then everything
+    /// written to the stream is discarded, so nothing is created or
+    /// modified.
+    ///
+    /// [`validate`]: Self::validate
+    #[inline]
+    pub fn materialize_dry_run(self, media_type: MediaType) -> Result<T, T::Err> {
+        T::from_lazy_output_dry_run(self.name, media_type, &self.policy, self.ambient_authority)
     }
 }
 
@@ -51,10 +117,29 @@ impl<T: FromLazyOutput> TryFromOsArg for LazyOutput<T> {
 
     #[inline]
     fn try_from_os_str_arg(os: &OsStr, ambient_authority: AmbientAuthority) -> Result<Self, Never> {
-        Ok(Self {
-            name: os.to_owned(),
-            ambient_authority,
-            _phantom: PhantomData::default(),
-        })
+        Ok(Self::new(os.to_owned(), ambient_authority))
     }
 }
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn lazy_policy() {
+    use crate::OutputByteStream;
+
+    let policy = OpenPolicy {
+        allow_exec: false,
+        ..OpenPolicy::default()
+    };
+    let lazy = LazyOutput::<OutputByteStream>::new("$(cat)".into(), clap::ambient_authority())
+        .with_policy(policy);
+    let err = lazy.validate().unwrap_err();
+    assert!(matches!(err, OpenError::ValidationFailed(_)));
+    assert_eq!(err.to_string(), "child processes are disabled by policy");
+    assert_eq!(
+        lazy.materialize(MediaType::unknown())
+            .err()
+            .unwrap()
+            .to_string(),
+        "child processes are disabled by policy"
+    );
+}
@@ -22,6 +22,94 @@
 //! [`InputTextStream`]: https://docs.rs/nameless/latest/nameless/struct.InputTextStream.html
 //! [`OutputTextStream`]: https://docs.rs/nameless/latest/nameless/struct.OutputTextStream.html
 //! [`InteractiveTextStream`]: https://docs.rs/nameless/latest/nameless/struct.InteractiveTextStream.html
+//!
+//! # Custom argument types
+//!
+//! Stream types are converted from command-line arguments with
+//! [`TryFromOsArg`], which takes an [`AmbientAuthority`] to mark that the
+//! conversion opens resources by name. Programs may implement it for their
+//! own types, such as wrappers around nameless streams, to use them as
+//! `kommand` or `clap_derive` arguments. Implementations should pass the
+//! `AmbientAuthority` along to any streams they open.
+//!
+//! [`TryFromOsArg`]: https://docs.rs/nameless/latest/nameless/trait.TryFromOsArg.html
+//! [`AmbientAuthority`]: https://docs.rs/nameless/latest/nameless/struct.AmbientAuthority.html
+//!
+//! # Closing streams
+//!
+//! Streams release their resources when they're dropped, independently of
+//! each other, so they can be dropped in any order. No stream's `drop` waits
+//! indefinitely on another process, with the one exception of the pager
+//! that `OutputTextStream` may run on a terminal, which is waited for until
+//! the user quits it. By kind:
+//!
+//!  - Files are closed, releasing any `lock=` locks.
+//!  - Standard input and output are released.
+//!  - HTTP connections are kept for reuse by later streams if the response
+//!    was read to the end, and closed otherwise. See [`set_http_pool`].
+//!    Responses being stored in the cache set with [`set_http_cache`] are
+//!    only stored if they were read to the end.
+//!  - Child process inputs, from `$(...)` and pipelines, wait for the
+//!    children at the end of the stream, reporting failures in the stream's
+//!    `end_status`. If the stream is dropped before its end, the pipe from
+//!    the last child is closed, and children which don't exit within two
+//!    seconds are killed. Either way, they're reaped.
+//!  - Child process outputs close the child's stdin, and the child is
+//!    killed if it doesn't exit within two seconds, and reaped.
+//!  - Interactive child processes have their stdin closed, and are reaped.
+//!  - Clipboard outputs set the clipboard.
+//!  - HTTP upload outputs send the closing multipart boundary and wait for
+//!    the response, reporting non-2xx statuses.
+//!  - Text outputs shown in a pager print any status lines held up by
+//!    their `status_channel` once the pager exits.
+//!
+//! Output and interactive streams which weren't closed are closed when
+//! they're dropped, flushing any buffered output, and abandoned if closing
+//! fails. Dropping a stream never panics or exits the process. Errors
+//! encountered while dropping, such as failing to flush buffered output or
+//! to set the clipboard, are passed to the callback set with
+//! [`on_drop_error`], which by default prints them to stderr. To handle
+//! such errors directly, close output streams explicitly before dropping
+//! them. Errors which streams recover from, such as failing to rotate a
+//! file output, are passed to the same callback.
+//!
+//! [`on_drop_error`]: https://docs.rs/nameless/latest/nameless/fn.on_drop_error.html
+//! [`set_http_pool`]: https://docs.rs/nameless/latest/nameless/fn.set_http_pool.html
+//! [`set_http_cache`]: https://docs.rs/nameless/latest/nameless/fn.set_http_cache.html
+//!
+//! # Tracing
+//!
+//! With the "tracing" feature, input and output streams emit [`tracing`]
+//! spans and events with the target `nameless`, at the `INFO` level. Their
+//! names and fields are a stable contract:
+//!
+//!  - `nameless.open`, a span from the start of opening a stream until it's
+//!    closed, with these fields:
+//!     - `kind`: what the stream is connected to, one of `file`, `stdio`,
+//!       `http`, `data`, `child`, `socket`, `char-device`, `scp`, or
+//!       `clipboard`. Absent if the open failed.
+//!     - `scheme`: the URL scheme of the name, or `path`, `stdio`, `child`,
+//!       or `pipeline` for names which aren't URLs.
+//!     - `media_type`: the essence of the stream's media type, such as
+//!       `text/plain`. Absent if the open failed.
+//!     - `size_hint`: the size of an input in bytes, if known.
+//!     - `outcome`: `ok` or `error`.
+//!  - `nameless.first_byte`, an event within `nameless.open` when the first
+//!    byte is read or written, with a `latency_us` field holding the time in
+//!    microseconds since the open completed.
+//!  - `nameless.close`, an event within `nameless.open` when the stream is
+//!    dropped, with these fields:
+//!     - `bytes`: the number of bytes read or written.
+//!     - `duration_us`: the time in microseconds since the open started.
+//!     - `child_exit`: for input from a child process, `success` or a
+//!       description of how the child failed.
+//!
+//! None of these fields include the stream's name. When no subscriber is
+//! interested, the cost is a check per read or write. [`metrics_snapshot`]
+//! returns a summary of the same information without a subscriber.
+//!
+//! [`tracing`]: https://crates.io/crates/tracing
+//! [`metrics_snapshot`]: https://docs.rs/nameless/latest/nameless/fn.metrics_snapshot.html
 
 #![deny(missing_docs)]
 #![forbid(unsafe_code)]
@@ -33,30 +121,162 @@
 #[doc(hidden)]
 pub use clap;
 
+pub use clap::{ambient_authority, AmbientAuthority, TryFromOsArg};
 pub use mime::Mime;
 
+mod any_stream;
+mod boxed;
+mod cancellation_token;
+#[cfg(not(any(windows, target_os = "wasi")))]
+mod child_words;
+#[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+mod clipboard;
+#[cfg(feature = "codecs")]
+mod codecs;
+mod color_choice;
+mod compressed_progress;
+mod copy;
+mod diagnose;
+mod drop_error;
+mod duplicate_arguments;
+mod end_status;
+// Used by `kommand` for `#[env_or_default]` variables.
+#[doc(hidden)]
+pub mod env_or_default;
+mod existence;
+mod fragment;
+#[cfg(feature = "glob")]
+mod glob_expansion;
+mod gzip_level;
+mod http_cache;
+mod http_pool;
+mod http_upload;
 mod input_byte_stream;
+mod input_list;
 mod input_text_stream;
 mod interactive_byte_stream;
 mod interactive_text_stream;
+mod lazy_interactive;
 mod lazy_output;
+#[cfg(not(target_os = "wasi"))]
+mod line_server;
+mod lock;
 mod media_type;
+mod memory_budget;
+mod open_error;
 mod open_input;
 mod open_interactive;
 mod open_output;
+mod open_policy;
+mod open_results;
 mod output_byte_stream;
+mod output_format;
 mod output_text_stream;
+mod output_validation;
 mod path_to_name;
+mod poll;
+mod probe;
+mod prompt_writer;
 mod pseudonym;
+mod rotation;
+mod secret;
+mod source_sink;
+mod status_writer;
+mod stream_info;
+mod stream_kind;
+mod stream_options;
+#[cfg(feature = "structopt-compat")]
+mod structopt_compat;
 #[cfg(unix)]
 mod summon_bat;
+mod syntax;
+mod teardown;
+mod telemetry;
+#[cfg(test)]
+mod test_server;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod text_accounting;
+#[cfg(test)]
+mod text_roundtrip;
+mod unit;
 
+pub use boxed::CloseHandle;
+pub use cancellation_token::CancellationToken;
+#[cfg(feature = "codecs")]
+pub use codecs::{CodecError, FrameFormat, Framed, JsonLines};
+pub use color_choice::ColorChoice;
+pub use copy::{
+    copy, copy_cancellable, copy_stream, copy_with, CopyError, CopyOptions, CopyOutcome,
+    CopyProgress, CopyProgressCallback,
+};
+pub use drop_error::on_drop_error;
+pub use end_status::EndStatus;
+pub use existence::Existence;
+pub use fragment::{
+    register_fragment_resolver, FragmentResolver, LineRanges, MarkdownHeadings, YamlDocuments,
+};
+#[cfg(feature = "glob")]
+pub use glob_expansion::{expand_globs, GlobPolicy};
+pub use http_cache::{set_http_cache, CacheStatus, HttpCache};
+pub use http_pool::{set_http_pool, HttpPool};
 pub use input_byte_stream::InputByteStream;
+pub use input_list::InputList;
 pub use input_text_stream::InputTextStream;
 pub use interactive_byte_stream::InteractiveByteStream;
 pub use interactive_text_stream::InteractiveTextStream;
+pub use lazy_interactive::LazyInteractive;
 pub use lazy_output::LazyOutput;
+#[cfg(not(target_os = "wasi"))]
+pub use line_server::{BoundLineServer, LineContext, LineProtocolServer, LineWriter, Response};
 pub use media_type::MediaType;
-pub use output_byte_stream::OutputByteStream;
+pub use memory_budget::{on_memory_warning, set_memory_budget, MemoryBudget, MemoryWarning};
+pub use open_error::OpenError;
+pub use open_policy::{DuplicateArguments, OpenPolicy};
+pub use open_results::{OpenFailure, OpenFailures, OpenResults};
+pub use output_byte_stream::{ChildOutcome, OutputByteStream};
+pub use output_format::OutputFormat;
 pub use output_text_stream::OutputTextStream;
+pub use output_validation::OutputValidation;
+pub use probe::{probe, StreamProbe};
+pub use prompt_writer::{PromptWriter, WritePrompt};
 pub use pseudonym::Pseudonym;
+pub use secret::{ReadSecret, SecretOptions, SecretString};
+pub use source_sink::{PlainSink, PlainSource, Sink, Source};
+pub use status_writer::StatusWriter;
+pub use stream_info::StreamInfo;
+pub use stream_kind::StreamKind;
+pub use stream_options::StreamOptions;
+pub use syntax::{classify, supported_syntaxes, Directions, SyntaxDescriptor, SyntaxKind};
+#[cfg(feature = "tracing")]
+pub use telemetry::{metrics_snapshot, MetricsSnapshot};
+pub use text_accounting::TextAccounting;
+pub use unit::{UnitEnd, UnitTooLarge};
+
+// Used by `kommand` to build `--version` output.
+#[doc(hidden)]
+pub use syntax::long_version;
+
+// Used by `kommand` for `#[kommand(glob)]` arguments.
+#[cfg(feature = "glob")]
+#[doc(hidden)]
+pub use glob_expansion::{glob_args, glob_collect_args};
+
+// Used by `kommand` for `#[kommand(collect_errors)]` arguments.
+#[doc(hidden)]
+pub use open_results::collect_args;
+
+/// The version of the nameless crate linked into this program.
+pub const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Convert `os` into a `T` with ambient authority, as the former
+/// single-argument form of [`TryFromOsArg::try_from_os_str_arg`] did.
+#[deprecated(note = "use `TryFromOsArg::try_from_os_str_arg` with `ambient_authority()` instead")]
+#[inline]
+pub fn try_from_os_str_arg<T: TryFromOsArg>(os: &std::ffi::OsStr) -> Result<T, T::Error> {
+    T::try_from_os_str_arg(os, ambient_authority())
+}
+
+/// The former name of [`MediaType`].
+#[deprecated(note = "use `MediaType` instead")]
+pub type Type = MediaType;
@@ -0,0 +1,516 @@
+#[cfg(unix)]
+use crate::path_to_name::path_to_name;
+use crate::{CancellationToken, InteractiveByteStream};
+use anyhow::anyhow;
+use clap::{AmbientAuthority, TryFromOsArg};
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// How often idle connections and the accept loop check for shutdown and
+/// idle timeouts.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The type of the [`LineProtocolServer::on_line`] handler.
+type Handler = Arc<dyn Fn(&str, &mut LineContext) -> anyhow::Result<Response> + Send + Sync>;
+
+/// What a [`LineProtocolServer`] handler does after handling a line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Response {
+    /// Send the given line, without its newline, back to the client.
+    Reply(String),
+    /// Send nothing back.
+    NoReply,
+    /// Close the connection.
+    Close,
+}
+
+/// A handle for writing lines to a client of a [`LineProtocolServer`],
+/// including unsolicited ones, from any thread. Each line is written
+/// whole, so lines from different threads don't interleave.
+#[derive(Clone)]
+pub struct LineWriter {
+    inner: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl LineWriter {
+    fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Write `line` and a newline to the client, and flush.
+    pub fn send(&self, line: &str) -> io::Result<()> {
+        self.send_raw(&format!("{}\n", line))
+    }
+
+    fn send_raw(&self, s: &str) -> io::Result<()> {
+        let mut writer = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        writer.write_all(s.as_bytes())?;
+        writer.flush()
+    }
+}
+
+impl Debug for LineWriter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut b = f.debug_struct("LineWriter");
+        b.finish()
+    }
+}
+
+/// The connection a [`LineProtocolServer`] handler is handling a line for.
+#[derive(Debug)]
+pub struct LineContext {
+    peer: String,
+    writer: LineWriter,
+}
+
+impl LineContext {
+    /// Return a name for the client, such as `accept://127.0.0.1:50712`.
+    #[inline]
+    pub fn peer(&self) -> &str {
+        &self.peer
+    }
+
+    /// Write `line` and a newline to the client, before any reply.
+    #[inline]
+    pub fn send(&self, line: &str) -> io::Result<()> {
+        self.writer.send(line)
+    }
+
+    /// Return a handle for writing lines to the client later, such as from
+    /// another thread.
+    #[inline]
+    pub fn writer(&self) -> LineWriter {
+        self.writer.clone()
+    }
+}
+
+/// A line-oriented text service: it reads lines from clients, passes each
+/// to a handler, and writes back the handler's response.
+///
+/// The server's name may be an `accept:` URL, such as
+/// `accept://127.0.0.1:9999` or a Unix-domain socket path, in which case the
+/// server accepts any number of connections, handling up to
+/// [`max_connections`] of them at once, each on its own thread. Any other
+/// interactive stream name, such as `-` or `$(cmd)`, is served as a single
+/// connection.
+///
+/// Lines longer than [`max_line_len`] are discarded, and the client is sent
+/// an `error: ` line. Errors from the handler are also sent as `error: `
+/// lines, and the connection continues. Connections which send nothing for
+/// [`idle_timeout`] are closed.
+///
+/// Cancelling the [`shutdown_token`] stops accepting connections, closes
+/// the open ones between lines, and returns from [`serve`] once their
+/// threads have finished. To shut down on Ctrl-C, cancel the token from a
+/// signal handler.
+///
+/// ```rust,no_run
+/// use nameless::{LineProtocolServer, Response};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// LineProtocolServer::new("accept://127.0.0.1:9999")
+///     .on_line(|line, _ctx| Ok(Response::Reply(line.to_uppercase())))
+///     .run(nameless::ambient_authority())
+/// # }
+/// ```
+///
+/// [`max_connections`]: Self::max_connections
+/// [`max_line_len`]: Self::max_line_len
+/// [`idle_timeout`]: Self::idle_timeout
+/// [`shutdown_token`]: Self::shutdown_token
+/// [`serve`]: BoundLineServer::serve
+pub struct LineProtocolServer {
+    name: OsString,
+    config: Config,
+}
+
+/// The settings shared by all of a server's connections.
+#[derive(Clone)]
+struct Config {
+    handler: Option<Handler>,
+    prompt: Option<String>,
+    max_line_len: usize,
+    idle_timeout: Option<Duration>,
+    max_connections: usize,
+    shutdown: CancellationToken,
+}
+
+impl LineProtocolServer {
+    /// Construct a server for the stream named `name`.
+    pub fn new(name: impl Into<OsString>) -> Self {
+        Self {
+            name: name.into(),
+            config: Config {
+                handler: None,
+                prompt: None,
+                max_line_len: 64 * 1024,
+                idle_timeout: None,
+                max_connections: 64,
+                shutdown: CancellationToken::new(),
+            },
+        }
+    }
+
+    /// Set the function to call with each line, without its line ending.
+    /// Lines which aren't valid UTF-8 are answered with an error without
+    /// calling it.
+    pub fn on_line<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&str, &mut LineContext) -> anyhow::Result<Response> + Send + Sync + 'static,
+    {
+        self.config.handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Write `prompt` to each client when it connects and after each
+    /// response.
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.config.prompt = Some(prompt.into());
+        self
+    }
+
+    /// Set the longest line, in bytes, not counting its line ending, which
+    /// is passed to the handler. The default is 64 KiB.
+    pub fn max_line_len(mut self, max_line_len: usize) -> Self {
+        self.config.max_line_len = max_line_len;
+        self
+    }
+
+    /// Close connections which send nothing for `idle_timeout`. By default,
+    /// connections may be idle indefinitely. This applies to sockets
+    /// accepted from `accept:` URLs.
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.config.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Set the most connections to handle at once. Further connections
+    /// wait to be accepted until others close. The default is 64.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.config.max_connections = max_connections.max(1);
+        self
+    }
+
+    /// Set the token which shuts the server down when it's cancelled.
+    pub fn shutdown_token(mut self, token: CancellationToken) -> Self {
+        self.config.shutdown = token;
+        self
+    }
+
+    /// Open the server's stream, or start listening on its `accept:` URL,
+    /// without handling anything yet.
+    pub fn bind(self, ambient_authority: AmbientAuthority) -> anyhow::Result<BoundLineServer> {
+        if self.config.handler.is_none() {
+            return Err(anyhow!("LineProtocolServer has no `on_line` handler"));
+        }
+        let listener = Listener::bind(&self.name, ambient_authority)?;
+        Ok(BoundLineServer {
+            listener,
+            config: self.config,
+        })
+    }
+
+    /// Bind the server and serve connections until it's shut down, or
+    /// until its single stream ends.
+    pub fn run(self, ambient_authority: AmbientAuthority) -> anyhow::Result<()> {
+        self.bind(ambient_authority)?.serve()
+    }
+}
+
+impl Debug for LineProtocolServer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut b = f.debug_struct("LineProtocolServer");
+        b.field("max_line_len", &self.config.max_line_len);
+        b.field("idle_timeout", &self.config.idle_timeout);
+        b.field("max_connections", &self.config.max_connections);
+        b.finish()
+    }
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+    Single(Box<InteractiveByteStream>, String),
+}
+
+impl Listener {
+    fn bind(name: &OsStr, ambient_authority: AmbientAuthority) -> anyhow::Result<Self> {
+        let url = match name.to_str().map(Url::parse) {
+            Some(Ok(url)) if url.scheme() == "accept" => url,
+            _ => {
+                let stream = InteractiveByteStream::try_from_os_str_arg(name, ambient_authority)?;
+                return Ok(Self::Single(
+                    Box::new(stream),
+                    name.to_string_lossy().into_owned(),
+                ));
+            }
+        };
+        if url.query().is_some() || url.fragment().is_some() {
+            return Err(anyhow!(
+                "LineProtocolServer accept URLs don't support options"
+            ));
+        }
+
+        if url.path().is_empty() {
+            let host = url
+                .host_str()
+                .ok_or_else(|| anyhow!("accept URL should have a host"))?;
+            let port = url
+                .port()
+                .ok_or_else(|| anyhow!("accept URL should have a port"))?;
+            let listener = TcpListener::bind((host, port))?;
+            listener.set_nonblocking(true)?;
+            return Ok(Self::Tcp(listener));
+        }
+
+        #[cfg(unix)]
+        {
+            if url.port().is_some() || url.host_str().is_some() {
+                return Err(anyhow!("Unix-domain accept URL should only contain a path"));
+            }
+            let path = PathBuf::from(url.path());
+            let listener = UnixListener::bind(&path)?;
+            listener.set_nonblocking(true)?;
+            Ok(Self::Unix(listener, path))
+        }
+
+        #[cfg(not(unix))]
+        Err(anyhow!("Unsupported accept URL: {}", url))
+    }
+}
+
+/// A [`LineProtocolServer`] which is listening, returned by
+/// [`LineProtocolServer::bind`].
+pub struct BoundLineServer {
+    listener: Listener,
+    config: Config,
+}
+
+impl BoundLineServer {
+    /// For a server listening on an `accept:` URL, return the `connect:`
+    /// URL which clients can use to reach it. This reports the actual port
+    /// when the URL asked for port 0.
+    pub fn connect_name(&self) -> Option<String> {
+        match &self.listener {
+            Listener::Tcp(listener) => listener
+                .local_addr()
+                .ok()
+                .map(|addr| format!("connect://{}", addr)),
+            #[cfg(unix)]
+            Listener::Unix(_, path) => path_to_name("connect", path).ok(),
+            Listener::Single(..) => None,
+        }
+    }
+
+    /// Serve connections until the server is shut down, or until its
+    /// single stream ends.
+    pub fn serve(self) -> anyhow::Result<()> {
+        let config = self.config;
+        match self.listener {
+            Listener::Tcp(listener) => accept_loop(&config, || {
+                let (stream, addr) = listener.accept()?;
+                let connection = tcp_connection(stream)?;
+                Ok((connection, format!("accept://{}", addr)))
+            }),
+            #[cfg(unix)]
+            Listener::Unix(listener, path) => {
+                let peer = path_to_name("accept", &path)?;
+                accept_loop(&config, || {
+                    let (stream, _addr) = listener.accept()?;
+                    let connection = unix_connection(stream)?;
+                    Ok((connection, peer.clone()))
+                })
+            }
+            Listener::Single(stream, peer) => {
+                let (reader, writer, close) = stream.into_boxed_halves();
+                let result = serve_connection(reader, LineWriter::new(writer), peer, &config);
+                // A stream which was read to its end is already closed.
+                if !matches!(result, Ok(true)) {
+                    close.close()?;
+                }
+                result?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Debug for BoundLineServer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut b = f.debug_struct("BoundLineServer");
+        b.field("connect_name", &self.connect_name());
+        b.finish()
+    }
+}
+
+/// The reading and writing halves of an accepted socket.
+type Connection = (Box<dyn Read + Send>, Box<dyn Write + Send>);
+
+fn tcp_connection(stream: TcpStream) -> io::Result<Connection> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let writer = stream.try_clone()?;
+    Ok((Box::new(stream), Box::new(writer)))
+}
+
+#[cfg(unix)]
+fn unix_connection(stream: UnixStream) -> io::Result<Connection> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let writer = stream.try_clone()?;
+    Ok((Box::new(stream), Box::new(writer)))
+}
+
+/// Accept connections with `accept`, which is non-blocking, and serve each
+/// on its own thread, until the server is shut down. Then wait for the
+/// connections to finish.
+fn accept_loop(
+    config: &Config,
+    mut accept: impl FnMut() -> io::Result<(Connection, String)>,
+) -> anyhow::Result<()> {
+    let active = Arc::new(AtomicUsize::new(0));
+    let mut threads: Vec<JoinHandle<()>> = Vec::new();
+
+    while !config.shutdown.is_cancelled() {
+        threads.retain(|thread| !thread.is_finished());
+        if active.load(Ordering::SeqCst) >= config.max_connections {
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+
+        let ((reader, writer), peer) = match accept() {
+            Ok(accepted) => accepted,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        active.fetch_add(1, Ordering::SeqCst);
+        let active = Arc::clone(&active);
+        let config = config.clone();
+        threads.push(thread::spawn(move || {
+            // Errors on one connection, such as a client disconnecting
+            // abruptly, don't affect the others.
+            let _ = serve_connection(reader, LineWriter::new(writer), peer, &config);
+            active.fetch_sub(1, Ordering::SeqCst);
+        }));
+    }
+
+    for thread in threads {
+        let _ = thread.join();
+    }
+    Ok(())
+}
+
+/// Read lines from `reader` and handle them, until the client closes the
+/// connection, the handler closes it, or the server shuts down. Return
+/// whether it was the client which closed it.
+fn serve_connection(
+    mut reader: impl Read,
+    writer: LineWriter,
+    peer: String,
+    config: &Config,
+) -> io::Result<bool> {
+    let handler = config.handler.as_ref().unwrap();
+    let mut ctx = LineContext {
+        peer,
+        writer: writer.clone(),
+    };
+    let mut pending = Vec::new();
+    let mut discarding = false;
+    let mut last_active = Instant::now();
+    let mut chunk = [0; 4096];
+
+    if let Some(prompt) = &config.prompt {
+        writer.send_raw(prompt)?;
+    }
+
+    loop {
+        while let Some(newline) = pending.iter().position(|byte| *byte == b'\n') {
+            let mut line = pending.drain(..=newline).collect::<Vec<u8>>();
+            if discarding {
+                discarding = false;
+                continue;
+            }
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+
+            let response = if line.len() > config.max_line_len {
+                Err(line_too_long(config))
+            } else {
+                match std::str::from_utf8(&line) {
+                    Ok(line) => handler(line, &mut ctx),
+                    Err(_) => Err(anyhow!("line isn't valid UTF-8")),
+                }
+            };
+            match response {
+                Ok(Response::Reply(reply)) => writer.send(&reply)?,
+                Ok(Response::NoReply) => {}
+                Ok(Response::Close) => return Ok(false),
+                Err(err) => writer.send(&format!("error: {:#}", err))?,
+            }
+            if let Some(prompt) = &config.prompt {
+                writer.send_raw(prompt)?;
+            }
+        }
+
+        // Don't buffer more than a line's worth of a line which is too long.
+        if discarding {
+            pending.clear();
+        } else if pending.len() > config.max_line_len {
+            pending.clear();
+            discarding = true;
+            writer.send(&format!("error: {:#}", line_too_long(config)))?;
+        }
+
+        if config.shutdown.is_cancelled() {
+            return Ok(false);
+        }
+        match reader.read(&mut chunk) {
+            Ok(0) => return Ok(true),
+            Ok(n) => {
+                pending.extend_from_slice(&chunk[..n]);
+                last_active = Instant::now();
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                if let Some(idle_timeout) = config.idle_timeout {
+                    if last_active.elapsed() >= idle_timeout {
+                        return Ok(false);
+                    }
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn line_too_long(config: &Config) -> anyhow::Error {
+    anyhow!("line is longer than {} bytes", config.max_line_len)
+}
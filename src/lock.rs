@@ -0,0 +1,216 @@
+use crate::OpenError;
+use anyhow::anyhow;
+use std::fs::File;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often to retry taking a lock which is held by someone else.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// An advisory lock to take on a file after opening it, from a `lock=`
+/// option on a `file:` URL.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LockOptions {
+    exclusive: bool,
+    wait: Option<Duration>,
+}
+
+impl LockOptions {
+    /// Parse the value of a `lock=` option, which is `shared` or
+    /// `exclusive`, optionally followed by `,wait=<duration>`.
+    pub(crate) fn parse(value: &str) -> anyhow::Result<Self> {
+        let mut parts = value.split(',');
+        let exclusive = match parts.next() {
+            Some("shared") => false,
+            Some("exclusive") => true,
+            _ => return Err(anyhow!("unsupported lock option \"{}\"", value)),
+        };
+        let mut wait = None;
+        for part in parts {
+            match part.strip_prefix("wait=") {
+                Some(duration) => wait = Some(humantime::parse_duration(duration)?),
+                None => return Err(anyhow!("unsupported lock option \"{}\"", part)),
+            }
+        }
+        Ok(Self { exclusive, wait })
+    }
+
+    /// Options for an exclusive lock, waiting up to `wait` for it if given.
+    pub(crate) fn exclusive(wait: Option<Duration>) -> Self {
+        Self {
+            exclusive: true,
+            wait,
+        }
+    }
+}
+
+/// Take an advisory lock on `file`, which was opened from `path`. The lock
+/// is released when the file is closed.
+///
+/// If the lock is held by someone else, wait for it if the options say to,
+/// and otherwise fail with `OpenError::Locked`.
+pub(crate) fn lock(file: &File, path: &Path, options: LockOptions) -> anyhow::Result<()> {
+    let deadline = options.wait.map(|wait| Instant::now() + wait);
+    loop {
+        if try_lock(file, options.exclusive)? {
+            return Ok(());
+        }
+        let now = Instant::now();
+        match deadline {
+            Some(deadline) if now < deadline => {
+                thread::sleep(LOCK_POLL_INTERVAL.min(deadline - now))
+            }
+            _ => {
+                return Err(OpenError::Locked {
+                    path: path.to_owned(),
+                    holder_hint: holder_hint(file),
+                }
+                .into())
+            }
+        }
+    }
+}
+
+/// Try to take the lock without blocking, returning `false` if someone else
+/// holds a conflicting lock.
+#[cfg(not(any(windows, target_os = "wasi")))]
+fn try_lock(file: &File, exclusive: bool) -> anyhow::Result<bool> {
+    use rustix::fs::{flock, FlockOperation};
+    use rustix::io::Errno;
+
+    let operation = if exclusive {
+        FlockOperation::NonBlockingLockExclusive
+    } else {
+        FlockOperation::NonBlockingLockShared
+    };
+    loop {
+        match flock(file, operation) {
+            Ok(()) => return Ok(true),
+            Err(Errno::WOULDBLOCK) => return Ok(false),
+            Err(Errno::INTR) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// On Windows, `File::try_lock` and `File::try_lock_shared` call
+/// `LockFileEx` with `LOCKFILE_FAIL_IMMEDIATELY`, and with
+/// `LOCKFILE_EXCLUSIVE_LOCK` for exclusive locks, over the whole file.
+#[cfg(windows)]
+fn try_lock(file: &File, exclusive: bool) -> anyhow::Result<bool> {
+    use std::fs::TryLockError;
+
+    let result = if exclusive {
+        file.try_lock()
+    } else {
+        file.try_lock_shared()
+    };
+    match result {
+        Ok(()) => Ok(true),
+        Err(TryLockError::WouldBlock) => Ok(false),
+        Err(TryLockError::Error(err)) => Err(err.into()),
+    }
+}
+
+#[cfg(target_os = "wasi")]
+fn try_lock(_file: &File, _exclusive: bool) -> anyhow::Result<bool> {
+    Err(OpenError::UnsupportedOnPlatform("file locks").into())
+}
+
+/// On Linux, find a process holding a lock on `file` in `/proc/locks`, to
+/// help users find out what's in the way.
+#[cfg(target_os = "linux")]
+fn holder_hint(file: &File) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = file.metadata().ok()?;
+    let dev = metadata.dev();
+    // Lines look like "1: FLOCK  ADVISORY  WRITE 1234 08:01:5678 0 EOF".
+    let id = format!(
+        "{:02x}:{:02x}:{}",
+        rustix::fs::major(dev),
+        rustix::fs::minor(dev),
+        metadata.ino()
+    );
+    let locks = std::fs::read_to_string("/proc/locks").ok()?;
+    locks.lines().find_map(|line| {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        // Blocked waiters are listed with a "->" field; skip them.
+        if fields.get(1) == Some(&"->") || fields.get(5) != Some(&id.as_str()) {
+            return None;
+        }
+        Some(format!("held by process {}", fields[4]))
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn holder_hint(_file: &File) -> Option<String> {
+    None
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn exclusive_locks() {
+    use crate::OutputByteStream;
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use std::sync::{Arc, Barrier};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut url = url::Url::from_file_path(dir.path().join("locked.txt")).unwrap();
+    url.set_query(Some("lock=exclusive"));
+    let name = url.to_string();
+
+    // Hold both opens until both have been attempted.
+    let barrier = Arc::new(Barrier::new(2));
+    let threads = (0..2)
+        .map(|_| {
+            let name = name.clone();
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                let result =
+                    OutputByteStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority());
+                barrier.wait();
+                result.map(|mut output| output.close().unwrap())
+            })
+        })
+        .collect::<Vec<_>>();
+    let results = threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+    let err = results.into_iter().find_map(Result::err).unwrap();
+    assert!(matches!(
+        err.downcast_ref::<OpenError>(),
+        Some(OpenError::Locked { .. })
+    ));
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn wait_for_lock() {
+    use crate::OutputByteStream;
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut url = url::Url::from_file_path(dir.path().join("locked.txt")).unwrap();
+    url.set_query(Some("lock=exclusive"));
+    let mut first =
+        OutputByteStream::try_from_os_str_arg(url.as_str().as_ref(), clap::ambient_authority())
+            .unwrap();
+
+    url.set_query(Some("lock=exclusive,wait=10s"));
+    let second = thread::spawn(move || {
+        OutputByteStream::try_from_os_str_arg(url.as_str().as_ref(), clap::ambient_authority())
+            .map(|mut output| output.close().unwrap())
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    first.close().unwrap();
+    drop(first);
+    second.join().unwrap().unwrap();
+}
@@ -1,6 +1,9 @@
 use mime::Mime;
+use std::collections::HashMap;
 use std::ffi::OsStr;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::{OnceLock, PoisonError, RwLock};
 
 /// The type of content in a stream. This can be either a Media Type
 /// (aka Mime Type) or a filename extension, both, or neither if nothing
@@ -45,9 +48,21 @@ impl MediaType {
     }
 
     /// Construct a type representing the given filename extension.
+    ///
+    /// Extensions registered with [`MediaType::register_extension`] take
+    /// precedence, followed by those in the `NAMELESS_MIME_TYPES` file, if
+    /// the "mime-types-file" feature is enabled, followed by the
+    /// `mime_guess` database.
     pub fn from_extension(extension: Option<&OsStr>) -> Self {
         if let Some(ext) = extension {
             if let Some(s) = ext.to_str() {
+                if let Some(mime) = registered_mime(s) {
+                    return Self {
+                        mime,
+                        extension: s.to_string(),
+                    };
+                }
+
                 let mut guesses = mime_guess::from_ext(s).iter();
 
                 if let Some(first) = guesses.next() {
@@ -73,6 +88,70 @@ impl MediaType {
         }
     }
 
+    /// Construct a type for the file at `path`, from its file name.
+    ///
+    /// Well-known names without extensions, such as `Makefile` and
+    /// `LICENSE`, have their own types, and names registered with
+    /// [`MediaType::register_filename`] take precedence over them. Other
+    /// names are looked up by their extension, as with
+    /// [`MediaType::from_extension`]. Names are matched case-insensitively.
+    ///
+    /// Dotfiles such as `.gitignore`, and names ending in a dot, such as
+    /// `file.`, have no extension.
+    pub fn from_path(path: &Path) -> Self {
+        if let Some(mime) = path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .and_then(filename_mime)
+        {
+            return Self {
+                mime,
+                extension: String::new(),
+            };
+        }
+
+        Self::from_extension(path_extension(path))
+    }
+
+    /// Register `mime` as the Media Type for files with the extension `ext`,
+    /// overriding the `mime_guess` database. Extensions are matched
+    /// case-insensitively.
+    ///
+    /// Registrations are process-wide, and the last registration for an
+    /// extension wins, including for types constructed after it; types
+    /// constructed before it are unaffected. The most recently registered
+    /// extension for a Media Type is also what
+    /// [`MediaType::preferred_extension`] returns for it.
+    pub fn register_extension(ext: &str, mime: Mime) {
+        registry()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(ext, mime);
+    }
+
+    /// Register `mime` as the Media Type for files named `name`, such as
+    /// `"Justfile"`, overriding their extension and the built-in table used
+    /// by [`MediaType::from_path`]. Names are matched case-insensitively.
+    ///
+    /// As with [`MediaType::register_extension`], registrations are
+    /// process-wide and the last registration for a name wins.
+    pub fn register_filename(name: &str, mime: Mime) {
+        registry()
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .by_filename
+            .insert(name.to_lowercase(), mime);
+    }
+
+    /// Register each of the `(extension, Media Type)` pairs in `pairs`, as
+    /// with [`MediaType::register_extension`].
+    pub fn register_from_pairs<'a, I: IntoIterator<Item = (&'a str, Mime)>>(pairs: I) {
+        let mut registry = registry().write().unwrap_or_else(PoisonError::into_inner);
+        for (ext, mime) in pairs {
+            registry.insert(ext, mime);
+        }
+    }
+
     /// Return the Media Type, which is "*/*" if unknown.
     #[inline]
     pub fn mime(&self) -> &Mime {
@@ -85,6 +164,45 @@ impl MediaType {
         &self.extension
     }
 
+    /// Return the filename extension to use when naming a file of this
+    /// type, such as `"jpg"` for `image/jpeg`, or `None` if there isn't
+    /// one.
+    ///
+    /// This is the extension the type was constructed from, if any, and
+    /// otherwise the conventional extension for its Media Type.
+    pub fn preferred_extension(&self) -> Option<&str> {
+        if !self.extension.is_empty() {
+            return Some(&self.extension);
+        }
+
+        // A wildcard subtype would match the extensions of every subtype.
+        if self.mime.subtype().as_str() == mime::STAR {
+            return None;
+        }
+
+        if let Some(ext) = registered_extension(self.mime.essence_str()) {
+            return Some(ext);
+        }
+
+        // Some types have several extensions, and the first one in
+        // `mime_guess`'s list isn't always the one people use.
+        let preferred = match self.mime.essence_str() {
+            "image/jpeg" => Some("jpg"),
+            "image/tiff" => Some("tiff"),
+            "text/plain" => Some("txt"),
+            "text/html" => Some("html"),
+            "text/markdown" | "text/x-markdown" => Some("md"),
+            "text/x-yaml" | "application/yaml" | "application/x-yaml" => Some("yaml"),
+            "application/javascript" | "text/javascript" => Some("js"),
+            "audio/mpeg" => Some("mp3"),
+            "video/mpeg" => Some("mpeg"),
+            _ => None,
+        };
+        preferred.or_else(|| {
+            mime_guess::get_mime_extensions(&self.mime).and_then(|exts| exts.first().copied())
+        })
+    }
+
     /// Return a type which is the generalization of `self` and `other`. Falls
     /// back to `MediaType::unknown()` if it cannot be determined.
     pub fn union(self, other: Self) -> Self {
@@ -116,13 +234,13 @@ impl MediaType {
                 MediaType::from_mime(Mime::from_str(&s).unwrap())
             }
         } else if other == MediaType::text() {
-            if self.mime.type_() == other.mime.type_() {
+            if self.mime.type_() == other.mime.type_() || self.is_text_like() {
                 self
             } else {
                 MediaType::unknown()
             }
         } else if self == MediaType::text() {
-            if self.mime.type_() == other.mime.type_() {
+            if self.mime.type_() == other.mime.type_() || other.is_text_like() {
                 other
             } else {
                 MediaType::unknown()
@@ -131,6 +249,181 @@ impl MediaType {
             MediaType::unknown()
         }
     }
+
+    /// Test whether this is a non-`text/*` type whose contents are
+    /// nonetheless text, such as `application/json`.
+    fn is_text_like(&self) -> bool {
+        self.mime.type_() == mime::APPLICATION
+            && (matches!(
+                self.mime.subtype().as_str(),
+                "json" | "xml" | "yaml" | "x-yaml" | "javascript" | "toml"
+            ) || matches!(
+                self.mime.suffix().map(|suffix| suffix.as_str()),
+                Some("json") | Some("xml") | Some("yaml")
+            ))
+    }
+}
+
+/// Media types registered for filename extensions, which take precedence
+/// over `mime_guess`.
+#[derive(Default)]
+struct Registry {
+    /// Map from lowercased extensions to Media Types.
+    by_extension: HashMap<&'static str, Mime>,
+
+    /// Map from Media Type essences to the most recently registered
+    /// extension for them.
+    by_essence: HashMap<String, &'static str>,
+
+    /// Map from lowercased file names to Media Types.
+    by_filename: HashMap<String, Mime>,
+}
+
+impl Registry {
+    fn insert(&mut self, ext: &str, mime: Mime) {
+        let ext = ext.to_ascii_lowercase();
+
+        // `preferred_extension` returns extensions borrowed from `self`,
+        // so they're leaked; reuse the existing string when an extension is
+        // registered again, so that only distinct extensions are leaked.
+        let ext = match self.by_extension.get_key_value(ext.as_str()) {
+            Some((existing, _)) => *existing,
+            None => Box::leak(ext.into_boxed_str()),
+        };
+
+        if let Some(old) = self.by_extension.insert(ext, mime.clone()) {
+            if self.by_essence.get(old.essence_str()) == Some(&ext) {
+                self.by_essence.remove(old.essence_str());
+            }
+        }
+        self.by_essence.insert(mime.essence_str().to_owned(), ext);
+    }
+
+    fn mime(&self, ext: &str) -> Option<Mime> {
+        self.by_extension
+            .get(ext.to_ascii_lowercase().as_str())
+            .cloned()
+    }
+
+    fn extension(&self, essence: &str) -> Option<&'static str> {
+        self.by_essence.get(essence).copied()
+    }
+}
+
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Look up `ext` in the registered types and then in the
+/// `NAMELESS_MIME_TYPES` file.
+fn registered_mime(ext: &str) -> Option<Mime> {
+    registry()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .mime(ext)
+        .or_else(|| types_file().and_then(|file| file.mime(ext)))
+}
+
+/// Look up the file name `name` in the registered types and then in the
+/// well-known names.
+fn filename_mime(name: &str) -> Option<Mime> {
+    let name = name.to_lowercase();
+    registry()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .by_filename
+        .get(name.as_str())
+        .cloned()
+        .or_else(|| well_known_filename(&name))
+}
+
+/// Return the type for the well-known lowercased file name `name`, for
+/// names which don't have extensions.
+fn well_known_filename(name: &str) -> Option<Mime> {
+    let mime = match name {
+        "makefile" | "gnumakefile" => "text/x-makefile",
+        "dockerfile" | "containerfile" => "text/x-dockerfile",
+        "license" | "licence" | "copying" | "readme" | "authors" | "changelog" | "news"
+        | ".gitignore" | ".gitattributes" | ".dockerignore" => "text/plain",
+        _ => return None,
+    };
+    Some(Mime::from_str(mime).unwrap())
+}
+
+/// Return the extension of the file name in `path`.
+///
+/// Unlike `Path::extension`, this treats names with no dot after their
+/// leading dots, such as `..gitignore`, and names ending in a dot, such as
+/// `file.`, as having no extension.
+fn path_extension(path: &Path) -> Option<&OsStr> {
+    let ext = path.extension().filter(|ext| !ext.is_empty())?;
+    match path.file_name()?.to_str() {
+        Some(name) if !name.trim_start_matches('.').contains('.') => None,
+        _ => Some(ext),
+    }
+}
+
+/// Look up the extension for `essence` in the registered types and then in
+/// the `NAMELESS_MIME_TYPES` file.
+fn registered_extension(essence: &str) -> Option<&'static str> {
+    registry()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .extension(essence)
+        .or_else(|| types_file().and_then(|file| file.extension(essence)))
+}
+
+/// Return the types loaded from the file named by the `NAMELESS_MIME_TYPES`
+/// environment variable, which is read on first use.
+#[cfg(feature = "mime-types-file")]
+fn types_file() -> Option<&'static Registry> {
+    static TYPES_FILE: OnceLock<Option<Registry>> = OnceLock::new();
+    TYPES_FILE
+        .get_or_init(|| {
+            let path = std::env::var_os("NAMELESS_MIME_TYPES")?;
+            match std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|contents| parse_types_file(&contents))
+            {
+                Ok(registry) => Some(registry),
+                Err(err) => {
+                    eprintln!(
+                        "warning: ignoring NAMELESS_MIME_TYPES file {}: {:#}",
+                        std::path::Path::new(&path).display(),
+                        err
+                    );
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+#[cfg(not(feature = "mime-types-file"))]
+fn types_file() -> Option<&'static Registry> {
+    None
+}
+
+/// Parse a TOML file mapping extensions to Media Types, such as:
+///
+/// ```toml
+/// parquet = "application/vnd.apache.parquet"
+/// fq = "text/x-fastq"
+/// ```
+#[cfg(feature = "mime-types-file")]
+fn parse_types_file(contents: &str) -> anyhow::Result<Registry> {
+    let table: toml::Table = contents.parse()?;
+    let mut registry = Registry::default();
+    for (ext, value) in table {
+        let mime = value
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("the type for extension {:?} isn't a string", ext))?;
+        let mime = Mime::from_str(mime)
+            .map_err(|err| anyhow::anyhow!("invalid type for extension {:?}: {}", ext, err))?;
+        registry.insert(&ext, mime);
+    }
+    Ok(registry)
 }
 
 #[test]
@@ -143,6 +436,63 @@ fn mime_from_extension() {
     );
 }
 
+#[test]
+fn mime_from_path() {
+    let path = |s| MediaType::from_path(Path::new(s));
+    let mime = |s| Mime::from_str(s).unwrap();
+
+    // Well-known names without extensions, in any case.
+    assert_eq!(path("Makefile").mime(), &mime("text/x-makefile"));
+    assert_eq!(path("src/GNUmakefile").mime(), &mime("text/x-makefile"));
+    assert_eq!(path("Dockerfile").mime(), &mime("text/x-dockerfile"));
+    assert_eq!(path("LICENSE").mime(), &mime("text/plain"));
+    assert_eq!(path("README").mime(), &mime("text/plain"));
+    assert_eq!(path("readme").mime(), &mime("text/plain"));
+    assert_eq!(path("LICENSE").extension(), "");
+
+    // Their extensions still count when they have them.
+    assert_eq!(path("README.md"), path("x.md"));
+    assert_eq!(path("LICENSE-MIT"), MediaType::unknown());
+
+    // Dotfiles have no extension.
+    assert_eq!(path(".gitignore").mime(), &mime("text/plain"));
+    assert_eq!(path(".bashrc"), MediaType::unknown());
+    assert_eq!(path("..bashrc"), MediaType::unknown());
+    assert_eq!(path(".config/.env"), MediaType::unknown());
+    assert_eq!(path(".eslintrc.json"), path("x.json"));
+
+    // Nor do names ending in a dot.
+    assert_eq!(path("file."), MediaType::unknown());
+    assert_eq!(path("archive.tar."), MediaType::unknown());
+    assert_eq!(path("."), MediaType::unknown());
+    assert_eq!(path(""), MediaType::unknown());
+
+    // Extensions are matched case-insensitively.
+    assert_eq!(path("PHOTO.JPG").mime(), &mime("image/jpeg"));
+    assert_eq!(path("ARCHIVE.TAR").mime(), &mime("application/x-tar"));
+    assert_eq!(path("archive.TAR.GZ").mime(), path("archive.tar.gz").mime());
+    assert_eq!(path("hello.Txt").mime(), &mime("text/plain"));
+}
+
+#[test]
+fn mime_registered_filename() {
+    // Registrations are process-wide, so use names no other test uses.
+    let path = |s| MediaType::from_path(Path::new(s));
+    let mime = |s| Mime::from_str(s).unwrap();
+
+    assert_eq!(path("nameless-filename-test"), MediaType::unknown());
+    MediaType::register_filename("nameless-filename-test", mime("text/x-first"));
+    assert_eq!(path("Nameless-Filename-Test").mime(), &mime("text/x-first"));
+
+    // Registered names take precedence over extensions and the built-in
+    // table.
+    MediaType::register_filename("nameless-filename-test.json", mime("text/x-second"));
+    assert_eq!(
+        path("nameless-filename-test.json").mime(),
+        &mime("text/x-second")
+    );
+}
+
 #[test]
 fn mime_union() {
     assert_eq!(
@@ -152,3 +502,81 @@ fn mime_union() {
         &Mime::from_str("image/*").unwrap()
     );
 }
+
+#[test]
+fn mime_union_text_like() {
+    let json = MediaType::from_extension(Some(OsStr::new("json")));
+    assert_eq!(MediaType::text().union(json.clone()), json);
+    assert_eq!(json.clone().union(MediaType::text()), json);
+    assert_eq!(
+        MediaType::text().union(MediaType::from_extension(Some(OsStr::new("png")))),
+        MediaType::unknown()
+    );
+}
+
+#[test]
+fn mime_preferred_extension() {
+    let mime = |s| MediaType::from_mime(Mime::from_str(s).unwrap());
+    assert_eq!(mime("image/png").preferred_extension(), Some("png"));
+    assert_eq!(mime("image/jpeg").preferred_extension(), Some("jpg"));
+    assert_eq!(MediaType::text().preferred_extension(), Some("txt"));
+    assert_eq!(
+        MediaType::from_extension(Some(OsStr::new("jpeg"))).preferred_extension(),
+        Some("jpeg")
+    );
+    assert_eq!(mime("image/*").preferred_extension(), None);
+    assert_eq!(MediaType::unknown().preferred_extension(), None);
+    assert_eq!(
+        mime("application/x-nameless-test").preferred_extension(),
+        None
+    );
+}
+
+#[test]
+fn mime_registry() {
+    // Registrations are process-wide, so use extensions no other test uses.
+    let ext = |s| MediaType::from_extension(Some(OsStr::new(s)));
+    let mime = |s| Mime::from_str(s).unwrap();
+
+    assert_eq!(ext("nameless-registry-test"), MediaType::unknown());
+    MediaType::register_extension("nameless-registry-test", mime("application/x-first"));
+    assert_eq!(
+        ext("nameless-registry-test").mime(),
+        &mime("application/x-first")
+    );
+    assert_eq!(
+        ext("NAMELESS-REGISTRY-TEST").mime(),
+        &mime("application/x-first")
+    );
+
+    // Last write wins.
+    MediaType::register_from_pairs([("nameless-registry-test", mime("application/x-second"))]);
+    assert_eq!(
+        ext("nameless-registry-test").mime(),
+        &mime("application/x-second")
+    );
+
+    // The reverse lookup follows the registrations.
+    let from_mime = |s| MediaType::from_mime(Mime::from_str(s).unwrap());
+    assert_eq!(
+        from_mime("application/x-second").preferred_extension(),
+        Some("nameless-registry-test")
+    );
+    assert_eq!(from_mime("application/x-first").preferred_extension(), None);
+}
+
+#[cfg(feature = "mime-types-file")]
+#[test]
+fn mime_types_file() {
+    let registry =
+        parse_types_file("parquet = \"application/vnd.apache.parquet\"\nfq = \"text/x-fastq\"\n")
+            .unwrap();
+    assert_eq!(
+        registry.mime("parquet"),
+        Some(Mime::from_str("application/vnd.apache.parquet").unwrap())
+    );
+    assert_eq!(registry.extension("text/x-fastq"), Some("fq"));
+
+    assert!(parse_types_file("parquet = 1\n").is_err());
+    assert!(parse_types_file("parquet = \"not a type\"\n").is_err());
+}
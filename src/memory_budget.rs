@@ -0,0 +1,336 @@
+use crate::OpenError;
+use std::fmt;
+#[cfg(not(test))]
+use std::io::Write;
+use std::io::{self, BufRead};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// Limits on the memory nameless itself uses to hold a whole stream, or a
+/// whole record of one, set with [`set_memory_budget`].
+///
+/// The budget applies to each buffer separately. It covers decoding `data:`
+/// URLs, [`InputTextStream::read_to_text_bytes`] and
+/// [`InputTextStream::read_to_text_string`], resolving `#fragment`s,
+/// buffering `clipboard:` output, the entries of [`InputList`]s, and receiving
+/// records with [`JsonLines`] or [`Framed`]. It's advisory for plain
+/// `read_to_end` calls, which grow the caller's buffer.
+///
+/// [`InputTextStream::read_to_text_bytes`]: crate::InputTextStream::read_to_text_bytes
+/// [`InputTextStream::read_to_text_string`]: crate::InputTextStream::read_to_text_string
+/// [`InputList`]: crate::InputList
+/// [`JsonLines`]: https://docs.rs/nameless/latest/nameless/struct.JsonLines.html
+/// [`Framed`]: https://docs.rs/nameless/latest/nameless/struct.Framed.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct MemoryBudget {
+    /// The most bytes a single buffer may hold. Exceeding it fails with
+    /// [`OpenError::MemoryBudgetExceeded`].
+    pub limit: usize,
+
+    /// The fraction of `limit`, from 0 to 1, at which a buffer reports a
+    /// [`MemoryWarning`] to the [`on_memory_warning`] callback. Each buffer
+    /// reports at most once.
+    pub warn_at: f64,
+}
+
+impl MemoryBudget {
+    /// A budget which never fails and never warns.
+    pub fn unlimited() -> Self {
+        Self {
+            limit: usize::MAX,
+            warn_at: 1.0,
+        }
+    }
+}
+
+impl Default for MemoryBudget {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            limit: 256 * 1024 * 1024,
+            warn_at: 0.75,
+        }
+    }
+}
+
+static BUDGET: RwLock<Option<MemoryBudget>> = RwLock::new(None);
+
+/// Set the memory budget for buffers created after this call, in any
+/// thread.
+pub fn set_memory_budget(budget: MemoryBudget) {
+    *BUDGET.write().unwrap_or_else(PoisonError::into_inner) = Some(budget);
+}
+
+/// A report that a buffer has crossed its budget's `warn_at` fraction.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct MemoryWarning {
+    /// The part of nameless doing the buffering, such as `"data URL"`.
+    pub feature: &'static str,
+    /// The number of bytes the buffer holds.
+    pub used: usize,
+    /// The budget's limit.
+    pub limit: usize,
+}
+
+impl fmt::Display for MemoryWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is holding {} bytes in memory, approaching the budget of {} bytes",
+            self.feature, self.used, self.limit
+        )
+    }
+}
+
+type WarningCallback = Arc<dyn Fn(&MemoryWarning) + Send + Sync>;
+
+static CALLBACK: RwLock<Option<WarningCallback>> = RwLock::new(None);
+
+/// Set the function to call when a buffer crosses its [`MemoryBudget`]'s
+/// `warn_at` fraction. By default, warnings are printed to stderr.
+///
+/// The callback may be called from any thread.
+pub fn on_memory_warning<F: Fn(&MemoryWarning) + Send + Sync + 'static>(callback: F) {
+    *CALLBACK.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(callback));
+}
+
+#[cfg(not(test))]
+fn warn(warning: &MemoryWarning) {
+    // Don't hold the lock while calling the callback, so that it can call
+    // `on_memory_warning` itself.
+    let callback = CALLBACK
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    match callback {
+        Some(callback) => callback(warning),
+        None => {
+            let _ = writeln!(io::stderr(), "warning: {}", warning);
+        }
+    }
+}
+
+#[cfg(not(test))]
+fn current() -> MemoryBudget {
+    BUDGET
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .unwrap_or_default()
+}
+
+/// Tracks one buffer against the budget which was in effect when it was
+/// created.
+pub(crate) struct BudgetTracker {
+    feature: &'static str,
+    budget: MemoryBudget,
+    warned: bool,
+}
+
+impl BudgetTracker {
+    pub(crate) fn new(feature: &'static str) -> Self {
+        Self {
+            feature,
+            budget: current(),
+            warned: false,
+        }
+    }
+
+    /// Check that a buffer holding `used` bytes is within the budget,
+    /// warning the first time it crosses the `warn_at` fraction.
+    pub(crate) fn check(&mut self, used: usize) -> Result<(), OpenError> {
+        if used > self.budget.limit {
+            return Err(OpenError::MemoryBudgetExceeded {
+                feature: self.feature,
+                needed: used,
+                budget: self.budget.limit,
+            });
+        }
+        if !self.warned && used as f64 >= self.budget.limit as f64 * self.budget.warn_at {
+            self.warned = true;
+            warn(&MemoryWarning {
+                feature: self.feature,
+                used,
+                limit: self.budget.limit,
+            });
+        }
+        Ok(())
+    }
+
+    /// Like [`Self::check`], but for use in I/O code.
+    pub(crate) fn check_io(&mut self, used: usize) -> io::Result<()> {
+        self.check(used)
+            .map_err(|err| io::Error::new(io::ErrorKind::OutOfMemory, err))
+    }
+}
+
+/// If `err` is an `OpenError`, such as from [`BudgetTracker::check_io`],
+/// return it, and otherwise return `err`.
+pub(crate) fn into_open_error(err: io::Error) -> Result<OpenError, io::Error> {
+    if OpenError::of(&err).is_none() {
+        return Err(err);
+    }
+    Ok(*err.into_inner().unwrap().downcast::<OpenError>().unwrap())
+}
+
+/// Like `BufRead::read_until`, within the budget. A record which exceeds
+/// the budget is consumed and discarded, so that the next record can still
+/// be read.
+pub(crate) fn read_until_within(
+    reader: &mut impl BufRead,
+    separator: u8,
+    record: &mut Vec<u8>,
+    tracker: &mut BudgetTracker,
+) -> io::Result<usize> {
+    let start = record.len();
+    let mut exceeded = None;
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        let (done, used) = match available.iter().position(|byte| *byte == separator) {
+            Some(index) => (true, index + 1),
+            None => (available.is_empty(), available.len()),
+        };
+        if exceeded.is_none() {
+            match tracker.check_io(record.len() - start + used) {
+                Ok(()) => record.extend_from_slice(&available[..used]),
+                Err(err) => {
+                    record.truncate(start);
+                    exceeded = Some(err);
+                }
+            }
+        }
+        reader.consume(used);
+        if done {
+            break;
+        }
+    }
+    match exceeded {
+        Some(err) => Err(err),
+        None => Ok(record.len() - start),
+    }
+}
+
+/// Like `BufRead::read_until` with a newline, within the budget.
+#[cfg(feature = "codecs")]
+pub(crate) fn read_line_within(
+    reader: &mut impl BufRead,
+    line: &mut Vec<u8>,
+    tracker: &mut BudgetTracker,
+) -> io::Result<usize> {
+    read_until_within(reader, b'\n', line, tracker)
+}
+
+#[cfg(test)]
+thread_local! {
+    /// A budget for the current test's thread, so that tests can use small
+    /// budgets without disturbing tests running concurrently.
+    static TEST_BUDGET: std::cell::Cell<Option<MemoryBudget>> =
+        const { std::cell::Cell::new(None) };
+
+    /// The features which have warned on the current test's thread.
+    static TEST_WARNINGS: std::cell::RefCell<Vec<&'static str>> =
+        const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// In tests, budgets are per-thread, so that tests don't disturb each
+/// other.
+#[cfg(test)]
+fn current() -> MemoryBudget {
+    TEST_BUDGET.with(std::cell::Cell::get).unwrap_or_default()
+}
+
+/// In tests, warnings are recorded for `with_test_budget` to return.
+#[cfg(test)]
+fn warn(warning: &MemoryWarning) {
+    TEST_WARNINGS.with(|warnings| warnings.borrow_mut().push(warning.feature));
+}
+
+/// Run `f` with `budget` in effect on this thread, and return its result
+/// and the features which warned.
+#[cfg(test)]
+pub(crate) fn with_test_budget<T>(
+    budget: MemoryBudget,
+    f: impl FnOnce() -> T,
+) -> (T, Vec<&'static str>) {
+    TEST_BUDGET.with(|test_budget| test_budget.set(Some(budget)));
+    TEST_WARNINGS.with(|warnings| warnings.borrow_mut().clear());
+    let result = f();
+    TEST_BUDGET.with(|test_budget| test_budget.set(None));
+    let warnings = TEST_WARNINGS.with(|warnings| warnings.take());
+    (result, warnings)
+}
+
+/// A budget of `limit` bytes, which warns at half of it.
+#[cfg(test)]
+pub(crate) fn test_budget(limit: usize) -> MemoryBudget {
+    MemoryBudget {
+        limit,
+        warn_at: 0.5,
+    }
+}
+
+/// Return the feature named by a `MemoryBudgetExceeded` error in the chain
+/// of `err`.
+#[cfg(test)]
+pub(crate) fn exceeded_feature(err: &anyhow::Error) -> Option<&'static str> {
+    err.chain().find_map(|cause| {
+        let open_error = cause
+            .downcast_ref::<OpenError>()
+            .or_else(|| cause.downcast_ref::<io::Error>().and_then(OpenError::of))?;
+        match open_error {
+            OpenError::MemoryBudgetExceeded { feature, .. } => Some(*feature),
+            _ => None,
+        }
+    })
+}
+
+#[test]
+fn memory_budget_check() {
+    let ((), warnings) = with_test_budget(test_budget(10), || {
+        let mut tracker = BudgetTracker::new("test");
+        tracker.check(4).unwrap();
+        tracker.check(5).unwrap();
+        tracker.check(10).unwrap();
+        match tracker.check(11) {
+            Err(OpenError::MemoryBudgetExceeded {
+                feature: "test",
+                needed: 11,
+                budget: 10,
+            }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    });
+    assert_eq!(warnings, ["test"]);
+}
+
+#[test]
+fn memory_budget_read_until() {
+    let (result, _) = with_test_budget(test_budget(4), || {
+        let mut reader = &b"one\0three\0two"[..];
+        let mut tracker = BudgetTracker::new("test");
+        let mut read = || {
+            let mut record = Vec::new();
+            read_until_within(&mut reader, b'\0', &mut record, &mut tracker).map(|_| record)
+        };
+        [read(), read(), read(), read()]
+    });
+    let [one, three, two, end] = result;
+    assert_eq!(one.unwrap(), b"one\0");
+    let err = three.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+    assert!(matches!(
+        OpenError::of(&err),
+        Some(OpenError::MemoryBudgetExceeded {
+            feature: "test",
+            ..
+        })
+    ));
+
+    // The oversized record was discarded.
+    assert_eq!(two.unwrap(), b"two");
+    assert_eq!(end.unwrap(), b"");
+}
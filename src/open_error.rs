@@ -0,0 +1,164 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Errors from opening streams which callers may wish to handle specially.
+///
+/// These are returned inside an `anyhow::Error`, from which they can be
+/// obtained with `downcast_ref`. Errors which occur while reading or
+/// writing, such as [`Self::MemoryBudgetExceeded`], are returned inside an
+/// `io::Error`, from which they can be obtained with [`OpenError::of`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OpenError {
+    /// No connection arrived at an `accept:` URL within its
+    /// `accept_timeout`.
+    AcceptTimeout(Duration),
+    /// A `lock=` option was given, and another process holds a conflicting
+    /// lock on the file.
+    Locked {
+        /// The path of the locked file.
+        path: PathBuf,
+        /// A description of the lock's holder, if one could be found.
+        holder_hint: Option<String>,
+    },
+    /// The name uses a syntax, such as an HTTP URL or a child process, which
+    /// isn't available on the current platform, such as WASI.
+    UnsupportedOnPlatform(&'static str),
+    /// The operation was cancelled with a [`CancellationToken`].
+    ///
+    /// [`CancellationToken`]: crate::CancellationToken
+    Cancelled,
+    /// A glob pattern matched nothing.
+    GlobNoMatches(String),
+    /// A glob pattern matched more paths than the limit.
+    GlobTooManyMatches {
+        /// The pattern.
+        pattern: String,
+        /// The maximum number of matches.
+        limit: usize,
+    },
+    /// An input's `#fragment` doesn't name a section of it.
+    UnknownFragment {
+        /// The fragment, without the `#`.
+        fragment: String,
+        /// The fragments which would have named sections of the input, if
+        /// they were cheap to compute.
+        available: Vec<String>,
+    },
+    /// Reading an input to resolve its `#fragment` failed.
+    FragmentRead(io::Error),
+    /// Buffering a stream in memory would exceed the [`MemoryBudget`].
+    ///
+    /// [`MemoryBudget`]: crate::MemoryBudget
+    MemoryBudgetExceeded {
+        /// The part of nameless doing the buffering, such as `"data URL"`.
+        feature: &'static str,
+        /// The number of bytes the buffer would need to hold.
+        needed: usize,
+        /// The budget's limit.
+        budget: usize,
+    },
+    /// Two arguments in a list name the same input, and the policy's
+    /// [`DuplicateArguments`] is `Error`.
+    ///
+    /// [`DuplicateArguments`]: crate::DuplicateArguments
+    DuplicateArgument {
+        /// The later argument.
+        name: String,
+        /// The index of the earlier argument in the list.
+        first: usize,
+        /// The index of the later argument in the list.
+        second: usize,
+    },
+    /// The name passed to [`probe`] names something which can't be probed
+    /// without opening it, such as a child process or an interactive
+    /// stream.
+    ///
+    /// [`probe`]: crate::probe
+    NotProbeable(&'static str),
+    /// [`probe`] couldn't obtain an input's metadata, because its name is
+    /// invalid or the underlying `stat` or `HEAD` request failed.
+    ///
+    /// [`probe`]: crate::probe
+    ProbeFailed(anyhow::Error),
+    /// [`LazyOutput::validate`] found that the output couldn't be opened,
+    /// because its name is invalid or its destination isn't writable.
+    ///
+    /// [`LazyOutput::validate`]: crate::LazyOutput::validate
+    ValidationFailed(anyhow::Error),
+}
+
+impl OpenError {
+    /// If `e` wraps an `OpenError`, return it.
+    pub fn of(e: &io::Error) -> Option<&Self> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<Self>())
+    }
+}
+
+impl Error for OpenError {}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AcceptTimeout(timeout) => write!(
+                f,
+                "no connection was accepted within {}",
+                humantime::format_duration(*timeout)
+            ),
+            Self::Locked { path, holder_hint } => {
+                write!(f, "{} is locked", path.display())?;
+                if let Some(holder_hint) = holder_hint {
+                    write!(f, " ({})", holder_hint)?;
+                }
+                Ok(())
+            }
+            Self::UnsupportedOnPlatform(syntax) => {
+                write!(f, "{} are not supported on this platform", syntax)
+            }
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::GlobNoMatches(pattern) => write!(f, "no matches for {}", pattern),
+            Self::GlobTooManyMatches { pattern, limit } => {
+                write!(f, "{} matches more than {} paths", pattern, limit)
+            }
+            Self::UnknownFragment {
+                fragment,
+                available,
+            } => {
+                write!(f, "no section named \"#{}\" in the input", fragment)?;
+                if !available.is_empty() {
+                    write!(f, "; available: {}", available.join(", "))?;
+                }
+                Ok(())
+            }
+            Self::FragmentRead(err) => {
+                write!(f, "failed to read input to resolve its fragment: {}", err)
+            }
+            Self::MemoryBudgetExceeded {
+                feature,
+                needed,
+                budget,
+            } => write!(
+                f,
+                "{} needs {} bytes of memory, which exceeds the budget of {} bytes",
+                feature, needed, budget
+            ),
+            Self::DuplicateArgument {
+                name,
+                first,
+                second,
+            } => write!(
+                f,
+                "argument {} ({}) names the same input as argument {}",
+                second + 1,
+                name,
+                first + 1
+            ),
+            Self::NotProbeable(syntax) => write!(f, "{} names cannot be probed", syntax),
+            Self::ProbeFailed(err) => write!(f, "{:#}", err),
+            Self::ValidationFailed(err) => write!(f, "{:#}", err),
+        }
+    }
+}
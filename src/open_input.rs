@@ -1,58 +1,152 @@
+#[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+use crate::clipboard::{read_clipboard, system_clipboard, ClipboardOptions};
+use crate::compressed_progress::CompressedProgress;
+use crate::diagnose::open_error;
+use crate::end_status::{EndState, TrackedReader};
+#[cfg(not(target_os = "wasi"))]
+use crate::http_cache::{self, http_cache, CachedEntry, EntryHeader, StoringReader};
+#[cfg(not(target_os = "wasi"))]
+use crate::http_pool::http_agent;
+use crate::lock::{lock, LockOptions};
+use crate::memory_budget::BudgetTracker;
 use crate::path_to_name::path_to_name;
-use crate::{MediaType, Mime};
+#[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+use crate::stream_options::CLIPBOARD;
+use crate::stream_options::{
+    parse_options, parse_url_options, split_options, take_prefixed_options, FILE_INPUT, HTTP,
+};
+use crate::syntax::{classify_with_policy, split_path_fragment};
+use crate::telemetry::{traced_open, Telemetry};
+#[cfg(target_os = "wasi")]
+use crate::OpenError;
+#[cfg(not(any(windows, target_os = "wasi")))]
+use crate::{
+    child_words::split_child,
+    drop_error::report_drop_error,
+    syntax::split_pipeline,
+    teardown::{reap_child, CHILD_EXIT_GRACE},
+};
+use crate::{CacheStatus, MediaType, Mime, OpenPolicy, StreamKind, StreamOptions, SyntaxKind};
 use anyhow::anyhow;
 use clap::AmbientAuthority;
 use data_url::DataUrl;
-use flate2::read::GzDecoder;
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use io_arrays::ReadAt;
 use io_streams::StreamReader;
+use percent_encoding::percent_decode_str;
 use std::ffi::OsStr;
 use std::fs::File;
+use std::io::Read;
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 use url::Url;
-#[cfg(feature = "ssh2")]
+#[cfg(all(feature = "ssh2", not(target_os = "wasi")))]
 use {percent_encoding::percent_decode, ssh2::Session, std::net::TcpStream};
+#[cfg(not(any(windows, target_os = "wasi")))]
+use {
+    std::io,
+    std::process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    std::thread::{self, JoinHandle},
+};
 
 pub(crate) struct Input {
     pub(crate) name: String,
     pub(crate) reader: StreamReader,
     pub(crate) media_type: MediaType,
     pub(crate) initial_size: Option<u64>,
+    pub(crate) kind: StreamKind,
+    pub(crate) end_state: EndState,
+    pub(crate) compressed: Option<CompressedProgress>,
+    /// The `#fragment` selecting a section of the input, if any.
+    pub(crate) fragment: Option<String>,
+    /// The options given with the input's name.
+    pub(crate) options: StreamOptions,
+    /// How the input was served from the HTTP cache, if one is set.
+    pub(crate) cache_status: Option<CacheStatus>,
+    /// Whether `reader` is a pipe from a thread which produces the input.
+    #[cfg_attr(not(all(feature = "poll", unix)), allow(dead_code))]
+    pub(crate) piped_thread: bool,
 }
 
 pub(crate) fn open_input(
     os: &OsStr,
-    _ambient_authority: AmbientAuthority,
-) -> anyhow::Result<Input> {
-    if let Some(s) = os.to_str() {
-        // If we can parse it as a URL, treat it as such.
-        if let Ok(url) = Url::parse(s) {
-            return open_url(url);
-        }
+    policy: &OpenPolicy,
+    ambient_authority: AmbientAuthority,
+) -> anyhow::Result<(Input, Telemetry)> {
+    traced_open(os, || open_untraced(os, policy, ambient_authority))
+}
 
-        // Special-case "-" to mean stdin.
-        if s == "-" {
-            return acquire_stdin();
-        }
-    }
+/// Open an input, without tracing it, for `open_input` and for the sources
+/// of pipelines.
+fn open_untraced(
+    os: &OsStr,
+    policy: &OpenPolicy,
+    ambient_authority: AmbientAuthority,
+) -> anyhow::Result<Input> {
+    match classify_with_policy(os, policy)? {
+        SyntaxKind::Pipeline => {
+            if !policy.allow_exec {
+                return Err(anyhow!("pipelines are disabled by policy"));
+            }
+            #[cfg(not(any(windows, target_os = "wasi")))]
+            {
+                let s = os.to_str().unwrap();
+                spawn_pipeline(s, &split_pipeline(s).unwrap(), policy, ambient_authority)
+            }
 
-    #[cfg(not(windows))]
-    {
-        let lossy = os.to_string_lossy();
+            #[cfg(windows)]
+            {
+                let _ = ambient_authority;
+                Err(anyhow!("pipelines are not supported on Windows yet"))
+            }
 
-        // Strings beginning with "$(" are commands.
-        if lossy.starts_with("$(") {
-            return spawn_child(os, &lossy);
+            #[cfg(target_os = "wasi")]
+            {
+                let _ = ambient_authority;
+                Err(OpenError::UnsupportedOnPlatform("pipelines").into())
+            }
+        }
+        SyntaxKind::Url(_) => open_url(Url::parse(os.to_str().unwrap()).unwrap()),
+        SyntaxKind::Stdio => acquire_stdin(),
+        SyntaxKind::Command => {
+            if !policy.allow_exec {
+                return Err(anyhow!("child processes are disabled by policy"));
+            }
+            #[cfg(not(any(windows, target_os = "wasi")))]
+            {
+                spawn_child(os)
+            }
+            #[cfg(windows)]
+            {
+                Err(anyhow!("child processes are not supported on Windows yet"))
+            }
+            #[cfg(target_os = "wasi")]
+            {
+                Err(OpenError::UnsupportedOnPlatform("child processes").into())
+            }
+        }
+        SyntaxKind::Path => {
+            let (path, fragment) = split_path_fragment(os);
+            let (path, query) = split_options(path, &FILE_INPUT);
+            let options = parse_options(query, &FILE_INPUT)?;
+            let mut input = open_file(Path::new(path), options)?;
+            input.fragment = fragment.map(str::to_owned);
+            Ok(input)
         }
     }
-
-    // Otherwise try opening it as a path in the filesystem namespace.
-    open_path(Path::new(os))
 }
 
 fn acquire_stdin() -> anyhow::Result<Input> {
     let reader = StreamReader::stdin()?;
     Ok(Input {
+        end_state: EndState::default(),
+        compressed: None,
+        fragment: None,
+        options: StreamOptions::default(),
+        cache_status: None,
+        piped_thread: false,
+        kind: StreamKind::Stdio,
         name: "-".to_owned(),
         reader,
         media_type: MediaType::unknown(),
@@ -62,36 +156,107 @@ fn acquire_stdin() -> anyhow::Result<Input> {
 
 fn open_url(url: Url) -> anyhow::Result<Input> {
     match url.scheme() {
-        "http" | "https" => open_http_url_str(url.as_str()),
+        #[cfg(not(target_os = "wasi"))]
+        "http" | "https" => {
+            let mut url = url;
+            let options = take_prefixed_options(&mut url, &HTTP)?;
+            let mut input = open_http_url_str(url.as_str())?;
+            input.options = options;
+            Ok(input)
+        }
+        #[cfg(target_os = "wasi")]
+        "http" | "https" => Err(OpenError::UnsupportedOnPlatform("HTTP URLs").into()),
         "data" => open_data_url_str(url.as_str()),
         "file" => {
             if !url.username().is_empty()
                 || url.password().is_some()
                 || url.has_host()
                 || url.port().is_some()
-                || url.query().is_some()
-                || url.fragment().is_some()
             {
-                return Err(anyhow!("file URL should only contain a path"));
+                return Err(anyhow!("file URL should only contain a path and options"));
             }
+            let options = parse_url_options(&url, &FILE_INPUT)?;
+            let fragment = url
+                .fragment()
+                .filter(|fragment| !fragment.is_empty())
+                .map(|fragment| {
+                    percent_decode_str(fragment)
+                        .decode_utf8_lossy()
+                        .into_owned()
+                });
+            // The query and fragment aren't part of the path.
+            let mut url = url;
+            url.set_query(None);
+            url.set_fragment(None);
             // TODO: https://docs.rs/url/latest/url/struct.Url.html#method.to_file_path
             // is ambiguous about how it can fail. What is `Path::new_opt`?
-            open_path(
+            let mut input = open_file(
                 &url.to_file_path()
                     .map_err(|_: ()| anyhow!("unknown file URL weirdness"))?,
-            )
+                options,
+            )?;
+            input.fragment = fragment;
+            Ok(input)
         }
-        #[cfg(feature = "ssh2")]
+        #[cfg(all(feature = "ssh2", not(target_os = "wasi")))]
         "scp" => open_scp_url(&url),
+        #[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+        "clipboard" => open_clipboard_url(&url),
         other => Err(anyhow!("unsupported URL scheme \"{}\"", other)),
     }
 }
 
+#[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+fn open_clipboard_url(url: &Url) -> anyhow::Result<Input> {
+    let options = ClipboardOptions::parse(url)?;
+    let text = read_clipboard(&mut *system_clipboard()?, &options)?;
+    let reader = StreamReader::bytes(text.as_bytes())?;
+    Ok(Input {
+        end_state: EndState::default(),
+        compressed: None,
+        fragment: None,
+        options: parse_url_options(url, &CLIPBOARD)?,
+        cache_status: None,
+        piped_thread: false,
+        kind: StreamKind::Clipboard,
+        name: url.as_str().to_owned(),
+        reader,
+        media_type: MediaType::text(),
+        initial_size: Some(text.len().try_into().unwrap()),
+    })
+}
+
+#[cfg(not(target_os = "wasi"))]
 fn open_http_url_str(http_url_str: &str) -> anyhow::Result<Input> {
+    let cache = http_cache();
+    let cached = cache
+        .as_ref()
+        .and_then(|cache| http_cache::lookup(cache, http_url_str));
+    let cached = match cached {
+        Some(cached) if cached.is_fresh() => {
+            return open_cached_http_url_str(http_url_str, cached, CacheStatus::Hit)
+        }
+        cached => cached,
+    };
+
     // TODO: Set any headers, like "Accept"?
-    let response = ureq::get(http_url_str)
+    let mut request = http_agent().get(http_url_str);
+    if let Some(cached) = &cached {
+        if let Some(etag) = &cached.header.etag {
+            request = request.set("If-None-Match", etag);
+        }
+        if let Some(last_modified) = &cached.header.last_modified {
+            request = request.set("If-Modified-Since", last_modified);
+        }
+    }
+    let response = request
         .call()
         .map_err(|e| anyhow!("HTTP error fetching {}: {}", http_url_str, e))?;
+    if response.status() == 304 {
+        if let Some(cached) = cached {
+            return open_cached_http_url_str(http_url_str, cached, CacheStatus::Revalidated);
+        }
+    }
 
     let initial_size = Some(
         response
@@ -101,10 +266,56 @@ fn open_http_url_str(http_url_str: &str) -> anyhow::Result<Input> {
     );
     let media_type = response.content_type();
     let media_type = MediaType::from_mime(Mime::from_str(media_type)?);
+    let entry = match &cache {
+        Some(_) => EntryHeader::from_response(http_url_str, &response),
+        None => None,
+    };
 
-    let reader = response.into_reader();
-    let reader = StreamReader::piped_thread(Box::new(reader))?;
+    let end_state = EndState::default();
+    let reader = TrackedReader::new(response.into_reader(), end_state.clone(), initial_size);
+    let reader: Box<dyn Read + Send> = match (&cache, entry) {
+        (Some(cache), Some(entry)) => Box::new(StoringReader::new(
+            reader,
+            cache.clone(),
+            &entry,
+            initial_size,
+        )),
+        _ => Box::new(reader),
+    };
+    let reader = StreamReader::piped_thread(reader)?;
+    Ok(Input {
+        end_state,
+        compressed: None,
+        fragment: None,
+        options: StreamOptions::default(),
+        cache_status: cache.map(|_| CacheStatus::Miss),
+        piped_thread: true,
+        kind: StreamKind::Http,
+        name: http_url_str.to_owned(),
+        media_type,
+        reader,
+        initial_size,
+    })
+}
+
+/// Serve an HTTP input from its entry in the HTTP cache.
+#[cfg(not(target_os = "wasi"))]
+fn open_cached_http_url_str(
+    http_url_str: &str,
+    cached: CachedEntry,
+    cache_status: CacheStatus,
+) -> anyhow::Result<Input> {
+    let media_type = MediaType::from_mime(Mime::from_str(&cached.header.media_type)?);
+    let initial_size = Some(cached.body_len);
+    let reader = StreamReader::file(cached.into_body());
     Ok(Input {
+        end_state: EndState::default(),
+        compressed: None,
+        fragment: None,
+        options: StreamOptions::default(),
+        cache_status: Some(cache_status),
+        piped_thread: false,
+        kind: StreamKind::Http,
         name: http_url_str.to_owned(),
         media_type,
         reader,
@@ -116,10 +327,24 @@ fn open_data_url_str(data_url_str: &str) -> anyhow::Result<Input> {
     // TODO: `DataUrl` should really implement `std::error::Error`.
     let data_url =
         DataUrl::process(data_url_str).map_err(|e| anyhow!("invalid data URL syntax: {:?}", e))?;
+
+    // Check the budget against an estimate before decoding, so that an
+    // oversized body isn't decoded only to be rejected. Base64 decodes to
+    // about 3/4 of its length, and percent-encoding to at least 1/3.
+    let mut budget = BudgetTracker::new("data URL");
+    let (header, encoded) = data_url_str.split_once(',').unwrap_or_default();
+    let estimate = if header.to_ascii_lowercase().trim_end().ends_with(";base64") {
+        encoded.len() / 4 * 3
+    } else {
+        encoded.len() / 3
+    };
+    budget.check(estimate)?;
+
     // TODO: `DataUrl` should really really implement `std::error::Error`.
     let (body, fragment) = data_url
         .decode_to_vec()
         .map_err(|_| anyhow!("invalid base64 encoding"))?;
+    budget.check(body.len())?;
 
     if fragment.is_some() {
         return Err(anyhow!("data urls with fragments are unsupported"));
@@ -132,15 +357,22 @@ fn open_data_url_str(data_url_str: &str) -> anyhow::Result<Input> {
 
     let reader = StreamReader::bytes(&body)?;
     Ok(Input {
+        end_state: EndState::default(),
+        compressed: None,
+        fragment: None,
+        options: StreamOptions::default(),
+        cache_status: None,
+        piped_thread: false,
+        kind: StreamKind::Data,
         name: data_url_str.to_owned(),
         reader,
         media_type,
-        initial_size: Some(data_url_str.len().try_into().unwrap()),
+        initial_size: Some(body.len().try_into().unwrap()),
     })
 }
 
 // Handle URLs of the form `scp://[user@]host[:port][/path]`.
-#[cfg(feature = "ssh2")]
+#[cfg(all(feature = "ssh2", not(target_os = "wasi")))]
 fn open_scp_url(scp_url: &Url) -> anyhow::Result<Input> {
     if scp_url.query().is_some() || scp_url.fragment().is_some() {
         return Err(anyhow!("scp URL should only contain a socket address, optional username, optional password, and optional path"));
@@ -177,9 +409,18 @@ fn open_scp_url(scp_url: &Url) -> anyhow::Result<Input> {
 
     let path = Path::new(scp_url.path());
     let (channel, stat) = sess.scp_recv(path)?;
-    let reader = StreamReader::piped_thread(Box::new(channel))?;
-    let media_type = MediaType::from_extension(path.extension());
+    let end_state = EndState::default();
+    let reader = TrackedReader::new(channel, end_state.clone(), Some(stat.size()));
+    let reader = StreamReader::piped_thread(Box::new(reader))?;
+    let media_type = MediaType::from_path(path);
     Ok(Input {
+        end_state,
+        compressed: None,
+        fragment: None,
+        options: StreamOptions::default(),
+        cache_status: None,
+        piped_thread: true,
+        kind: StreamKind::Scp,
         name: scp_url.as_str().to_owned(),
         reader,
         media_type,
@@ -187,28 +428,79 @@ fn open_scp_url(scp_url: &Url) -> anyhow::Result<Input> {
     })
 }
 
-fn open_path(path: &Path) -> anyhow::Result<Input> {
+/// How to decode gzip files which consist of multiple concatenated members,
+/// such as those produced by appending the output of `gzip -c` to a file.
+#[derive(Clone, Copy)]
+enum GzipMembers {
+    /// Decode all the members, as the `gzip` command does.
+    Multi,
+    /// Stop after the first member.
+    Single,
+}
+
+/// Open the file at `path`, with the options from a `file:` URL or a plain
+/// path.
+fn open_file(path: &Path, options: StreamOptions) -> anyhow::Result<Input> {
+    let gzip_members = match options.get("gzip") {
+        Some("single") => GzipMembers::Single,
+        _ => GzipMembers::Multi,
+    };
+    let lock_options = options.get("lock").map(LockOptions::parse).transpose()?;
+    let mut input = open_path(path, gzip_members, lock_options)?;
+    input.options = options;
+    Ok(input)
+}
+
+fn open_path(
+    path: &Path,
+    gzip_members: GzipMembers,
+    lock_options: Option<LockOptions>,
+) -> anyhow::Result<Input> {
     let name = path_to_name("file", path)?;
     // TODO: Should we have our own error type?
-    let file = File::open(path).map_err(|err| anyhow!("{}: {}", path.display(), err))?;
+    let file = File::open(path).map_err(|err| open_error(path, err))?;
+    if let Some(lock_options) = lock_options {
+        lock(&file, path, lock_options)?;
+    }
     if path.extension() == Some(Path::new("gz").as_os_str()) {
         // TODO: We shouldn't really need to allocate a `PathBuf` here.
         let path = path.with_extension("");
-        let media_type = MediaType::from_extension(path.extension());
+        let media_type = MediaType::from_path(&path);
         let initial_size = None;
-        let reader = GzDecoder::new(file);
+        let compressed = CompressedProgress::new(file.metadata()?.len());
+        let file = compressed.reader(file);
+        let decoder: Box<dyn Read + Send> = match gzip_members {
+            GzipMembers::Multi => Box::new(MultiGzDecoder::new(file)),
+            GzipMembers::Single => Box::new(GzDecoder::new(file)),
+        };
+        let end_state = EndState::default();
+        let reader = TrackedReader::new(decoder, end_state.clone(), None);
         let reader = StreamReader::piped_thread(Box::new(reader))?;
         Ok(Input {
+            end_state,
+            compressed: Some(compressed),
+            fragment: None,
+            options: StreamOptions::default(),
+            cache_status: None,
+            piped_thread: true,
+            kind: StreamKind::File,
             name,
             reader,
             media_type,
             initial_size,
         })
     } else {
-        let media_type = MediaType::from_extension(path.extension());
+        let media_type = MediaType::from_path(path);
         let initial_size = Some(file.metadata()?.len());
         let reader = StreamReader::file(file);
         Ok(Input {
+            end_state: EndState::default(),
+            compressed: None,
+            fragment: None,
+            options: StreamOptions::default(),
+            cache_status: None,
+            piped_thread: false,
+            kind: StreamKind::File,
             name,
             reader,
             media_type,
@@ -217,32 +509,373 @@ fn open_path(path: &Path) -> anyhow::Result<Input> {
     }
 }
 
-#[cfg(not(windows))]
-fn spawn_child(os: &OsStr, lossy: &str) -> anyhow::Result<Input> {
-    use std::process::{Command, Stdio};
-    assert!(lossy.starts_with("$("));
-    if !lossy.ends_with(')') {
-        return Err(anyhow!("child string must end in ')'"));
+/// Open a file which was opened once for several inputs, giving this input
+/// its own position in it.
+pub(crate) fn open_shared_file(path: &Path, file: Arc<File>) -> anyhow::Result<Input> {
+    let name = path_to_name("file", path)?;
+    let media_type = MediaType::from_path(path);
+    let initial_size = Some(file.metadata()?.len());
+    let reader = SharedFileReader { file, offset: 0 };
+    let reader = StreamReader::piped_thread(Box::new(reader))?;
+    Ok(Input {
+        end_state: EndState::default(),
+        compressed: None,
+        fragment: None,
+        options: StreamOptions::default(),
+        cache_status: None,
+        piped_thread: true,
+        kind: StreamKind::File,
+        name,
+        reader,
+        media_type,
+        initial_size,
+    })
+}
+
+/// A reader over a shared file, which uses positional reads so that it
+/// doesn't disturb the other readers.
+struct SharedFileReader {
+    file: Arc<File>,
+    offset: u64,
+}
+
+impl Read for SharedFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.file.read_at(buf, self.offset)?;
+        self.offset += n as u64;
+        Ok(n)
     }
-    let s = if let Some(s) = os.to_str() {
-        s
-    } else {
-        return Err(anyhow!("Non-UTF-8 child strings not yet supported"));
-    };
-    let words = shell_words::split(&s[2..s.len() - 1])?;
-    let (first, rest) = words
-        .split_first()
-        .ok_or_else(|| anyhow!("child stream specified with '(...)' must contain a command"))?;
-    let child = Command::new(first)
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+fn spawn_child(os: &OsStr) -> anyhow::Result<Input> {
+    let words = split_child(os)?;
+    let (first, rest) = words.split_first().unwrap();
+    let name = os.to_string_lossy().into_owned();
+    let mut child = Command::new(first)
         .args(rest)
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .spawn()?;
-    let reader = StreamReader::child_stdout(child.stdout.unwrap());
+    let stdout = child.stdout.take().unwrap();
+    let reader = ChildrenReader {
+        stdout: Some(stdout),
+        children: vec![(name.clone(), child)],
+        unfed: None,
+        feeder: None,
+    };
+    let end_state = EndState::default();
+    let reader = TrackedReader::new(reader, end_state.clone(), None);
+    let reader = StreamReader::piped_thread(Box::new(reader))?;
     Ok(Input {
-        name: s.to_owned(),
+        end_state,
+        compressed: None,
+        fragment: None,
+        options: StreamOptions::default(),
+        cache_status: None,
+        piped_thread: true,
+        kind: StreamKind::Child,
+        name,
         reader,
         media_type: MediaType::unknown(),
         initial_size: None,
     })
 }
+
+/// Split a pipeline stage, written either as `cmd args` or `$(cmd args)`,
+/// into words.
+#[cfg(not(any(windows, target_os = "wasi")))]
+fn stage_words(stage: &str) -> anyhow::Result<Vec<std::ffi::OsString>> {
+    if stage.starts_with("$(") {
+        split_child(OsStr::new(stage))
+    } else {
+        split_child(OsStr::new(&format!("$({})", stage)))
+    }
+}
+
+/// Spawn the commands of a pipeline. The first stage is an input name in
+/// any syntax other than a pipeline, and each subsequent stage is a command
+/// whose stdin is connected to the previous stage's output.
+///
+/// If a stage fails to spawn, the stages already spawned are killed and
+/// reaped. The source isn't fed to the first command until the stream is
+/// first read, so no feeder thread is running yet.
+#[cfg(not(any(windows, target_os = "wasi")))]
+fn spawn_pipeline(
+    name: &str,
+    stages: &[&str],
+    policy: &OpenPolicy,
+    ambient_authority: AmbientAuthority,
+) -> anyhow::Result<Input> {
+    let (source, commands) = stages.split_first().unwrap();
+    let commands = commands
+        .iter()
+        .map(|command| Ok(((*command).to_owned(), stage_words(command)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let source = open_untraced(OsStr::new(source), policy, ambient_authority)?;
+    if source.fragment.is_some() {
+        return Err(anyhow!("fragments aren't supported in pipeline sources"));
+    }
+    let mut source = Some(source.reader);
+
+    let mut reader = ChildrenReader {
+        stdout: None,
+        children: Vec::new(),
+        unfed: None,
+        feeder: None,
+    };
+    for (command, words) in commands {
+        let (first, rest) = words.split_first().unwrap();
+        let stdin = match reader.stdout.take() {
+            Some(stdout) => Stdio::from(stdout),
+            None => Stdio::piped(),
+        };
+        let spawned = Command::new(first)
+            .args(rest)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                reader.abort();
+                return Err(anyhow!(
+                    "failed to spawn pipeline stage `{}`: {}",
+                    command,
+                    e
+                ));
+            }
+        };
+
+        if let (Some(stdin), Some(source)) = (child.stdin.take(), source.take()) {
+            reader.unfed = Some((source, stdin));
+        }
+        reader.stdout = child.stdout.take();
+        reader.children.push((command, child));
+    }
+
+    let end_state = EndState::default();
+    let reader = TrackedReader::new(reader, end_state.clone(), None);
+    let reader = StreamReader::piped_thread(Box::new(reader))?;
+    Ok(Input {
+        end_state,
+        compressed: None,
+        fragment: None,
+        options: StreamOptions::default(),
+        cache_status: None,
+        piped_thread: true,
+        kind: StreamKind::Child,
+        name: name.to_owned(),
+        reader,
+        media_type: MediaType::unknown(),
+        initial_size: None,
+    })
+}
+
+/// A reader for the stdout of the last of a sequence of child processes,
+/// which waits for all of them at the end of the stream and reports an
+/// error if any of them failed.
+#[cfg(not(any(windows, target_os = "wasi")))]
+struct ChildrenReader {
+    stdout: Option<ChildStdout>,
+    children: Vec<(String, Child)>,
+    /// For a pipeline, the source and the first command's stdin, until the
+    /// first read starts the feeder thread.
+    unfed: Option<(StreamReader, ChildStdin)>,
+    feeder: Option<JoinHandle<io::Result<()>>>,
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+impl ChildrenReader {
+    /// Feed the source into the first command from a thread.
+    fn start_feeder(&mut self) {
+        if let Some((mut source, mut stdin)) = self.unfed.take() {
+            self.feeder = Some(thread::spawn(move || -> io::Result<()> {
+                match io::copy(&mut source, &mut stdin) {
+                    Ok(_) => Ok(()),
+                    // The command exited without reading all its input,
+                    // which is its prerogative, as in a shell pipeline.
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+                    Err(e) => Err(e),
+                }
+            }));
+        }
+    }
+
+    /// Kill and reap the children spawned so far, for a pipeline which
+    /// failed to start.
+    fn abort(&mut self) {
+        self.unfed = None;
+        self.stdout = None;
+        for (_command, mut child) in self.children.drain(..) {
+            // The child may have exited already, in which case `kill`
+            // fails and `wait` collects its status.
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        // Wait for all the children and the feeder before reporting any
+        // errors, so that none are left behind.
+        let mut result = Ok(());
+        for (command, mut child) in self.children.drain(..) {
+            match child.wait() {
+                Ok(status) if status.success() => {}
+                Ok(status) => {
+                    if result.is_ok() {
+                        result = Err(io::Error::other(format!(
+                            "child process `{}` failed: {}",
+                            command, status
+                        )));
+                    }
+                }
+                Err(e) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+
+        if let Some(feeder) = self.feeder.take() {
+            let fed = feeder
+                .join()
+                .map_err(|_| io::Error::other("pipeline input thread panicked"))
+                .and_then(|fed| fed);
+            if result.is_ok() {
+                result = fed;
+            }
+        }
+
+        result
+    }
+}
+
+/// If the stream is dropped before its end, close the pipe from the last
+/// child, so that the children see that their output is unwanted, and reap
+/// them, killing any which don't exit promptly. The feeder thread isn't
+/// joined, as it may be blocked reading its source; it exits once the first
+/// child's stdin is closed.
+#[cfg(not(any(windows, target_os = "wasi")))]
+impl Drop for ChildrenReader {
+    fn drop(&mut self) {
+        drop(self.unfed.take());
+        drop(self.stdout.take());
+        for (_command, mut child) in self.children.drain(..) {
+            if let Err(e) = reap_child(&mut child, CHILD_EXIT_GRACE) {
+                report_drop_error(e);
+            }
+        }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+impl Read for ChildrenReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.start_feeder();
+        let n = self.stdout.as_mut().unwrap().read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            self.finish()?;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn pipeline_data_url() {
+    let (mut input, _telemetry) = open_input(
+        "data:,hello | $(tr a-z A-Z)".as_ref(),
+        &OpenPolicy::default(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    let mut s = String::new();
+    input.reader.read_to_string(&mut s).unwrap();
+    assert_eq!(s, "HELLO");
+    assert_eq!(input.name, "data:,hello | $(tr a-z A-Z)");
+    assert_eq!(input.media_type, MediaType::unknown());
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn pipeline_bare_commands() {
+    let (mut input, _telemetry) = open_input(
+        "data:,hello | tr a-z A-Z | $(tr L l) | sed 's/ | /x/'".as_ref(),
+        &OpenPolicy::default(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    let mut s = String::new();
+    input.reader.read_to_string(&mut s).unwrap();
+    assert_eq!(s, "HEllO");
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn pipeline_failing_stage() {
+    use crate::InputByteStream;
+    use clap::TryFromOsArg;
+
+    let mut input = InputByteStream::try_from_os_str_arg(
+        "data:,hello | $(sh -c 'cat > /dev/null; exit 3') | $(cat)".as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    let mut s = String::new();
+    let err = input.read_to_string(&mut s).unwrap_err();
+    assert!(err.to_string().contains("exit status: 3"), "{}", err);
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn pipeline_failing_spawn() {
+    let err = match open_input(
+        "data:,hello | $(cat) | $(nameless-test-no-such-command)".as_ref(),
+        &OpenPolicy::default(),
+        clap::ambient_authority(),
+    ) {
+        Ok(_) => panic!("spawning a missing command succeeded"),
+        Err(err) => err,
+    };
+    assert!(
+        err.to_string()
+            .contains("failed to spawn pipeline stage `$(nameless-test-no-such-command)`"),
+        "{}",
+        err
+    );
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn pipeline_disabled_by_policy() {
+    let policy = OpenPolicy {
+        allow_exec: false,
+        ..OpenPolicy::default()
+    };
+    assert!(open_input(
+        "data:,hello | $(tr a-z A-Z)".as_ref(),
+        &policy,
+        clap::ambient_authority(),
+    )
+    .is_err());
+}
+
+#[test]
+fn data_url_memory_budget() {
+    use crate::memory_budget::{exceeded_feature, test_budget, with_test_budget};
+
+    let (result, _) = with_test_budget(test_budget(4), || open_data_url_str("data:,hello"));
+    assert_eq!(exceeded_feature(&result.err().unwrap()), Some("data URL"));
+    let (result, _) = with_test_budget(test_budget(5), || open_data_url_str("data:,hello"));
+    assert!(result.is_ok());
+
+    // Oversized base64 is rejected before it's decoded, even if it's
+    // invalid.
+    let base64 = format!("data:;base64,{}!", "AAAA".repeat(4));
+    let (result, _) = with_test_budget(test_budget(8), || open_data_url_str(&base64));
+    assert_eq!(exceeded_feature(&result.err().unwrap()), Some("data URL"));
+    let (result, _) = with_test_budget(test_budget(12), || open_data_url_str(&base64));
+    assert_eq!(result.err().unwrap().to_string(), "invalid base64 encoding");
+}
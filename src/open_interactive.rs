@@ -1,62 +1,196 @@
-use crate::path_to_name::path_to_name;
+#[cfg(not(any(windows, target_os = "wasi")))]
+use crate::child_words::split_child;
+use crate::drop_error::report_drop_error;
+#[cfg(not(any(windows, target_os = "wasi")))]
+use crate::stream_options::{parse_options, split_options, CHILD_INTERACTIVE};
+#[cfg(not(target_os = "wasi"))]
+use crate::stream_options::{parse_url_options, take_prefixed_options, ACCEPT, CONNECT};
+use crate::syntax::classify_with_policy;
+use crate::teardown::{reap_child, CHILD_EXIT_GRACE};
+use crate::{OpenError, OpenPolicy, StreamKind, SyntaxKind};
 use anyhow::anyhow;
-use char_device::CharDevice;
 use clap::AmbientAuthority;
 use io_streams::StreamDuplexer;
+use layered_io::Status;
 use std::ffi::OsStr;
-use std::net::{TcpListener, TcpStream};
 #[cfg(unix)]
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::Path;
+use std::process::Child;
 use url::Url;
+#[cfg(not(target_os = "wasi"))]
+use {
+    crate::path_to_name::path_to_name,
+    crate::CancellationToken,
+    char_device::CharDevice,
+    std::io,
+    std::net::{TcpListener, TcpStream},
+    std::thread,
+    std::time::{Duration, Instant},
+};
+
+/// How often to check for a connection when accepting with a timeout.
+#[cfg(not(target_os = "wasi"))]
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 pub(crate) struct Interactive {
     pub(crate) name: String,
+    pub(crate) kind: StreamKind,
     pub(crate) duplexer: StreamDuplexer,
+    pub(crate) child: Option<InteractiveChild>,
+}
+
+/// A child process whose pipes, or the master side of whose
+/// pseudo-terminal, are the duplexer of an `Interactive`. It's reaped when
+/// the stream is dropped.
+#[cfg_attr(any(windows, target_os = "wasi"), allow(dead_code))]
+pub(crate) struct InteractiveChild {
+    child: Child,
+    pty: bool,
+}
+
+/// Reads from the master side of a pseudo-terminal fail with `EIO` once the
+/// child has exited, so treat that as the end of the stream.
+pub(crate) fn pty_read_result(
+    child: &Option<InteractiveChild>,
+    result: std::io::Result<(usize, Status)>,
+) -> std::io::Result<(usize, Status)> {
+    match result {
+        Err(err) if child.as_ref().is_some_and(|child| child.pty) && is_pty_hangup(&err) => {
+            Ok((0, Status::End))
+        }
+        result => result,
+    }
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+fn is_pty_hangup(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(rustix::io::Errno::IO.raw_os_error())
+}
+
+#[cfg(any(windows, target_os = "wasi"))]
+fn is_pty_hangup(_err: &std::io::Error) -> bool {
+    false
+}
+
+impl Drop for InteractiveChild {
+    fn drop(&mut self) {
+        // The child's pipes are closed before this is dropped, so give it a
+        // chance to see the end of its input and exit.
+        if !self.pty {
+            if let Err(err) = reap_child(&mut self.child, CHILD_EXIT_GRACE) {
+                report_drop_error(err);
+            }
+            return;
+        }
+
+        // The master side is closed before this is dropped, so the child
+        // sees its terminal hang up, but it may ignore that, so don't wait
+        // for it to exit on its own.
+        match self.child.try_wait() {
+            Ok(Some(_status)) => {}
+            Ok(None) => {
+                let _ = self.child.kill();
+                if let Err(err) = self.child.wait() {
+                    report_drop_error(err);
+                }
+            }
+            Err(err) => report_drop_error(err),
+        }
+    }
 }
 
 pub(crate) fn open_interactive(
     os: &OsStr,
+    policy: &OpenPolicy,
     _ambient_authority: AmbientAuthority,
 ) -> anyhow::Result<Interactive> {
-    if let Some(s) = os.to_str() {
-        // If we can parse it as a URL, treat it as such.
-        if let Ok(url) = Url::parse(s) {
-            return open_url(url);
-        }
-
-        // Special-case "-" to mean (stdin, stdout).
-        if s == "-" {
-            return acquire_stdin_stdout();
+    match classify_with_policy(os, policy)? {
+        SyntaxKind::Pipeline => Err(anyhow!("pipelines are only supported for input")),
+        SyntaxKind::Url(_) => open_url(Url::parse(os.to_str().unwrap()).unwrap(), policy),
+        SyntaxKind::Stdio => acquire_stdin_stdout(),
+        SyntaxKind::Command => {
+            if !policy.allow_exec {
+                return Err(anyhow!("child processes are disabled by policy"));
+            }
+            #[cfg(not(any(windows, target_os = "wasi")))]
+            {
+                spawn_child(os)
+            }
+            #[cfg(windows)]
+            {
+                Err(anyhow!("child processes are not supported on Windows yet"))
+            }
+            #[cfg(target_os = "wasi")]
+            {
+                Err(OpenError::UnsupportedOnPlatform("child processes").into())
+            }
         }
+        SyntaxKind::Path => open_path(Path::new(os)),
     }
+}
 
-    #[cfg(not(windows))]
-    {
-        let lossy = os.to_string_lossy();
-
-        // Strings beginning with "$(" are commands.
-        if lossy.starts_with("$(") {
-            return spawn_child(os, &lossy);
+/// Check that `os` is a supported interactive stream name, without binding,
+/// connecting, spawning, or opening anything.
+pub(crate) fn validate_interactive(os: &OsStr, policy: &OpenPolicy) -> anyhow::Result<()> {
+    match classify_with_policy(os, policy)? {
+        SyntaxKind::Pipeline => Err(anyhow!("pipelines are only supported for input")),
+        SyntaxKind::Url(_) => {
+            let url = Url::parse(os.to_str().unwrap()).unwrap();
+            match url.scheme() {
+                #[cfg(not(target_os = "wasi"))]
+                "connect" => parse_connect_url(url).map(drop),
+                #[cfg(not(target_os = "wasi"))]
+                "accept" => parse_accept_url(&url).map(drop),
+                #[cfg(target_os = "wasi")]
+                "connect" | "accept" => Err(OpenError::UnsupportedOnPlatform("socket URLs").into()),
+                scheme @ "http" | scheme @ "https" | scheme @ "file" | scheme @ "data" => {
+                    Err(anyhow!("non-interactive URL scheme \"{}\"", scheme))
+                }
+                other => Err(anyhow!("unsupported URL scheme \"{}\"", other)),
+            }
+        }
+        SyntaxKind::Stdio | SyntaxKind::Path => Ok(()),
+        SyntaxKind::Command => {
+            if !policy.allow_exec {
+                return Err(anyhow!("child processes are disabled by policy"));
+            }
+            #[cfg(not(any(windows, target_os = "wasi")))]
+            {
+                let (command_str, query) = split_options(os, &CHILD_INTERACTIVE);
+                parse_options(query, &CHILD_INTERACTIVE)?;
+                split_child(command_str).map(drop)
+            }
+            #[cfg(windows)]
+            {
+                Err(anyhow!("child processes are not supported on Windows yet"))
+            }
+            #[cfg(target_os = "wasi")]
+            {
+                Err(OpenError::UnsupportedOnPlatform("child processes").into())
+            }
         }
     }
-
-    // Otherwise try opening it as a path in the filesystem namespace.
-    open_path(Path::new(os))
 }
 
 fn acquire_stdin_stdout() -> anyhow::Result<Interactive> {
     let duplexer = StreamDuplexer::stdin_stdout()?;
     Ok(Interactive {
         name: "-".to_owned(),
+        kind: StreamKind::Stdio,
         duplexer,
+        child: None,
     })
 }
 
-fn open_url(url: Url) -> anyhow::Result<Interactive> {
+fn open_url(url: Url, policy: &OpenPolicy) -> anyhow::Result<Interactive> {
     match url.scheme() {
+        #[cfg(not(target_os = "wasi"))]
         "connect" => open_connect_url(url),
-        "accept" => open_accept_url(url),
+        #[cfg(not(target_os = "wasi"))]
+        "accept" => open_accept_url(url, policy.cancel_token.as_ref()),
+        #[cfg(target_os = "wasi")]
+        "connect" | "accept" => Err(OpenError::UnsupportedOnPlatform("socket URLs").into()),
         scheme @ "http" | scheme @ "https" | scheme @ "file" | scheme @ "data" => {
             Err(anyhow!("non-interactive URL scheme \"{}\"", scheme))
         }
@@ -64,7 +198,12 @@ fn open_url(url: Url) -> anyhow::Result<Interactive> {
     }
 }
 
-fn open_connect_url(url: Url) -> anyhow::Result<Interactive> {
+/// Check a `connect:` URL, and return it without its options.
+#[cfg(not(target_os = "wasi"))]
+fn parse_connect_url(mut url: Url) -> anyhow::Result<Url> {
+    // nameless' own options have a `nameless.` prefix here. There aren't
+    // any yet, but this reports typos the same way as elsewhere.
+    take_prefixed_options(&mut url, &CONNECT)?;
     if !url.username().is_empty()
         || url.password().is_some()
         || url.query().is_some()
@@ -74,38 +213,46 @@ fn open_connect_url(url: Url) -> anyhow::Result<Interactive> {
     }
 
     if url.path().is_empty() {
-        let port = match url.port() {
-            Some(port) => port,
-            None => return Err(anyhow!("TCP connect URL should have a port")),
-        };
-        let host_str = match url.host_str() {
-            Some(host_str) => host_str,
-            None => return Err(anyhow!("TCP connect URL should have a host")),
-        };
-
-        let duplexer = TcpStream::connect((host_str, port))?;
+        if url.port().is_none() {
+            return Err(anyhow!("TCP connect URL should have a port"));
+        }
+        if url.host_str().is_none() {
+            return Err(anyhow!("TCP connect URL should have a host"));
+        }
+    } else if url.port().is_some() || url.host_str().is_some() {
+        return Err(anyhow!(
+            "Unix-domain connect URL should only contain a path"
+        ));
+    }
+    Ok(url)
+}
+
+#[cfg(not(target_os = "wasi"))]
+fn open_connect_url(url: Url) -> anyhow::Result<Interactive> {
+    let url = parse_connect_url(url)?;
+
+    if url.path().is_empty() {
+        let duplexer = TcpStream::connect((url.host_str().unwrap(), url.port().unwrap()))?;
         let duplexer = StreamDuplexer::tcp_stream(duplexer);
 
         return Ok(Interactive {
             name: url.to_string(),
+            kind: StreamKind::Socket,
             duplexer,
+            child: None,
         });
     }
 
     #[cfg(unix)]
     {
-        if url.port().is_some() || url.host_str().is_some() {
-            return Err(anyhow!(
-                "Unix-domain connect URL should only contain a path"
-            ));
-        }
-
         let duplexer = UnixStream::connect(url.path())?;
         let duplexer = StreamDuplexer::unix_stream(duplexer);
 
         Ok(Interactive {
             name: url.to_string(),
+            kind: StreamKind::Socket,
             duplexer,
+            child: None,
         })
     }
 
@@ -115,87 +262,333 @@ fn open_connect_url(url: Url) -> anyhow::Result<Interactive> {
     }
 }
 
-fn open_accept_url(url: Url) -> anyhow::Result<Interactive> {
-    if !url.username().is_empty()
-        || url.password().is_some()
-        || url.query().is_some()
-        || url.fragment().is_some()
-    {
-        return Err(anyhow!("accept URL should only contain a socket address"));
-    }
+/// Options for accepting a connection, from the query of an `accept:` URL.
+#[cfg(not(target_os = "wasi"))]
+struct AcceptOptions {
+    /// How long to wait for a connection, from `accept_timeout=`.
+    timeout: Option<Duration>,
+    /// Whether to use stdin and stdout if the timeout passes, from
+    /// `fallback=stdio`.
+    fallback: bool,
+}
 
-    if url.path().is_empty() {
-        let port = match url.port() {
-            Some(port) => port,
-            None => return Err(anyhow!("accept URL should have a port")),
-        };
-        let host_str = match url.host_str() {
-            Some(host_str) => host_str,
-            None => return Err(anyhow!("accept URL should have a host")),
-        };
-
-        let listener = TcpListener::bind((host_str, port))?;
-
-        let (duplexer, addr) = listener.accept()?;
-        let duplexer = StreamDuplexer::tcp_stream(duplexer);
+/// Check an `accept:` URL, and return its options.
+#[cfg(not(target_os = "wasi"))]
+fn parse_accept_url(url: &Url) -> anyhow::Result<AcceptOptions> {
+    if !url.username().is_empty() || url.password().is_some() || url.fragment().is_some() {
+        return Err(anyhow!(
+            "accept URL should only contain a socket address and options"
+        ));
+    }
 
-        return Ok(Interactive {
-            name: format!("accept://{}", addr),
-            duplexer,
-        });
+    let options = parse_url_options(url, &ACCEPT)?;
+    let timeout = options.duration("accept_timeout");
+    let fallback = options.get("fallback").is_some();
+    if fallback && timeout.is_none() {
+        return Err(anyhow!("accept fallback requires an accept_timeout"));
     }
 
-    #[cfg(unix)]
-    {
-        if url.port().is_some() || url.host_str().is_some() {
-            return Err(anyhow!(
-                "Unix-domain connect URL should only contain a path"
-            ));
+    if url.path().is_empty() {
+        if url.port().is_none() {
+            return Err(anyhow!("accept URL should have a port"));
         }
+        if url.host_str().is_none() {
+            return Err(anyhow!("accept URL should have a host"));
+        }
+    } else if url.port().is_some() || url.host_str().is_some() {
+        return Err(anyhow!(
+            "Unix-domain connect URL should only contain a path"
+        ));
+    }
+    Ok(AcceptOptions { timeout, fallback })
+}
 
-        let listener = UnixListener::bind(url.path())?;
+#[cfg(not(target_os = "wasi"))]
+fn open_accept_url(url: Url, cancel: Option<&CancellationToken>) -> anyhow::Result<Interactive> {
+    let AcceptOptions { timeout, fallback } = parse_accept_url(&url)?;
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
 
-        let (duplexer, addr) = listener.accept()?;
-        let duplexer = StreamDuplexer::unix_stream(duplexer);
-        let name = path_to_name("accept", addr.as_pathname().unwrap())?;
+    let accepted = if url.path().is_empty() {
+        accept_tcp(&url, deadline, cancel)?
+    } else {
+        accept_unix(&url, deadline, cancel)?
+    };
 
-        Ok(Interactive { name, duplexer })
+    match accepted {
+        Some(interactive) => Ok(interactive),
+        None if fallback => {
+            // Name the stream so that reports show why it's stdio.
+            let mut interactive = acquire_stdin_stdout()?;
+            interactive.name = format!("{} (fell back to stdio)", url);
+            Ok(interactive)
+        }
+        None => Err(OpenError::AcceptTimeout(timeout.unwrap()).into()),
     }
+}
 
-    #[cfg(windows)]
-    {
-        Err(anyhow!("Unsupported connect URL: {}", url))
+#[cfg(not(target_os = "wasi"))]
+fn accept_tcp(
+    url: &Url,
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+) -> anyhow::Result<Option<Interactive>> {
+    let listener = TcpListener::bind((url.host_str().unwrap(), url.port().unwrap()))?;
+    listener.set_nonblocking(deadline.is_some() || cancel.is_some())?;
+
+    let (duplexer, addr) = match accept_until(deadline, cancel, || listener.accept())? {
+        Some(accepted) => accepted,
+        None => return Ok(None),
+    };
+    duplexer.set_nonblocking(false)?;
+    let duplexer = StreamDuplexer::tcp_stream(duplexer);
+
+    Ok(Some(Interactive {
+        name: format!("accept://{}", addr),
+        kind: StreamKind::Socket,
+        duplexer,
+        child: None,
+    }))
+}
+
+#[cfg(unix)]
+fn accept_unix(
+    url: &Url,
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+) -> anyhow::Result<Option<Interactive>> {
+    let listener = UnixListener::bind(url.path())?;
+    listener.set_nonblocking(deadline.is_some() || cancel.is_some())?;
+
+    let (duplexer, addr) = match accept_until(deadline, cancel, || listener.accept())? {
+        Some(accepted) => accepted,
+        None => return Ok(None),
+    };
+    duplexer.set_nonblocking(false)?;
+    let duplexer = StreamDuplexer::unix_stream(duplexer);
+    let name = path_to_name("accept", addr.as_pathname().unwrap())?;
+
+    Ok(Some(Interactive {
+        name,
+        kind: StreamKind::Socket,
+        duplexer,
+        child: None,
+    }))
+}
+
+#[cfg(windows)]
+fn accept_unix(
+    url: &Url,
+    _deadline: Option<Instant>,
+    _cancel: Option<&CancellationToken>,
+) -> anyhow::Result<Option<Interactive>> {
+    Err(anyhow!("Unsupported connect URL: {}", url))
+}
+
+/// Call `accept` until it succeeds, polling until `deadline` if there is
+/// one, in which case the listener must be in non-blocking mode. Returns
+/// `None` if the deadline passes first. If there's a `cancel` token, the
+/// listener must also be in non-blocking mode, and this fails with
+/// `OpenError::Cancelled` once it's cancelled.
+#[cfg(not(target_os = "wasi"))]
+fn accept_until<T>(
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+    mut accept: impl FnMut() -> io::Result<T>,
+) -> anyhow::Result<Option<T>> {
+    loop {
+        match accept() {
+            Ok(accepted) => return Ok(Some(accepted)),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if cancel.is_some_and(CancellationToken::is_cancelled) {
+                    return Err(OpenError::Cancelled.into());
+                }
+                let now = Instant::now();
+                match deadline {
+                    Some(deadline) if now < deadline => {
+                        thread::sleep(ACCEPT_POLL_INTERVAL.min(deadline - now))
+                    }
+                    Some(_) => return Ok(None),
+                    None => thread::sleep(ACCEPT_POLL_INTERVAL),
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
     }
 }
 
+#[cfg(not(target_os = "wasi"))]
 fn open_path(path: &Path) -> anyhow::Result<Interactive> {
     let name = path_to_name("file", path)?;
     let duplexer = CharDevice::open(path)?;
     let duplexer = StreamDuplexer::char_device(duplexer);
-    Ok(Interactive { name, duplexer })
+    Ok(Interactive {
+        name,
+        kind: StreamKind::CharDevice,
+        duplexer,
+        child: None,
+    })
 }
 
-#[cfg(not(windows))]
-fn spawn_child(os: &OsStr, lossy: &str) -> anyhow::Result<Interactive> {
-    use std::process::Command;
-    assert!(lossy.starts_with("$("));
-    if !lossy.ends_with(')') {
-        return Err(anyhow!("child string must end in ')'"));
-    }
-    let s = if let Some(s) = os.to_str() {
-        s
-    } else {
-        return Err(anyhow!("Non-UTF-8 child strings not yet supported"));
-    };
-    let words = shell_words::split(&s[2..s.len() - 1])?;
-    let (first, rest) = words
-        .split_first()
-        .ok_or_else(|| anyhow!("child stream specified with '(...)' must contain a command"))?;
+#[cfg(target_os = "wasi")]
+fn open_path(_path: &Path) -> anyhow::Result<Interactive> {
+    Err(OpenError::UnsupportedOnPlatform("interactive character devices").into())
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+fn spawn_child(os: &OsStr) -> anyhow::Result<Interactive> {
+    use std::process::{Command, Stdio};
+
+    let (command_str, query) = split_options(os, &CHILD_INTERACTIVE);
+    let pty = parse_options(query, &CHILD_INTERACTIVE)?.flag("pty");
+    let words = split_child(command_str)?;
+    let (first, rest) = words.split_first().unwrap();
     let mut command = Command::new(first);
     command.args(rest);
-    let duplexer = StreamDuplexer::duplex_with_command(command)?;
+    let name = os.to_string_lossy().into_owned();
+    if pty {
+        let (duplexer, child) = spawn_pty(command)?;
+        return Ok(Interactive {
+            name,
+            kind: StreamKind::Child,
+            duplexer,
+            child: Some(InteractiveChild { child, pty: true }),
+        });
+    }
+    // Spawn the child here rather than with `duplex_with_command`, so that
+    // it can be reaped when the stream is dropped.
+    command.stdin(Stdio::piped()).stdout(Stdio::piped());
+    let mut child = command.spawn()?;
+    let stdout = child.stdout.take().unwrap();
+    let stdin = child.stdin.take().unwrap();
     Ok(Interactive {
-        name: lossy.to_owned(),
-        duplexer,
+        name,
+        kind: StreamKind::Child,
+        duplexer: StreamDuplexer::child_stdout_stdin(stdout, stdin),
+        child: Some(InteractiveChild { child, pty: false }),
     })
 }
+
+/// Run `command` with a new pseudo-terminal as its stdin, stdout, and
+/// stderr, and return a duplexer for the master side.
+///
+/// The child doesn't become a session leader with the terminal as its
+/// controlling terminal, as that would require `unsafe` code in a
+/// `pre_exec` hook, but it does see its stdio as a terminal.
+#[cfg(not(any(windows, target_os = "wasi")))]
+fn spawn_pty(mut command: std::process::Command) -> anyhow::Result<(StreamDuplexer, Child)> {
+    use rustix::pty::{grantpt, openpt, ptsname, unlockpt, OpenptFlags};
+    use std::fs::{File, OpenOptions};
+    use std::os::unix::ffi::OsStrExt;
+    use std::process::Stdio;
+
+    let master = openpt(OpenptFlags::RDWR | OpenptFlags::NOCTTY)?;
+    grantpt(&master)?;
+    unlockpt(&master)?;
+
+    // If we're running in a terminal, start the child with the same size.
+    if let Ok(winsize) = rustix::termios::tcgetwinsize(io::stdout()) {
+        rustix::termios::tcsetwinsize(&master, winsize)?;
+    }
+
+    let slave_name = ptsname(&master, Vec::new())?;
+    let slave = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(OsStr::from_bytes(slave_name.as_bytes()))?;
+    command
+        .stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    let child = command.spawn()?;
+
+    // Drop our copies of the slave side, so that reads from the master
+    // side fail with `EIO` once the child exits.
+    drop(command);
+
+    let duplexer = CharDevice::new(File::from(master))?;
+    Ok((StreamDuplexer::char_device(duplexer), child))
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[test]
+fn accept_timeout() {
+    let err = open_interactive(
+        "accept://127.0.0.1:0?accept_timeout=100ms".as_ref(),
+        &OpenPolicy::default(),
+        clap::ambient_authority(),
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<OpenError>(),
+        Some(OpenError::AcceptTimeout(timeout)) if *timeout == Duration::from_millis(100)
+    ));
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[test]
+fn accept_timeout_fallback() {
+    let interactive = open_interactive(
+        "accept://127.0.0.1:0?accept_timeout=100ms&fallback=stdio".as_ref(),
+        &OpenPolicy::default(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    assert_eq!(
+        interactive.name,
+        "accept://127.0.0.1:0?accept_timeout=100ms&fallback=stdio (fell back to stdio)"
+    );
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[test]
+fn accept_before_timeout() {
+    // Find a free port, so that the client knows where to connect.
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    // Connect shortly before the timeout expires.
+    let client = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(700));
+        loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(stream) => return stream,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    });
+
+    let interactive = open_interactive(
+        format!("accept://127.0.0.1:{}?accept_timeout=1s", port).as_ref(),
+        &OpenPolicy::default(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    assert!(interactive.name.starts_with("accept://127.0.0.1:"));
+    client.join().unwrap();
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[test]
+fn accept_cancelled() {
+    let token = CancellationToken::with_deadline(Instant::now() + Duration::from_millis(100));
+    let policy = OpenPolicy {
+        cancel_token: Some(token),
+        ..OpenPolicy::default()
+    };
+    let err = open_interactive(
+        "accept://127.0.0.1:0".as_ref(),
+        &policy,
+        clap::ambient_authority(),
+    )
+    .err()
+    .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<OpenError>(),
+        Some(OpenError::Cancelled)
+    ));
+}
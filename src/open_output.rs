@@ -1,142 +1,755 @@
+#[cfg(not(any(windows, target_os = "wasi")))]
+use crate::child_words::split_child;
+#[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+use crate::clipboard::{system_clipboard, ClipboardOptions, ClipboardWriter};
+use crate::diagnose::open_error;
+use crate::gzip_level::gzip_level;
+use crate::http_upload::UploadStatus;
+#[cfg(not(target_os = "wasi"))]
+use crate::http_upload::{MultipartOptions, MultipartWriter};
+use crate::lock::{lock, LockOptions};
+use crate::output_validation::{validate_path, OutputValidation};
 use crate::path_to_name::path_to_name;
-use crate::MediaType;
+use crate::rotation::{RotatePolicy, Rotation};
+#[cfg(not(any(windows, target_os = "wasi")))]
+use crate::stream_options::CHILD_OUTPUT;
+#[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+use crate::stream_options::CLIPBOARD;
+use crate::stream_options::{parse_options, parse_url_options, split_options, FILE_OUTPUT};
+#[cfg(not(target_os = "wasi"))]
+use crate::stream_options::{take_prefixed_options, HTTP_OUTPUT};
+use crate::syntax::classify_with_policy;
+use crate::teardown::ChildExit;
+#[cfg(not(any(windows, target_os = "wasi")))]
+use crate::teardown::ChildWriter;
+use crate::telemetry::{traced_open, Telemetry};
+#[cfg(target_os = "wasi")]
+use crate::OpenError;
+use crate::{Existence, MediaType, OpenPolicy, StreamKind, StreamOptions, SyntaxKind};
 use anyhow::anyhow;
 use clap::AmbientAuthority;
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use io_streams::StreamWriter;
-use std::ffi::OsStr;
-use std::fs::File;
-use std::path::Path;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
 use url::Url;
 
 pub(crate) struct Output {
     pub(crate) name: String,
     pub(crate) writer: StreamWriter,
     pub(crate) media_type: MediaType,
+    pub(crate) kind: StreamKind,
+    /// The gzip compression level, for compressed outputs.
+    pub(crate) compression_level: Option<u32>,
+    /// Whether the file was created or already existed, for file outputs.
+    pub(crate) existence: Option<Existence>,
+    /// The exit status of the child process, for child outputs.
+    pub(crate) child_exit: Option<ChildExit>,
+    /// For child outputs with a `?restart` option, the name to open again
+    /// to respawn the child for each unit of output.
+    pub(crate) restart: Option<OsString>,
+    /// For file outputs with a `?rotate` option, the state for rotating the
+    /// file.
+    pub(crate) rotation: Option<Rotation>,
+    /// The outcome of the upload, for HTTP outputs.
+    pub(crate) upload: Option<UploadStatus>,
+    /// The options given with the output's name.
+    pub(crate) options: StreamOptions,
 }
 
 pub(crate) fn open_output(
     os: &OsStr,
     media_type: MediaType,
+    policy: &OpenPolicy,
     _ambient_authority: AmbientAuthority,
-) -> anyhow::Result<Output> {
-    if let Some(s) = os.to_str() {
-        // If we can parse it as a URL, treat it as such.
-        if let Ok(url) = Url::parse(s) {
-            return open_url(url, media_type);
-        }
+) -> anyhow::Result<(Output, Telemetry)> {
+    traced_open(os, || open_untraced(os, media_type, policy))
+}
 
-        // Special-case "-" to mean stdout.
-        if s == "-" {
-            return acquire_stdout(media_type);
+/// Open an output, without tracing it.
+fn open_untraced(os: &OsStr, media_type: MediaType, policy: &OpenPolicy) -> anyhow::Result<Output> {
+    match classify_with_policy(os, policy)? {
+        SyntaxKind::Pipeline => Err(anyhow!("pipelines are only supported for input")),
+        SyntaxKind::Url(_) => open_url(Url::parse(os.to_str().unwrap()).unwrap(), media_type),
+        SyntaxKind::Stdio => acquire_stdout(media_type),
+        SyntaxKind::Command => {
+            if !policy.allow_exec {
+                return Err(anyhow!("child processes are disabled by policy"));
+            }
+            #[cfg(not(any(windows, target_os = "wasi")))]
+            {
+                spawn_child(os, media_type)
+            }
+            #[cfg(windows)]
+            {
+                Err(anyhow!("child processes are not supported on Windows yet"))
+            }
+            #[cfg(target_os = "wasi")]
+            {
+                Err(OpenError::UnsupportedOnPlatform("child processes").into())
+            }
+        }
+        SyntaxKind::Path => {
+            let (path, options) = split_path_options(os)?;
+            open_path(path, media_type, options)
         }
     }
+}
 
-    #[cfg(not(windows))]
-    {
-        let lossy = os.to_string_lossy();
-
-        // Strings beginning with "$(" are commands.
-        if lossy.starts_with("$(") {
-            return spawn_child(os, &lossy, media_type);
+/// Check that `os` could be opened as an output, without creating or
+/// modifying anything.
+pub(crate) fn validate_output(os: &OsStr, policy: &OpenPolicy) -> anyhow::Result<OutputValidation> {
+    let kind = match classify_with_policy(os, policy)? {
+        SyntaxKind::Pipeline => return Err(anyhow!("pipelines are only supported for input")),
+        SyntaxKind::Url(_) => {
+            let url = Url::parse(os.to_str().unwrap()).unwrap();
+            match url.scheme() {
+                #[cfg(not(target_os = "wasi"))]
+                "http" | "https" => {
+                    let mut url = url;
+                    MultipartOptions::from_options(&take_prefixed_options(
+                        &mut url,
+                        &HTTP_OUTPUT,
+                    )?)?;
+                    StreamKind::Http
+                }
+                #[cfg(target_os = "wasi")]
+                "http" | "https" => {
+                    return Err(OpenError::UnsupportedOnPlatform("HTTP URLs").into())
+                }
+                "file" => {
+                    let (path, options) = parse_file_url(url)?;
+                    return validate_path(&path, options.create_parents);
+                }
+                "data" => return Err(anyhow!("output to data URL isn't possible")),
+                #[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+                "clipboard" => {
+                    ClipboardOptions::parse(&url)?;
+                    system_clipboard()?;
+                    StreamKind::Clipboard
+                }
+                other => return Err(anyhow!("unsupported URL scheme \"{}\"", other)),
+            }
         }
-    }
+        SyntaxKind::Stdio => StreamKind::Stdio,
+        SyntaxKind::Command => {
+            if !policy.allow_exec {
+                return Err(anyhow!("child processes are disabled by policy"));
+            }
+            #[cfg(not(any(windows, target_os = "wasi")))]
+            {
+                split_output_child(os)?;
+                StreamKind::Child
+            }
+            #[cfg(windows)]
+            {
+                return Err(anyhow!("child processes are not supported on Windows yet"));
+            }
+            #[cfg(target_os = "wasi")]
+            {
+                return Err(OpenError::UnsupportedOnPlatform("child processes").into());
+            }
+        }
+        SyntaxKind::Path => {
+            let (path, options) = split_path_options(os)?;
+            return validate_path(path, options.create_parents);
+        }
+    };
+    Ok(OutputValidation {
+        kind,
+        exists: false,
+    })
+}
 
-    // Otherwise try opening it as a path in the filesystem namespace.
-    open_path(Path::new(os), media_type)
+/// Validate `os` as with `validate_output`, and return an output which
+/// discards everything written to it.
+pub(crate) fn open_output_dry_run(
+    os: &OsStr,
+    media_type: MediaType,
+    policy: &OpenPolicy,
+) -> anyhow::Result<Output> {
+    let validation = validate_output(os, policy)?;
+    Ok(Output {
+        kind: validation.kind,
+        name: os.to_string_lossy().into_owned(),
+        writer: StreamWriter::piped_thread(Box::new(io::sink()))?,
+        media_type,
+        compression_level: None,
+        existence: None,
+        child_exit: None,
+        restart: None,
+        rotation: None,
+        upload: None,
+        options: StreamOptions::default(),
+    })
 }
 
 fn acquire_stdout(media_type: MediaType) -> anyhow::Result<Output> {
     let stdout = StreamWriter::stdout()?;
 
     Ok(Output {
+        kind: StreamKind::Stdio,
         name: "-".to_string(),
         writer: stdout,
         media_type,
+        compression_level: None,
+        existence: None,
+        child_exit: None,
+        restart: None,
+        rotation: None,
+        upload: None,
+        options: StreamOptions::default(),
     })
 }
 
 fn open_url(url: Url, media_type: MediaType) -> anyhow::Result<Output> {
     match url.scheme() {
-        // TODO: POST the data to HTTP? But the `Write` trait makes this
-        // tricky because there's no hook for closing and finishing the
-        // stream. `Drop` can't fail.
-        "http" | "https" => Err(anyhow!("output to HTTP not supported yet")),
+        #[cfg(not(target_os = "wasi"))]
+        "http" | "https" => open_http_url(url, media_type),
+        #[cfg(target_os = "wasi")]
+        "http" | "https" => Err(OpenError::UnsupportedOnPlatform("HTTP URLs").into()),
         "file" => {
-            if !url.username().is_empty()
-                || url.password().is_some()
-                || url.has_host()
-                || url.port().is_some()
-                || url.query().is_some()
-                || url.fragment().is_some()
-            {
-                return Err(anyhow!("file URL should only contain a path"));
-            }
-            // TODO: https://docs.rs/url/latest/url/struct.Url.html#method.to_file_path
-            // is ambiguous about how it can fail. What is `Path::new_opt`?
-            open_path(
-                &url.to_file_path()
-                    .map_err(|_: ()| anyhow!("unknown file URL weirdness"))?,
-                media_type,
-            )
+            let (path, options) = parse_file_url(url)?;
+            open_path(&path, media_type, options)
         }
         "data" => Err(anyhow!("output to data URL isn't possible")),
+        #[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+        "clipboard" => open_clipboard_url(&url, media_type),
         other => Err(anyhow!("unsupported URL scheme \"{}\"", other)),
     }
 }
 
-fn open_path(path: &Path, media_type: MediaType) -> anyhow::Result<Output> {
+/// Open an `http:` or `https:` URL as a multipart upload. Plain POST and
+/// PUT outputs aren't supported yet.
+#[cfg(not(target_os = "wasi"))]
+fn open_http_url(mut url: Url, media_type: MediaType) -> anyhow::Result<Output> {
+    let name = url.as_str().to_owned();
+    let options = take_prefixed_options(&mut url, &HTTP_OUTPUT)?;
+    let writer = MultipartWriter::new(
+        url.as_str(),
+        &MultipartOptions::from_options(&options)?,
+        &media_type,
+    )?;
+    let upload = writer.status();
+    let writer = StreamWriter::piped_thread(Box::new(writer))?;
+    Ok(Output {
+        kind: StreamKind::Http,
+        name,
+        writer,
+        media_type,
+        compression_level: None,
+        existence: None,
+        child_exit: None,
+        restart: None,
+        rotation: None,
+        upload: Some(upload),
+        options,
+    })
+}
+
+/// Options for an output file, from the query of a `file:` URL or a plain
+/// path.
+struct FileOptions {
+    /// A lock to take, from `lock=`.
+    lock: Option<LockOptions>,
+    /// The gzip compression level for `.gz` files, from `gzip_level=`.
+    gzip_level: Option<u32>,
+    /// The permissions to create the file with, from `mode=`.
+    mode: Option<u32>,
+    /// Whether to create missing parent directories, from `mkdir=parents`.
+    create_parents: bool,
+    /// The permissions to create parent directories with, from `dir_mode=`.
+    dir_mode: Option<u32>,
+    /// How to rotate the file, from `rotate=`.
+    rotate: Option<RotatePolicy>,
+    /// The options these were parsed from.
+    options: StreamOptions,
+}
+
+impl FileOptions {
+    fn from_options(options: StreamOptions) -> anyhow::Result<Self> {
+        let gzip_level = match options.get("gzip_level") {
+            Some(_) => match options.integer("gzip_level") {
+                Some(level) if level <= 9 => Some(level),
+                _ => return Err(anyhow!("gzip_level should be a number from 0 to 9")),
+            },
+            None => None,
+        };
+        let file_options = Self {
+            lock: options.get("lock").map(LockOptions::parse).transpose()?,
+            gzip_level,
+            mode: options
+                .get("mode")
+                .map(|value| parse_mode("mode", value))
+                .transpose()?,
+            create_parents: options.get("mkdir").is_some(),
+            dir_mode: options
+                .get("dir_mode")
+                .map(|value| parse_mode("dir_mode", value))
+                .transpose()?,
+            rotate: options.get("rotate").map(RotatePolicy::parse).transpose()?,
+            options,
+        };
+        if file_options.dir_mode.is_some() && !file_options.create_parents {
+            return Err(anyhow!("dir_mode requires mkdir=parents"));
+        }
+        if file_options.rotate.is_some() && file_options.lock.is_some() {
+            // The lock would only cover the first file.
+            return Err(anyhow!("rotate can't be combined with lock"));
+        }
+        Ok(file_options)
+    }
+
+    /// Check the options against the path they were given with.
+    fn check_path(&self, path: &Path) -> anyhow::Result<()> {
+        if self.rotate.is_some() && is_gz(path) {
+            return Err(anyhow!(
+                "rotate isn't supported for gzip-compressed outputs"
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn is_gz(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("gz"))
+}
+
+/// Split the options off of a plain path.
+fn split_path_options(os: &OsStr) -> anyhow::Result<(&Path, FileOptions)> {
+    let (path, query) = split_options(os, &FILE_OUTPUT);
+    let options = FileOptions::from_options(parse_options(query, &FILE_OUTPUT)?)?;
+    let path = Path::new(path);
+    options.check_path(path)?;
+    Ok((path, options))
+}
+
+/// Split a `file:` URL into its path and its options.
+fn parse_file_url(url: Url) -> anyhow::Result<(PathBuf, FileOptions)> {
+    if !url.username().is_empty()
+        || url.password().is_some()
+        || url.has_host()
+        || url.port().is_some()
+        || url.fragment().is_some()
+    {
+        return Err(anyhow!("file URL should only contain a path and options"));
+    }
+    let options = FileOptions::from_options(parse_url_options(&url, &FILE_OUTPUT)?)?;
+    // The query isn't part of the path.
+    let mut url = url;
+    url.set_query(None);
+    // TODO: https://docs.rs/url/latest/url/struct.Url.html#method.to_file_path
+    // is ambiguous about how it can fail. What is `Path::new_opt`?
+    let path = url
+        .to_file_path()
+        .map_err(|_: ()| anyhow!("unknown file URL weirdness"))?;
+    options.check_path(&path)?;
+    Ok((path, options))
+}
+
+/// Parse an octal permissions value, such as `0644`, from a `mode=` or
+/// `dir_mode=` option.
+fn parse_mode(key: &str, value: &str) -> anyhow::Result<u32> {
+    match u32::from_str_radix(value, 8) {
+        Ok(mode) if !value.starts_with('+') && mode <= 0o7777 => Ok(mode),
+        _ => Err(anyhow!(
+            "{} should be octal permissions such as 0644, not \"{}\"",
+            key,
+            value
+        )),
+    }
+}
+
+#[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+fn open_clipboard_url(url: &Url, media_type: MediaType) -> anyhow::Result<Output> {
+    let options = ClipboardOptions::parse(url)?;
+    let media_type = if options.html {
+        MediaType::union(
+            media_type,
+            MediaType::from_mime("text/html".parse().unwrap()),
+        )
+    } else {
+        MediaType::union(media_type, MediaType::text())
+    };
+    let writer = ClipboardWriter::new(system_clipboard()?, &options);
+    let writer = StreamWriter::piped_thread(Box::new(writer))?;
+    Ok(Output {
+        kind: StreamKind::Clipboard,
+        name: url.as_str().to_owned(),
+        writer,
+        media_type,
+        compression_level: None,
+        existence: None,
+        child_exit: None,
+        restart: None,
+        rotation: None,
+        upload: None,
+        options: parse_url_options(url, &CLIPBOARD)?,
+    })
+}
+
+fn open_path(path: &Path, media_type: MediaType, options: FileOptions) -> anyhow::Result<Output> {
+    let FileOptions {
+        lock: lock_options,
+        gzip_level: gzip_level_option,
+        mode,
+        create_parents,
+        dir_mode,
+        rotate,
+        options,
+    } = options;
     let name = path_to_name("file", path)?;
-    let file = File::create(path).map_err(|err| anyhow!("{}: {}", path.display(), err))?;
-    if path.extension() == Some(Path::new("gz").as_os_str()) {
+    if create_parents {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => {
+                let mut builder = DirBuilder::new();
+                builder.recursive(true);
+                set_dir_mode(&mut builder, dir_mode);
+                builder
+                    .create(parent)
+                    .map_err(|err| open_error(parent, err))?;
+            }
+            _ => {}
+        }
+    }
+    // Don't truncate the file until we hold the lock, if there is one.
+    let (file, existence) =
+        create_or_open(path, lock_options.is_none(), mode).map_err(|err| open_error(path, err))?;
+    if let Some(lock_options) = lock_options {
+        lock_and_truncate(&file, path, existence, lock_options)?;
+    }
+    if is_gz(path) {
         // TODO: We shouldn't really need to allocate a `PathBuf` here.
         let path = path.with_extension("");
-        let media_type = MediaType::union(media_type, MediaType::from_extension(path.extension()));
-        // 6 is the default gzip compression level.
+        let media_type = MediaType::union(media_type, MediaType::from_path(&path));
+        // Don't spend time compressing content which is already compressed.
+        let level = gzip_level(&media_type, gzip_level_option);
         let writer =
-            StreamWriter::piped_thread(Box::new(GzEncoder::new(file, Compression::new(6))))?;
+            StreamWriter::piped_thread(Box::new(GzEncoder::new(file, Compression::new(level))))?;
         Ok(Output {
+            kind: StreamKind::File,
             name,
             writer,
             media_type,
+            compression_level: Some(level),
+            existence: Some(existence),
+            child_exit: None,
+            restart: None,
+            rotation: None,
+            upload: None,
+            options,
         })
     } else {
-        let media_type = MediaType::union(media_type, MediaType::from_extension(path.extension()));
+        let media_type = MediaType::union(media_type, MediaType::from_path(path));
         let writer = StreamWriter::file(file);
         Ok(Output {
+            kind: StreamKind::File,
             name,
             writer,
             media_type,
+            compression_level: None,
+            existence: Some(existence),
+            child_exit: None,
+            restart: None,
+            rotation: rotate.map(|policy| Rotation::new(path, policy, mode)),
+            upload: None,
+            options,
         })
     }
 }
 
-#[cfg(not(windows))]
-fn spawn_child(os: &OsStr, lossy: &str, media_type: MediaType) -> anyhow::Result<Output> {
-    use std::process::{Command, Stdio};
-    assert!(lossy.starts_with("$("));
-    if !lossy.ends_with(')') {
-        return Err(anyhow!("child string must end in ')'"));
+/// Open `path` for writing, creating it if it doesn't exist, and report
+/// whether it was created. Creating with `create_new` first, rather than
+/// checking whether the file exists beforehand, avoids reporting the wrong
+/// answer if another process creates the file in between.
+fn create_or_open(path: &Path, truncate: bool, mode: Option<u32>) -> io::Result<(File, Existence)> {
+    let mut open_options = OpenOptions::new();
+    open_options.write(true).create_new(true);
+    set_file_mode(&mut open_options, mode);
+    match open_options.open(path) {
+        Ok(file) => return Ok((file, Existence::Created)),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(err),
     }
-    let s = if let Some(s) = os.to_str() {
-        s
-    } else {
-        return Err(anyhow!("Non-UTF-8 child strings not yet supported"));
-    };
-    let words = shell_words::split(&s[2..s.len() - 1])?;
-    let (first, rest) = words
-        .split_first()
-        .ok_or_else(|| anyhow!("child stream specified with '(...)' must contain a command"))?;
+
+    // Keep `create`, so that if the file is removed in between, or `path`
+    // is a dangling symlink, we still create it as before.
+    let mut open_options = OpenOptions::new();
+    open_options.write(true).create(true).truncate(truncate);
+    set_file_mode(&mut open_options, mode);
+    let file = open_options.open(path)?;
+    Ok((file, Existence::Overwrote))
+}
+
+/// Take the `lock=` lock on `file`, which was opened from `path`, and then
+/// truncate it. If that fails and the open created the file, remove it
+/// again, so that a failed open doesn't leave an empty file behind.
+fn lock_and_truncate(
+    file: &File,
+    path: &Path,
+    existence: Existence,
+    lock_options: LockOptions,
+) -> anyhow::Result<()> {
+    let result = lock(file, path, lock_options).and_then(|()| Ok(file.set_len(0)?));
+    if result.is_err() && existence == Existence::Created {
+        // The open has already failed; there's nothing more to do if the
+        // removal fails too.
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+/// Set the permissions for creating a file. As with `open(2)`, the process'
+/// umask applies, and existing files keep their permissions.
+#[cfg(unix)]
+pub(crate) fn set_file_mode(open_options: &mut OpenOptions, mode: Option<u32>) {
+    use std::os::unix::fs::OpenOptionsExt;
+    if let Some(mode) = mode {
+        open_options.mode(mode);
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn set_file_mode(_open_options: &mut OpenOptions, mode: Option<u32>) {
+    if mode.is_some() {
+        eprintln!("warning: the mode option is ignored on this platform");
+    }
+}
+
+/// Set the permissions for creating directories. As with `mkdir(2)`, the
+/// process' umask applies.
+#[cfg(unix)]
+fn set_dir_mode(builder: &mut DirBuilder, mode: Option<u32>) {
+    use std::os::unix::fs::DirBuilderExt;
+    if let Some(mode) = mode {
+        builder.mode(mode);
+    }
+}
+
+#[cfg(not(unix))]
+fn set_dir_mode(_builder: &mut DirBuilder, mode: Option<u32>) {
+    if mode.is_some() {
+        eprintln!("warning: the dir_mode option is ignored on this platform");
+    }
+}
+
+/// Split a child output string into the command and its arguments, and
+/// its options.
+#[cfg(not(any(windows, target_os = "wasi")))]
+fn split_output_child(os: &OsStr) -> anyhow::Result<(Vec<OsString>, StreamOptions)> {
+    let (command, query) = split_options(os, &CHILD_OUTPUT);
+    let options = parse_options(query, &CHILD_OUTPUT)?;
+    Ok((split_child(command)?, options))
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+pub(crate) fn spawn_child(os: &OsStr, media_type: MediaType) -> anyhow::Result<Output> {
+    use std::process::{Command, Stdio};
+    let (words, options) = split_output_child(os)?;
+    let restart = options.flag("restart");
+    let (first, rest) = words.split_first().unwrap();
     let child = Command::new(first)
         .args(rest)
         .stdin(Stdio::piped())
         .stdout(Stdio::null())
         .spawn()?;
-    let writer = StreamWriter::child_stdin(child.stdin.unwrap());
+    // Keep the child, so that it's reaped when the stream is dropped.
+    let writer = ChildWriter::new(child);
+    let child_exit = writer.exit();
+    let writer = StreamWriter::piped_thread(Box::new(writer))?;
     Ok(Output {
-        name: lossy.to_owned(),
+        kind: StreamKind::Child,
+        name: os.to_string_lossy().into_owned(),
         writer,
         media_type,
+        compression_level: None,
+        existence: None,
+        child_exit: Some(child_exit),
+        restart: if restart { Some(os.to_owned()) } else { None },
+        rotation: None,
+        upload: None,
+        options,
     })
 }
+
+#[cfg(unix)]
+#[test]
+fn file_url_modes() {
+    use crate::OutputByteStream;
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn create(path: &Path, query: &str) -> anyhow::Result<()> {
+        let mut url = Url::from_file_path(path).unwrap();
+        url.set_query(Some(query));
+        let mut output = OutputByteStream::try_from_os_str_arg(
+            url.as_str().as_ref(),
+            clap::ambient_authority(),
+        )?;
+        output.write_all(b"contents")?;
+        output.close()?;
+        Ok(())
+    }
+
+    fn mode(path: &Path) -> u32 {
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let secret = dir.path().join("secret");
+    create(&secret, "mode=0600").unwrap();
+    assert_eq!(mode(&secret), 0o600);
+    let script = dir.path().join("script.sh");
+    create(&script, "mode=0755").unwrap();
+    assert_eq!(mode(&script), 0o755);
+
+    let nested = dir.path().join("a").join("b").join("out.txt");
+    assert!(create(&nested, "mode=0600").is_err());
+    create(&nested, "mkdir=parents&dir_mode=0700&mode=0600").unwrap();
+    assert_eq!(mode(&nested), 0o600);
+    assert_eq!(mode(&dir.path().join("a")), 0o700);
+    assert_eq!(mode(&dir.path().join("a").join("b")), 0o700);
+    assert_eq!(std::fs::read(&nested).unwrap(), b"contents");
+
+    for query in [
+        "mode=644x",
+        "mode=0999",
+        "mode=",
+        "mode=17777",
+        "dir_mode=0700",
+    ] {
+        let err = create(&dir.path().join("bad"), query).unwrap_err();
+        assert!(!err.to_string().is_empty());
+    }
+    assert!(!dir.path().join("bad").exists());
+}
+
+#[test]
+fn output_existence() {
+    use crate::{OutputByteStream, OutputTextStream};
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.txt");
+    let open = |name: &OsStr| {
+        OutputByteStream::try_from_os_str_arg(name, clap::ambient_authority()).unwrap()
+    };
+    let existence = |name: &OsStr| {
+        let mut output = open(name);
+        let existence = output.existence();
+        output.close().unwrap();
+        existence
+    };
+
+    let mut output = open(path.as_os_str());
+    assert_eq!(output.existence(), Some(Existence::Created));
+    assert_eq!(output.info().existence(), Some(Existence::Created));
+    output.close().unwrap();
+
+    assert_eq!(existence(path.as_os_str()), Some(Existence::Overwrote));
+
+    // Compressed and locked outputs report it too.
+    let gz = dir.path().join("out.txt.gz");
+    assert_eq!(existence(gz.as_os_str()), Some(Existence::Created));
+    assert_eq!(existence(gz.as_os_str()), Some(Existence::Overwrote));
+    #[cfg(not(any(windows, target_os = "wasi")))]
+    {
+        let mut url = Url::from_file_path(&path).unwrap();
+        url.set_query(Some("lock=exclusive"));
+        assert_eq!(existence(url.as_str().as_ref()), Some(Existence::Overwrote));
+    }
+
+    let mut text = OutputTextStream::try_from_os_str_arg(
+        dir.path().join("new.txt").as_os_str(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    assert_eq!(text.existence(), Some(Existence::Created));
+    text.close().unwrap();
+
+    // Outputs which aren't files don't report it.
+    #[cfg(not(any(windows, target_os = "wasi")))]
+    assert_eq!(existence("$(cat)".as_ref()), None);
+}
+
+#[test]
+fn plain_path_options() {
+    use crate::OutputByteStream;
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+
+    let dir = tempfile::tempdir().unwrap();
+    let open = |name: &Path| {
+        OutputByteStream::try_from_os_str_arg(name.as_os_str(), clap::ambient_authority())
+    };
+
+    let nested = dir.path().join("a").join("out.txt?mkdir=parents");
+    let mut output = open(&nested).unwrap();
+    assert_eq!(output.info().options().get("mkdir"), Some("parents"));
+    output.close().unwrap();
+    assert!(dir.path().join("a").join("out.txt").exists());
+
+    let err = open(&dir.path().join("out.txt?mkdri=parents")).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("unknown option `mkdri` for file outputs; did you mean `mkdir`?"));
+    assert!(!dir.path().join("out.txt").exists());
+
+    // Text after a `?` which doesn't look like options is part of the name.
+    #[cfg(not(windows))]
+    {
+        open(&dir.path().join("what?.txt"))
+            .unwrap()
+            .close()
+            .unwrap();
+        assert!(dir.path().join("what?.txt").exists());
+        open(&dir.path().join("notes?v2")).unwrap().close().unwrap();
+        assert!(dir.path().join("notes?v2").exists());
+    }
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn failed_lock_removes_created_file() {
+    use crate::OpenError;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("locked.txt");
+    let file = File::create(&path).unwrap();
+    let holder = File::open(&path).unwrap();
+    lock(&holder, &path, LockOptions::exclusive(None)).unwrap();
+
+    // A file the open found already there stays.
+    let err = lock_and_truncate(
+        &file,
+        &path,
+        Existence::Overwrote,
+        LockOptions::exclusive(None),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<OpenError>(),
+        Some(OpenError::Locked { .. })
+    ));
+    assert!(path.exists());
+
+    // A file the open created is removed.
+    let err = lock_and_truncate(
+        &file,
+        &path,
+        Existence::Created,
+        LockOptions::exclusive(None),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<OpenError>(),
+        Some(OpenError::Locked { .. })
+    ));
+    assert!(!path.exists());
+}
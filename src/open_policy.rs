@@ -0,0 +1,80 @@
+use crate::{CancellationToken, ColorChoice};
+
+/// Policy settings controlling which stream syntaxes may be opened.
+///
+/// The default policy permits everything the current platform and build
+/// support, which matches the behavior of parsing stream arguments with
+/// `kommand` or `clap_derive`.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct OpenPolicy {
+    /// Permit names of the form `$(...)`, which run child processes.
+    pub allow_exec: bool,
+
+    /// A token for cancelling opens which wait, such as `accept:` URLs,
+    /// which then fail with [`OpenError::Cancelled`].
+    ///
+    /// [`OpenError::Cancelled`]: crate::OpenError::Cancelled
+    pub cancel_token: Option<CancellationToken>,
+
+    /// Whether interactive text streams pass color escape sequences
+    /// through. By default, they do when the output is a terminal which
+    /// supports color.
+    pub color: ColorChoice,
+
+    /// Reject names which could be either a URL or a path, such as
+    /// `notes:draft`, whose scheme isn't one nameless knows and which has
+    /// no `//`, rather than guessing. By default, these are treated as
+    /// paths on Windows, and as URLs with unsupported schemes elsewhere.
+    pub strict_urls: bool,
+
+    /// What to do when a list of inputs opened with `open_list` names the
+    /// same input more than once.
+    pub duplicate_arguments: DuplicateArguments,
+}
+
+impl Default for OpenPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            allow_exec: true,
+            cancel_token: None,
+            color: ColorChoice::Auto,
+            strict_urls: false,
+            duplicate_arguments: DuplicateArguments::Allow,
+        }
+    }
+}
+
+/// What to do when a list of inputs names the same input more than once,
+/// such as `-i data.csv -i data.csv`, for [`OpenPolicy::duplicate_arguments`].
+///
+/// Arguments name the same input if they're the same string, or if they're
+/// plain paths to the same file, such as `./data.csv` and `data.csv`.
+/// Whatever the policy, the `Pseudonym`s of repeated strings are written
+/// with an occurrence count, such as `data.csv (2nd occurrence)`, so that
+/// they can be told apart in reports.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum DuplicateArguments {
+    /// Open each argument independently.
+    Allow,
+    /// Open a file named more than once only once, and give each argument
+    /// an independent reader over it. Other syntaxes, such as `$(...)`,
+    /// are still opened once per argument, since running a command twice
+    /// may be intended.
+    Dedupe,
+    /// Fail with [`OpenError::DuplicateArgument`], naming the positions of
+    /// the first pair of arguments which name the same input, before
+    /// opening anything.
+    ///
+    /// [`OpenError::DuplicateArgument`]: crate::OpenError::DuplicateArgument
+    Error,
+}
+
+impl Default for DuplicateArguments {
+    #[inline]
+    fn default() -> Self {
+        Self::Allow
+    }
+}
@@ -0,0 +1,285 @@
+use clap::{AmbientAuthority, TryFromOsArg};
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::slice;
+use std::vec;
+
+/// The results of opening a list of streams, where each one is opened
+/// independently, so that one failure doesn't prevent the others from being
+/// opened, and all the failures can be reported together.
+///
+/// With `kommand`, use `#[kommand(collect_errors)]` on an `OpenResults`
+/// argument to open every element of a list argument this way, rather than
+/// stopping at the first failure:
+///
+/// ```rust,ignore
+/// #[kommand::main]
+/// fn main(#[kommand(collect_errors)] inputs: OpenResults<InputByteStream>) -> anyhow::Result<()> {
+///     for input in inputs.into_result()? {
+///         // ...
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct OpenResults<T> {
+    pub(crate) entries: Vec<Result<T, OpenFailure>>,
+}
+
+impl<T: TryFromOsArg<Error = anyhow::Error>> OpenResults<T> {
+    /// Open each of `names`, collecting the successes and failures in order.
+    pub fn open<I: IntoIterator<Item = OsString>>(
+        names: I,
+        ambient_authority: AmbientAuthority,
+    ) -> Self {
+        let entries = names
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| {
+                T::try_from_os_str_arg(&name, ambient_authority)
+                    .map_err(|error| OpenFailure::new(index, name, error))
+            })
+            .collect();
+        Self { entries }
+    }
+}
+
+impl<T> OpenResults<T> {
+    /// Return the number of entries, including failures.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Test whether there are no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Test whether any entry failed to open.
+    #[inline]
+    pub fn has_failures(&self) -> bool {
+        self.entries.iter().any(Result::is_err)
+    }
+
+    /// Iterate over all the entries, in the order they were given.
+    #[inline]
+    pub fn iter(&self) -> slice::Iter<'_, Result<T, OpenFailure>> {
+        self.entries.iter()
+    }
+
+    /// Iterate over the streams which opened successfully.
+    pub fn successes(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter().filter_map(|entry| entry.as_ref().ok())
+    }
+
+    /// Iterate over the entries which failed to open.
+    pub fn failures(&self) -> impl Iterator<Item = &OpenFailure> {
+        self.entries.iter().filter_map(|entry| entry.as_ref().err())
+    }
+
+    /// Consume `self` and return the streams which opened successfully,
+    /// discarding the failures.
+    pub fn into_successes(self) -> Vec<T> {
+        self.into_parts().0
+    }
+
+    /// Consume `self` and return the successes and the failures.
+    pub fn into_parts(self) -> (Vec<T>, Vec<OpenFailure>) {
+        let mut successes = Vec::new();
+        let mut failures = Vec::new();
+        for entry in self.entries {
+            match entry {
+                Ok(stream) => successes.push(stream),
+                Err(failure) => failures.push(failure),
+            }
+        }
+        (successes, failures)
+    }
+
+    /// Consume `self` and return all the streams if every entry opened
+    /// successfully, or otherwise an error describing all the failures.
+    pub fn into_result(self) -> Result<Vec<T>, OpenFailures> {
+        let (successes, failures) = self.into_parts();
+        if failures.is_empty() {
+            Ok(successes)
+        } else {
+            Err(OpenFailures { failures })
+        }
+    }
+}
+
+impl<T> IntoIterator for OpenResults<T> {
+    type Item = Result<T, OpenFailure>;
+    type IntoIter = vec::IntoIter<Result<T, OpenFailure>>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a OpenResults<T> {
+    type Item = &'a Result<T, OpenFailure>;
+    type IntoIter = slice::Iter<'a, Result<T, OpenFailure>>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+/// An entry in [`OpenResults`] which failed to open.
+#[derive(Debug)]
+pub struct OpenFailure {
+    index: usize,
+    name: OsString,
+    error: anyhow::Error,
+}
+
+impl OpenFailure {
+    pub(crate) fn new(index: usize, name: OsString, error: anyhow::Error) -> Self {
+        Self { index, name, error }
+    }
+
+    /// Return the position of the entry in the list of names, starting at
+    /// zero.
+    ///
+    /// With `#[kommand(glob)]`, this is the position of the argument the
+    /// name was expanded from, so several failures may share an index.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Return the name which failed to open.
+    #[inline]
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+
+    /// Return the error from opening the name.
+    #[inline]
+    pub fn error(&self) -> &anyhow::Error {
+        &self.error
+    }
+
+    /// Consume `self` and return the error from opening the name.
+    #[inline]
+    pub fn into_error(self) -> anyhow::Error {
+        self.error
+    }
+}
+
+impl Error for OpenFailure {}
+
+impl fmt::Display for OpenFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:#}", self.name.to_string_lossy(), self.error)
+    }
+}
+
+/// The error returned by [`OpenResults::into_result`], describing every
+/// entry which failed to open.
+#[derive(Debug)]
+pub struct OpenFailures {
+    failures: Vec<OpenFailure>,
+}
+
+impl OpenFailures {
+    /// Return the failures, in the order the names were given.
+    #[inline]
+    pub fn failures(&self) -> &[OpenFailure] {
+        &self.failures
+    }
+
+    /// Consume `self` and return the failures.
+    #[inline]
+    pub fn into_failures(self) -> Vec<OpenFailure> {
+        self.failures
+    }
+}
+
+impl Error for OpenFailures {}
+
+impl fmt::Display for OpenFailures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.failures.as_slice() {
+            [failure] => write!(f, "{}", failure),
+            failures => {
+                write!(f, "{} streams failed to open:", failures.len())?;
+                for failure in failures {
+                    write!(f, "\n  {}", failure)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Open the streams named by `args`, collecting the failures. This is used
+/// by `kommand` for `#[kommand(collect_errors)]` arguments.
+#[doc(hidden)]
+pub fn collect_args<T: TryFromOsArg<Error = anyhow::Error>>(args: Vec<OsString>) -> OpenResults<T> {
+    OpenResults::open(args, clap::ambient_authority())
+}
+
+#[test]
+fn open_results_mixed() {
+    use crate::InputByteStream;
+    use std::io::Read;
+
+    let dir = tempfile::tempdir().unwrap();
+    let good = dir.path().join("good.txt");
+    std::fs::write(&good, "good contents").unwrap();
+    let missing = dir.path().join("missing.txt");
+    let bad_dir = dir.path().join("no-such-dir").join("file.txt");
+
+    let names = vec![
+        good.clone().into_os_string(),
+        missing.clone().into_os_string(),
+        "data:,hello".into(),
+        "nosuchscheme://example.com/".into(),
+        bad_dir.clone().into_os_string(),
+    ];
+    let results = OpenResults::<InputByteStream>::open(names, clap::ambient_authority());
+    assert_eq!(results.len(), 5);
+    assert!(results.has_failures());
+
+    let failures = results
+        .failures()
+        .map(|failure| (failure.index(), failure.name().to_owned()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        failures,
+        vec![
+            (1, missing.into_os_string()),
+            (3, "nosuchscheme://example.com/".into()),
+            (4, bad_dir.into_os_string()),
+        ]
+    );
+
+    let err = results.into_result().err().unwrap();
+    assert_eq!(err.failures().len(), 3);
+    assert!(err.to_string().starts_with("3 streams failed to open:"));
+
+    // The successes still stream correctly.
+    let names = vec![
+        good.into_os_string(),
+        "nosuchscheme://example.com/".into(),
+        "data:,hello".into(),
+    ];
+    let results = OpenResults::<InputByteStream>::open(names, clap::ambient_authority());
+    let (successes, failures) = results.into_parts();
+    assert_eq!(failures.len(), 1);
+    let contents = successes
+        .into_iter()
+        .map(|mut input| {
+            let mut s = String::new();
+            input.read_to_string(&mut s).unwrap();
+            s
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(contents, vec!["good contents", "hello"]);
+}
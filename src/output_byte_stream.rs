@@ -1,13 +1,26 @@
+use crate::any_stream::AnyWriter;
+use crate::boxed::{share, CloseHandle, SharedWriter};
+use crate::drop_error::{close_on_drop, report_drop_error};
+use crate::http_upload::UploadStatus;
 use crate::lazy_output::FromLazyOutput;
-use crate::open_output::{open_output, Output};
-use crate::{MediaType, Pseudonym};
+#[cfg(not(any(windows, target_os = "wasi")))]
+use crate::open_output::spawn_child;
+use crate::open_output::{open_output, open_output_dry_run, Output};
+use crate::rotation::Rotation;
+use crate::source_sink::Sink;
+use crate::teardown::ChildExit;
+use crate::telemetry::Telemetry;
+use crate::{Existence, MediaType, OpenPolicy, Pseudonym, StreamInfo, StreamKind, StreamOptions};
 use anyhow::anyhow;
 use clap::{AmbientAuthority, TryFromOsArg};
 use io_streams::StreamWriter;
 use layered_io::{Bufferable, LayeredWriter, WriteLayered};
 use std::ffi::{OsStr, OsString};
-use std::fmt::{self, Arguments, Debug, Formatter};
+use std::fmt::{self, Debug, Formatter};
 use std::io::{self, IoSlice, Write};
+use std::mem;
+use std::process::ExitStatus;
+use std::time::Duration;
 use terminal_io::{NeverTerminalWriter, TerminalWriter, WriteTerminal};
 
 /// An output stream for binary output.
@@ -28,25 +41,114 @@ use terminal_io::{NeverTerminalWriter, TerminalWriter, WriteTerminal};
 ///    providing paths to files to open.
 ///  - "-" is interpreted as standard output.
 ///  - "(...)" runs a command with a pipe to the child process' stdin, on
-///    platforms whch support it.
+///    platforms whch support it. A `?restart` suffix, as in
+///    `$(sort > out.txt)?restart`, runs the command again for each unit of
+///    output; see [`finish_unit`].
+///  - With the "clipboard" feature, `clipboard:` places the output on the
+///    system clipboard. The output is buffered, and only placed on the
+///    clipboard when the stream is closed, so partial writes never appear.
+///    A `?type=text/html` option sets HTML, and a `?limit=<bytes>` option
+///    changes the limit on the buffered size from the default of 16 MiB.
+///  - Names starting with `https:` or `http:` with a
+///    `?nameless.multipart=<field>` option upload the output as the file
+///    field `<field>` of a `multipart/form-data` POST request, streamed as
+///    it's written. The part has the stream's media type, and a filename
+///    from a `nameless.filename=` option, or `upload` with the media type's
+///    extension. Other query parameters are sent to the server. Closing the
+///    stream fails if the server responds with a non-2xx status.
 ///  - Names which don't parse as URLs are interpreted as plain local
 ///    filesystem paths. To force a string to be interpreted as a plain local
 ///    path, arrange for it to begin with `./` or `/`.
+///  - `file:` URLs with a `?lock=exclusive` option take an advisory lock on
+///    the file, failing if another process holds a conflicting lock, or
+///    waiting up to a given time with `?lock=exclusive,wait=10s`. The lock is
+///    released when the stream is closed. Advisory locks only exclude other
+///    programs which also take locks.
+///  - Paths ending in `.gz` are gzip-compressed. Content which is already
+///    compressed, such as `.png.gz` or `.zip.gz`, is stored without
+///    compression, which is still valid gzip. A `file:` URL with a
+///    `?gzip_level=<0-9>` option chooses the level explicitly.
+///  - `file:` URLs with a `?mode=0600` option create the file with the given
+///    octal permissions, subject to the umask, on Unix-family platforms. A
+///    `?mkdir=parents` option creates missing parent directories, with
+///    permissions from a `dir_mode=` option if given.
+///  - Plain paths accept the same options as `file:` URLs, as in
+///    `out.txt?mode=0600`. Unrecognized options are rejected, with a
+///    suggestion if they look like a misspelling. To name a path containing
+///    `?`, begin it with `./`.
+///  - `file:` URLs and plain paths with a `?rotate=size:10MiB` or
+///    `?rotate=daily` option rotate the file, as with [`OutputTextStream`],
+///    except that rotation may happen between any two writes.
 ///
 /// Programs using `OutputByteStream` as an argument should avoid using
 /// `std::io::stdout`, `std::println`, or anything else which uses standard
 /// output implicitly.
+///
+/// [`finish_unit`]: Self::finish_unit
+/// [`OutputTextStream`]: crate::OutputTextStream
 pub struct OutputByteStream {
     name: String,
-    writer: LayeredWriter<NeverTerminalWriter<StreamWriter>>,
+    kind: StreamKind,
+    /// The outcome of an HTTP upload. If the stream is dropped without
+    /// being closed, this isn't waited for, and the writer reports upload
+    /// errors itself.
+    upload: Option<UploadStatus>,
+    writer: LayeredWriter<NeverTerminalWriter<AnyWriter>>,
     media_type: MediaType,
+    compression_level: Option<u32>,
+    existence: Option<Existence>,
+    child_exit: Option<ChildExit>,
+    /// For `?restart` children, the name to open again for each unit.
+    restart: Option<OsString>,
+    /// Whether `finish_unit` has ended the current unit.
+    unit_finished: bool,
+    /// For `?rotate` files, the state for rotating the file.
+    rotation: Option<Rotation>,
+    finish_timeout: Option<Duration>,
+    options: StreamOptions,
+    telemetry: Telemetry,
+}
+
+/// How a unit of output ended, returned by
+/// [`OutputByteStream::finish_unit`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ChildOutcome {
+    /// The child process read to the end of its input and exited with the
+    /// given status, which may or may not be a success.
+    Exited(ExitStatus),
+    /// The child process didn't exit within the finish timeout, and was
+    /// killed.
+    TimedOut(ExitStatus),
+    /// The stream isn't connected to a child process, so there's no unit
+    /// to finish. The stream is flushed and stays open.
+    NotApplicable,
 }
 
 impl OutputByteStream {
+    /// Open `name` as if it were a command-line argument, under `policy`
+    /// rather than the default policy.
+    ///
+    /// This is the output counterpart of [`InputByteStream::open_list`] and
+    /// [`LazyInteractive::with_policy`]. To open an output lazily under a
+    /// policy, use [`LazyOutput::with_policy`].
+    ///
+    /// [`InputByteStream::open_list`]: crate::InputByteStream::open_list
+    /// [`LazyInteractive::with_policy`]: crate::LazyInteractive::with_policy
+    /// [`LazyOutput::with_policy`]: crate::LazyOutput::with_policy
+    pub fn open(
+        name: &OsStr,
+        policy: &OpenPolicy,
+        ambient_authority: AmbientAuthority,
+    ) -> anyhow::Result<Self> {
+        open_output(name, MediaType::unknown(), policy, ambient_authority)
+            .and_then(Self::from_output)
+    }
+
     /// Write the given `Pseudonym` to the output stream.
     #[inline]
     pub fn write_pseudonym(&mut self, pseudonym: &Pseudonym) -> io::Result<()> {
-        Write::write_all(self, pseudonym.name.as_bytes())
+        Write::write_all(self, pseudonym.display_name().as_bytes())
     }
 
     /// Return a `Pseudonym` which encapsulates this stream's name (typically
@@ -65,22 +167,217 @@ impl OutputByteStream {
         &self.media_type
     }
 
-    fn from_output(output: Output) -> anyhow::Result<Self> {
-        let writer = NeverTerminalWriter::new(output.writer);
+    /// Return whether the output file was newly created or already existed,
+    /// or `None` if the output isn't a file.
+    #[inline]
+    pub fn existence(&self) -> Option<Existence> {
+        self.existence
+    }
+
+    /// Return a summary of this stream's metadata.
+    #[inline]
+    pub fn info(&self) -> StreamInfo {
+        StreamInfo {
+            kind: self.kind,
+            media_type: self.media_type.clone(),
+            initial_size: None,
+            compression_level: self.compression_level,
+            existence: self.existence,
+            options: self.options.clone(),
+            cache_status: None,
+        }
+    }
+
+    /// Consume `self` and return it as a boxed `dyn Write`, for passing to
+    /// APIs which take one, along with a [`CloseHandle`] for closing it.
+    ///
+    /// Call [`CloseHandle::close`] once the writer is no longer needed, to
+    /// flush and close the stream, wait for a `$(...)` child process to
+    /// exit, and get any errors. Dropping the writer and the handle without
+    /// calling `close` only cleans up on a best-effort basis.
+    pub fn into_boxed_write(self) -> (Box<dyn Write + Send>, CloseHandle) {
+        let shared = share(self);
+        let handle = CloseHandle::new(shared.clone());
+        (Box::new(SharedWriter(shared)), handle)
+    }
+
+    /// End a unit of output to a `$(...)` child process: close the child's
+    /// stdin, so that it sees the end of its input, wait for it to exit, and
+    /// return how it exited.
+    ///
+    /// This lets a program hand off a complete unit of output, such as to
+    /// `$(sort > final.txt)`, and confirm that the child has finished with
+    /// it, while continuing with other work. Afterward, writes fail, unless
+    /// the stream's name has a `?restart` suffix, in which case the first
+    /// write, or the next `finish_unit`, runs the command again for the next
+    /// unit.
+    ///
+    /// By default, this waits indefinitely for the child; see
+    /// [`set_finish_timeout`]. For streams which aren't connected to a child
+    /// process, this flushes the stream and returns
+    /// [`ChildOutcome::NotApplicable`].
+    ///
+    /// [`set_finish_timeout`]: Self::set_finish_timeout
+    pub fn finish_unit(&mut self) -> io::Result<ChildOutcome> {
+        self.start_unit()?;
+        let child_exit = match self.child_exit.take() {
+            Some(child_exit) => child_exit,
+            None => {
+                self.writer.flush()?;
+                return Ok(ChildOutcome::NotApplicable);
+            }
+        };
+
+        child_exit.set_grace(self.finish_timeout);
+        self.unit_finished = true;
+        self.writer.close()?;
+        Ok(match child_exit.wait_killed()? {
+            (status, false) => ChildOutcome::Exited(status),
+            (status, true) => ChildOutcome::TimedOut(status),
+        })
+    }
+
+    /// Set how long [`finish_unit`] waits for a child process to exit
+    /// before killing it. `None`, the default, waits indefinitely.
+    ///
+    /// [`finish_unit`]: Self::finish_unit
+    #[inline]
+    pub fn set_finish_timeout(&mut self, timeout: Option<Duration>) {
+        self.finish_timeout = timeout;
+    }
+
+    /// If `finish_unit` has ended the current unit, start a new one by
+    /// running a `?restart` command again, or fail.
+    fn start_unit(&mut self) -> io::Result<()> {
+        if !self.unit_finished {
+            return Ok(());
+        }
+        match &self.restart {
+            #[cfg(not(any(windows, target_os = "wasi")))]
+            Some(name) => {
+                let output =
+                    spawn_child(name, self.media_type.clone()).map_err(io::Error::other)?;
+                self.writer =
+                    LayeredWriter::new(NeverTerminalWriter::new(AnyWriter::Stream(output.writer)));
+                self.child_exit = output.child_exit;
+                self.unit_finished = false;
+                Ok(())
+            }
+            _ => Err(io::Error::other(
+                "output was already finished with `finish_unit`",
+            )),
+        }
+    }
 
-        let writer = TerminalWriter::with_handle(writer);
+    /// If the output file is due to be rotated, continue in a fresh file.
+    fn rotate_if_due(&mut self) -> io::Result<()> {
+        let rotation = match &mut self.rotation {
+            Some(rotation) if rotation.due() => rotation,
+            _ => return Ok(()),
+        };
+        self.writer.flush()?;
+        match rotation.rotate() {
+            Ok(file) => {
+                let writer = NeverTerminalWriter::new(AnyWriter::Stream(StreamWriter::file(file)));
+                mem::replace(&mut self.writer, LayeredWriter::new(writer)).close()
+            }
+            // As with `OutputTextStream`, continue in the current file.
+            Err(err) => {
+                report_drop_error(err);
+                Ok(())
+            }
+        }
+    }
+
+    /// Record that `n` bytes were written, for rotation.
+    #[inline]
+    fn wrote(&mut self, n: usize) {
+        if let Some(rotation) = &mut self.rotation {
+            rotation.wrote(n);
+        }
+    }
+
+    fn from_output((output, telemetry): (Output, Telemetry)) -> anyhow::Result<Self> {
+        let writer = TerminalWriter::with_handle(output.writer);
         if writer.is_output_terminal() {
             return Err(anyhow!("attempted to write binary output to a terminal"));
         }
 
-        let writer = LayeredWriter::new(writer.into_inner());
+        let writer = NeverTerminalWriter::new(AnyWriter::Stream(writer.into_inner()));
+        let writer = LayeredWriter::new(writer);
 
         Ok(Self {
             name: output.name,
+            kind: output.kind,
+            upload: output.upload,
             writer,
             media_type: output.media_type,
+            compression_level: output.compression_level,
+            existence: output.existence,
+            child_exit: output.child_exit,
+            restart: output.restart,
+            unit_finished: false,
+            rotation: output.rotation,
+            finish_timeout: None,
+            options: output.options,
+            telemetry,
         })
     }
+
+    /// Consume `self` and return a stream which encodes everything written
+    /// to it as [JSON Lines] records of the form
+    /// `{"seq":0,"data":"<base64>"}`, for passing binary data through
+    /// text-only channels. Each record holds up to `record_size` bytes.
+    /// Flushing or closing the stream emits any pending bytes as a shorter
+    /// record. [`InputByteStream::from_jsonl_base64`] decodes the records.
+    ///
+    /// This requires the "codecs" feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `record_size` is zero.
+    ///
+    /// [JSON Lines]: https://jsonlines.org/
+    /// [`InputByteStream::from_jsonl_base64`]: crate::InputByteStream::from_jsonl_base64
+    #[cfg(feature = "codecs")]
+    pub fn into_jsonl_base64(self, record_size: usize) -> Self {
+        assert!(record_size > 0, "record_size must be nonzero");
+        self.wrap_boxed(|output| {
+            Box::new(crate::codecs::JsonlBase64Writer::new(output, record_size))
+        })
+    }
+
+    /// Wrap this stream in another writer, presented as an
+    /// `OutputByteStream` with the same metadata.
+    #[cfg(any(feature = "testing", feature = "codecs"))]
+    pub(crate) fn wrap_boxed(
+        self,
+        wrap: impl FnOnce(WrappedOutput) -> Box<dyn Write + Send>,
+    ) -> Self {
+        let name = self.name.clone();
+        let kind = self.kind;
+        let media_type = self.media_type.clone();
+        let compression_level = self.compression_level;
+        let existence = self.existence;
+        let options = self.options.clone();
+        let writer = NeverTerminalWriter::new(AnyWriter::Boxed(wrap(WrappedOutput(self))));
+        Self {
+            name,
+            kind,
+            upload: None,
+            writer: LayeredWriter::new(writer),
+            media_type,
+            compression_level,
+            existence,
+            child_exit: None,
+            restart: None,
+            unit_finished: false,
+            rotation: None,
+            finish_timeout: None,
+            options,
+            telemetry: Telemetry::default(),
+        }
+    }
 }
 
 /// Implement `From<&OsStr>` so that `clap_derive` can parse `OutputByteStream`
@@ -97,31 +394,79 @@ impl TryFromOsArg for OutputByteStream {
         os: &OsStr,
         ambient_authority: AmbientAuthority,
     ) -> anyhow::Result<Self> {
-        open_output(os, MediaType::unknown(), ambient_authority).and_then(Self::from_output)
+        Self::open(os, &OpenPolicy::default(), ambient_authority)
+    }
+}
+
+impl Sink for OutputByteStream {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        Some(&self.media_type)
+    }
+
+    #[inline]
+    fn close_boxed(&mut self) -> io::Result<()> {
+        self.close()
     }
 }
 
 impl WriteLayered for OutputByteStream {
     #[inline]
     fn close(&mut self) -> io::Result<()> {
-        self.writer.close()
+        // `finish_unit` has already closed the writer and waited for the
+        // child.
+        if self.unit_finished {
+            return Ok(());
+        }
+        self.writer.close()?;
+
+        // Closing the writer ends the body of an HTTP upload, so wait for
+        // the response.
+        if let Some(upload) = self.upload.take() {
+            upload.wait()?;
+        }
+
+        // Closing the writer closes the child's stdin, so wait for it to
+        // exit.
+        if let Some(child_exit) = self.child_exit.take() {
+            let status = child_exit.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!(
+                    "child process exited with non-success exit status: {}",
+                    status
+                )));
+            }
+        }
+
+        Ok(())
     }
 }
 
 impl Write for OutputByteStream {
     #[inline]
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.writer.write(buf)
+        self.start_unit()?;
+        self.rotate_if_due()?;
+        let n = self.telemetry.transfer(self.writer.write(buf))?;
+        self.wrote(n);
+        Ok(n)
     }
 
     #[inline]
     fn flush(&mut self) -> io::Result<()> {
+        if self.unit_finished {
+            return Ok(());
+        }
         self.writer.flush()
     }
 
     #[inline]
     fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
-        self.writer.write_vectored(bufs)
+        self.start_unit()?;
+        self.rotate_if_due()?;
+        let n = self.telemetry.transfer(self.writer.write_vectored(bufs))?;
+        self.wrote(n);
+        Ok(n)
     }
 
     #[cfg(can_vector)]
@@ -132,18 +477,24 @@ impl Write for OutputByteStream {
 
     #[inline]
     fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
-        self.writer.write_all(buf)
+        self.start_unit()?;
+        self.rotate_if_due()?;
+        let result = self.writer.write_all(buf);
+        self.telemetry.transfer_all(result, buf.len())?;
+        self.wrote(buf.len());
+        Ok(())
     }
 
     #[cfg(write_all_vectored)]
     #[inline]
     fn write_all_vectored(&mut self, bufs: &mut [IoSlice<'_>]) -> io::Result<()> {
-        self.writer.write_all_vectored(bufs)
-    }
-
-    #[inline]
-    fn write_fmt(&mut self, fmt: Arguments<'_>) -> io::Result<()> {
-        self.writer.write_fmt(fmt)
+        self.start_unit()?;
+        self.rotate_if_due()?;
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+        let result = self.writer.write_all_vectored(bufs);
+        self.telemetry.transfer_all(result, len)?;
+        self.wrote(len);
+        Ok(())
     }
 }
 
@@ -160,9 +511,64 @@ impl FromLazyOutput for OutputByteStream {
     fn from_lazy_output(
         name: OsString,
         media_type: MediaType,
+        policy: &OpenPolicy,
         ambient_authority: AmbientAuthority,
     ) -> Result<Self, anyhow::Error> {
-        open_output(&name, media_type, ambient_authority).and_then(Self::from_output)
+        open_output(&name, media_type, policy, ambient_authority).and_then(Self::from_output)
+    }
+
+    fn from_lazy_output_dry_run(
+        name: OsString,
+        media_type: MediaType,
+        policy: &OpenPolicy,
+        _ambient_authority: AmbientAuthority,
+    ) -> Result<Self, anyhow::Error> {
+        open_output_dry_run(&name, media_type, policy)
+            .and_then(|output| Self::from_output((output, Telemetry::default())))
+    }
+}
+
+/// An `OutputByteStream` inside a writer made by `wrap_boxed`. Closing the
+/// outer stream only flushes and drops that writer, so this closes the inner
+/// stream when it's dropped, reporting any error with `report_drop_error`.
+#[cfg(any(feature = "testing", feature = "codecs"))]
+pub(crate) struct WrappedOutput(OutputByteStream);
+
+#[cfg(any(feature = "testing", feature = "codecs"))]
+impl Write for WrappedOutput {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    #[inline]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.0.write_vectored(bufs)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(any(feature = "testing", feature = "codecs"))]
+impl Drop for WrappedOutput {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.close() {
+            crate::drop_error::report_drop_error(e);
+        }
+    }
+}
+
+impl Drop for OutputByteStream {
+    fn drop(&mut self) {
+        // Close the writer, flushing any buffered output. We can't return
+        // `Err` from a `drop` function, so errors are reported with
+        // `report_drop_error`. Callers should use `close()` to end the stream
+        // if they wish to handle these errors, or to wait for a child
+        // process or an upload to finish.
+        close_on_drop(&mut self.writer);
     }
 }
 
@@ -174,3 +580,123 @@ impl Debug for OutputByteStream {
         b.finish()
     }
 }
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn finish_unit_child() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.txt");
+    let name = format!("$(sh -c 'cat > {}')", path.display());
+    let mut output =
+        OutputByteStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority()).unwrap();
+    output.write_all(b"hello\n").unwrap();
+    match output.finish_unit().unwrap() {
+        ChildOutcome::Exited(status) => assert!(status.success()),
+        other => panic!("unexpected outcome: {:?}", other),
+    }
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello\n");
+
+    // Without `?restart`, the stream is finished.
+    assert!(output.write_all(b"more\n").is_err());
+    output.close().unwrap();
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn finish_unit_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.txt");
+    let log = dir.path().join("spawns.txt");
+    let name = format!(
+        "$(sh -c 'echo spawn >> {}; cat >> {}')?restart",
+        log.display(),
+        path.display()
+    );
+    let mut output =
+        OutputByteStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority()).unwrap();
+
+    output.write_all(b"one\n").unwrap();
+    assert!(matches!(
+        output.finish_unit().unwrap(),
+        ChildOutcome::Exited(status) if status.success()
+    ));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\n");
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "spawn\n");
+
+    output.write_all(b"two\n").unwrap();
+    assert!(matches!(
+        output.finish_unit().unwrap(),
+        ChildOutcome::Exited(status) if status.success()
+    ));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "spawn\nspawn\n");
+
+    // Closing after a finished unit doesn't start another.
+    output.close().unwrap();
+    drop(output);
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "spawn\nspawn\n");
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn finish_unit_timeout() {
+    let mut output = OutputByteStream::try_from_os_str_arg(
+        "$(sh -c 'cat; exec sleep 60')".as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    output.set_finish_timeout(Some(Duration::from_millis(100)));
+    assert!(matches!(
+        output.finish_unit().unwrap(),
+        ChildOutcome::TimedOut(_)
+    ));
+}
+
+#[test]
+fn finish_unit_not_applicable() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.txt");
+    let mut output =
+        OutputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    output.write_all(b"hello").unwrap();
+    assert_eq!(output.finish_unit().unwrap(), ChildOutcome::NotApplicable);
+    output.write_all(b" world").unwrap();
+    output.close().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world");
+}
+
+#[test]
+fn open_with_policy() {
+    use crate::OutputTextStream;
+
+    let dir = tempfile::tempdir().unwrap();
+    let strict = OpenPolicy {
+        strict_urls: true,
+        ..OpenPolicy::default()
+    };
+    let err = OutputByteStream::open("notes:draft".as_ref(), &strict, clap::ambient_authority())
+        .unwrap_err();
+    assert!(err.to_string().contains("ambiguous"), "{}", err);
+    let err = OutputTextStream::open("notes:draft".as_ref(), &strict, clap::ambient_authority())
+        .unwrap_err();
+    assert!(err.to_string().contains("ambiguous"), "{}", err);
+
+    // Unambiguous names open as usual.
+    let path = dir.path().join("notes:draft");
+    let mut output =
+        OutputByteStream::open(path.as_os_str(), &strict, clap::ambient_authority()).unwrap();
+    output.write_all(b"draft\n").unwrap();
+    output.close().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "draft\n");
+
+    #[cfg(not(any(windows, target_os = "wasi")))]
+    {
+        let no_exec = OpenPolicy {
+            allow_exec: false,
+            ..OpenPolicy::default()
+        };
+        let err = OutputByteStream::open("$(cat)".as_ref(), &no_exec, clap::ambient_authority())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "child processes are disabled by policy");
+    }
+}
@@ -0,0 +1,165 @@
+use crate::OutputTextStream;
+use anyhow::anyhow;
+use std::fmt;
+use std::str::FromStr;
+use terminal_io::WriteTerminal;
+
+/// A format for structured output, chosen with [`OutputFormat::negotiate`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum OutputFormat {
+    /// Human-oriented text, for display on a terminal.
+    Pretty,
+    /// JSON.
+    Json,
+    /// YAML.
+    Yaml,
+    /// Comma-separated values.
+    Csv,
+}
+
+impl OutputFormat {
+    /// Choose the format to write to `output` in.
+    ///
+    /// An `explicit` format, typically from a command-line flag, takes
+    /// precedence. Otherwise, if the output's media type declares JSON, YAML,
+    /// or CSV, such as with a `.json` extension, that's used. Otherwise the
+    /// output is `Pretty` if it's a terminal and `Json` if it's a pipe or a
+    /// file.
+    pub fn negotiate(output: &OutputTextStream, explicit: Option<Self>) -> Self {
+        Self::negotiate_with(
+            explicit,
+            output.declared_format(),
+            output.is_output_terminal(),
+        )
+    }
+
+    fn negotiate_with(explicit: Option<Self>, declared: Option<Self>, is_terminal: bool) -> Self {
+        if let Some(explicit) = explicit {
+            explicit
+        } else if let Some(declared) = declared {
+            declared
+        } else if is_terminal {
+            Self::Pretty
+        } else {
+            Self::Json
+        }
+    }
+}
+
+/// Parse a format name, so that an override can be a command-line argument.
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "pretty" | "text" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            "csv" => Ok(Self::Csv),
+            _ => Err(anyhow!(
+                "unknown output format \"{}\"; expected one of pretty, json, yaml, or csv",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pretty => "pretty",
+            Self::Json => "json",
+            Self::Yaml => "yaml",
+            Self::Csv => "csv",
+        })
+    }
+}
+
+#[test]
+fn negotiate_precedence() {
+    use OutputFormat::*;
+
+    // An explicit format beats everything.
+    assert_eq!(
+        OutputFormat::negotiate_with(Some(Csv), Some(Json), true),
+        Csv
+    );
+    assert_eq!(
+        OutputFormat::negotiate_with(Some(Pretty), None, false),
+        Pretty
+    );
+
+    // A declared format beats the terminal check.
+    assert_eq!(OutputFormat::negotiate_with(None, Some(Yaml), true), Yaml);
+
+    // Terminals get pretty output, and everything else gets JSON.
+    assert_eq!(OutputFormat::negotiate_with(None, None, true), Pretty);
+    assert_eq!(OutputFormat::negotiate_with(None, None, false), Json);
+}
+
+#[test]
+fn negotiate_files() {
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+
+    let dir = tempfile::tempdir().unwrap();
+    for (name, declared, negotiated) in [
+        ("out.json", Some(OutputFormat::Json), OutputFormat::Json),
+        ("out.yaml", Some(OutputFormat::Yaml), OutputFormat::Yaml),
+        ("out.csv", Some(OutputFormat::Csv), OutputFormat::Csv),
+        ("out.txt", None, OutputFormat::Json),
+    ] {
+        let mut output = OutputTextStream::try_from_os_str_arg(
+            dir.path().join(name).as_os_str(),
+            clap::ambient_authority(),
+        )
+        .unwrap();
+        assert_eq!(output.declared_format(), declared, "{}", name);
+        assert_eq!(
+            OutputFormat::negotiate(&output, None),
+            negotiated,
+            "{}",
+            name
+        );
+        assert_eq!(
+            OutputFormat::negotiate(&output, Some(OutputFormat::Pretty)),
+            OutputFormat::Pretty
+        );
+        output.close().unwrap();
+    }
+}
+
+#[cfg(not(windows))]
+#[test]
+fn negotiate_pipe() {
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+
+    let mut output = OutputTextStream::try_from_os_str_arg(
+        "$(sh -c 'cat > /dev/null')".as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+    assert_eq!(output.declared_format(), None);
+    assert_eq!(OutputFormat::negotiate(&output, None), OutputFormat::Json);
+    output.close().unwrap();
+}
+
+#[test]
+fn parse_output_format() {
+    assert_eq!("json".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+    assert_eq!("YML".parse::<OutputFormat>().unwrap(), OutputFormat::Yaml);
+    assert_eq!(
+        "text".parse::<OutputFormat>().unwrap(),
+        OutputFormat::Pretty
+    );
+    assert!("xml".parse::<OutputFormat>().is_err());
+    for format in [
+        OutputFormat::Pretty,
+        OutputFormat::Json,
+        OutputFormat::Yaml,
+        OutputFormat::Csv,
+    ] {
+        assert_eq!(format.to_string().parse::<OutputFormat>().unwrap(), format);
+    }
+}
@@ -1,18 +1,31 @@
+use crate::drop_error::{close_on_drop, report_drop_error};
+use crate::http_upload::UploadStatus;
 use crate::lazy_output::FromLazyOutput;
-use crate::open_output::{open_output, Output};
+use crate::open_output::{open_output, open_output_dry_run, Output};
+use crate::rotation::Rotation;
+use crate::source_sink::Sink;
+use crate::status_writer::{SharedStatus, StatusState, StatusWriter};
 #[cfg(unix)]
 use crate::summon_bat::summon_bat;
-use crate::{MediaType, Pseudonym};
+use crate::telemetry::Telemetry;
+use crate::text_accounting::Accountant;
+use crate::{
+    Existence, MediaType, OpenPolicy, OutputFormat, Pseudonym, StreamInfo, StreamKind,
+    StreamOptions, TextAccounting,
+};
 use basic_text::{TextStr, TextWriter, WriteText};
 use clap::{AmbientAuthority, TryFromOsArg};
-#[cfg(not(windows))]
+#[cfg(unix)]
 use io_extras::os::rustix::AsRawFd;
 use io_streams::StreamWriter;
 use layered_io::{Bufferable, LayeredWriter, WriteLayered};
 use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Arguments, Debug, Formatter};
+use std::fs::File;
 use std::io::{self, IoSlice, Write};
-use std::process::{exit, Child};
+use std::mem;
+use std::process::{Child, ExitStatus};
+use std::sync::Arc;
 use terminal_io::{Terminal, TerminalColorSupport, TerminalWriter, WriteTerminal};
 use utf8_io::{Utf8Writer, WriteStr};
 
@@ -26,6 +39,14 @@ use utf8_io::{Utf8Writer, WriteStr};
 /// it in a [`std::io::BufWriter`] or [`std::io::LineWriter`] is
 /// recommended for performance.
 ///
+/// Output is [Basic Text], and Basic Text is written unchanged, so text
+/// read from an [`InputTextStream`] round-trips through an
+/// `OutputTextStream` byte for byte. The conversions `InputTextStream`
+/// applies to its input deliberately aren't applied to output: a program
+/// controls what it writes, and silently rewriting it, such as turning
+/// CRLF into LF, would hide bugs, so text which isn't Basic Text is
+/// reported as an error instead.
+///
 /// The primary way to construct an `OutputTextStream` is to use it as
 /// a type in a `kommand` argument or a `clap_derive` struct. Command-line
 /// arguments will then be automatically converted into output streams.
@@ -35,25 +56,99 @@ use utf8_io::{Utf8Writer, WriteStr};
 ///  - "-" is interpreted as standard output.
 ///  - "(...)" runs a command with a pipe to the child process' stdin, on
 ///    platforms whch support it.
+///  - With the "clipboard" feature, `clipboard:` places the output on the
+///    system clipboard. The output is buffered, and only placed on the
+///    clipboard when the stream is closed, so partial writes never appear.
+///    A `?type=text/html` option sets HTML, and a `?limit=<bytes>` option
+///    changes the limit on the buffered size from the default of 16 MiB.
+///  - Names starting with `https:` or `http:` with a
+///    `?nameless.multipart=<field>` option upload the output as the file
+///    field `<field>` of a `multipart/form-data` POST request, as with
+///    [`OutputByteStream`].
 ///  - Names which don't parse as URLs are interpreted as plain local
 ///    filesystem paths. To force a string to be interpreted as a plain local
 ///    path, arrange for it to begin with `./` or `/`.
+///  - `file:` URLs with a `?lock=exclusive` option take an advisory lock on
+///    the file, failing if another process holds a conflicting lock, or
+///    waiting up to a given time with `?lock=exclusive,wait=10s`. The lock is
+///    released when the stream is closed. Advisory locks only exclude other
+///    programs which also take locks.
+///  - Paths ending in `.gz` are gzip-compressed. Content which is already
+///    compressed, such as `.png.gz` or `.zip.gz`, is stored without
+///    compression, which is still valid gzip. A `file:` URL with a
+///    `?gzip_level=<0-9>` option chooses the level explicitly.
+///  - `file:` URLs with a `?mode=0600` option create the file with the given
+///    octal permissions, subject to the umask, on Unix-family platforms. A
+///    `?mkdir=parents` option creates missing parent directories, with
+///    permissions from a `dir_mode=` option if given.
+///  - Plain paths accept the same options as `file:` URLs, as in
+///    `out.txt?mode=0600`. Unrecognized options are rejected, with a
+///    suggestion if they look like a misspelling. To name a path containing
+///    `?`, begin it with `./`.
+///  - `file:` URLs and plain paths with a `?rotate=size:10MiB` option
+///    rotate the file once it reaches the given size, renaming it to
+///    `<path>.1` and shifting older files up, keeping 5 of them or the
+///    number given with `,keep:<count>`. With `?rotate=daily`, the file is
+///    rotated when the UTC date changes, and renamed with the date it was
+///    opened, as in `<path>.2021-06-30`. Rotation happens only between
+///    writes which end a line, so the content of a write is never split
+///    between files. If the file can't be renamed, output continues in it,
+///    and the error is passed to the [`on_drop_error`] callback as a
+///    warning. Rotation can't be combined with gzip compression or locking.
 ///
 /// Programs using `OutputTextStream` as an argument should avoid using
 /// `std::io::stdout`, `std::println`, or anything else which uses standard
 /// output implicitly.
+///
+/// [`OutputByteStream`]: crate::OutputByteStream
+/// [`on_drop_error`]: crate::on_drop_error
+/// [Basic Text]: https://docs.rs/basic-text
+/// [`InputTextStream`]: crate::InputTextStream
 pub struct OutputTextStream {
     name: String,
+    kind: StreamKind,
+    /// The outcome of an HTTP upload; see `OutputByteStream`.
+    upload: Option<UploadStatus>,
     writer: TextWriter<Utf8Writer<LayeredWriter<TerminalWriter<StreamWriter>>>>,
     media_type: MediaType,
+    compression_level: Option<u32>,
+    existence: Option<Existence>,
     helper_child: Option<(Child, StreamWriter)>,
+    status: SharedStatus,
+    accountant: Option<Accountant>,
+    /// For file outputs with a `?rotate` option, the state for rotating the
+    /// file.
+    rotation: Option<Rotation>,
+    /// Whether the last write ended partway through a line, in which case
+    /// rotation waits for the line to end.
+    mid_line: bool,
+    options: StreamOptions,
+    telemetry: Telemetry,
 }
 
 impl OutputTextStream {
+    /// Open `name` as if it were a command-line argument, under `policy`
+    /// rather than the default policy.
+    ///
+    /// This is the output counterpart of [`InputByteStream::open_list`] and
+    /// [`LazyInteractive::with_policy`]. To open an output lazily under a
+    /// policy, use [`LazyOutput::with_policy`].
+    ///
+    /// [`InputByteStream::open_list`]: crate::InputByteStream::open_list
+    /// [`LazyInteractive::with_policy`]: crate::LazyInteractive::with_policy
+    /// [`LazyOutput::with_policy`]: crate::LazyOutput::with_policy
+    pub fn open(
+        name: &OsStr,
+        policy: &OpenPolicy,
+        ambient_authority: AmbientAuthority,
+    ) -> anyhow::Result<Self> {
+        open_output(name, MediaType::text(), policy, ambient_authority).map(Self::from_output)
+    }
+
     /// Write the given `Pseudonym` to the output stream.
     #[inline]
     pub fn write_pseudonym(&mut self, pseudonym: &Pseudonym) -> io::Result<()> {
-        Write::write_all(self, pseudonym.name.as_bytes())
+        Write::write_all(self, pseudonym.display_name().as_bytes())
     }
 
     /// Return a `Pseudonym` which encapsulates this stream's name (typically
@@ -72,7 +167,108 @@ impl OutputTextStream {
         &self.media_type
     }
 
-    fn from_output(output: Output) -> Self {
+    /// Return the media type of the stream.
+    #[deprecated(note = "use `media_type` instead")]
+    #[inline]
+    pub fn type_(&self) -> &MediaType {
+        self.media_type()
+    }
+
+    /// Return whether the output file was newly created or already existed,
+    /// or `None` if the output isn't a file.
+    #[inline]
+    pub fn existence(&self) -> Option<Existence> {
+        self.existence
+    }
+
+    /// Return a summary of this stream's metadata.
+    #[inline]
+    pub fn info(&self) -> StreamInfo {
+        StreamInfo {
+            kind: self.kind,
+            media_type: self.media_type.clone(),
+            initial_size: None,
+            compression_level: self.compression_level,
+            existence: self.existence,
+            options: self.options.clone(),
+            cache_status: None,
+        }
+    }
+
+    /// If the output stream's media type declares a structured format, such
+    /// as with a `.json`, `.yaml`, or `.csv` extension, return it.
+    pub fn declared_format(&self) -> Option<OutputFormat> {
+        let mime = self.media_type.mime();
+        match (mime.type_().as_str(), mime.subtype().as_str()) {
+            (_, "json") => Some(OutputFormat::Json),
+            (_, "yaml") | (_, "x-yaml") => Some(OutputFormat::Yaml),
+            ("text", "csv") => Some(OutputFormat::Csv),
+            _ => match mime.suffix().map(|suffix| suffix.as_str()) {
+                Some("json") => Some(OutputFormat::Json),
+                Some("yaml") => Some(OutputFormat::Yaml),
+                _ => None,
+            },
+        }
+    }
+
+    /// Start counting the bytes, lines, and line lengths of the text written
+    /// to this stream, to be reported by [`Self::accounting`].
+    #[inline]
+    pub fn enable_accounting(&mut self) {
+        self.accountant.get_or_insert_with(Accountant::default);
+    }
+
+    /// If accounting has been enabled with [`Self::enable_accounting`],
+    /// return the counts of the text written since then.
+    #[inline]
+    pub fn accounting(&self) -> Option<TextAccounting> {
+        self.accountant.as_ref().map(Accountant::accounting)
+    }
+
+    /// Return a writer for short status and error lines to accompany this
+    /// stream's output, which go to stderr. While the output is being shown
+    /// in a pager, status lines are held until the pager exits, so that
+    /// they aren't hidden behind it or mixed into a page. See
+    /// [`StatusWriter`].
+    #[inline]
+    pub fn status_channel(&self) -> StatusWriter {
+        StatusWriter::new(Arc::clone(&self.status))
+    }
+
+    #[inline]
+    fn account(&mut self, bytes: &[u8]) {
+        self.telemetry.transferred(bytes.len());
+        if let Some(accountant) = &mut self.accountant {
+            accountant.account(bytes);
+        }
+        if let Some(rotation) = &mut self.rotation {
+            rotation.wrote(bytes.len());
+            if let Some(last) = bytes.last() {
+                self.mid_line = *last != b'\n';
+            }
+        }
+    }
+
+    /// If the output file is due to be rotated, and the last write ended a
+    /// line, continue in a fresh file.
+    fn rotate_if_due(&mut self) -> io::Result<()> {
+        let rotation = match &mut self.rotation {
+            Some(rotation) if !self.mid_line && rotation.due() => rotation,
+            _ => return Ok(()),
+        };
+        self.writer.flush()?;
+        match rotation.rotate() {
+            Ok(file) => mem::replace(&mut self.writer, file_writer(file)).close(),
+            // Rotating is tried again at the next rotation point, and the
+            // output is still intact, so this isn't worth failing the write.
+            Err(err) => {
+                report_drop_error(err);
+                Ok(())
+            }
+        }
+    }
+
+    fn from_output((output, telemetry): (Output, Telemetry)) -> Self {
         #[cfg(unix)]
         let is_stdout = output.writer.as_raw_fd() == rustix::stdio::raw_stdout();
         let terminal = TerminalWriter::with_handle(output.writer);
@@ -97,9 +293,19 @@ impl OutputTextStream {
 
                 return Self {
                     name: output.name,
+                    kind: output.kind,
+                    upload: output.upload,
                     writer,
                     media_type: output.media_type,
+                    compression_level: output.compression_level,
+                    existence: output.existence,
                     helper_child: Some((stdout_helper_child, terminal.into_inner())),
+                    status: StatusState::stderr(true),
+                    accountant: None,
+                    rotation: None,
+                    mid_line: false,
+                    options: output.options,
+                    telemetry,
                 };
             }
         }
@@ -110,9 +316,19 @@ impl OutputTextStream {
         let media_type = output.media_type.union(MediaType::text());
         Self {
             name: output.name,
+            kind: output.kind,
+            upload: output.upload,
             writer,
             media_type,
+            compression_level: output.compression_level,
+            existence: output.existence,
             helper_child: None,
+            status: StatusState::stderr(false),
+            accountant: None,
+            rotation: output.rotation,
+            mid_line: false,
+            options: output.options,
+            telemetry,
         }
     }
 }
@@ -131,7 +347,19 @@ impl TryFromOsArg for OutputTextStream {
         os: &OsStr,
         ambient_authority: AmbientAuthority,
     ) -> anyhow::Result<Self> {
-        open_output(os, MediaType::text(), ambient_authority).map(Self::from_output)
+        Self::open(os, &OpenPolicy::default(), ambient_authority)
+    }
+}
+
+impl Sink for OutputTextStream {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        Some(&self.media_type)
+    }
+
+    #[inline]
+    fn close_boxed(&mut self) -> io::Result<()> {
+        self.close()
     }
 }
 
@@ -140,8 +368,17 @@ impl WriteLayered for OutputTextStream {
     fn close(&mut self) -> io::Result<()> {
         self.writer.close()?;
 
+        if let Some(upload) = self.upload.take() {
+            upload.wait()?;
+        }
+
         if let Some(mut helper_child) = self.helper_child.take() {
-            helper_child.0.wait()?;
+            let status = helper_child.0.wait();
+            StatusState::release(&self.status)?;
+            let status = status?;
+            if !status.success() {
+                return Err(helper_failure(status));
+            }
         }
 
         Ok(())
@@ -151,14 +388,20 @@ impl WriteLayered for OutputTextStream {
 impl WriteStr for OutputTextStream {
     #[inline]
     fn write_str(&mut self, buf: &str) -> io::Result<()> {
-        self.writer.write_str(buf)
+        self.rotate_if_due()?;
+        self.writer.write_str(buf)?;
+        self.account(buf.as_bytes());
+        Ok(())
     }
 }
 
 impl Write for OutputTextStream {
     #[inline]
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.writer.write(buf)
+        self.rotate_if_due()?;
+        let n = self.writer.write(buf)?;
+        self.account(&buf[..n]);
+        Ok(n)
     }
 
     #[inline]
@@ -168,7 +411,35 @@ impl Write for OutputTextStream {
 
     #[inline]
     fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
-        self.writer.write_vectored(bufs)
+        if self.rotation.is_some() {
+            // Write the buffers one at a time, so that `account` sees where
+            // each write ended. Stop where rotation is due, so that it
+            // happens at the start of a write.
+            let mut total = 0;
+            for buf in bufs {
+                if total != 0 && !self.mid_line && self.rotation.as_ref().is_some_and(Rotation::due)
+                {
+                    break;
+                }
+                let n = match self.write(buf) {
+                    Ok(n) => n,
+                    Err(err) if total == 0 => return Err(err),
+                    Err(_) => break,
+                };
+                total += n;
+                if n < buf.len() {
+                    break;
+                }
+            }
+            return Ok(total);
+        }
+
+        let n = self.writer.write_vectored(bufs)?;
+        self.telemetry.transferred(n);
+        if let Some(accountant) = &mut self.accountant {
+            accountant.account_vectored(bufs, n);
+        }
+        Ok(n)
     }
 
     #[cfg(can_vector)]
@@ -179,18 +450,39 @@ impl Write for OutputTextStream {
 
     #[inline]
     fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
-        self.writer.write_all(buf)
+        self.rotate_if_due()?;
+        self.writer.write_all(buf)?;
+        self.account(buf);
+        Ok(())
     }
 
     #[cfg(write_all_vectored)]
     #[inline]
     fn write_all_vectored(&mut self, bufs: &mut [IoSlice<'_>]) -> io::Result<()> {
-        self.writer.write_all_vectored(bufs)
+        if self.accountant.is_none() && self.rotation.is_none() {
+            let len = bufs.iter().map(|buf| buf.len()).sum();
+            let result = self.writer.write_all_vectored(bufs);
+            return self.telemetry.transfer_all(result, len);
+        }
+
+        // `write_all_vectored` consumes `bufs`, so save the contents first.
+        let contents = bufs
+            .iter()
+            .flat_map(|buf| buf.iter().copied())
+            .collect::<Vec<u8>>();
+        self.rotate_if_due()?;
+        self.writer.write_all_vectored(bufs)?;
+        self.account(&contents);
+        Ok(())
     }
 
     #[inline]
     fn write_fmt(&mut self, fmt: Arguments<'_>) -> io::Result<()> {
-        self.writer.write_fmt(fmt)
+        if self.accountant.is_none() && self.rotation.is_none() && !self.telemetry.is_counting() {
+            return self.writer.write_fmt(fmt);
+        }
+
+        WriteStr::write_str(self, &fmt::format(fmt))
     }
 }
 
@@ -223,62 +515,78 @@ impl WriteTerminal for OutputTextStream {
 impl WriteText for OutputTextStream {
     #[inline]
     fn write_text(&mut self, buf: &TextStr) -> io::Result<()> {
-        self.writer.write_text(buf)
+        self.rotate_if_due()?;
+        self.writer.write_text(buf)?;
+        self.account(buf.as_str().as_bytes());
+        Ok(())
     }
 }
 
 impl Drop for OutputTextStream {
     fn drop(&mut self) {
-        if let Some(mut helper_child) = self.helper_child.take() {
-            // Wait for the child. We can't return `Err` from a `drop` function,
-            // so just print a message and exit. Callers should use
-            // `end()` to declare the end of the stream if they wish to avoid
-            // these errors.
-
-            // Close standard output, prompting the child process to exit.
-            if let Err(e) = self.writer.close() {
-                eprintln!("Output formatting process encountered error: {:?}", e);
-                #[cfg(not(windows))]
-                exit(rustix::process::EXIT_FAILURE);
-                #[cfg(windows)]
-                exit(libc::EXIT_FAILURE);
-            }
+        // Close the writer, flushing any buffered output, and closing
+        // standard output of any helper process, prompting it to exit. We
+        // can't return `Err` from a `drop` function, so errors are reported
+        // with `report_drop_error`. Callers should use `close()` to end the
+        // stream if they wish to handle these errors.
+        close_on_drop(&mut self.writer);
 
+        if let Some(mut helper_child) = self.helper_child.take() {
+            // Unlike other child processes, which are killed if they don't
+            // exit promptly once their pipes are closed, the pager is waited
+            // for without a time limit, because it exits when the user quits
+            // it. The pipe to it is closed by now, and no locks are held
+            // across this wait, so it can't hold up other streams.
             match helper_child.0.wait() {
-                Ok(status) => {
-                    if !status.success() {
-                        eprintln!(
-                            "Output formatting process exited with non-success exit status: {:?}",
-                            status
-                        );
-                        #[cfg(not(windows))]
-                        exit(rustix::process::EXIT_FAILURE);
-                        #[cfg(windows)]
-                        exit(libc::EXIT_FAILURE);
-                    }
-                }
+                Ok(status) if status.success() => {}
+                Ok(status) => report_drop_error(helper_failure(status)),
+                Err(e) => report_drop_error(e),
+            }
 
-                Err(e) => {
-                    eprintln!("Unable to wait for output formatting process: {:?}", e);
-                    #[cfg(not(windows))]
-                    exit(rustix::process::EXIT_FAILURE);
-                    #[cfg(windows)]
-                    exit(libc::EXIT_FAILURE);
-                }
+            // Now that the pager is gone, print any status lines it held
+            // up.
+            if let Err(e) = StatusState::release(&self.status) {
+                report_drop_error(e);
             }
         }
     }
 }
 
+/// Return the writer for a fresh file from rotating a file output, as
+/// `from_output` would create for it.
+fn file_writer(file: File) -> TextWriter<Utf8Writer<LayeredWriter<TerminalWriter<StreamWriter>>>> {
+    let writer = LayeredWriter::new(TerminalWriter::with_handle(StreamWriter::file(file)));
+    TextWriter::with_ansi_color_output(Utf8Writer::new(writer))
+}
+
+/// Describe an output formatting process which exited unsuccessfully.
+fn helper_failure(status: ExitStatus) -> io::Error {
+    io::Error::other(format!(
+        "output formatting process exited with non-success exit status: {}",
+        status
+    ))
+}
+
 impl FromLazyOutput for OutputTextStream {
     type Err = anyhow::Error;
 
     fn from_lazy_output(
         name: OsString,
         media_type: MediaType,
+        policy: &OpenPolicy,
         ambient_authority: AmbientAuthority,
     ) -> Result<Self, anyhow::Error> {
-        open_output(&name, media_type, ambient_authority).map(Self::from_output)
+        open_output(&name, media_type, policy, ambient_authority).map(Self::from_output)
+    }
+
+    fn from_lazy_output_dry_run(
+        name: OsString,
+        media_type: MediaType,
+        policy: &OpenPolicy,
+        _ambient_authority: AmbientAuthority,
+    ) -> Result<Self, anyhow::Error> {
+        open_output_dry_run(&name, media_type, policy)
+            .map(|output| Self::from_output((output, Telemetry::default())))
     }
 }
 
@@ -290,3 +598,132 @@ impl Debug for OutputTextStream {
         b.finish()
     }
 }
+
+#[test]
+fn accounting() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("accounting.txt");
+    let mut output =
+        OutputTextStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    assert_eq!(output.accounting(), None);
+    output.enable_accounting();
+
+    output.write_str("one ").unwrap();
+    output.write_all(b"line\ntw").unwrap();
+    write!(output, "o").unwrap();
+    output.write_str("\nthe third line\n").unwrap();
+
+    let accounting = output.accounting().unwrap();
+    assert_eq!(accounting.bytes(), 28);
+    assert_eq!(accounting.lines(), 3);
+    assert_eq!(accounting.max_line_len(), 14);
+
+    output.close().unwrap();
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn status_deferred_until_pager_exits() {
+    use std::fs::{self, OpenOptions};
+    use std::process::{Command, Stdio};
+
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("terminal.txt");
+    let terminal = || {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log)
+            .unwrap()
+    };
+
+    // A fake pager, which, like a real one, shows its input some time after
+    // receiving it, here on the same "terminal" as the status output.
+    let mut pager = Command::new("sh")
+        .arg("-c")
+        .arg("sleep 0.2; cat")
+        .stdin(Stdio::piped())
+        .stdout(terminal())
+        .spawn()
+        .unwrap();
+    let writer = StreamWriter::child_stdin(pager.stdin.take().unwrap());
+    let writer = LayeredWriter::new(TerminalWriter::with_handle(writer));
+    let writer = TextWriter::with_ansi_color_output(Utf8Writer::new(writer));
+    let mut output = OutputTextStream {
+        name: "-".to_owned(),
+        kind: StreamKind::Stdio,
+        upload: None,
+        writer,
+        media_type: MediaType::text(),
+        compression_level: None,
+        existence: None,
+        helper_child: Some((pager, StreamWriter::file(terminal()))),
+        status: StatusState::new(true, Box::new(terminal())),
+        accountant: None,
+        rotation: None,
+        mid_line: false,
+        options: StreamOptions::default(),
+        telemetry: Telemetry::default(),
+    };
+
+    let mut status = output.status_channel();
+    output.write_str("page one\n").unwrap();
+    writeln!(status, "status one").unwrap();
+    output.write_str("page two\n").unwrap();
+    writeln!(status, "status two").unwrap();
+    output.close().unwrap();
+
+    // Once the pager is gone, status lines are written immediately.
+    writeln!(status, "status three").unwrap();
+    assert_eq!(
+        fs::read_to_string(&log).unwrap(),
+        "page one\npage two\nstatus one\nstatus two\nstatus three\n"
+    );
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn drop_reports_helper_errors() {
+    use crate::drop_error::DropErrorRecorder;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::process::{Command, Stdio};
+
+    let recorder = DropErrorRecorder::install();
+
+    // A formatter which fails without reading its input. Once it has
+    // exited, the pipe to it is closed under the stream.
+    let mut formatter = Command::new("sh")
+        .arg("-c")
+        .arg("exit 3")
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let writer = StreamWriter::child_stdin(formatter.stdin.take().unwrap());
+    assert_eq!(formatter.wait().unwrap().code(), Some(3));
+    let writer = LayeredWriter::new(TerminalWriter::with_handle(writer));
+    let writer = TextWriter::with_ansi_color_output(Utf8Writer::new(writer));
+    let mut output = OutputTextStream {
+        name: "-".to_owned(),
+        kind: StreamKind::Stdio,
+        upload: None,
+        writer,
+        media_type: MediaType::text(),
+        compression_level: None,
+        existence: None,
+        helper_child: Some((formatter, StreamWriter::file(tempfile::tempfile().unwrap()))),
+        status: StatusState::new(true, Box::new(io::sink())),
+        accountant: None,
+        rotation: None,
+        mid_line: false,
+        options: StreamOptions::default(),
+        telemetry: Telemetry::default(),
+    };
+    let err = output.write_str("lost\n").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+    // Dropping the stream doesn't panic or exit, and reports the formatter's
+    // failure. The failed write already returned its error, and ended the
+    // writer, so that isn't reported again.
+    assert!(catch_unwind(AssertUnwindSafe(|| drop(output))).is_ok());
+    assert_eq!(recorder.take(), vec![io::ErrorKind::Other]);
+}
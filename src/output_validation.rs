@@ -0,0 +1,180 @@
+use crate::diagnose::open_error;
+use crate::StreamKind;
+use anyhow::anyhow;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A report from [`LazyOutput::validate`], describing an output which
+/// appears to be openable.
+///
+/// Validation is inherently racy: the filesystem may change between
+/// validating an output and opening it, so a successful validation doesn't
+/// guarantee that opening will succeed, and a later open should still be
+/// prepared to fail.
+///
+/// [`LazyOutput::validate`]: crate::LazyOutput::validate
+#[derive(Clone, Debug)]
+pub struct OutputValidation {
+    pub(crate) kind: StreamKind,
+    pub(crate) exists: bool,
+}
+
+impl OutputValidation {
+    /// Return the kind of resource the output would be connected to.
+    #[inline]
+    pub fn kind(&self) -> StreamKind {
+        self.kind
+    }
+
+    /// Test whether the output is an existing file, which opening it would
+    /// truncate.
+    #[inline]
+    pub fn exists(&self) -> bool {
+        self.exists
+    }
+}
+
+/// Check that a file could be created or truncated at `path`, without
+/// creating or modifying anything. If `create_parents` is set, missing
+/// parent directories would be created, so the nearest existing ancestor
+/// is checked instead of the parent.
+pub(crate) fn validate_path(path: &Path, create_parents: bool) -> anyhow::Result<OutputValidation> {
+    let exists = match fs::metadata(path) {
+        Ok(metadata) => {
+            if metadata.is_dir() {
+                return Err(anyhow!("{}: is a directory", path.display()));
+            }
+            check_writable(path, &metadata)?;
+            true
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let mut parent = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            if create_parents {
+                while !parent.exists() {
+                    parent = match parent.parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => parent,
+                        _ => Path::new("."),
+                    };
+                }
+            }
+            let metadata = fs::metadata(parent).map_err(|err| open_error(parent, err))?;
+            if !metadata.is_dir() {
+                return Err(anyhow!("{}: not a directory", parent.display()));
+            }
+            check_writable(parent, &metadata)?;
+            check_space(parent)?;
+            false
+        }
+        Err(err) => return Err(open_error(path, err)),
+    };
+    Ok(OutputValidation {
+        kind: StreamKind::File,
+        exists,
+    })
+}
+
+#[cfg(unix)]
+fn check_writable(path: &Path, _metadata: &fs::Metadata) -> anyhow::Result<()> {
+    use rustix::fs::{accessat, Access, AtFlags, CWD};
+
+    accessat(CWD, path, Access::WRITE_OK, AtFlags::EACCESS)
+        .map_err(|errno| open_error(path, errno.into()))
+}
+
+#[cfg(not(unix))]
+fn check_writable(path: &Path, metadata: &fs::Metadata) -> anyhow::Result<()> {
+    if metadata.permissions().readonly() {
+        return Err(open_error(path, io::ErrorKind::PermissionDenied.into()));
+    }
+    Ok(())
+}
+
+/// Fail if the filesystem containing `dir` has no space left.
+#[cfg(unix)]
+fn check_space(dir: &Path) -> anyhow::Result<()> {
+    use rustix::io::Errno;
+
+    match rustix::fs::statvfs(dir) {
+        Ok(statvfs) if statvfs.f_bavail == 0 => Err(open_error(dir, Errno::NOSPC.into())),
+        // Failing to query the filesystem isn't a reason to fail.
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn check_space(_dir: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+fn lazy(path: &Path) -> crate::LazyOutput<crate::OutputByteStream> {
+    use clap::TryFromOsArg;
+    crate::LazyOutput::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap()
+}
+
+#[test]
+fn validate_missing_parent() {
+    let dir = tempfile::tempdir().unwrap();
+    let err = lazy(&dir.path().join("missing/out.txt"))
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("missing"));
+}
+
+#[cfg(unix)]
+#[test]
+fn validate_read_only_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Root can write anywhere.
+    if rustix::process::geteuid().is_root() {
+        return;
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o555)).unwrap();
+    let result = lazy(&dir.path().join("out.txt")).validate();
+    fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(result.is_err());
+}
+
+#[test]
+fn validate_existing_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.txt");
+    fs::write(&path, "keep me").unwrap();
+
+    let validation = lazy(&path).validate().unwrap();
+    assert_eq!(validation.kind(), StreamKind::File);
+    assert!(validation.exists());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "keep me");
+
+    let validation = lazy(&dir.path().join("new.txt")).validate().unwrap();
+    assert!(!validation.exists());
+
+    assert!(lazy(dir.path()).validate().is_err());
+}
+
+#[test]
+fn dry_run_writes_nothing() {
+    use crate::MediaType;
+    use layered_io::WriteLayered;
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.txt");
+    let mut output = lazy(&path)
+        .materialize_dry_run(MediaType::unknown())
+        .unwrap();
+    output.write_all(b"discarded").unwrap();
+    output.close().unwrap();
+    assert!(!path.exists());
+
+    assert!(lazy(&dir.path().join("missing/out.txt"))
+        .materialize_dry_run(MediaType::unknown())
+        .is_err());
+}
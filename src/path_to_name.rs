@@ -11,6 +11,8 @@ use {
 pub(crate) fn path_to_name(scheme: &str, path: &Path) -> anyhow::Result<String> {
     #[cfg(unix)]
     use std::os::unix::ffi::OsStrExt;
+    #[cfg(target_os = "wasi")]
+    use std::os::wasi::ffi::OsStrExt;
     if path.is_absolute() {
         let mut result = String::new();
         let mut components = path.components();
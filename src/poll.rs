@@ -0,0 +1,251 @@
+//! Support for waiting on streams in `poll`-style event loops, with the
+//! `poll` feature.
+
+use layered_io::Status;
+use std::io;
+#[cfg(all(feature = "poll", unix))]
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+
+/// What to poll to wait for input on a stream.
+///
+/// The file descriptors here are duplicates of the stream's own, so fields
+/// holding a `PollHandle` are declared before the stream's reader. That way,
+/// the pipe from a piped thread is fully closed when the reader is dropped,
+/// which the thread needs in order to exit.
+#[cfg(all(feature = "poll", unix))]
+pub(crate) enum PollHandle {
+    /// The file descriptor the stream reads from.
+    Direct(OwnedFd),
+    /// A pipe which a thread inside the stream writes to as it produces
+    /// input, which becomes readable when the thread has data.
+    Notification(OwnedFd),
+    /// The stream has no file descriptor to poll.
+    None,
+}
+
+#[cfg(all(feature = "poll", unix))]
+impl PollHandle {
+    /// Return a `PollHandle` for a stream reading from `fd`, which is the
+    /// pipe from a piped thread if `piped_thread` is true.
+    pub(crate) fn new(fd: impl AsFd, piped_thread: bool) -> Self {
+        // If the descriptor can't be duplicated, the stream just isn't
+        // pollable. The duplicate is close-on-exec, so that child processes
+        // don't hold the pipe from a piped thread open.
+        match rustix::io::fcntl_dupfd_cloexec(fd, 0) {
+            Ok(fd) if piped_thread => Self::Notification(fd),
+            Ok(fd) => Self::Direct(fd),
+            Err(_) => Self::None,
+        }
+    }
+
+    pub(crate) fn direct(&self) -> Option<BorrowedFd<'_>> {
+        match self {
+            Self::Direct(fd) => Some(fd.as_fd()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn notification(&self) -> Option<BorrowedFd<'_>> {
+        match self {
+            Self::Notification(fd) => Some(fd.as_fd()),
+            _ => None,
+        }
+    }
+}
+
+/// Tracks whether a stream which decodes its input may be holding decoded
+/// input that hasn't been returned yet, by observing the results of its
+/// reads.
+///
+/// The decoding layers don't say what they're holding, and a read which
+/// returns less than was asked for may still leave input behind, such as
+/// the rest of a line being normalized, so this is conservative: input may
+/// be buffered after any read which didn't end the stream or fail.
+///
+/// Only streams with the `poll` feature ask, so without it, nothing is
+/// recorded.
+#[derive(Default)]
+pub(crate) struct ReadAhead {
+    #[cfg(all(feature = "poll", unix))]
+    maybe_buffered: bool,
+}
+
+impl ReadAhead {
+    /// Return whether input may be buffered.
+    #[cfg(all(feature = "poll", unix))]
+    pub(crate) fn buffered(&self) -> bool {
+        self.maybe_buffered
+    }
+
+    #[cfg(all(feature = "poll", unix))]
+    #[inline]
+    fn set(&mut self, maybe_buffered: bool) {
+        self.maybe_buffered = maybe_buffered;
+    }
+
+    #[cfg(not(all(feature = "poll", unix)))]
+    #[inline]
+    fn set(&mut self, _maybe_buffered: bool) {}
+
+    /// Observe the result of a read into a buffer of `len` bytes.
+    pub(crate) fn read(&mut self, result: io::Result<usize>, len: usize) -> io::Result<usize> {
+        match &result {
+            // An empty read says nothing about the stream.
+            Ok(0) if len == 0 => {}
+            Ok(n) => self.set(*n != 0),
+            Err(_) => self.set(false),
+        }
+        result
+    }
+
+    /// Observe the result of a read into a buffer of `len` bytes which
+    /// reports a `Status`.
+    pub(crate) fn read_with_status(
+        &mut self,
+        result: io::Result<(usize, Status)>,
+        len: usize,
+    ) -> io::Result<(usize, Status)> {
+        match &result {
+            Ok((_, Status::End)) | Err(_) => self.set(false),
+            Ok((0, _)) if len == 0 => {}
+            Ok(_) => self.set(true),
+        }
+        result
+    }
+
+    /// Observe the result of a read which fails if it doesn't fill its
+    /// buffer.
+    pub(crate) fn read_exact<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        self.set(result.is_ok());
+        result
+    }
+
+    /// Observe the result of a read which fails if it doesn't fill its
+    /// buffer, and which reports a `Status`.
+    pub(crate) fn read_exact_with_status(
+        &mut self,
+        result: io::Result<Status>,
+    ) -> io::Result<Status> {
+        self.set(matches!(&result, Ok(status) if *status != Status::End));
+        result
+    }
+
+    /// Observe the result of a read which reads until the end.
+    pub(crate) fn read_to_end<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        self.set(false);
+        result
+    }
+}
+
+/// Wait up to `timeout` milliseconds for `fd` to become readable.
+#[cfg(all(test, feature = "poll", unix))]
+fn readable(fd: BorrowedFd<'_>, timeout: i32) -> bool {
+    use rustix::event::{poll, PollFd, PollFlags};
+
+    let mut fds = [PollFd::new(&fd, PollFlags::IN)];
+    poll(&mut fds, timeout).unwrap() != 0
+}
+
+#[cfg(all(test, feature = "poll", unix))]
+fn connect(path: &std::path::Path) -> std::ffi::OsString {
+    format!("connect:{}", path.display()).into()
+}
+
+#[cfg(all(feature = "poll", unix))]
+#[test]
+fn poll_interactive_socket() {
+    use crate::InteractiveByteStream;
+    use clap::TryFromOsArg;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("socket");
+    let listener = UnixListener::bind(&path).unwrap();
+    let mut stream =
+        InteractiveByteStream::try_from_os_str_arg(&connect(&path), clap::ambient_authority())
+            .unwrap();
+    let (mut peer, _addr) = listener.accept().unwrap();
+
+    assert!(!readable(stream.as_poll_handle().unwrap(), 0));
+    peer.write_all(b"hello").unwrap();
+    assert!(readable(stream.as_poll_handle().unwrap(), 5000));
+
+    let mut buf = [0; 5];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    assert!(!stream.has_buffered_input());
+    assert!(!readable(stream.as_poll_handle().unwrap(), 0));
+
+    // The end of the stream makes it readable too.
+    drop(peer);
+    assert!(readable(stream.as_poll_handle().unwrap(), 5000));
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+}
+
+#[cfg(all(feature = "poll", unix))]
+#[test]
+fn buffered_input_after_read() {
+    use crate::InteractiveTextStream;
+    use clap::TryFromOsArg;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("socket");
+    let listener = UnixListener::bind(&path).unwrap();
+    let mut stream =
+        InteractiveTextStream::try_from_os_str_arg(&connect(&path), clap::ambient_authority())
+            .unwrap();
+    let (mut peer, _addr) = listener.accept().unwrap();
+    assert!(!stream.has_buffered_input());
+
+    peer.write_all(b"hello\nworld\n").unwrap();
+    assert!(readable(stream.as_poll_handle().unwrap(), 5000));
+
+    // After a read, the decoding layers may be holding more input.
+    let mut buf = [0; basic_text::NORMALIZATION_BUFFER_SIZE];
+    let n = stream.read(&mut buf).unwrap();
+    assert_ne!(n, 0);
+    assert!(b"hello\nworld\n".starts_with(&buf[..n]));
+    assert!(stream.has_buffered_input());
+
+    // Once the stream ends, nothing is left.
+    drop(peer);
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert_eq!([&buf[..n], &rest].concat(), b"hello\nworld\n");
+    assert!(!stream.has_buffered_input());
+}
+
+#[cfg(all(feature = "poll", unix))]
+#[test]
+fn poll_http_notification() {
+    use crate::test_server::{response, TestServer};
+    use crate::InputByteStream;
+    use clap::TryFromOsArg;
+    use std::io::Read;
+
+    let server = TestServer::start(|_request| {
+        response(
+            "200 OK",
+            &[("Content-Type", "text/plain")],
+            b"hello, world\n",
+        )
+    });
+    let mut input = InputByteStream::try_from_os_str_arg(
+        server.url("/data.txt").as_ref(),
+        clap::ambient_authority(),
+    )
+    .unwrap();
+
+    // HTTP bodies are read by a thread, so there's no fd to poll directly,
+    // but the notification fd becomes readable once the thread has data.
+    assert!(input.as_poll_handle().is_none());
+    assert!(readable(input.notification_handle().unwrap(), 5000));
+    assert!(!input.has_buffered_input());
+
+    let mut s = String::new();
+    input.read_to_string(&mut s).unwrap();
+    assert_eq!(s, "hello, world\n");
+}
@@ -0,0 +1,361 @@
+#[cfg(not(target_os = "wasi"))]
+use crate::http_pool::http_agent;
+use crate::stream_options::{parse_options, parse_url_options, split_options, FILE_INPUT};
+#[cfg(not(target_os = "wasi"))]
+use crate::stream_options::{take_prefixed_options, HTTP};
+use crate::syntax::{classify_with_policy, split_path_fragment};
+use crate::{MediaType, Mime, OpenError, OpenPolicy, StreamKind, SyntaxKind};
+use anyhow::anyhow;
+use data_url::DataUrl;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use url::Url;
+
+/// HTTP headers which are included in a `StreamProbe`, when present.
+const PROBED_HEADERS: &[&str] = &[
+    "Content-Type",
+    "Content-Length",
+    "Content-Encoding",
+    "Last-Modified",
+    "ETag",
+    "Accept-Ranges",
+];
+
+/// Metadata about an input, obtained without reading any of its contents.
+///
+/// See [`probe`] for details.
+#[derive(Clone, Debug)]
+pub struct StreamProbe {
+    media_type: MediaType,
+    size: Option<u64>,
+    kind: StreamKind,
+    headers: Vec<(String, String)>,
+}
+
+impl StreamProbe {
+    /// Return the media type implied by the input's metadata.
+    #[inline]
+    pub fn media_type(&self) -> &MediaType {
+        &self.media_type
+    }
+
+    /// Return the size of the input in bytes, if known. For gzipped files
+    /// this is unknown, as the uncompressed size isn't in the metadata.
+    #[inline]
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Return the kind of resource the input refers to.
+    #[inline]
+    pub fn kind(&self) -> StreamKind {
+        self.kind
+    }
+
+    /// For HTTP inputs, return selected response headers, such as
+    /// `Content-Type`, `Last-Modified`, and `ETag`. Empty for other inputs.
+    #[inline]
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+}
+
+/// Obtain the metadata for an input stream name without reading any of its
+/// contents.
+///
+/// This accepts the same syntaxes as [`InputByteStream`]. HTTP URLs are
+/// probed with a `HEAD` request, falling back to a ranged `GET` whose body
+/// is never read if the server rejects `HEAD`. Files are probed with their
+/// filesystem metadata, and `data:` URLs are answered immediately. Child
+/// processes and interactive syntaxes fail with
+/// [`OpenError::NotProbeable`], and other failures are reported as
+/// [`OpenError::ProbeFailed`].
+///
+/// [`InputByteStream`]: crate::InputByteStream
+pub fn probe(os: &OsStr, policy: &OpenPolicy) -> Result<StreamProbe, OpenError> {
+    probe_name(os, policy).map_err(|err| match err.downcast::<OpenError>() {
+        Ok(err) => err,
+        Err(err) => OpenError::ProbeFailed(err),
+    })
+}
+
+fn probe_name(os: &OsStr, policy: &OpenPolicy) -> anyhow::Result<StreamProbe> {
+    match classify_with_policy(os, policy)? {
+        SyntaxKind::Url(_) => {
+            let s = os
+                .to_str()
+                .ok_or_else(|| anyhow!("URL is not valid UTF-8"))?;
+            probe_url(Url::parse(s)?)
+        }
+        SyntaxKind::Stdio => Ok(StreamProbe {
+            media_type: MediaType::unknown(),
+            size: None,
+            kind: StreamKind::Stdio,
+            headers: Vec::new(),
+        }),
+        SyntaxKind::Command | SyntaxKind::Pipeline => {
+            if !policy.allow_exec {
+                return Err(anyhow!("child processes are disabled by policy"));
+            }
+            Err(OpenError::NotProbeable("child process").into())
+        }
+        // Probe the whole file named by a path with a `#fragment`.
+        SyntaxKind::Path => {
+            let (path, query) = split_options(split_path_fragment(os).0, &FILE_INPUT);
+            parse_options(query, &FILE_INPUT)?;
+            probe_path(Path::new(path))
+        }
+    }
+}
+
+fn probe_url(mut url: Url) -> anyhow::Result<StreamProbe> {
+    match url.scheme() {
+        #[cfg(not(target_os = "wasi"))]
+        "http" | "https" => {
+            take_prefixed_options(&mut url, &HTTP)?;
+            probe_http_url_str(url.as_str())
+        }
+        #[cfg(target_os = "wasi")]
+        "http" | "https" => Err(OpenError::UnsupportedOnPlatform("HTTP URLs").into()),
+        "data" => probe_data_url_str(url.as_str()),
+        "file" => {
+            if !url.username().is_empty()
+                || url.password().is_some()
+                || url.has_host()
+                || url.port().is_some()
+            {
+                return Err(anyhow!("file URL should only contain a path and options"));
+            }
+            // Options only matter once the file is opened. As with paths,
+            // probe the whole file named by a URL with a `#fragment`.
+            parse_url_options(&url, &FILE_INPUT)?;
+            url.set_query(None);
+            url.set_fragment(None);
+            probe_path(
+                &url.to_file_path()
+                    .map_err(|_: ()| anyhow!("unknown file URL weirdness"))?,
+            )
+        }
+        #[cfg(all(feature = "ssh2", not(target_os = "wasi")))]
+        "scp" => Err(OpenError::NotProbeable("scp URL").into()),
+        #[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+        "clipboard" => Err(OpenError::NotProbeable("clipboard URL").into()),
+        "connect" | "accept" => Err(OpenError::NotProbeable("interactive stream").into()),
+        other => Err(anyhow!("unsupported URL scheme \"{}\"", other)),
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+fn probe_http_url_str(http_url_str: &str) -> anyhow::Result<StreamProbe> {
+    let response = match http_agent().head(http_url_str).call() {
+        Ok(response) => response,
+        // Some servers don't implement `HEAD`. Fall back to a `GET` for
+        // the smallest possible range; we never read the body.
+        Err(ureq::Error::Status(405, _)) | Err(ureq::Error::Status(501, _)) => http_agent()
+            .get(http_url_str)
+            .set("Range", "bytes=0-0")
+            .call()
+            .map_err(|e| anyhow!("HTTP error probing {}: {}", http_url_str, e))?,
+        Err(e) => return Err(anyhow!("HTTP error probing {}: {}", http_url_str, e)),
+    };
+
+    let size = if response.status() == 206 {
+        // For a partial response, the full size follows the `/` in
+        // `Content-Range`, unless it's `*`.
+        response
+            .header("Content-Range")
+            .and_then(|range| range.rsplit('/').next())
+            .and_then(|total| total.parse().ok())
+    } else {
+        response
+            .header("Content-Length")
+            .and_then(|len| len.parse().ok())
+    };
+
+    let media_type = MediaType::from_mime(Mime::from_str(response.content_type())?);
+
+    let headers = PROBED_HEADERS
+        .iter()
+        .filter_map(|name| {
+            response
+                .header(name)
+                .map(|value| ((*name).to_owned(), value.to_owned()))
+        })
+        .collect();
+
+    Ok(StreamProbe {
+        media_type,
+        size,
+        kind: StreamKind::Http,
+        headers,
+    })
+}
+
+fn probe_data_url_str(data_url_str: &str) -> anyhow::Result<StreamProbe> {
+    let data_url =
+        DataUrl::process(data_url_str).map_err(|e| anyhow!("invalid data URL syntax: {:?}", e))?;
+    // Count the decoded bytes rather than collecting them, so that probing
+    // a data URL doesn't buffer its body, and needs no memory budget.
+    let mut size = 0_u64;
+    data_url
+        .decode(|bytes| {
+            size += u64::try_from(bytes.len()).unwrap();
+            Ok::<_, ()>(())
+        })
+        .map_err(|_| anyhow!("invalid base64 encoding"))?;
+
+    let media_type =
+        MediaType::from_mime(Mime::from_str(&data_url.mime_type().to_string()).unwrap());
+
+    Ok(StreamProbe {
+        media_type,
+        size: Some(size),
+        kind: StreamKind::Data,
+        headers: Vec::new(),
+    })
+}
+
+fn probe_path(path: &Path) -> anyhow::Result<StreamProbe> {
+    let metadata = fs::metadata(path).map_err(|err| anyhow!("{}: {}", path.display(), err))?;
+    if path.extension() == Some(Path::new("gz").as_os_str()) {
+        // Report the type of the contents, as `open_input` decompresses.
+        let path = path.with_extension("");
+        Ok(StreamProbe {
+            media_type: MediaType::from_path(&path),
+            size: None,
+            kind: StreamKind::File,
+            headers: Vec::new(),
+        })
+    } else {
+        Ok(StreamProbe {
+            media_type: MediaType::from_path(path),
+            size: Some(metadata.len()),
+            kind: StreamKind::File,
+            headers: Vec::new(),
+        })
+    }
+}
+
+#[test]
+fn probe_data_url() {
+    let probe = probe(
+        "data:text/plain;base64,SGVsbG8sIFdvcmxkIQ==".as_ref(),
+        &OpenPolicy::default(),
+    )
+    .unwrap();
+    assert_eq!(probe.kind(), StreamKind::Data);
+    assert_eq!(probe.size(), Some(13));
+    assert_eq!(probe.media_type().mime(), &mime::TEXT_PLAIN);
+}
+
+#[test]
+fn probe_data_url_budget() {
+    use crate::memory_budget::{test_budget, with_test_budget};
+
+    // Probing a data URL too big to open doesn't buffer its body.
+    let name = format!("data:,{}", "x".repeat(1000));
+    let (probe, warnings) = with_test_budget(test_budget(64), || {
+        probe(name.as_ref(), &OpenPolicy::default())
+    });
+    assert_eq!(probe.unwrap().size(), Some(1000));
+    assert!(warnings.is_empty(), "{:?}", warnings);
+}
+
+#[test]
+fn probe_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let plain = dir.path().join("hello.txt");
+    fs::write(&plain, "hello\n").unwrap();
+    let gzipped = dir.path().join("hello.json.gz");
+    fs::write(&gzipped, b"not actually read").unwrap();
+
+    let probe_plain = probe(plain.as_os_str(), &OpenPolicy::default()).unwrap();
+    assert_eq!(probe_plain.kind(), StreamKind::File);
+    assert_eq!(probe_plain.size(), Some(6));
+    assert_eq!(probe_plain.media_type().mime(), &mime::TEXT_PLAIN);
+
+    let probe_gzipped = probe(gzipped.as_os_str(), &OpenPolicy::default()).unwrap();
+    assert_eq!(probe_gzipped.size(), None);
+    assert_eq!(probe_gzipped.media_type().mime(), &mime::APPLICATION_JSON);
+
+    let mut url = Url::from_file_path(&plain).unwrap();
+    url.set_fragment(Some("L1-3"));
+    let probe_fragment = probe(url.as_str().as_ref(), &OpenPolicy::default()).unwrap();
+    assert_eq!(probe_fragment.kind(), StreamKind::File);
+    assert_eq!(probe_fragment.size(), Some(6));
+
+    let err = probe(
+        dir.path().join("missing.txt").as_os_str(),
+        &OpenPolicy::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, OpenError::ProbeFailed(_)));
+}
+
+#[test]
+fn probe_not_probeable() {
+    for name in [
+        "$(echo hello)",
+        "data:,hello | tr a-z A-Z",
+        "connect://127.0.0.1:9",
+        "accept://127.0.0.1:0",
+    ] {
+        let err = probe(name.as_ref(), &OpenPolicy::default()).unwrap_err();
+        assert!(matches!(err, OpenError::NotProbeable(_)), "{}", name);
+    }
+}
+
+#[test]
+fn probe_http_head() {
+    use crate::test_server::{response, TestServer};
+
+    let server = TestServer::start(|request| {
+        assert_eq!(request.method, "HEAD");
+        assert_eq!(request.path, "/page");
+        response(
+            "200 OK",
+            &[
+                ("Content-Type", "text/html"),
+                ("Content-Length", "1234"),
+                ("ETag", "\"abc\""),
+            ],
+            b"",
+        )
+    });
+
+    let probe = probe(server.url("/page").as_ref(), &OpenPolicy::default()).unwrap();
+    assert_eq!(probe.kind(), StreamKind::Http);
+    assert_eq!(probe.size(), Some(1234));
+    assert_eq!(probe.media_type().mime(), &mime::TEXT_HTML);
+    assert!(probe
+        .headers()
+        .iter()
+        .any(|(name, value)| name == "ETag" && value == "\"abc\""));
+}
+
+#[test]
+fn probe_http_head_fallback() {
+    use crate::test_server::{response, TestServer};
+
+    let server = TestServer::start(|request| match request.method.as_str() {
+        "HEAD" => response("405 Method Not Allowed", &[], b""),
+        "GET" => {
+            assert_eq!(request.header("Range"), Some("bytes=0-0"));
+            response(
+                "206 Partial Content",
+                &[
+                    ("Content-Type", "text/csv"),
+                    ("Content-Range", "bytes 0-0/5678"),
+                ],
+                b"a",
+            )
+        }
+        other => panic!("unexpected method {}", other),
+    });
+
+    let probe = probe(server.url("/data").as_ref(), &OpenPolicy::default()).unwrap();
+    assert_eq!(probe.size(), Some(5678));
+    assert_eq!(probe.media_type().mime(), &mime::TEXT_CSV);
+}
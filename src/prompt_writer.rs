@@ -0,0 +1,228 @@
+use layered_io::Bufferable;
+use std::io::{self, BufRead, IoSliceMut, Read, Write};
+
+/// Adds [`write_prompt`] to all writers.
+///
+/// Line-buffered writers, such as `io_streams::BufReaderLineWriter`, hold a
+/// prompt like `"> "` in their buffer until a newline is written or
+/// something flushes them. `write_prompt` writes and then flushes, so the
+/// prompt is visible immediately however the writer is read from next.
+///
+/// [`write_prompt`]: WritePrompt::write_prompt
+pub trait WritePrompt: Write {
+    /// Write `prompt` and flush it immediately, whether or not it ends in a
+    /// newline.
+    fn write_prompt(&mut self, prompt: &str) -> io::Result<()> {
+        self.write_all(prompt.as_bytes())?;
+        self.flush()
+    }
+}
+
+impl<W: Write + ?Sized> WritePrompt for W {}
+
+/// A wrapper around a line-buffered stream which controls when partial
+/// lines are flushed.
+///
+/// By default it passes everything through unchanged, preserving the inner
+/// stream's buffering. With [`eager_flush_on_partial`], any write which
+/// doesn't end in a newline is flushed immediately, which is useful for
+/// progress output written with `write!`. With [`flush_threshold`], a
+/// partial line is flushed once that many bytes of it have been written,
+/// so that long lines don't wait for their newline.
+///
+/// Reads are passed through to the inner stream.
+///
+/// [`eager_flush_on_partial`]: Self::eager_flush_on_partial
+/// [`flush_threshold`]: Self::flush_threshold
+pub struct PromptWriter<T: Write> {
+    inner: T,
+    eager_flush_on_partial: bool,
+    flush_threshold: Option<usize>,
+    /// The number of bytes written since the last newline or flush.
+    pending: usize,
+}
+
+impl<T: Write> PromptWriter<T> {
+    /// Wrap `inner`, with the default behavior of never flushing partial
+    /// lines.
+    #[inline]
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            eager_flush_on_partial: false,
+            flush_threshold: None,
+            pending: 0,
+        }
+    }
+
+    /// Flush after any write which doesn't end in a newline.
+    #[inline]
+    pub fn eager_flush_on_partial(mut self, eager: bool) -> Self {
+        self.eager_flush_on_partial = eager;
+        self
+    }
+
+    /// Flush a partial line once `threshold` bytes of it are pending.
+    #[inline]
+    pub fn flush_threshold(mut self, threshold: usize) -> Self {
+        self.flush_threshold = Some(threshold);
+        self
+    }
+
+    /// Return a reference to the inner stream.
+    #[inline]
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Return a mutable reference to the inner stream.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consume `self` and return the inner stream.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Write> Write for PromptWriter<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        let written = &buf[..n];
+        match written.iter().rposition(|b| *b == b'\n') {
+            Some(pos) => self.pending = n - pos - 1,
+            None => self.pending += n,
+        }
+        let over_threshold = self
+            .flush_threshold
+            .is_some_and(|threshold| self.pending >= threshold);
+        if self.pending != 0 && (self.eager_flush_on_partial || over_threshold) {
+            self.flush()?;
+        }
+        Ok(n)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.pending = 0;
+        self.inner.flush()
+    }
+}
+
+impl<T: Write + Read> Read for PromptWriter<T> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    #[inline]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        self.inner.read_vectored(bufs)
+    }
+}
+
+impl<T: Write + BufRead> BufRead for PromptWriter<T> {
+    #[inline]
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    #[inline]
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+impl<T: Write + Bufferable> Bufferable for PromptWriter<T> {
+    #[inline]
+    fn abandon(&mut self) {
+        self.pending = 0;
+        self.inner.abandon()
+    }
+}
+
+/// A stand-in for a line-buffered writer, which records what has been
+/// flushed through it.
+#[cfg(test)]
+#[derive(Default)]
+struct LineBuffered {
+    buffer: Vec<u8>,
+    flushed: Vec<u8>,
+    max_line_len: Option<usize>,
+}
+
+#[cfg(test)]
+impl Write for LineBuffered {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Like `LineWriter`, write up to the last newline through.
+        self.buffer.extend_from_slice(buf);
+        if let Some(pos) = self.buffer.iter().rposition(|b| *b == b'\n') {
+            self.flushed.extend(self.buffer.drain(..=pos));
+        }
+        // Protect against unbounded lines by flushing long partial lines.
+        if let Some(max_line_len) = self.max_line_len {
+            if self.buffer.len() > max_line_len {
+                self.flushed.append(&mut self.buffer);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flushed.append(&mut self.buffer);
+        Ok(())
+    }
+}
+
+#[test]
+fn partial_lines_default() {
+    let mut writer = PromptWriter::new(LineBuffered::default());
+    write!(writer, "one\ntw").unwrap();
+    assert_eq!(writer.get_ref().flushed, b"one\n");
+    writer.write_prompt("> ").unwrap();
+    assert_eq!(writer.get_ref().flushed, b"one\ntw> ");
+}
+
+#[test]
+fn partial_lines_eager() {
+    let mut writer = PromptWriter::new(LineBuffered::default()).eager_flush_on_partial(true);
+    write!(writer, "10%").unwrap();
+    assert_eq!(writer.get_ref().flushed, b"10%");
+    writer.write_all(b" 20%\nthird line\n").unwrap();
+    assert_eq!(writer.get_ref().flushed, b"10% 20%\nthird line\n");
+    writer.write_all(b"\r30%").unwrap();
+    assert_eq!(writer.get_ref().flushed, b"10% 20%\nthird line\n\r30%");
+}
+
+#[test]
+fn partial_lines_threshold() {
+    let mut writer = PromptWriter::new(LineBuffered::default()).flush_threshold(8);
+    writer.write_all(b"abc").unwrap();
+    writer.write_all(b"def\n").unwrap();
+    writer.write_all(b"ghij").unwrap();
+    assert_eq!(writer.get_ref().flushed, b"abcdef\n");
+    writer.write_all(b"klmn").unwrap();
+    assert_eq!(writer.get_ref().flushed, b"abcdef\nghijklmn");
+    writer.write_all(b"o").unwrap();
+    assert_eq!(writer.get_ref().flushed, b"abcdef\nghijklmn");
+}
+
+#[test]
+fn partial_lines_max_line_len() {
+    // The inner writer's own protection against long lines still applies,
+    // and the threshold counts from wherever the line started.
+    let inner = LineBuffered {
+        max_line_len: Some(4),
+        ..LineBuffered::default()
+    };
+    let mut writer = PromptWriter::new(inner).flush_threshold(16);
+    writer.write_all(b"abcdefg").unwrap();
+    assert_eq!(writer.get_ref().flushed, b"abcdefg");
+    writer.write_all(b"hij").unwrap();
+    assert_eq!(writer.get_ref().flushed, b"abcdefg");
+    writer.write_all(b"klmnopq\nrs").unwrap();
+    assert_eq!(writer.get_ref().flushed, b"abcdefghijklmnopq\n");
+}
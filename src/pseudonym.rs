@@ -1,12 +1,159 @@
+use crate::syntax::{classify, SyntaxKind};
+use crate::MediaType;
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use url::Url;
+
 /// This struct encapsulates the name of an entity whose name is being
 /// hidden in the `nameless` API. It can be written to an `OutputByteStream`
 /// but it's otherwise entirely opaque.
+///
+/// When a list of inputs names the same input more than once, the later
+/// occurrences are written with an occurrence count, such as
+/// `data.csv (2nd occurrence)`, so that they can be told apart.
 pub struct Pseudonym {
     pub(crate) name: String,
+    /// Which occurrence of `name` in a list of arguments this is, from 1.
+    pub(crate) occurrence: usize,
 }
 
 impl Pseudonym {
     pub(crate) fn new(name: String) -> Self {
-        Self { name }
+        Self::with_occurrence(name, 1)
+    }
+
+    pub(crate) fn with_occurrence(name: String, occurrence: usize) -> Self {
+        Self { name, occurrence }
     }
+
+    /// Return the name as it's written to a stream.
+    pub(crate) fn display_name(&self) -> Cow<'_, str> {
+        if self.occurrence <= 1 {
+            return Cow::Borrowed(&self.name);
+        }
+        let suffix = match (self.occurrence % 10, self.occurrence % 100) {
+            (_, 11..=13) => "th",
+            (1, _) => "st",
+            (2, _) => "nd",
+            (3, _) => "rd",
+            _ => "th",
+        };
+        Cow::Owned(format!(
+            "{} ({}{} occurrence)",
+            self.name, self.occurrence, suffix
+        ))
+    }
+
+    /// If this names a file in the local filesystem, return the name of a
+    /// sibling file with the extension replaced by the preferred extension
+    /// of `media_type`, suitable for passing to [`LazyOutput::new`]. For
+    /// example, `photo.png` with `image/webp` becomes `photo.webp`.
+    ///
+    /// Since `.gz` files are decompressed on input and compressed on
+    /// output, a `.gz` suffix is kept and only the extension inside it is
+    /// replaced, so `notes.txt.gz` becomes `notes.md.gz`. Names without an
+    /// extension have one appended.
+    ///
+    /// This returns `None` for URLs, standard input, and child processes,
+    /// and when `media_type` has no preferred extension.
+    ///
+    /// [`LazyOutput::new`]: crate::LazyOutput::new
+    pub fn with_extension(&self, media_type: &MediaType) -> Option<OsString> {
+        let path = match classify(self.name.as_ref()) {
+            SyntaxKind::Path => PathBuf::from(&self.name),
+            SyntaxKind::Url(scheme) if scheme == "file" => {
+                Url::parse(&self.name).ok()?.to_file_path().ok()?
+            }
+            _ => return None,
+        };
+        let extension = media_type.preferred_extension()?;
+
+        let file_name = Path::new(path.file_name()?);
+        let gz = file_name.extension() == Some(Path::new("gz").as_os_str());
+        let inner = if gz {
+            Path::new(file_name.file_stem()?)
+        } else {
+            file_name
+        };
+
+        let mut new_name = inner.file_stem()?.to_owned();
+        new_name.push(".");
+        new_name.push(extension);
+        if gz {
+            new_name.push(".gz");
+        }
+        let sibling = path.with_file_name(new_name);
+
+        // Make sure the new name is interpreted as a path when it's opened,
+        // and not as a URL, a command, or a pipeline.
+        if classify(sibling.as_os_str()) == SyntaxKind::Path {
+            return Some(sibling.into_os_string());
+        }
+        if sibling.is_relative() {
+            let dotted = Path::new(".").join(&sibling);
+            if classify(dotted.as_os_str()) == SyntaxKind::Path {
+                return Some(dotted.into_os_string());
+            }
+            return None;
+        }
+        Url::from_file_path(&sibling)
+            .ok()
+            .map(|url| url.as_str().into())
+    }
+}
+
+#[test]
+fn pseudonym_display_name() {
+    let display = |occurrence| {
+        Pseudonym::with_occurrence("data.csv".to_owned(), occurrence)
+            .display_name()
+            .into_owned()
+    };
+    assert_eq!(display(1), "data.csv");
+    assert_eq!(display(2), "data.csv (2nd occurrence)");
+    assert_eq!(display(3), "data.csv (3rd occurrence)");
+    assert_eq!(display(4), "data.csv (4th occurrence)");
+    assert_eq!(display(11), "data.csv (11th occurrence)");
+    assert_eq!(display(21), "data.csv (21st occurrence)");
+    assert_eq!(display(112), "data.csv (112th occurrence)");
+}
+
+#[test]
+fn pseudonym_with_extension() {
+    use mime::Mime;
+    use std::str::FromStr;
+
+    let webp = MediaType::from_mime(Mime::from_str("image/webp").unwrap());
+    let with_webp = |name: &str| Pseudonym::new(name.to_owned()).with_extension(&webp);
+
+    assert_eq!(with_webp("photo.png"), Some("photo.webp".into()));
+    assert_eq!(with_webp("dir/photo.png"), Some("dir/photo.webp".into()));
+    assert_eq!(with_webp("/tmp/photo.png"), Some("/tmp/photo.webp".into()));
+
+    // Only the extension inside a `.gz` suffix is replaced.
+    assert_eq!(with_webp("archive.tar.gz"), Some("archive.webp.gz".into()));
+    assert_eq!(with_webp("photo.gz"), Some("photo.webp.gz".into()));
+
+    // Extensionless names get an extension appended.
+    assert_eq!(with_webp("README"), Some("README.webp".into()));
+    assert_eq!(with_webp("dir/.hidden"), Some("dir/.hidden.webp".into()));
+
+    // Percent-encoded `file:` names are decoded into paths.
+    #[cfg(unix)]
+    assert_eq!(
+        with_webp("file:///tmp/a%20b%2Epng"),
+        Some("/tmp/a b.webp".into())
+    );
+
+    // URLs, stdio, and child processes don't name sibling files.
+    assert_eq!(with_webp("https://example.com/photo.png"), None);
+    assert_eq!(with_webp("-"), None);
+    assert_eq!(with_webp("$(cat photo.png)"), None);
+
+    // Types without a known extension have no derived name.
+    assert_eq!(
+        Pseudonym::new("photo.png".to_owned()).with_extension(&MediaType::unknown()),
+        None
+    );
 }
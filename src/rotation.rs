@@ -0,0 +1,614 @@
+//! Rotation of file outputs opened with a `rotate=` option, for long-running
+//! programs writing logs.
+//!
+//! Rotation is checked by the stream at the start of each write, so it only
+//! ever happens between writes, and there are no background threads. A file
+//! may grow past the size limit by up to one write.
+
+use crate::open_output::set_file_mode;
+use anyhow::anyhow;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The number of rotated files kept if `keep:` isn't given.
+const DEFAULT_KEEP: usize = 5;
+
+/// When to rotate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum RotateWhen {
+    /// Once the file holds at least this many bytes.
+    Size(u64),
+    /// Once the UTC date changes.
+    Daily,
+}
+
+/// Options for rotating a file output, from `rotate=`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct RotatePolicy {
+    pub(crate) when: RotateWhen,
+    /// The number of rotated files to keep, besides the current one.
+    pub(crate) keep: usize,
+}
+
+impl RotatePolicy {
+    /// Parse a `rotate=` value, such as `size:10MiB,keep:5` or `daily`.
+    pub(crate) fn parse(value: &str) -> anyhow::Result<Self> {
+        let usage = || {
+            anyhow!(
+                "rotate should be `size:<size>` or `daily`, optionally followed by \
+                 `,keep:<count>`, not \"{}\"",
+                value
+            )
+        };
+        let mut parts = value.split(',');
+        let when = match parts.next() {
+            Some("daily") => RotateWhen::Daily,
+            Some(part) => match part.strip_prefix("size:").and_then(parse_size) {
+                Some(size) if size > 0 => RotateWhen::Size(size),
+                _ => return Err(usage()),
+            },
+            None => return Err(usage()),
+        };
+        let mut keep = DEFAULT_KEEP;
+        for part in parts {
+            match part.strip_prefix("keep:").map(str::parse) {
+                Some(Ok(count)) if count > 0 => keep = count,
+                _ => return Err(usage()),
+            }
+        }
+        Ok(Self { when, keep })
+    }
+}
+
+/// Parse a size such as `4096`, `64KiB`, `10MiB`, or `1GiB`.
+fn parse_size(s: &str) -> Option<u64> {
+    let (digits, scale) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => (&s[..i], &s[i..]),
+        None => (s, ""),
+    };
+    let scale = match scale {
+        "" | "B" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        _ => return None,
+    };
+    digits.parse::<u64>().ok()?.checked_mul(scale)
+}
+
+/// The rotation state of an open file output.
+pub(crate) struct Rotation {
+    path: PathBuf,
+    policy: RotatePolicy,
+    mode: Option<u32>,
+    /// The number of bytes written to the current file.
+    written: u64,
+    /// The day the current file was opened, in days since the epoch.
+    day: u64,
+}
+
+impl Rotation {
+    /// Start tracking rotation for `path`, which was just created or
+    /// truncated with permissions `mode`.
+    pub(crate) fn new(path: &Path, policy: RotatePolicy, mode: Option<u32>) -> Self {
+        Self {
+            path: path.to_owned(),
+            policy,
+            mode,
+            written: 0,
+            day: today(),
+        }
+    }
+
+    /// Return whether the current file should be rotated before the next
+    /// write.
+    pub(crate) fn due(&self) -> bool {
+        match self.policy.when {
+            RotateWhen::Size(size) => self.written >= size,
+            RotateWhen::Daily => today() != self.day,
+        }
+    }
+
+    /// Record that `n` bytes were written to the current file.
+    pub(crate) fn wrote(&mut self, n: usize) {
+        self.written += n as u64;
+    }
+
+    /// Move the current file aside, prune old files, and return a fresh file
+    /// at the original path. The caller should flush its writes to the
+    /// current file first.
+    ///
+    /// If this fails, the caller should continue with the current file, and
+    /// pass the error to the [`on_drop_error`] callback as a warning.
+    /// Rotation is tried again at the next rotation point, rather than at
+    /// every write.
+    ///
+    /// [`on_drop_error`]: crate::on_drop_error
+    pub(crate) fn rotate(&mut self) -> io::Result<File> {
+        let result = match self.policy.when {
+            RotateWhen::Size(_) => self.rotate_numbered(),
+            RotateWhen::Daily => self.rotate_dated(),
+        };
+        self.written = 0;
+        self.day = today();
+        result.map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "failed to rotate {}, continuing with the current file: {}",
+                    self.path.display(),
+                    err
+                ),
+            )
+        })
+    }
+
+    /// Rotate to numbered files: `app.log.1` is the most recent, and
+    /// `app.log.<keep>` the oldest.
+    fn rotate_numbered(&self) -> io::Result<File> {
+        remove_if_exists(&self.sibling(self.policy.keep))?;
+        for index in (1..self.policy.keep).rev() {
+            rename_if_exists(&self.sibling(index), &self.sibling(index + 1))?;
+        }
+        fs::rename(&self.path, self.sibling(1))?;
+        self.create()
+    }
+
+    /// Rotate to files named with the date they were written, such as
+    /// `app.log.2021-06-30`, keeping the most recent `keep` of them.
+    fn rotate_dated(&self) -> io::Result<File> {
+        // Make room first, so that a failure leaves the current file in
+        // place.
+        let kept = self.prune_dated(self.policy.keep - 1)?;
+        let date = date(self.day);
+        // A program restarted on the same day may rotate more than once.
+        // Number each file after the newest of its day, rather than reusing
+        // an index freed by pruning, so that the files sort in the order
+        // they were rotated.
+        let newest = kept
+            .iter()
+            .filter(|(kept_date, _index)| *kept_date == date)
+            .map(|(_date, index)| *index)
+            .max();
+        let mut index = match newest {
+            None => 0,
+            Some(None) => 1,
+            Some(Some(index)) => index + 1,
+        };
+        let target = |index| match index {
+            0 => self.sibling(&date),
+            index => self.sibling(format!("{}.{}", date, index)),
+        };
+        while target(index).exists() {
+            index += 1;
+        }
+        fs::rename(&self.path, target(index))?;
+        self.create()
+    }
+
+    /// Remove dated files beyond the most recent `keep`, and return the
+    /// dates and indices of the ones kept, oldest first.
+    fn prune_dated(&self, keep: usize) -> io::Result<Vec<(String, Option<u32>)>> {
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let prefix = self.sibling("");
+        let prefix = prefix.file_name().unwrap().to_string_lossy();
+        let mut names = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            if let Some(suffix) = name.to_str().and_then(|name| name.strip_prefix(&*prefix)) {
+                if let Some((date, index)) = date_suffix(suffix) {
+                    names.push(((date.to_owned(), index), name));
+                }
+            }
+        }
+        // Dates sort chronologically, and same-day files after their day, in
+        // the numeric order of their indices, so that `.10` follows `.9`.
+        names.sort();
+        let excess = names.len().saturating_sub(keep);
+        for (_key, name) in &names[..excess] {
+            remove_if_exists(&dir.join(name))?;
+        }
+        Ok(names.drain(excess..).map(|(key, _name)| key).collect())
+    }
+
+    /// Return the path with `.<suffix>` appended.
+    fn sibling(&self, suffix: impl ToString) -> PathBuf {
+        let mut name = OsString::from(self.path.as_os_str());
+        name.push(".");
+        name.push(suffix.to_string());
+        PathBuf::from(name)
+    }
+
+    /// Create the fresh file, with the same permissions as the original.
+    fn create(&self) -> io::Result<File> {
+        let mut open_options = OpenOptions::new();
+        open_options.write(true).create(true).truncate(true);
+        set_file_mode(&mut open_options, self.mode);
+        open_options.open(&self.path)
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Return the current UTC day, in days since the epoch.
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / 86400)
+}
+
+/// Format `day`, in days since the epoch, as `YYYY-MM-DD`.
+fn date(day: u64) -> String {
+    let time = UNIX_EPOCH + Duration::from_secs(day * 86400);
+    humantime::format_rfc3339_seconds(time).to_string()[..10].to_owned()
+}
+
+/// If `suffix` is a date suffix added by `rotate_dated`, such as
+/// `2021-06-30` or `2021-06-30.1`, return its date and index, which sort in
+/// the order the files were rotated.
+fn date_suffix(suffix: &str) -> Option<(&str, Option<u32>)> {
+    let (date, index) = match suffix.split_once('.') {
+        Some((date, index)) => (date, Some(index.parse::<u32>().ok()?)),
+        None => (suffix, None),
+    };
+    let is_date = date.len() == 10
+        && date.bytes().enumerate().all(|(i, b)| match i {
+            4 | 7 => b == b'-',
+            _ => b.is_ascii_digit(),
+        });
+    is_date.then_some((date, index))
+}
+
+#[test]
+fn parse_policy() {
+    assert_eq!(
+        RotatePolicy::parse("size:10MiB,keep:3").unwrap(),
+        RotatePolicy {
+            when: RotateWhen::Size(10 << 20),
+            keep: 3
+        }
+    );
+    assert_eq!(
+        RotatePolicy::parse("size:4096").unwrap(),
+        RotatePolicy {
+            when: RotateWhen::Size(4096),
+            keep: DEFAULT_KEEP
+        }
+    );
+    assert_eq!(
+        RotatePolicy::parse("daily").unwrap().when,
+        RotateWhen::Daily
+    );
+    for bad in [
+        "",
+        "hourly",
+        "size:",
+        "size:0",
+        "size:10MB",
+        "size:1KiB,keep:0",
+        "daily,keep",
+    ] {
+        let err = RotatePolicy::parse(bad).unwrap_err();
+        assert!(err.to_string().starts_with("rotate should be"), "{}", err);
+    }
+}
+
+#[test]
+fn dates() {
+    assert_eq!(date(0), "1970-01-01");
+    assert_eq!(date(18808), "2021-06-30");
+    assert_eq!(date_suffix("2021-06-30"), Some(("2021-06-30", None)));
+    assert_eq!(date_suffix("2021-06-30.2"), Some(("2021-06-30", Some(2))));
+    assert_eq!(date_suffix("1"), None);
+    assert_eq!(date_suffix("2021-06-30.gz"), None);
+    assert_eq!(date_suffix("2021/06/30"), None);
+    assert!(date_suffix("2021-06-30.9") < date_suffix("2021-06-30.10"));
+    assert!(date_suffix("2021-06-30.10") < date_suffix("2021-07-01"));
+}
+
+#[test]
+fn rotate_daily() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let policy = RotatePolicy {
+        when: RotateWhen::Daily,
+        keep: 2,
+    };
+    fs::write(&path, "").unwrap();
+    let mut rotation = Rotation::new(&path, policy, None);
+    assert!(!rotation.due());
+
+    // Pretend each file was opened on a day in the past.
+    for (day, contents) in [(18806, "one\n"), (18807, "two\n"), (18808, "three\n")] {
+        fs::write(&path, contents).unwrap();
+        rotation.day = day;
+        assert!(rotation.due());
+        let mut file = rotation.rotate().unwrap();
+        assert!(!rotation.due());
+        file.write_all(b"current\n").unwrap();
+    }
+
+    let mut names = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+        names,
+        ["app.log", "app.log.2021-06-29", "app.log.2021-06-30"]
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("app.log.2021-06-30")).unwrap(),
+        "three\n"
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), "current\n");
+
+    // Rotating twice on the same day keeps both files.
+    rotation.day = 18808;
+    rotation.rotate().unwrap();
+    assert!(dir.path().join("app.log.2021-06-30.1").exists());
+    assert!(!dir.path().join("app.log.2021-06-29").exists());
+}
+
+#[test]
+fn rotate_daily_many() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let policy = RotatePolicy {
+        when: RotateWhen::Daily,
+        keep: 3,
+    };
+    let mut rotation = Rotation::new(&path, policy, None);
+
+    // Rotate a dozen times in one day, so that the indices reach `.11`.
+    for i in 0..12 {
+        fs::write(&path, format!("{}\n", i)).unwrap();
+        rotation.day = 18808;
+        rotation.rotate().unwrap();
+    }
+
+    assert_eq!(
+        file_names(dir.path()),
+        [
+            "app.log",
+            "app.log.2021-06-30.10",
+            "app.log.2021-06-30.11",
+            "app.log.2021-06-30.9",
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("app.log.2021-06-30.11")).unwrap(),
+        "11\n"
+    );
+}
+
+#[cfg(test)]
+fn file_names(dir: &Path) -> Vec<String> {
+    let mut names = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+#[test]
+fn rotate_text_by_size() {
+    use crate::OutputTextStream;
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use utf8_io::WriteStr;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let name = format!("{}?rotate=size:10B,keep:2", path.display());
+    let mut output =
+        OutputTextStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority()).unwrap();
+
+    // Each file gets the writes up to and including the one which reaches
+    // the limit.
+    for i in 0..7 {
+        output.write_str(&format!("line {}\n", i)).unwrap();
+    }
+    // A write larger than the limit isn't split.
+    let long = format!("{}\n", "x".repeat(29));
+    output.write_str(&long).unwrap();
+    output.write_str("end\n").unwrap();
+    output.close().unwrap();
+
+    assert_eq!(
+        file_names(dir.path()),
+        ["app.log", "app.log.1", "app.log.2"]
+    );
+    let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+    assert_eq!(read("app.log"), "end\n");
+    assert_eq!(read("app.log.1"), format!("line 6\n{}", long));
+    assert_eq!(read("app.log.2"), "line 4\nline 5\n");
+}
+
+#[test]
+fn rotate_text_at_line_ends() {
+    use crate::OutputTextStream;
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use std::io::Write;
+    use utf8_io::WriteStr;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let name = format!("{}?rotate=size:10B", path.display());
+    let mut output =
+        OutputTextStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority()).unwrap();
+
+    // Rotation is due after "abc", but waits for the line to end.
+    output.write_str("line 0\n").unwrap();
+    output.write_str("abc").unwrap();
+    output.write_all(b"def").unwrap();
+    writeln!(output, "ghi").unwrap();
+    output.write_str("next\n").unwrap();
+    output.close().unwrap();
+
+    assert_eq!(file_names(dir.path()), ["app.log", "app.log.1"]);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("app.log.1")).unwrap(),
+        "line 0\nabcdefghi\n"
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), "next\n");
+}
+
+#[test]
+fn rotate_bytes_by_size() {
+    use crate::OutputByteStream;
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.bin");
+    let name = format!("{}?rotate=size:4B,keep:1", path.display());
+    let mut output =
+        OutputByteStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority()).unwrap();
+    for chunk in [&b"ab"[..], b"cd", b"ef", b"gh", b"ij"] {
+        output.write_all(chunk).unwrap();
+    }
+    output.close().unwrap();
+
+    assert_eq!(file_names(dir.path()), ["data.bin", "data.bin.1"]);
+    assert_eq!(fs::read(dir.path().join("data.bin.1")).unwrap(), b"efgh");
+    assert_eq!(fs::read(&path).unwrap(), b"ij");
+}
+
+#[cfg(unix)]
+#[test]
+fn rotate_with_mode() {
+    use crate::OutputTextStream;
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use std::os::unix::fs::PermissionsExt;
+    use utf8_io::WriteStr;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let name = format!("{}?rotate=size:1B&mode=0600", path.display());
+    let mut output =
+        OutputTextStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority()).unwrap();
+    output.write_str("one\n").unwrap();
+    output.write_str("two\n").unwrap();
+    output.close().unwrap();
+
+    for name in ["app.log", "app.log.1"] {
+        let metadata = fs::metadata(dir.path().join(name)).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600, "{}", name);
+    }
+}
+
+#[test]
+fn rotate_rename_failure() {
+    use crate::drop_error::DropErrorRecorder;
+    use crate::OutputTextStream;
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use utf8_io::WriteStr;
+
+    let recorder = DropErrorRecorder::install();
+
+    // A non-empty directory where the rotated file would go can't be
+    // replaced.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    fs::create_dir_all(dir.path().join("app.log.1").join("blocker")).unwrap();
+    let name = format!("{}?rotate=size:1B,keep:1", path.display());
+    let mut output =
+        OutputTextStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority()).unwrap();
+    output.write_str("one\n").unwrap();
+
+    // The write which would have started the fresh file succeeds, writing
+    // to the current file, and the failure is reported as a warning.
+    output.write_str("two\n").unwrap();
+    assert_eq!(recorder.take().len(), 1);
+    output.write_str("three\n").unwrap();
+    output.close().unwrap();
+
+    // Each write was a rotation point, and each attempt failed.
+    assert_eq!(recorder.take().len(), 1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\nthree\n");
+}
+
+#[test]
+fn rotate_text_vectored() {
+    use crate::OutputTextStream;
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use std::io::{IoSlice, Write};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let name = format!("{}?rotate=size:8B", path.display());
+    let mut output =
+        OutputTextStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority()).unwrap();
+
+    // All the buffers are written, up to a rotation point.
+    let bufs = [
+        IoSlice::new(b"a"),
+        IoSlice::new(b""),
+        IoSlice::new(b"b\nc\n"),
+    ];
+    assert_eq!(output.write_vectored(&bufs).unwrap(), 5);
+    let bufs = [IoSlice::new(b"long line\n"), IoSlice::new(b"next\n")];
+    assert_eq!(output.write_vectored(&bufs).unwrap(), 10);
+    output.write_all(b"next\n").unwrap();
+    output.close().unwrap();
+
+    assert_eq!(file_names(dir.path()), ["app.log", "app.log.1"]);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("app.log.1")).unwrap(),
+        "ab\nc\nlong line\n"
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), "next\n");
+}
+
+#[test]
+fn rotate_option_errors() {
+    use crate::OutputTextStream;
+    use clap::TryFromOsArg;
+
+    let dir = tempfile::tempdir().unwrap();
+    let open = |name: String| {
+        OutputTextStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority())
+            .unwrap_err()
+            .to_string()
+    };
+
+    let gz = dir.path().join("app.log.gz");
+    assert_eq!(
+        open(format!("{}?rotate=daily", gz.display())),
+        "rotate isn't supported for gzip-compressed outputs"
+    );
+    let url = url::Url::from_file_path(dir.path().join("app.log")).unwrap();
+    assert_eq!(
+        open(format!("{}?rotate=daily&lock=exclusive", url)),
+        "rotate can't be combined with lock"
+    );
+    assert!(open(format!("{}?rotate=weekly", url)).starts_with("rotate should be"));
+    assert!(file_names(dir.path()).is_empty());
+}
@@ -0,0 +1,500 @@
+//! Reading passwords and other secrets from interactive streams.
+
+use crate::drop_error::report_drop_error;
+use crate::InteractiveTextStream;
+use io_streams::BufReaderLineWriter;
+use std::fmt::{self, Debug, Formatter};
+use std::hint::black_box;
+use std::io::{self, Read, Write};
+#[cfg(not(any(windows, target_os = "wasi")))]
+use std::os::fd::OwnedFd;
+use terminal_io::ReadTerminal;
+
+/// A string holding a secret, such as a password, which is overwritten with
+/// zeros when it's dropped.
+///
+/// Its `Debug` output doesn't include the secret, and it has no `Display`
+/// impl, so that it isn't printed by accident. Clearing is best-effort:
+/// copies held by the operating system or by lower layers of a stream
+/// aren't cleared.
+pub struct SecretString(String);
+
+impl SecretString {
+    /// Return the secret.
+    #[inline]
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        // `into_bytes` reuses the string's buffer, so this clears the
+        // secret in place.
+        zeroize(&mut std::mem::take(&mut self.0).into_bytes());
+    }
+}
+
+impl Debug for SecretString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString(..)")
+    }
+}
+
+/// Options for [`ReadSecret::read_secret_with`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct SecretOptions {
+    /// Fail if the input isn't a terminal, rather than reading the secret
+    /// without a prompt. This prevents secrets from being read from a pipe
+    /// or file, where they may also have been logged.
+    pub require_tty: bool,
+}
+
+/// Adds [`read_secret`] to interactive streams.
+///
+/// When the input is a terminal, the prompt is written and echo is turned
+/// off while the user types, and restored afterward, even if reading fails
+/// or panics. When it isn't a terminal, there's no one to show the prompt
+/// to, so it's skipped and a line is read as-is, or with the `require_tty`
+/// option, reading fails.
+///
+/// Either way, the trailing newline is removed. Only the secret's own line
+/// is read from the stream, so subsequent lines can still be read.
+///
+/// [`read_secret`]: ReadSecret::read_secret
+pub trait ReadSecret {
+    /// Read a line holding a secret, prompting for it with `prompt` on a
+    /// terminal.
+    #[inline]
+    fn read_secret(&mut self, prompt: &str) -> io::Result<SecretString> {
+        self.read_secret_with(prompt, &SecretOptions::default())
+    }
+
+    /// Like [`read_secret`], but with the given options.
+    ///
+    /// [`read_secret`]: ReadSecret::read_secret
+    fn read_secret_with(
+        &mut self,
+        prompt: &str,
+        options: &SecretOptions,
+    ) -> io::Result<SecretString>;
+}
+
+impl ReadSecret for InteractiveTextStream {
+    fn read_secret_with(
+        &mut self,
+        prompt: &str,
+        options: &SecretOptions,
+    ) -> io::Result<SecretString> {
+        let echo = StreamEcho::new(self)?;
+        read_secret(self, &echo, prompt, options)
+    }
+}
+
+impl ReadSecret for BufReaderLineWriter<InteractiveTextStream> {
+    fn read_secret_with(
+        &mut self,
+        prompt: &str,
+        options: &SecretOptions,
+    ) -> io::Result<SecretString> {
+        let echo = StreamEcho::new(self.get_ref())?;
+        read_secret(self, &echo, prompt, options)
+    }
+}
+
+/// Control over whether a terminal echoes its input, abstracted so that
+/// tests can use a mock terminal.
+trait Echo {
+    /// Test whether the input is a terminal at all.
+    fn is_terminal(&self) -> bool;
+
+    /// Test whether the terminal is echoing its input.
+    fn echo(&self) -> io::Result<bool>;
+
+    /// Turn echoing on or off.
+    fn set_echo(&self, echo: bool) -> io::Result<()>;
+}
+
+/// The echo setting of the terminal an `InteractiveTextStream` reads from,
+/// if it reads from one.
+struct StreamEcho {
+    /// A duplicate of the stream's file descriptor, so that echo can be
+    /// changed while the stream is being read. Echo is a setting of the
+    /// terminal itself, so it applies to both.
+    #[cfg(not(any(windows, target_os = "wasi")))]
+    terminal: Option<OwnedFd>,
+    #[cfg(any(windows, target_os = "wasi"))]
+    is_terminal: bool,
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+impl StreamEcho {
+    fn new(stream: &InteractiveTextStream) -> io::Result<Self> {
+        let terminal = match stream.input_terminal() {
+            Some(fd) if stream.is_input_terminal() => Some(rustix::io::fcntl_dupfd_cloexec(fd, 0)?),
+            _ => None,
+        };
+        Ok(Self { terminal })
+    }
+
+    fn terminal(&self) -> io::Result<&OwnedFd> {
+        self.terminal
+            .as_ref()
+            .ok_or_else(|| io::Error::other("input isn't a terminal"))
+    }
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+impl Echo for StreamEcho {
+    fn is_terminal(&self) -> bool {
+        self.terminal.is_some()
+    }
+
+    fn echo(&self) -> io::Result<bool> {
+        use rustix::termios::{tcgetattr, LocalModes};
+
+        let termios = tcgetattr(self.terminal()?)?;
+        Ok(termios.local_modes.contains(LocalModes::ECHO))
+    }
+
+    fn set_echo(&self, echo: bool) -> io::Result<()> {
+        use rustix::termios::{tcgetattr, tcsetattr, LocalModes, OptionalActions};
+
+        let terminal = self.terminal()?;
+        let mut termios = tcgetattr(terminal)?;
+        termios.local_modes.set(LocalModes::ECHO, echo);
+        Ok(tcsetattr(terminal, OptionalActions::Now, &termios)?)
+    }
+}
+
+#[cfg(any(windows, target_os = "wasi"))]
+impl StreamEcho {
+    fn new(stream: &InteractiveTextStream) -> io::Result<Self> {
+        Ok(Self {
+            is_terminal: stream.is_input_terminal(),
+        })
+    }
+}
+
+#[cfg(any(windows, target_os = "wasi"))]
+impl Echo for StreamEcho {
+    fn is_terminal(&self) -> bool {
+        self.is_terminal
+    }
+
+    fn echo(&self) -> io::Result<bool> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "turning off terminal echo isn't supported on this platform",
+        ))
+    }
+
+    fn set_echo(&self, _echo: bool) -> io::Result<()> {
+        self.echo().map(drop)
+    }
+}
+
+/// Turns echo off while it's alive, and restores it when it's dropped,
+/// including when unwinding from a panic.
+struct EchoGuard<'a, E: Echo> {
+    echo: &'a E,
+    restore: bool,
+}
+
+impl<'a, E: Echo> EchoGuard<'a, E> {
+    fn disable(echo: &'a E) -> io::Result<Self> {
+        // If echo is already off, leave it that way afterward.
+        let restore = echo.echo()?;
+        echo.set_echo(false)?;
+        Ok(Self { echo, restore })
+    }
+}
+
+impl<E: Echo> Drop for EchoGuard<'_, E> {
+    fn drop(&mut self) {
+        if self.restore {
+            if let Err(err) = self.echo.set_echo(true) {
+                report_drop_error(err);
+            }
+        }
+    }
+}
+
+/// The implementation of `read_secret_with`, for any stream and echo
+/// control.
+fn read_secret<S: Read + Write, E: Echo>(
+    stream: &mut S,
+    echo: &E,
+    prompt: &str,
+    options: &SecretOptions,
+) -> io::Result<SecretString> {
+    if !echo.is_terminal() {
+        if options.require_tty {
+            return Err(io::Error::other(
+                "refusing to read a secret from input which isn't a terminal",
+            ));
+        }
+        return read_secret_line(stream);
+    }
+
+    stream.write_all(prompt.as_bytes())?;
+    stream.flush()?;
+    let secret = {
+        let _guard = EchoGuard::disable(echo)?;
+        read_secret_line(stream)
+    };
+    // The user's newline wasn't echoed, so end the prompt's line.
+    stream.write_all(b"\n")?;
+    stream.flush()?;
+    secret
+}
+
+/// Read one line, without its newline, being careful not to leave copies
+/// of it in memory. It's read a byte at a time, so that nothing after the
+/// newline is consumed.
+fn read_secret_line(stream: &mut impl Read) -> io::Result<SecretString> {
+    let mut line = Vec::with_capacity(64);
+    let mut byte = [0_u8];
+    let result = loop {
+        match stream.read(&mut byte) {
+            Ok(0) if line.is_empty() => {
+                break Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "input ended before a secret was read",
+                ))
+            }
+            Ok(0) => break Ok(()),
+            Ok(_) if byte[0] == b'\n' => break Ok(()),
+            Ok(_) => push_byte(&mut line, byte[0]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => break Err(err),
+        }
+    };
+    zeroize(&mut byte);
+    if let Err(err) = result {
+        zeroize(&mut line);
+        return Err(err);
+    }
+
+    // Text streams translate CRLF, but other streams may not.
+    if let Some(last) = line.last_mut().filter(|last| **last == b'\r') {
+        *last = 0;
+        line.pop();
+    }
+    match String::from_utf8(line) {
+        Ok(secret) => Ok(SecretString(secret)),
+        Err(err) => {
+            zeroize(&mut err.into_bytes());
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "secret is not valid UTF-8",
+            ))
+        }
+    }
+}
+
+/// Append `byte` to `line`, clearing the old buffer when growing it rather
+/// than leaving it to the allocator.
+fn push_byte(line: &mut Vec<u8>, byte: u8) {
+    if line.len() == line.capacity() {
+        let mut bigger = Vec::with_capacity(line.capacity() * 2);
+        bigger.extend_from_slice(line);
+        zeroize(line);
+        *line = bigger;
+    }
+    line.push(byte);
+}
+
+/// Overwrite `bytes` with zeros, in a way the optimizer won't remove.
+fn zeroize(bytes: &mut [u8]) {
+    bytes.fill(0);
+    black_box(bytes);
+}
+
+/// A mock terminal which records changes to its echo setting.
+#[cfg(test)]
+struct MockTerminal {
+    is_terminal: bool,
+    echo: std::cell::Cell<bool>,
+    changes: std::cell::RefCell<Vec<bool>>,
+}
+
+#[cfg(test)]
+impl MockTerminal {
+    fn new(is_terminal: bool) -> Self {
+        Self {
+            is_terminal,
+            echo: std::cell::Cell::new(true),
+            changes: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Echo for MockTerminal {
+    fn is_terminal(&self) -> bool {
+        self.is_terminal
+    }
+
+    fn echo(&self) -> io::Result<bool> {
+        Ok(self.echo.get())
+    }
+
+    fn set_echo(&self, echo: bool) -> io::Result<()> {
+        self.echo.set(echo);
+        self.changes.borrow_mut().push(echo);
+        Ok(())
+    }
+}
+
+/// An in-memory stream pair, with input to read and a record of output.
+#[cfg(test)]
+struct Pair<R> {
+    input: R,
+    output: Vec<u8>,
+}
+
+#[cfg(test)]
+impl<R: Read> Read for Pair<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl<R> Write for Pair<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn secret_non_terminal() {
+    let mut pair = Pair {
+        input: &b"hunter2\r\nnext line\n"[..],
+        output: Vec::new(),
+    };
+    let terminal = MockTerminal::new(false);
+    let options = SecretOptions::default();
+    let secret = read_secret(&mut pair, &terminal, "Password: ", &options).unwrap();
+    assert_eq!(secret.expose_secret(), "hunter2");
+    assert_eq!(format!("{:?}", secret), "SecretString(..)");
+
+    // There's no prompt, echo is untouched, and the next line is unread.
+    assert!(pair.output.is_empty());
+    assert!(terminal.changes.borrow().is_empty());
+    let mut rest = String::new();
+    pair.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, "next line\n");
+
+    // The last line doesn't need a newline, but there must be a line.
+    let secret = read_secret(&mut pair, &terminal, "", &options);
+    assert_eq!(secret.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    let mut pair = Pair {
+        input: &b"last"[..],
+        output: Vec::new(),
+    };
+    let secret = read_secret(&mut pair, &terminal, "", &options).unwrap();
+    assert_eq!(secret.expose_secret(), "last");
+
+    // With `require_tty`, nothing is read.
+    let mut pair = Pair {
+        input: &b"hunter2\n"[..],
+        output: Vec::new(),
+    };
+    let options = SecretOptions { require_tty: true };
+    let err = read_secret(&mut pair, &terminal, "Password: ", &options).unwrap_err();
+    assert!(err.to_string().contains("isn't a terminal"));
+    assert_eq!(pair.input, b"hunter2\n");
+}
+
+#[test]
+fn secret_terminal() {
+    let mut pair = Pair {
+        input: &b"hunter2\nnext line\n"[..],
+        output: Vec::new(),
+    };
+    let terminal = MockTerminal::new(true);
+    let secret = read_secret(&mut pair, &terminal, "Password: ", &Default::default()).unwrap();
+    assert_eq!(secret.expose_secret(), "hunter2");
+    assert_eq!(pair.output, b"Password: \n");
+    assert_eq!(*terminal.changes.borrow(), [false, true]);
+    assert!(terminal.echo.get());
+
+    // If echo was already off, it's left off.
+    terminal.echo.set(false);
+    terminal.changes.borrow_mut().clear();
+    let secret = read_secret(&mut pair, &terminal, "", &Default::default()).unwrap();
+    assert_eq!(secret.expose_secret(), "next line");
+    assert_eq!(*terminal.changes.borrow(), [false]);
+}
+
+#[test]
+fn secret_echo_restored() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    struct Failing(bool);
+
+    impl Read for Failing {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            if self.0 {
+                panic!("read panicked");
+            }
+            Err(io::Error::other("read failed"))
+        }
+    }
+
+    let terminal = MockTerminal::new(true);
+    let mut pair = Pair {
+        input: Failing(false),
+        output: Vec::new(),
+    };
+    let err = read_secret(&mut pair, &terminal, "", &Default::default()).unwrap_err();
+    assert_eq!(err.to_string(), "read failed");
+    assert_eq!(*terminal.changes.borrow(), [false, true]);
+
+    terminal.changes.borrow_mut().clear();
+    let mut pair = Pair {
+        input: Failing(true),
+        output: Vec::new(),
+    };
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        read_secret(&mut pair, &terminal, "", &Default::default())
+    }));
+    assert!(result.is_err());
+    assert_eq!(*terminal.changes.borrow(), [false, true]);
+    assert!(terminal.echo.get());
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn secret_from_child() {
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use std::io::BufRead;
+
+    // A child's pipes aren't a terminal, so there's no prompt to echo back.
+    let io =
+        InteractiveTextStream::try_from_os_str_arg("$(cat)".as_ref(), clap::ambient_authority())
+            .unwrap();
+    let mut io = BufReaderLineWriter::new(io);
+    io.write_all(b"hunter2\nnext line\n").unwrap();
+    io.flush().unwrap();
+    let secret = io.read_secret("Password: ").unwrap();
+    assert_eq!(secret.expose_secret(), "hunter2");
+    let mut line = String::new();
+    io.read_line(&mut line).unwrap();
+    assert_eq!(line, "next line\n");
+
+    let options = SecretOptions {
+        require_tty: true,
+        ..SecretOptions::default()
+    };
+    assert!(io.read_secret_with("Password: ", &options).is_err());
+    io.get_mut().close().unwrap();
+}
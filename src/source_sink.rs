@@ -0,0 +1,385 @@
+//! Traits for writing functions which work with nameless streams and with
+//! other `Read` and `Write` types, and can use nameless' metadata when it's
+//! available.
+
+use crate::{MediaType, StreamKind};
+use std::fmt::{self, Arguments, Debug, Formatter};
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+
+/// A readable stream, with whatever metadata it has.
+///
+/// This is implemented by nameless' input and interactive streams. Other
+/// `Read` types can be wrapped in a [`PlainSource`], which has no metadata.
+///
+/// ```rust
+/// use nameless::{PlainSource, Source};
+/// use std::io::Read;
+///
+/// fn describe(mut input: impl Source) -> std::io::Result<String> {
+///     let mut s = String::new();
+///     input.read_to_string(&mut s)?;
+///     Ok(match input.media_type() {
+///         Some(media_type) => format!("{}: {}", media_type.mime(), s),
+///         None => s,
+///     })
+/// }
+///
+/// # fn main() -> std::io::Result<()> {
+/// assert_eq!(describe(PlainSource::new(&b"hello"[..]))?, "hello");
+/// # Ok(())
+/// # }
+/// ```
+pub trait Source: Read {
+    /// Return the media type of the stream, or `None` if the stream has no
+    /// metadata. Nameless streams return `Some`, though the media type may
+    /// itself be unknown.
+    fn media_type(&self) -> Option<&MediaType>;
+
+    /// Return the size of the stream, in bytes, if it's known before
+    /// reading. This is only a hint; the stream may end up shorter or longer.
+    fn size_hint(&self) -> Option<u64>;
+
+    /// Return the kind of resource the stream is connected to, or
+    /// [`StreamKind::Unknown`] if it isn't known.
+    fn kind(&self) -> StreamKind;
+}
+
+/// A writable stream, with whatever metadata it has.
+///
+/// This is implemented by nameless' output and interactive streams. Other
+/// `Write` types can be wrapped in a [`PlainSink`], which has no metadata.
+pub trait Sink: Write {
+    /// Return the media type of the stream, or `None` if the stream has no
+    /// metadata. Nameless streams return `Some`, though the media type may
+    /// itself be unknown.
+    fn media_type(&self) -> Option<&MediaType>;
+
+    /// Flush and close the stream, and report any errors, such as from a
+    /// child process exiting unsuccessfully. For nameless streams, this is
+    /// [`WriteLayered::close`]; for streams without a notion of closing, it
+    /// flushes. This can be called through a `Box<dyn Sink>`.
+    ///
+    /// [`WriteLayered::close`]: https://docs.rs/layered-io/latest/layered_io/trait.WriteLayered.html#tymethod.close
+    fn close_boxed(&mut self) -> io::Result<()>;
+}
+
+impl<S: Source + ?Sized> Source for &mut S {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        (**self).media_type()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<u64> {
+        (**self).size_hint()
+    }
+
+    #[inline]
+    fn kind(&self) -> StreamKind {
+        (**self).kind()
+    }
+}
+
+impl<S: Source + ?Sized> Source for Box<S> {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        (**self).media_type()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<u64> {
+        (**self).size_hint()
+    }
+
+    #[inline]
+    fn kind(&self) -> StreamKind {
+        (**self).kind()
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        (**self).media_type()
+    }
+
+    #[inline]
+    fn close_boxed(&mut self) -> io::Result<()> {
+        (**self).close_boxed()
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        (**self).media_type()
+    }
+
+    #[inline]
+    fn close_boxed(&mut self) -> io::Result<()> {
+        (**self).close_boxed()
+    }
+}
+
+/// A [`Source`] for a `Read` type which has no metadata, such as a `File`
+/// or a `Cursor`.
+pub struct PlainSource<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> PlainSource<R> {
+    /// Wrap `inner`.
+    #[inline]
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+
+    /// Return a reference to the wrapped reader.
+    #[inline]
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Return a mutable reference to the wrapped reader.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consume `self` and return the wrapped reader.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for PlainSource<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+
+    #[inline]
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        self.inner.read_vectored(bufs)
+    }
+
+    #[inline]
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.inner.read_to_end(buf)
+    }
+
+    #[inline]
+    fn read_to_string(&mut self, buf: &mut String) -> io::Result<usize> {
+        self.inner.read_to_string(buf)
+    }
+
+    #[inline]
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.read_exact(buf)
+    }
+}
+
+impl<R: Read> Source for PlainSource<R> {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        None
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<u64> {
+        None
+    }
+
+    #[inline]
+    fn kind(&self) -> StreamKind {
+        StreamKind::Unknown
+    }
+}
+
+impl<R: Read + Debug> Debug for PlainSource<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut b = f.debug_struct("PlainSource");
+        b.field("inner", &self.inner);
+        b.finish()
+    }
+}
+
+/// A [`Sink`] for a `Write` type which has no metadata, such as a `File` or
+/// a `Vec<u8>`.
+pub struct PlainSink<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> PlainSink<W> {
+    /// Wrap `inner`.
+    #[inline]
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Return a reference to the wrapped writer.
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Return a mutable reference to the wrapped writer.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Consume `self` and return the wrapped writer.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for PlainSink<W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    #[inline]
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner.write_all(buf)
+    }
+
+    #[inline]
+    fn write_fmt(&mut self, fmt: Arguments<'_>) -> io::Result<()> {
+        self.inner.write_fmt(fmt)
+    }
+}
+
+impl<W: Write> Sink for PlainSink<W> {
+    #[inline]
+    fn media_type(&self) -> Option<&MediaType> {
+        None
+    }
+
+    #[inline]
+    fn close_boxed(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write + Debug> Debug for PlainSink<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut b = f.debug_struct("PlainSink");
+        b.field("inner", &self.inner);
+        b.finish()
+    }
+}
+
+/// Metadata seen by a function which is generic over `Source`.
+#[cfg(test)]
+fn generic_metadata(
+    mut source: impl Source,
+) -> (Option<MediaType>, Option<u64>, StreamKind, String) {
+    let mut contents = String::new();
+    source.read_to_string(&mut contents).unwrap();
+    (
+        source.media_type().cloned(),
+        source.size_hint(),
+        source.kind(),
+        contents,
+    )
+}
+
+#[test]
+fn generic_sources() {
+    use crate::InputTextStream;
+    use clap::TryFromOsArg;
+    use std::fs::{self, File};
+    use std::io::Cursor;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hello.txt");
+    fs::write(&path, "hello\n").unwrap();
+
+    // A nameless stream has metadata.
+    let input =
+        InputTextStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    let (media_type, size_hint, kind, contents) = generic_metadata(input);
+    assert_eq!(media_type.unwrap().mime().essence_str(), "text/plain");
+    assert_eq!(size_hint, Some(6));
+    assert_eq!(kind, StreamKind::File);
+    assert_eq!(contents, "hello\n");
+
+    // Other readers don't.
+    let cursor = PlainSource::new(Cursor::new(b"hello\n".to_vec()));
+    assert_eq!(
+        generic_metadata(cursor),
+        (None, None, StreamKind::Unknown, "hello\n".to_owned())
+    );
+    let file = PlainSource::new(File::open(&path).unwrap());
+    assert_eq!(
+        generic_metadata(file),
+        (None, None, StreamKind::Unknown, "hello\n".to_owned())
+    );
+}
+
+#[test]
+fn generic_sinks() {
+    use crate::OutputTextStream;
+    use clap::TryFromOsArg;
+    use std::fs;
+
+    fn write_greeting(mut sink: impl Sink) -> io::Result<Option<String>> {
+        let media_type = sink.media_type().map(|m| m.mime().essence_str().to_owned());
+        sink.write_all(b"hello\n")?;
+        sink.close_boxed()?;
+        Ok(media_type)
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("greeting.txt");
+    let output =
+        OutputTextStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    assert_eq!(write_greeting(output).unwrap().unwrap(), "text/plain");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "hello\n");
+
+    let mut buf = PlainSink::new(Vec::new());
+    assert_eq!(write_greeting(&mut buf).unwrap(), None);
+    assert_eq!(buf.into_inner(), b"hello\n");
+
+    // Sinks can be used through `Box<dyn Sink>`.
+    let boxed: Box<dyn Sink> = Box::new(PlainSink::new(Vec::new()));
+    assert_eq!(write_greeting(boxed).unwrap(), None);
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[test]
+fn interactive_metadata() {
+    use crate::InteractiveByteStream;
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let name = format!("connect://{}", listener.local_addr().unwrap());
+    let mut stream =
+        InteractiveByteStream::try_from_os_str_arg(name.as_ref(), clap::ambient_authority())
+            .unwrap();
+
+    // Interactive streams are both sources and sinks, with a kind but no
+    // media type or size.
+    assert_eq!(Source::kind(&stream), StreamKind::Socket);
+    assert_eq!(Source::media_type(&stream), None);
+    assert_eq!(Source::size_hint(&stream), None);
+    assert_eq!(Sink::media_type(&stream), None);
+    stream.close().unwrap();
+}
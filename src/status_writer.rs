@@ -0,0 +1,136 @@
+use crate::memory_budget::BudgetTracker;
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use terminal_io::{Terminal, TerminalColorSupport, TerminalWriter, WriteTerminal};
+
+/// Status output shared between an `OutputTextStream` and its
+/// `StatusWriter`s.
+pub(crate) struct StatusState {
+    /// While a pager is running, status output is held here, to be written
+    /// once the pager exits.
+    deferred: Option<Vec<u8>>,
+    budget: BudgetTracker,
+    /// Where status output goes, which is stderr outside of tests.
+    sink: Box<dyn Write + Send>,
+}
+
+pub(crate) type SharedStatus = Arc<Mutex<StatusState>>;
+
+impl StatusState {
+    /// Return a new state writing to `sink`, and deferring output if
+    /// `paging` is true.
+    pub(crate) fn new(paging: bool, sink: Box<dyn Write + Send>) -> SharedStatus {
+        Arc::new(Mutex::new(Self {
+            deferred: if paging { Some(Vec::new()) } else { None },
+            budget: BudgetTracker::new("deferred status output"),
+            sink,
+        }))
+    }
+
+    /// Return a new state writing to stderr.
+    pub(crate) fn stderr(paging: bool) -> SharedStatus {
+        Self::new(paging, Box::new(io::stderr()))
+    }
+
+    /// Write out any deferred output, and stop deferring. This is called
+    /// once the pager has exited.
+    pub(crate) fn release(shared: &SharedStatus) -> io::Result<()> {
+        let mut state = lock(shared);
+        match state.deferred.take() {
+            Some(deferred) if !deferred.is_empty() => {
+                state.sink.write_all(&deferred)?;
+                state.sink.flush()
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn lock(shared: &SharedStatus) -> MutexGuard<'_, StatusState> {
+    shared.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A writer for short status and error lines which accompany an
+/// [`OutputTextStream`], returned by [`OutputTextStream::status_channel`].
+///
+/// Status output goes to stderr. When the stream's output is being shown
+/// in a pager, anything written to stderr would be hidden behind the
+/// pager's screen, or mixed into the middle of a page, so status output is
+/// held in memory until the pager exits, when the stream is closed or
+/// dropped, and then written to stderr in order. Otherwise, it's written
+/// immediately.
+///
+/// `StatusWriter` implements [`WriteTerminal`], describing stderr, so that
+/// status output can decide whether to use color the same way as the main
+/// stream does.
+///
+/// [`OutputTextStream`]: crate::OutputTextStream
+/// [`OutputTextStream::status_channel`]: crate::OutputTextStream::status_channel
+pub struct StatusWriter {
+    shared: SharedStatus,
+    terminal: TerminalWriter<io::Stderr>,
+}
+
+impl StatusWriter {
+    pub(crate) fn new(shared: SharedStatus) -> Self {
+        Self {
+            shared,
+            terminal: TerminalWriter::with_handle(io::stderr()),
+        }
+    }
+}
+
+impl Write for StatusWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let state = &mut *lock(&self.shared);
+        match &mut state.deferred {
+            Some(deferred) => {
+                state.budget.check_io(deferred.len() + buf.len())?;
+                deferred.extend_from_slice(buf);
+                Ok(())
+            }
+            None => state.sink.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let state = &mut *lock(&self.shared);
+        match state.deferred {
+            Some(_) => Ok(()),
+            None => state.sink.flush(),
+        }
+    }
+}
+
+impl Terminal for StatusWriter {}
+
+impl WriteTerminal for StatusWriter {
+    #[inline]
+    fn color_support(&self) -> TerminalColorSupport {
+        self.terminal.color_support()
+    }
+
+    #[inline]
+    fn color_preference(&self) -> bool {
+        self.terminal.color_preference()
+    }
+
+    #[inline]
+    fn is_output_terminal(&self) -> bool {
+        self.terminal.is_output_terminal()
+    }
+}
+
+impl Debug for StatusWriter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut b = f.debug_struct("StatusWriter");
+        b.field("deferring", &lock(&self.shared).deferred.is_some());
+        b.finish()
+    }
+}
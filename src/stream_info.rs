@@ -0,0 +1,71 @@
+use crate::{CacheStatus, Existence, MediaType, StreamKind, StreamOptions};
+
+/// A summary of a stream's metadata, without its name.
+///
+/// This is returned by the `info` functions on the stream types.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamInfo {
+    pub(crate) kind: StreamKind,
+    pub(crate) media_type: MediaType,
+    pub(crate) initial_size: Option<u64>,
+    pub(crate) compression_level: Option<u32>,
+    pub(crate) existence: Option<Existence>,
+    pub(crate) options: StreamOptions,
+    pub(crate) cache_status: Option<CacheStatus>,
+}
+
+impl StreamInfo {
+    /// Return the kind of resource the stream is connected to.
+    #[inline]
+    pub fn kind(&self) -> StreamKind {
+        self.kind
+    }
+
+    /// Return the stream's media type.
+    #[inline]
+    pub fn media_type(&self) -> &MediaType {
+        &self.media_type
+    }
+
+    /// Return the initial size of the stream in bytes, if known. This is
+    /// always `None` for output streams.
+    #[inline]
+    pub fn initial_size(&self) -> Option<u64> {
+        self.initial_size
+    }
+
+    /// Return the gzip compression level an output stream is compressing
+    /// with, if it's compressing. Outputs to `.gz` files of content which
+    /// is already compressed, such as PNG images, are stored with level 0
+    /// unless a level is requested with a `gzip_level=` option. This is
+    /// always `None` for input streams.
+    #[inline]
+    pub fn compression_level(&self) -> Option<u32> {
+        self.compression_level
+    }
+
+    /// Return whether an output file was newly created or already existed.
+    /// This is always `None` for input streams and for outputs which aren't
+    /// files.
+    #[inline]
+    pub fn existence(&self) -> Option<Existence> {
+        self.existence
+    }
+
+    /// Return the options given with the stream's name, such as
+    /// `lock=shared` or `mode=0600`.
+    #[inline]
+    pub fn options(&self) -> &StreamOptions {
+        &self.options
+    }
+
+    /// Return how an HTTP input was served from the cache set with
+    /// [`set_http_cache`]. This is `None` for other streams, and when no
+    /// cache is set.
+    ///
+    /// [`set_http_cache`]: crate::set_http_cache
+    #[inline]
+    pub fn cache_status(&self) -> Option<CacheStatus> {
+        self.cache_status
+    }
+}
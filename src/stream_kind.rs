@@ -0,0 +1,31 @@
+/// The kind of resource a stream is connected to.
+///
+/// This describes how a stream was opened, without revealing its name.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum StreamKind {
+    /// A file in the local filesystem.
+    File,
+    /// The process' standard input or output.
+    Stdio,
+    /// An `http:` or `https:` URL.
+    Http,
+    /// A `data:` URL.
+    Data,
+    /// A child process, spawned with the `$(...)` syntax.
+    Child,
+    /// A TCP or Unix-domain socket.
+    Socket,
+    /// A character device, such as a terminal.
+    CharDevice,
+    /// A file fetched with `scp:`.
+    Scp,
+    /// The system clipboard, with `clipboard:`.
+    Clipboard,
+    /// The kind isn't known, such as for a [`PlainSource`] or [`PlainSink`]
+    /// wrapping a stream nameless didn't open.
+    ///
+    /// [`PlainSource`]: crate::PlainSource
+    /// [`PlainSink`]: crate::PlainSink
+    Unknown,
+}
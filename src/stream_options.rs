@@ -0,0 +1,500 @@
+//! Parsing of the `?key=value&key2` options which may follow stream names.
+
+// Some kinds of streams aren't supported on Windows or WASI, so their
+// option specs are unused there.
+#![cfg_attr(any(windows, target_os = "wasi"), allow(dead_code))]
+
+use anyhow::anyhow;
+use std::ffi::OsStr;
+use std::str::FromStr;
+use std::time::Duration;
+use url::{form_urlencoded, Url};
+
+/// The prefix for nameless' own options on URLs whose queries belong to
+/// the server, such as `http:` URLs.
+pub(crate) const RESERVED_PREFIX: &str = "nameless.";
+
+/// The options given with a stream's name, such as the `lock=exclusive` in
+/// `file:///data.txt?lock=exclusive`, after they've been validated.
+///
+/// Options follow a `?` in `file:`, `accept:`, and `clipboard:` URLs, plain
+/// paths, and `$(...)` commands. A `?` in a plain path only begins options
+/// if the keys after it are options for files, and paths beginning with
+/// `./` never have options, so that they can name files containing `?`; in
+/// `file:` URLs, a literal `?` is written `%3F`. On `http:`, `https:`, and `connect:` URLs,
+/// whose queries mean something to the server, nameless' options have a
+/// `nameless.` prefix, and other query parameters are passed through.
+///
+/// Unknown options are errors, suggesting the closest known option, so
+/// that typos aren't silently ignored.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StreamOptions {
+    entries: Vec<(String, String)>,
+}
+
+impl StreamOptions {
+    /// Return the value of option `key`, without any `nameless.` prefix.
+    /// Options given without a value, such as `?restart`, have an empty
+    /// value. If an option is given more than once, the last one wins.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Iterate over the options, in the order they were given.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Test whether there are no options.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Test whether flag option `key` is present.
+    pub(crate) fn flag(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Return the value of option `key`, which was validated as a
+    /// [`Value::Duration`].
+    pub(crate) fn duration(&self, key: &str) -> Option<Duration> {
+        self.get(key)
+            .map(|value| humantime::parse_duration(value).unwrap())
+    }
+
+    /// Return the value of option `key`, which was validated as a
+    /// [`Value::Integer`], or `None` if it's absent or doesn't fit in a `T`.
+    pub(crate) fn integer<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key).and_then(|value| value.parse().ok())
+    }
+}
+
+/// The kinds of values options take, which are checked while parsing.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Value {
+    /// No value, as in `?restart`.
+    Flag,
+    /// A duration, such as `10s` or `1m 30s`.
+    Duration,
+    /// A non-negative integer.
+    Integer,
+    /// One of the given strings.
+    OneOf(&'static [&'static str]),
+    /// Any string, checked by the code which uses it.
+    Text,
+}
+
+/// The options a kind of stream accepts, and a description of the kind of
+/// stream for error messages.
+pub(crate) struct OptionSpec {
+    pub(crate) what: &'static str,
+    pub(crate) options: &'static [(&'static str, Value)],
+}
+
+pub(crate) const FILE_INPUT: OptionSpec = OptionSpec {
+    what: "file inputs",
+    options: &[
+        ("lock", Value::Text),
+        ("gzip", Value::OneOf(&["multi", "single"])),
+    ],
+};
+
+pub(crate) const FILE_OUTPUT: OptionSpec = OptionSpec {
+    what: "file outputs",
+    options: &[
+        ("lock", Value::Text),
+        ("gzip_level", Value::Integer),
+        ("mode", Value::Text),
+        ("dir_mode", Value::Text),
+        ("mkdir", Value::OneOf(&["parents"])),
+        ("rotate", Value::Text),
+    ],
+};
+
+pub(crate) const CHILD_OUTPUT: OptionSpec = OptionSpec {
+    what: "child process outputs",
+    options: &[("restart", Value::Flag)],
+};
+
+pub(crate) const CHILD_INTERACTIVE: OptionSpec = OptionSpec {
+    what: "interactive child processes",
+    options: &[("pty", Value::Flag)],
+};
+
+pub(crate) const ACCEPT: OptionSpec = OptionSpec {
+    what: "accept URLs",
+    options: &[
+        ("accept_timeout", Value::Duration),
+        ("fallback", Value::OneOf(&["stdio"])),
+    ],
+};
+
+pub(crate) const CONNECT: OptionSpec = OptionSpec {
+    what: "connect URLs",
+    options: &[],
+};
+
+pub(crate) const HTTP: OptionSpec = OptionSpec {
+    what: "HTTP URLs",
+    options: &[],
+};
+
+pub(crate) const HTTP_OUTPUT: OptionSpec = OptionSpec {
+    what: "HTTP outputs",
+    options: &[("multipart", Value::Text), ("filename", Value::Text)],
+};
+
+#[cfg(all(feature = "clipboard", not(target_os = "wasi")))]
+pub(crate) const CLIPBOARD: OptionSpec = OptionSpec {
+    what: "clipboard URLs",
+    options: &[
+        ("type", Value::OneOf(&["text/plain", "text/html"])),
+        ("limit", Value::Integer),
+    ],
+};
+
+/// Split a trailing `?key=value&key2` section off of a name which isn't a
+/// URL, such as a plain path or a `$(...)` command, returning the name and
+/// the options.
+///
+/// For `$(...)` commands, only a `?` after the closing `)` begins options.
+/// For paths, the text after the last `?` is only options if each of its
+/// keys is one of `spec`'s options, or close enough to one to be a typo,
+/// so that names such as `notes?v2` and `what?.txt` are returned whole.
+/// Names beginning with `./` are always returned whole.
+pub(crate) fn split_options<'a>(os: &'a OsStr, spec: &OptionSpec) -> (&'a OsStr, Option<&'a str>) {
+    match os.to_str() {
+        Some(s) if !s.starts_with("./") => match s.rsplit_once('?') {
+            Some((name, query))
+                if !name.is_empty()
+                    && looks_like_options(query)
+                    && if s.starts_with("$(") {
+                        name.ends_with(')')
+                    } else {
+                        names_options(query, spec)
+                    } =>
+            {
+                (OsStr::new(name), Some(query))
+            }
+            _ => (os, None),
+        },
+        _ => (os, None),
+    }
+}
+
+/// Test whether `query` is a sequence of `key` or `key=value` items
+/// separated by `&`, with keys made of lowercase letters, digits, `_`, and
+/// `.`, starting with a letter.
+fn looks_like_options(query: &str) -> bool {
+    query.split('&').all(|item| {
+        let key = item.split_once('=').map_or(item, |(key, _)| key);
+        key.starts_with(|c: char| c.is_ascii_lowercase())
+            && key
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.')
+    })
+}
+
+/// Test whether each key in `query`, which [`looks_like_options`], is one
+/// of `spec`'s options or a likely typo of one.
+fn names_options(query: &str, spec: &OptionSpec) -> bool {
+    query.split('&').all(|item| {
+        let key = item.split_once('=').map_or(item, |(key, _)| key);
+        spec.options.iter().any(|(k, _)| *k == key) || closest_option(key, spec).is_some()
+    })
+}
+
+/// Parse and validate the options in `query`, a `key=value&key2` string
+/// as it appears in a URL, against `spec`.
+pub(crate) fn parse_options(
+    query: Option<&str>,
+    spec: &OptionSpec,
+) -> anyhow::Result<StreamOptions> {
+    let mut options = StreamOptions::default();
+    for (key, value) in form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+        validate("", &key, &value, spec)?;
+        options.entries.push((key.into_owned(), value.into_owned()));
+    }
+    Ok(options)
+}
+
+/// Parse and validate the options in `url`'s query against `spec`.
+pub(crate) fn parse_url_options(url: &Url, spec: &OptionSpec) -> anyhow::Result<StreamOptions> {
+    parse_options(url.query(), spec)
+}
+
+/// For URLs whose query belongs to the server, remove the query parameters
+/// with the `nameless.` prefix from `url`, and parse and validate them
+/// against `spec`. Other parameters are left in place, byte for byte.
+pub(crate) fn take_prefixed_options(
+    url: &mut Url,
+    spec: &OptionSpec,
+) -> anyhow::Result<StreamOptions> {
+    let query = match url.query() {
+        Some(query) => query.to_owned(),
+        None => return Ok(StreamOptions::default()),
+    };
+    let (ours, theirs): (Vec<&str>, Vec<&str>) = query
+        .split('&')
+        .partition(|item| item.starts_with(RESERVED_PREFIX));
+    if ours.is_empty() {
+        return Ok(StreamOptions::default());
+    }
+
+    let mut options = StreamOptions::default();
+    for (key, value) in form_urlencoded::parse(ours.join("&").as_bytes()) {
+        let key = &key[RESERVED_PREFIX.len()..];
+        validate(RESERVED_PREFIX, key, &value, spec)?;
+        options.entries.push((key.to_owned(), value.into_owned()));
+    }
+    if theirs.is_empty() {
+        url.set_query(None);
+    } else {
+        url.set_query(Some(&theirs.join("&")));
+    }
+    Ok(options)
+}
+
+/// Check that `key` is an option in `spec`, and that `value` is valid for
+/// it. `prefix` is the prefix `key` was written with, for error messages.
+fn validate(prefix: &str, key: &str, value: &str, spec: &OptionSpec) -> anyhow::Result<()> {
+    let kind = match spec.options.iter().find(|(k, _)| *k == key) {
+        Some((_, kind)) => *kind,
+        None => return Err(unknown_option(prefix, key, spec)),
+    };
+    let key = format!("{}{}", prefix, key);
+    let valid = match kind {
+        Value::Flag => value.is_empty(),
+        Value::Duration => humantime::parse_duration(value).is_ok(),
+        Value::Integer => value.parse::<u64>().is_ok() && !value.starts_with('+'),
+        Value::OneOf(choices) => choices.contains(&value),
+        Value::Text => true,
+    };
+    if valid {
+        return Ok(());
+    }
+    Err(match kind {
+        Value::Flag => anyhow!("option `{}` doesn't take a value", key),
+        Value::Duration => anyhow!(
+            "option `{}` should be a duration such as \"10s\", not \"{}\"",
+            key,
+            value
+        ),
+        Value::Integer => anyhow!(
+            "option `{}` should be a non-negative integer, not \"{}\"",
+            key,
+            value
+        ),
+        Value::OneOf(choices) => anyhow!(
+            "unsupported {} option \"{}\"; expected {}",
+            key,
+            value,
+            choices
+                .iter()
+                .map(|choice| format!("\"{}\"", choice))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        ),
+        Value::Text => unreachable!(),
+    })
+}
+
+/// Return the option in `spec` which `key` is most likely a typo of, if any.
+fn closest_option(key: &str, spec: &OptionSpec) -> Option<&'static str> {
+    spec.options
+        .iter()
+        .map(|(k, _)| (edit_distance(key, k), *k))
+        .filter(|(distance, k)| *distance <= 2.max(k.len() / 3))
+        .min()
+        .map(|(_, k)| k)
+}
+
+fn unknown_option(prefix: &str, key: &str, spec: &OptionSpec) -> anyhow::Error {
+    match closest_option(key, spec) {
+        Some(k) => anyhow!(
+            "unknown option `{}{}` for {}; did you mean `{}{}`?",
+            prefix,
+            key,
+            spec.what,
+            prefix,
+            k
+        ),
+        None if spec.options.is_empty() => anyhow!(
+            "unknown option `{}{}`; {} have no options",
+            prefix,
+            key,
+            spec.what
+        ),
+        None => anyhow!(
+            "unknown option `{}{}` for {}; expected one of {}",
+            prefix,
+            key,
+            spec.what,
+            spec.options
+                .iter()
+                .map(|(k, _)| format!("`{}{}`", prefix, k))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[test]
+fn split_options_syntax() {
+    let split = |s: &'static str| {
+        let (name, query) = split_options(OsStr::new(s), &FILE_OUTPUT);
+        (name.to_str().unwrap(), query)
+    };
+    assert_eq!(split("out.txt?mode=0600"), ("out.txt", Some("mode=0600")));
+    assert_eq!(
+        split("out.txt?lock=exclusive&mkdir=parents"),
+        ("out.txt", Some("lock=exclusive&mkdir=parents"))
+    );
+    assert_eq!(split("$(sort)?restart"), ("$(sort)", Some("restart")));
+    assert_eq!(split("out.txt"), ("out.txt", None));
+
+    // `./` names files containing `?` literally.
+    assert_eq!(split("./out.txt?mode=0600"), ("./out.txt?mode=0600", None));
+
+    // Text which doesn't look like options is part of the name.
+    assert_eq!(split("what?.txt"), ("what?.txt", None));
+    assert_eq!(split("a?B=1"), ("a?B=1", None));
+    assert_eq!(split("$(echo a?b)"), ("$(echo a?b)", None));
+    assert_eq!(split("$(echo a?b=1)"), ("$(echo a?b=1)", None));
+    assert_eq!(split("?mode=0600"), ("?mode=0600", None));
+
+    // Unknown keys are part of a path's name, but typos of known ones
+    // split, to be reported as errors.
+    assert_eq!(split("notes?v2"), ("notes?v2", None));
+    assert_eq!(split("a?b=1"), ("a?b=1", None));
+    assert_eq!(
+        split("out.txt?mkdri=parents"),
+        ("out.txt", Some("mkdri=parents"))
+    );
+    assert_eq!(
+        split("$(echo a?b)?restart"),
+        ("$(echo a?b)", Some("restart"))
+    );
+}
+
+#[test]
+fn parse_options_values() {
+    let options = parse_options(Some("lock=exclusive,wait=1s&gzip_level=6"), &FILE_OUTPUT).unwrap();
+    assert_eq!(options.get("lock"), Some("exclusive,wait=1s"));
+    assert_eq!(options.integer::<u32>("gzip_level"), Some(6));
+    assert_eq!(options.get("mode"), None);
+    assert_eq!(
+        options.iter().collect::<Vec<_>>(),
+        [("lock", "exclusive,wait=1s"), ("gzip_level", "6")]
+    );
+
+    let options = parse_options(Some("accept_timeout=1m%2030s"), &ACCEPT).unwrap();
+    assert_eq!(
+        options.duration("accept_timeout"),
+        Some(Duration::from_secs(90))
+    );
+
+    let options = parse_options(Some("restart"), &CHILD_OUTPUT).unwrap();
+    assert!(options.flag("restart"));
+
+    // The last of repeated options wins.
+    let options = parse_options(Some("gzip=single&gzip=multi"), &FILE_INPUT).unwrap();
+    assert_eq!(options.get("gzip"), Some("multi"));
+
+    assert!(parse_options(None, &FILE_INPUT).unwrap().is_empty());
+}
+
+#[test]
+fn parse_options_errors() {
+    let err = |query, spec| parse_options(Some(query), spec).unwrap_err().to_string();
+
+    assert_eq!(
+        err("mkdri=parents", &FILE_OUTPUT),
+        "unknown option `mkdri` for file outputs; did you mean `mkdir`?"
+    );
+    assert_eq!(
+        err("acept_timeout=1s", &ACCEPT),
+        "unknown option `acept_timeout` for accept URLs; did you mean `accept_timeout`?"
+    );
+    assert_eq!(
+        err("append=1", &FILE_INPUT),
+        "unknown option `append` for file inputs; expected one of `lock`, `gzip`"
+    );
+    assert_eq!(
+        err("timeout=1s", &CONNECT),
+        "unknown option `timeout`; connect URLs have no options"
+    );
+    assert_eq!(
+        err("accept_timeout=soon", &ACCEPT),
+        "option `accept_timeout` should be a duration such as \"10s\", not \"soon\""
+    );
+    assert_eq!(
+        err("gzip_level=-1", &FILE_OUTPUT),
+        "option `gzip_level` should be a non-negative integer, not \"-1\""
+    );
+    assert_eq!(
+        err("gzip=double", &FILE_INPUT),
+        "unsupported gzip option \"double\"; expected \"multi\" or \"single\""
+    );
+    assert_eq!(
+        err("restart=yes", &CHILD_OUTPUT),
+        "option `restart` doesn't take a value"
+    );
+}
+
+#[test]
+fn prefixed_options() {
+    // Queries without the prefix are the server's, and pass through.
+    let mut url = Url::parse("https://example.com/search?q=a%20b&page=2").unwrap();
+    assert!(take_prefixed_options(&mut url, &HTTP).unwrap().is_empty());
+    assert_eq!(url.as_str(), "https://example.com/search?q=a%20b&page=2");
+
+    // Prefixed options are validated, and removed from the URL.
+    let mut url = Url::parse("https://example.com/?q=1&nameless.retries=3").unwrap();
+    let err = take_prefixed_options(&mut url, &HTTP).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "unknown option `nameless.retries`; HTTP URLs have no options"
+    );
+
+    let mut url = Url::parse("accept://127.0.0.1:0?nameless.accept_timeout=1s&x=1").unwrap();
+    let options = take_prefixed_options(&mut url, &ACCEPT).unwrap();
+    assert_eq!(
+        options.duration("accept_timeout"),
+        Some(Duration::from_secs(1))
+    );
+    assert_eq!(url.as_str(), "accept://127.0.0.1:0?x=1");
+
+    let mut url = Url::parse("accept://127.0.0.1:0?nameless.accept_timeout=1s").unwrap();
+    take_prefixed_options(&mut url, &ACCEPT).unwrap();
+    assert_eq!(url.as_str(), "accept://127.0.0.1:0");
+}
+
+#[test]
+fn edit_distances() {
+    assert_eq!(edit_distance("apend", "append"), 1);
+    assert_eq!(edit_distance("mkdri", "mkdir"), 2);
+    assert_eq!(edit_distance("", "lock"), 4);
+    assert_eq!(edit_distance("lock", "lock"), 0);
+}
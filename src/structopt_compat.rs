@@ -0,0 +1,68 @@
+//! `FromStr` impls for the stream types, for programs using parsers which
+//! call `FromStr` rather than `TryFromOsArg`, such as `structopt`.
+//!
+//! `kommand` and `clap_derive` remain the first-class way to use nameless.
+//! They pass arguments as `OsStr`s, so they support names which aren't
+//! valid UTF-8, and they pass an `AmbientAuthority` explicitly. The impls
+//! here are a migration aid: they can't see non-UTF-8 arguments, and they
+//! obtain their ambient authority implicitly.
+//!
+//! `structopt` parses each argument twice: once to validate it, discarding
+//! the result, and once to produce the value. An output or interactive
+//! stream discarded that way hasn't been closed, which panics, so
+//! `structopt` programs should take `LazyOutput` and `LazyInteractive`
+//! arguments for these, which open nothing until they're materialized.
+//! Inputs work directly, though they're opened twice.
+
+use crate::lazy_interactive::FromLazyInteractive;
+use crate::lazy_output::{FromLazyOutput, Never};
+use crate::{
+    InputByteStream, InputTextStream, InteractiveByteStream, InteractiveTextStream,
+    LazyInteractive, LazyOutput, OutputByteStream, OutputTextStream,
+};
+use clap::{ambient_authority, TryFromOsArg};
+use std::ffi::OsStr;
+use std::str::FromStr;
+
+/// Implement `FromStr` for stream types by delegating to `TryFromOsArg`.
+macro_rules! from_str_via_os_arg {
+    ($($ty:ty),*) => {
+        $(
+            impl FromStr for $ty {
+                type Err = anyhow::Error;
+
+                #[inline]
+                fn from_str(s: &str) -> anyhow::Result<Self> {
+                    Self::try_from_os_str_arg(OsStr::new(s), ambient_authority())
+                }
+            }
+        )*
+    };
+}
+
+from_str_via_os_arg!(
+    InputByteStream,
+    InputTextStream,
+    OutputByteStream,
+    OutputTextStream,
+    InteractiveByteStream,
+    InteractiveTextStream
+);
+
+impl<T: FromLazyOutput> FromStr for LazyOutput<T> {
+    type Err = Never;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Never> {
+        Self::try_from_os_str_arg(OsStr::new(s), ambient_authority())
+    }
+}
+
+impl<T: FromLazyInteractive> FromStr for LazyInteractive<T> {
+    type Err = Never;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Never> {
+        Self::try_from_os_str_arg(OsStr::new(s), ambient_authority())
+    }
+}
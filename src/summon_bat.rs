@@ -4,6 +4,8 @@
 
 use crate::MediaType;
 use io_extras::grip::AsRawGrip;
+use std::env;
+use std::ffi::OsStr;
 use std::process::{Child, Command, Stdio};
 
 /// Arrange for stdout to be connected to a pipe to a process which runs
@@ -11,13 +13,102 @@ use std::process::{Child, Command, Stdio};
 pub(crate) fn summon_bat(stdout: &impl AsRawGrip, media_type: &MediaType) -> Option<Child> {
     assert_eq!(stdout.as_raw_grip(), std::io::stdout().as_raw_grip());
 
+    let height = rustix::termios::tcgetwinsize(std::io::stdout())
+        .ok()
+        .map(|winsize| winsize.ws_row);
+    let paging = paging_usable(env::var_os("TERM").as_deref(), height);
+
     // If the "bat" command is available, use it.
-    Command::new("bat")
-        .arg("--file-name")
-        .arg(media_type.extension())
-        .arg("--style")
-        .arg("plain")
+    bat_command(media_type, paging)
         .stdin(Stdio::piped())
         .spawn()
         .ok()
 }
+
+/// Construct the command to run bat with, for content of type `media_type`.
+fn bat_command(media_type: &MediaType, paging: bool) -> Command {
+    let mut command = Command::new("bat");
+    match bat_language(media_type) {
+        Some(language) => command.arg("--language").arg(language),
+        None => command.arg("--file-name").arg(media_type.extension()),
+    };
+    command.arg("--style").arg("plain");
+    if !paging {
+        command.arg("--paging=never");
+    }
+    command
+}
+
+/// Return the bat language for common structured types, which bat can
+/// highlight even when the type has no single filename extension, such as
+/// `application/ld+json`.
+fn bat_language(media_type: &MediaType) -> Option<&'static str> {
+    let mime = media_type.mime();
+    let suffix = mime.suffix().map(|suffix| suffix.as_str());
+    match (mime.subtype().as_str(), suffix) {
+        ("json", _) | (_, Some("json")) => Some("json"),
+        ("yaml", _) | ("x-yaml", _) | (_, Some("yaml")) => Some("yaml"),
+        ("toml", _) => Some("toml"),
+        ("markdown", _) | ("x-markdown", _) => Some("markdown"),
+        ("xml", _) | (_, Some("xml")) => Some("xml"),
+        ("csv", _) => Some("csv"),
+        _ => None,
+    }
+}
+
+/// Test whether a pager would work, given the `TERM` environment variable
+/// and the terminal's height in rows, if known. Pagers don't work on dumb
+/// terminals, or when they can't tell how much fits on a screen.
+fn paging_usable(term: Option<&OsStr>, height: Option<u16>) -> bool {
+    term != Some(OsStr::new("dumb")) && matches!(height, Some(rows) if rows > 0)
+}
+
+#[test]
+fn bat_languages() {
+    use std::str::FromStr;
+
+    let mime = |s| MediaType::from_mime(mime::Mime::from_str(s).unwrap());
+    assert_eq!(bat_language(&mime("application/json")), Some("json"));
+    assert_eq!(bat_language(&mime("application/ld+json")), Some("json"));
+    assert_eq!(bat_language(&mime("application/x-yaml")), Some("yaml"));
+    assert_eq!(bat_language(&mime("application/toml")), Some("toml"));
+    assert_eq!(bat_language(&mime("text/markdown")), Some("markdown"));
+    assert_eq!(bat_language(&mime("image/svg+xml")), Some("xml"));
+    assert_eq!(bat_language(&mime("text/csv")), Some("csv"));
+    assert_eq!(bat_language(&mime("text/x-rust")), None);
+    assert_eq!(bat_language(&MediaType::unknown()), None);
+}
+
+#[test]
+fn bat_paging() {
+    let xterm = Some(OsStr::new("xterm-256color"));
+    assert!(paging_usable(xterm, Some(24)));
+    assert!(paging_usable(None, Some(24)));
+    assert!(!paging_usable(Some(OsStr::new("dumb")), Some(24)));
+    assert!(!paging_usable(xterm, None));
+    assert!(!paging_usable(xterm, Some(0)));
+}
+
+#[test]
+fn bat_argv() {
+    use std::str::FromStr;
+
+    let args = |media_type: &MediaType, paging| {
+        bat_command(media_type, paging)
+            .get_args()
+            .map(|arg| arg.to_str().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    let json = MediaType::from_mime(mime::Mime::from_str("application/json").unwrap());
+    assert_eq!(
+        args(&json, true),
+        ["--language", "json", "--style", "plain"]
+    );
+    let rust = MediaType::from_extension(Some(OsStr::new("rs")));
+    assert_eq!(
+        args(&rust, false),
+        ["--file-name", "rs", "--style", "plain", "--paging=never"]
+    );
+    assert_eq!(bat_command(&rust, true).get_program(), OsStr::new("bat"));
+}
@@ -0,0 +1,629 @@
+use crate::OpenPolicy;
+use anyhow::anyhow;
+use std::ffi::OsStr;
+use std::ops::BitOr;
+use url::Url;
+
+/// How a stream name is interpreted, as reported by [`classify`].
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum SyntaxKind {
+    /// A URL, with the given scheme, such as `"https"` or `"connect"`.
+    Url(String),
+    /// `-`, meaning standard input, standard output, or both.
+    Stdio,
+    /// `$(...)`, meaning a child process.
+    Command,
+    /// `source | cmd ...`, meaning an input piped through child processes.
+    Pipeline,
+    /// A path in the local filesystem.
+    Path,
+}
+
+/// Report how `os` would be interpreted as a stream name, without opening
+/// anything.
+///
+/// This is the same logic the stream types use to decide how to open a
+/// name. Not every kind is supported in every direction; for example,
+/// pipelines are only supported for input.
+///
+/// On Windows, names which parse as URLs with single-letter schemes, such
+/// as `C:\data`, are paths, as are names with other unknown schemes and no
+/// `//`, such as `notes:draft`. [`OpenPolicy::strict_urls`] makes opening
+/// the latter an error instead.
+pub fn classify(os: &OsStr) -> SyntaxKind {
+    if let Some(s) = os.to_str() {
+        // Strings containing " | " outside of quotes are pipelines.
+        if split_pipeline(s).is_some() {
+            return SyntaxKind::Pipeline;
+        }
+
+        // If we can parse it as a URL, treat it as such, unless it's more
+        // likely a path on this platform.
+        if let Ok(url) = Url::parse(s) {
+            if !is_path_like_url(s, url.scheme()) {
+                return SyntaxKind::Url(url.scheme().to_owned());
+            }
+        }
+
+        // Special-case "-" to mean stdin and/or stdout.
+        if s == "-" {
+            return SyntaxKind::Stdio;
+        }
+    }
+
+    // Strings beginning with "$(" are commands. The rest of the string
+    // needn't be UTF-8.
+    if starts_with_dollar_paren(os) {
+        return SyntaxKind::Command;
+    }
+
+    // Otherwise it's a path in the filesystem namespace.
+    SyntaxKind::Path
+}
+
+/// Like [`classify`], but when `policy` asks for strict URLs, fail on names
+/// which could be either a URL or a path, rather than guessing.
+pub(crate) fn classify_with_policy(os: &OsStr, policy: &OpenPolicy) -> anyhow::Result<SyntaxKind> {
+    if policy.strict_urls {
+        if let Some(scheme) = ambiguous_scheme(os) {
+            return Err(anyhow!(
+                "ambiguous stream name \"{}\": \"{}\" isn't a known URL scheme; \
+                 begin paths with \"./\" or URLs with \"{}://\"",
+                os.to_string_lossy(),
+                scheme,
+                scheme
+            ));
+        }
+    }
+    Ok(classify(os))
+}
+
+/// URL schemes nameless supports in some direction, on some platform.
+const KNOWN_SCHEMES: &[&str] = &[
+    "http",
+    "https",
+    "data",
+    "file",
+    "scp",
+    "connect",
+    "accept",
+    "clipboard",
+];
+
+/// If `os` parses as a URL with an unknown scheme and no `//` authority,
+/// such as `foo:bar`, which could as well be a path, return the scheme.
+/// Windows drive letters aren't ambiguous; they're always paths there.
+fn ambiguous_scheme(os: &OsStr) -> Option<String> {
+    let s = os.to_str()?;
+    let url = Url::parse(s).ok()?;
+    let scheme = url.scheme();
+    if KNOWN_SCHEMES.contains(&scheme)
+        || is_drive_letter(scheme)
+        || s.split_once(':')
+            .is_some_and(|(_, rest)| rest.starts_with("//"))
+    {
+        return None;
+    }
+    Some(scheme.to_owned())
+}
+
+/// Test whether `s`, which parses as a URL with scheme `scheme`, should be
+/// treated as a path instead. On Windows, `C:\data` and `C:/data` parse as
+/// URLs with scheme `c`, and names like `notes:stream` name alternate data
+/// streams, so only known schemes and names with a `//` authority are URLs.
+/// Elsewhere, every URL is a URL, so that unknown schemes are reported as
+/// such.
+#[cfg(windows)]
+fn is_path_like_url(s: &str, scheme: &str) -> bool {
+    is_drive_letter(scheme) || ambiguous_scheme(OsStr::new(s)).is_some()
+}
+
+#[cfg(not(windows))]
+fn is_path_like_url(_s: &str, _scheme: &str) -> bool {
+    false
+}
+
+#[cfg(windows)]
+fn is_drive_letter(scheme: &str) -> bool {
+    scheme.len() == 1
+}
+
+#[cfg(not(windows))]
+fn is_drive_letter(_scheme: &str) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn starts_with_dollar_paren(os: &OsStr) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    os.as_bytes().starts_with(b"$(")
+}
+
+#[cfg(target_os = "wasi")]
+fn starts_with_dollar_paren(os: &OsStr) -> bool {
+    use std::os::wasi::ffi::OsStrExt;
+    os.as_bytes().starts_with(b"$(")
+}
+
+#[cfg(windows)]
+fn starts_with_dollar_paren(os: &OsStr) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+    let mut wide = os.encode_wide();
+    wide.next() == Some(u16::from(b'$')) && wide.next() == Some(u16::from(b'('))
+}
+
+/// Split a `#fragment` off of a plain path naming an input, at the first
+/// `#`. Paths beginning with `./` are never split, so that paths containing
+/// `#` can be named literally.
+pub(crate) fn split_path_fragment(os: &OsStr) -> (&OsStr, Option<&str>) {
+    match os.to_str() {
+        Some(s) if !s.starts_with("./") => match s.split_once('#') {
+            Some((path, fragment)) if !path.is_empty() => (
+                OsStr::new(path),
+                Some(fragment).filter(|fragment| !fragment.is_empty()),
+            ),
+            _ => (os, None),
+        },
+        _ => (os, None),
+    }
+}
+
+/// Split a string of the form `SOURCE | cmd args | cmd2 args` into its
+/// stages, or return `None` if it isn't a pipeline. Separators are `|`
+/// characters surrounded by whitespace, outside of quotes and outside of
+/// `$(...)`. Commands may also be written as `$(cmd args)`.
+///
+/// A path which contains ` | ` can be named with a `file:` URL, which
+/// encodes the spaces.
+pub(crate) fn split_pipeline(s: &str) -> Option<Vec<&str>> {
+    let bytes = s.as_bytes();
+    let mut stages = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut depth = 0_usize;
+
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, '(') if i > 0 && bytes[i - 1] == b'$' => depth += 1,
+            (None, ')') if depth > 0 => depth -= 1,
+            (None, '|')
+                if depth == 0
+                    && i > 0
+                    && bytes[i - 1].is_ascii_whitespace()
+                    && bytes.get(i + 1).is_some_and(u8::is_ascii_whitespace) =>
+            {
+                stages.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    if stages.is_empty() {
+        return None;
+    }
+    stages.push(s[start..].trim());
+    if stages.iter().any(|stage| stage.is_empty()) {
+        return None;
+    }
+    Some(stages)
+}
+
+/// A set of stream directions in which a syntax is supported.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Directions(u8);
+
+impl Directions {
+    /// Supported for input streams.
+    pub const INPUT: Self = Self(1);
+    /// Supported for output streams.
+    pub const OUTPUT: Self = Self(2);
+    /// Supported for interactive streams.
+    pub const INTERACTIVE: Self = Self(4);
+
+    /// Test whether all the directions in `other` are in `self`.
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Directions {
+    type Output = Self;
+
+    #[inline]
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// A description of a stream name syntax, as returned by
+/// [`supported_syntaxes`].
+#[derive(Clone, Debug)]
+pub struct SyntaxDescriptor {
+    name: &'static str,
+    label: &'static str,
+    example: &'static str,
+    directions: Directions,
+    enabled: bool,
+}
+
+impl SyntaxDescriptor {
+    /// Return a short human-readable name for the syntax, such as
+    /// "http URL".
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Return a terse label for the syntax, such as "https:" or "$(exec)",
+    /// as listed in `--version` output.
+    #[inline]
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+
+    /// Return an example of a name using this syntax.
+    #[inline]
+    pub fn example(&self) -> &'static str {
+        self.example
+    }
+
+    /// Return the directions in which this syntax is supported.
+    #[inline]
+    pub fn directions(&self) -> Directions {
+        self.directions
+    }
+
+    /// Test whether this syntax is enabled in the current build and policy.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// Describe all the stream name syntaxes nameless knows about, and whether
+/// each is enabled in the current build under `policy`.
+pub fn supported_syntaxes(policy: &OpenPolicy) -> Vec<SyntaxDescriptor> {
+    use Directions as D;
+
+    // WASI has no child processes, sockets, or HTTP client.
+    let net = cfg!(not(target_os = "wasi"));
+    let exec = cfg!(not(any(windows, target_os = "wasi"))) && policy.allow_exec;
+    let descriptor = |name, label, example, directions, enabled| SyntaxDescriptor {
+        name,
+        label,
+        example,
+        directions,
+        enabled,
+    };
+
+    vec![
+        descriptor(
+            "http URL",
+            "https:",
+            "https://example.com/data.txt",
+            D::INPUT,
+            net,
+        ),
+        descriptor(
+            "data URL",
+            "data:",
+            "data:,Hello%2C%20World!",
+            D::INPUT,
+            true,
+        ),
+        descriptor(
+            "file URL",
+            "file:",
+            "file:///tmp/data.txt",
+            D::INPUT | D::OUTPUT,
+            true,
+        ),
+        descriptor(
+            "scp URL",
+            "scp:",
+            "scp://user@example.com/data.txt",
+            D::INPUT,
+            net && cfg!(feature = "ssh2"),
+        ),
+        descriptor(
+            "connect URL",
+            "connect:",
+            "connect://127.0.0.1:9999",
+            D::INTERACTIVE,
+            net,
+        ),
+        descriptor(
+            "accept URL",
+            "accept:",
+            "accept://127.0.0.1:9999",
+            D::INTERACTIVE,
+            net,
+        ),
+        descriptor(
+            "stdio",
+            "stdio",
+            "-",
+            D::INPUT | D::OUTPUT | D::INTERACTIVE,
+            true,
+        ),
+        descriptor(
+            "child command",
+            "$(exec)",
+            "$(echo hello)",
+            D::INPUT | D::OUTPUT | D::INTERACTIVE,
+            exec,
+        ),
+        descriptor(
+            "pipeline",
+            "pipeline",
+            "data:,hello | tr a-z A-Z",
+            D::INPUT,
+            exec,
+        ),
+        descriptor(
+            "path",
+            "path",
+            "data.txt",
+            D::INPUT | D::OUTPUT | D::INTERACTIVE,
+            true,
+        ),
+        descriptor(
+            "gzip path",
+            "*.gz",
+            "data.txt.gz",
+            D::INPUT | D::OUTPUT,
+            true,
+        ),
+        descriptor(
+            "clipboard URL",
+            "clipboard:",
+            "clipboard:",
+            D::INPUT | D::OUTPUT,
+            net && cfg!(feature = "clipboard"),
+        ),
+    ]
+}
+
+/// Build the `--version` text for a program using nameless, listing the
+/// stream syntaxes enabled in this build. This is used by `kommand`.
+#[doc(hidden)]
+pub fn long_version(version: &str) -> String {
+    let labels = supported_syntaxes(&OpenPolicy::default())
+        .into_iter()
+        .filter(SyntaxDescriptor::enabled)
+        .map(|syntax| syntax.label())
+        .collect::<Vec<_>>();
+    format!(
+        "{}\nstreams: {}\nnameless {}",
+        version,
+        labels.join(", "),
+        crate::CRATE_VERSION
+    )
+}
+
+#[test]
+fn classify_names() {
+    let url = |scheme: &str| SyntaxKind::Url(scheme.to_owned());
+    assert_eq!(classify("https://example.com/".as_ref()), url("https"));
+    assert_eq!(classify("data:,hello".as_ref()), url("data"));
+    assert_eq!(classify("file:///tmp/a.txt".as_ref()), url("file"));
+    assert_eq!(classify("accept://127.0.0.1:0".as_ref()), url("accept"));
+    assert_eq!(classify("-".as_ref()), SyntaxKind::Stdio);
+    assert_eq!(classify("foo.txt".as_ref()), SyntaxKind::Path);
+    assert_eq!(classify("./-".as_ref()), SyntaxKind::Path);
+    assert_eq!(
+        classify("data:,hello | $(tr a-z A-Z)".as_ref()),
+        SyntaxKind::Pipeline
+    );
+    assert_eq!(
+        classify("data:,hello | tr a-z A-Z".as_ref()),
+        SyntaxKind::Pipeline
+    );
+    assert_eq!(
+        classify("file:///tmp/notes%20|%20drafts.txt".as_ref()),
+        url("file")
+    );
+    assert_eq!(classify("$(echo hello)".as_ref()), SyntaxKind::Command);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn classify_scheme_like_names() {
+    use crate::InputByteStream;
+    use clap::TryFromOsArg;
+
+    // Names like `c:relative` are URLs with unsupported schemes, as always.
+    let url = |scheme: &str| SyntaxKind::Url(scheme.to_owned());
+    assert_eq!(classify("c:relative".as_ref()), url("c"));
+    assert_eq!(classify("notes:draft".as_ref()), url("notes"));
+    let err =
+        InputByteStream::try_from_os_str_arg("c:relative".as_ref(), clap::ambient_authority())
+            .unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("unsupported URL scheme \"c\""),
+        "{:#}",
+        err
+    );
+}
+
+#[cfg(windows)]
+#[test]
+fn classify_windows_paths() {
+    use crate::InputByteStream;
+    use clap::TryFromOsArg;
+
+    assert_eq!(classify(r"C:\x".as_ref()), SyntaxKind::Path);
+    assert_eq!(classify("C:/x".as_ref()), SyntaxKind::Path);
+    assert_eq!(classify("c:relative".as_ref()), SyntaxKind::Path);
+    assert_eq!(classify(r"\\server\share".as_ref()), SyntaxKind::Path);
+    assert_eq!(classify("notes:draft".as_ref()), SyntaxKind::Path);
+    assert_eq!(
+        classify("file:///C:/x".as_ref()),
+        SyntaxKind::Url("file".to_owned())
+    );
+
+    // Unknown schemes with a `//` authority are still URLs.
+    assert_eq!(
+        classify("foo://bar".as_ref()),
+        SyntaxKind::Url("foo".to_owned())
+    );
+    let err = InputByteStream::try_from_os_str_arg("foo://bar".as_ref(), clap::ambient_authority())
+        .unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("unsupported URL scheme \"foo\""),
+        "{:#}",
+        err
+    );
+
+    // Drive letters aren't ambiguous, even in strict mode.
+    let policy = OpenPolicy {
+        strict_urls: true,
+        ..OpenPolicy::default()
+    };
+    assert_eq!(
+        classify_with_policy(r"C:\x".as_ref(), &policy).unwrap(),
+        SyntaxKind::Path
+    );
+}
+
+#[test]
+fn strict_urls() {
+    let policy = OpenPolicy {
+        strict_urls: true,
+        ..OpenPolicy::default()
+    };
+    let classify = |s: &str| classify_with_policy(s.as_ref(), &policy);
+
+    let err = classify("notes:draft").unwrap_err();
+    assert!(err.to_string().contains("ambiguous"), "{}", err);
+    assert_eq!(classify("./notes:draft").unwrap(), SyntaxKind::Path);
+    assert_eq!(
+        classify("foo://bar").unwrap(),
+        SyntaxKind::Url("foo".to_owned())
+    );
+    assert_eq!(
+        classify("data:,notes").unwrap(),
+        SyntaxKind::Url("data".to_owned())
+    );
+    assert!(classify_with_policy("notes:draft".as_ref(), &OpenPolicy::default()).is_ok());
+}
+
+#[test]
+fn split_fragments() {
+    let split = |s: &'static str| {
+        let (path, fragment) = split_path_fragment(s.as_ref());
+        (path.to_str().unwrap(), fragment)
+    };
+    assert_eq!(split("notes.md"), ("notes.md", None));
+    assert_eq!(split("notes.md#intro"), ("notes.md", Some("intro")));
+    assert_eq!(split("/tmp/a.yaml#2"), ("/tmp/a.yaml", Some("2")));
+    assert_eq!(split("a.txt#L1#L2"), ("a.txt", Some("L1#L2")));
+    assert_eq!(split("a.txt#"), ("a.txt", None));
+    assert_eq!(split("#a.txt"), ("#a.txt", None));
+    assert_eq!(split("./a#b.txt"), ("./a#b.txt", None));
+    assert_eq!(split("./dir/a.txt#L3"), ("./dir/a.txt#L3", None));
+}
+
+#[cfg(unix)]
+#[test]
+fn classify_non_utf8() {
+    use std::os::unix::ffi::OsStrExt;
+    let os = |bytes| OsStr::from_bytes(bytes);
+    assert_eq!(classify(os(b"$(cat f\xff)")), SyntaxKind::Command);
+    assert_eq!(classify(os(b"http\xff://example.com/")), SyntaxKind::Path);
+    assert_eq!(classify(os(b"\xff$(cat)")), SyntaxKind::Path);
+}
+
+#[test]
+fn syntaxes_follow_policy() {
+    let find = |syntaxes: &[SyntaxDescriptor], name| {
+        syntaxes
+            .iter()
+            .find(|syntax| syntax.name() == name)
+            .unwrap()
+            .enabled()
+    };
+
+    let syntaxes = supported_syntaxes(&OpenPolicy::default());
+    assert!(find(&syntaxes, "http URL"));
+    assert_eq!(
+        find(&syntaxes, "child command"),
+        cfg!(not(any(windows, target_os = "wasi")))
+    );
+
+    let policy = OpenPolicy {
+        allow_exec: false,
+        ..OpenPolicy::default()
+    };
+    let syntaxes = supported_syntaxes(&policy);
+    assert!(find(&syntaxes, "http URL"));
+    assert!(!find(&syntaxes, "child command"));
+    assert!(!find(&syntaxes, "pipeline"));
+
+    // Examples are classified as the syntax they describe.
+    for (name, kind) in [
+        ("http URL", SyntaxKind::Url("https".to_owned())),
+        ("stdio", SyntaxKind::Stdio),
+        ("pipeline", SyntaxKind::Pipeline),
+        ("path", SyntaxKind::Path),
+    ] {
+        let syntax = syntaxes
+            .iter()
+            .find(|syntax| syntax.name() == name)
+            .unwrap();
+        assert_eq!(classify(syntax.example().as_ref()), kind, "{}", name);
+    }
+}
+
+#[test]
+fn split_pipelines() {
+    assert_eq!(split_pipeline("foo.txt"), None);
+    assert_eq!(split_pipeline("data:,a|b"), None);
+    assert_eq!(split_pipeline("$(sort | uniq)"), None);
+    assert_eq!(split_pipeline("a 'b | c'"), None);
+    assert_eq!(
+        split_pipeline("a | $(b c) | $(d)"),
+        Some(vec!["a", "$(b c)", "$(d)"])
+    );
+    assert_eq!(
+        split_pipeline("$(seq 10) | $(grep \"1 | 2\")"),
+        Some(vec!["$(seq 10)", "$(grep \"1 | 2\")"])
+    );
+
+    // Commands needn't be written as `$(...)`.
+    assert_eq!(
+        split_pipeline("a | b 'c | d' | $(e)"),
+        Some(vec!["a", "b 'c | d'", "$(e)"])
+    );
+    assert_eq!(split_pipeline("a |  | b"), None);
+    assert_eq!(split_pipeline("a | "), None);
+}
+
+#[test]
+fn long_version_lines() {
+    let long = long_version("1.2.3");
+    let lines = long.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "1.2.3");
+    assert!(lines[1].starts_with("streams: https:, data:, file:, "));
+    assert!(lines[1].contains(", stdio, "));
+    assert_eq!(
+        lines[1].contains("$(exec)"),
+        cfg!(not(any(windows, target_os = "wasi")))
+    );
+    assert_eq!(lines[2], format!("nameless {}", env!("CARGO_PKG_VERSION")));
+}
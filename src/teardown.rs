@@ -0,0 +1,336 @@
+// Child processes aren't spawned on Windows or WASI yet, but `ChildExit` is
+// still part of the output stream types there.
+#![cfg_attr(any(windows, target_os = "wasi"), allow(dead_code))]
+
+use crate::drop_error::report_drop_error;
+use std::io::{self, Write};
+use std::process::{Child, ChildStdin, ExitStatus};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a stream which is being dropped waits for a child process to
+/// exit on its own, after closing its end of the child's pipes, before
+/// killing it.
+pub(crate) const CHILD_EXIT_GRACE: Duration = Duration::from_secs(2);
+
+/// How often to check whether a child has exited.
+const REAP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Wait for `child` to exit, killing it if it's still running after
+/// `grace`. This never waits indefinitely, unlike `Child::wait`, so it's
+/// suitable for `Drop` implementations.
+pub(crate) fn reap_child(child: &mut Child, grace: Duration) -> io::Result<ExitStatus> {
+    reap_child_within(child, Some(grace)).map(|(status, _killed)| status)
+}
+
+/// Like [`reap_child`], but waiting indefinitely if `grace` is `None`, and
+/// also returning whether the child had to be killed.
+fn reap_child_within(child: &mut Child, grace: Option<Duration>) -> io::Result<(ExitStatus, bool)> {
+    let grace = match grace {
+        Some(grace) => grace,
+        None => return Ok((child.wait()?, false)),
+    };
+    let deadline = Instant::now() + grace;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, false));
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+        thread::sleep(REAP_POLL_INTERVAL);
+    }
+
+    // The child may exit on its own between `try_wait` and `kill`, in which
+    // case `kill` fails and `wait` collects its status.
+    let killed = child.kill().is_ok();
+    Ok((child.wait()?, killed))
+}
+
+/// The state shared between a [`ChildWriter`] and its [`ChildExit`].
+struct ExitState {
+    /// The child's exit status, and whether it was killed, once it's been
+    /// reaped.
+    result: Mutex<Option<io::Result<(ExitStatus, bool)>>>,
+    reaped: Condvar,
+    /// How long to wait for the child to exit after closing its stdin,
+    /// before killing it, or `None` to wait indefinitely.
+    grace: Mutex<Option<Duration>>,
+}
+
+/// The exit status of a child process owned by a [`ChildWriter`], which is
+/// available once the writer has been dropped and the child reaped.
+pub(crate) struct ChildExit {
+    state: Arc<ExitState>,
+}
+
+impl ChildExit {
+    /// Set how long the writer waits for the child to exit when it's
+    /// dropped, before killing it. `None` waits indefinitely.
+    pub(crate) fn set_grace(&self, grace: Option<Duration>) {
+        *self
+            .state
+            .grace
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = grace;
+    }
+
+    /// Wait for the child to be reaped and return its exit status.
+    pub(crate) fn wait(self) -> io::Result<ExitStatus> {
+        self.wait_killed().map(|(status, _killed)| status)
+    }
+
+    /// Wait for the child to be reaped and return its exit status, and
+    /// whether it was killed for not exiting within the grace period.
+    pub(crate) fn wait_killed(self) -> io::Result<(ExitStatus, bool)> {
+        let mut result = self
+            .state
+            .result
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        loop {
+            match result.take() {
+                Some(result) => return result,
+                None => {
+                    result = self
+                        .state
+                        .reaped
+                        .wait(result)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        }
+    }
+}
+
+/// A writer to a child process' stdin, which owns the child so that it's
+/// reaped when the writer is dropped, rather than left as a zombie.
+pub(crate) struct ChildWriter {
+    stdin: Option<ChildStdin>,
+    child: Child,
+    exit: Arc<ExitState>,
+}
+
+impl ChildWriter {
+    pub(crate) fn new(mut child: Child) -> Self {
+        Self {
+            stdin: child.stdin.take(),
+            child,
+            exit: Arc::new(ExitState {
+                result: Mutex::new(None),
+                reaped: Condvar::new(),
+                grace: Mutex::new(Some(CHILD_EXIT_GRACE)),
+            }),
+        }
+    }
+
+    /// Return a handle for waiting for the child's exit status once this
+    /// writer is dropped.
+    pub(crate) fn exit(&self) -> ChildExit {
+        ChildExit {
+            state: Arc::clone(&self.exit),
+        }
+    }
+
+    fn stdin(&mut self) -> &mut ChildStdin {
+        self.stdin.as_mut().unwrap()
+    }
+}
+
+impl Write for ChildWriter {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdin().write(buf)
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdin().flush()
+    }
+
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.stdin().write_all(buf)
+    }
+}
+
+impl Drop for ChildWriter {
+    fn drop(&mut self) {
+        // Close the child's stdin first, so that it sees the end of its input.
+        drop(self.stdin.take());
+        let grace = *self
+            .exit
+            .grace
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let result = reap_child_within(&mut self.child, grace);
+
+        // If nothing is waiting for the exit status, report errors here.
+        if Arc::strong_count(&self.exit) == 1 {
+            if let Err(e) = result {
+                report_drop_error(e);
+            }
+            return;
+        }
+        *self
+            .exit
+            .result
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(result);
+        self.exit.reaped.notify_all();
+    }
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn reap_exited_child() {
+    let mut child = std::process::Command::new("sh")
+        .args(["-c", "exit 3"])
+        .spawn()
+        .unwrap();
+    let status = reap_child(&mut child, Duration::from_secs(10)).unwrap();
+    assert_eq!(status.code(), Some(3));
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn reap_stalled_child() {
+    let mut child = std::process::Command::new("sleep")
+        .arg("60")
+        .spawn()
+        .unwrap();
+    let start = Instant::now();
+    let status = reap_child(&mut child, Duration::from_millis(100)).unwrap();
+    assert!(!status.success());
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+/// Open streams of every kind which owns a child process, in every pairing,
+/// with peers which have stalled, and drop them in both orders. Each
+/// pairing must finish promptly and leave no zombie processes behind.
+#[cfg(target_os = "linux")]
+#[test]
+fn teardown_pairings() {
+    use crate::{InputByteStream, InteractiveByteStream, OutputByteStream};
+    use clap::TryFromOsArg;
+    use layered_io::Bufferable;
+    use std::io::Read;
+    use std::path::Path;
+    use std::sync::mpsc;
+
+    enum Stream {
+        Input(InputByteStream),
+        Output(OutputByteStream),
+        Interactive(InteractiveByteStream),
+    }
+
+    // Spawn `command` under a shell which records its pid in `pid_file`.
+    fn child_name(pid_file: &Path, command: &str) -> String {
+        format!(
+            "$(sh -c 'echo $$ > {}; exec {}')",
+            pid_file.display(),
+            command
+        )
+    }
+
+    fn open(kind: usize, pid_file: &Path) -> Stream {
+        let ambient_authority = clap::ambient_authority();
+        match kind {
+            // A producer whose output pipe fills up.
+            0 => {
+                let name = child_name(pid_file, "yes");
+                let mut input =
+                    InputByteStream::try_from_os_str_arg(name.as_ref(), ambient_authority).unwrap();
+                let mut buf = [0; 16];
+                input.read_exact(&mut buf).unwrap();
+                Stream::Input(input)
+            }
+            // A consumer which never reads.
+            1 => {
+                let name = child_name(pid_file, "sleep 60");
+                let mut output =
+                    OutputByteStream::try_from_os_str_arg(name.as_ref(), ambient_authority)
+                        .unwrap();
+                output.write_all(b"unread\n").unwrap();
+                Stream::Output(output)
+            }
+            // A peer which is waiting for more input.
+            _ => {
+                let name = child_name(pid_file, "cat");
+                let mut interactive =
+                    InteractiveByteStream::try_from_os_str_arg(name.as_ref(), ambient_authority)
+                        .unwrap();
+                interactive.write_all(b"ping\n").unwrap();
+                let mut buf = [0; 5];
+                interactive.read_exact(&mut buf).unwrap();
+                Stream::Interactive(interactive)
+            }
+        }
+    }
+
+    fn read_pid(pid_file: &Path) -> u32 {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Ok(pid) = std::fs::read_to_string(pid_file) {
+                if let Ok(pid) = pid.trim().parse() {
+                    return pid;
+                }
+            }
+            assert!(Instant::now() < deadline, "child didn't start");
+            thread::sleep(REAP_POLL_INTERVAL);
+        }
+    }
+
+    // A reaped process has no `/proc` entry; a zombie still does.
+    fn assert_reaped(pid: u32) {
+        let deadline = Instant::now() + CHILD_EXIT_GRACE + Duration::from_secs(10);
+        while Path::new(&format!("/proc/{}", pid)).exists() {
+            assert!(Instant::now() < deadline, "child {} wasn't reaped", pid);
+            thread::sleep(REAP_POLL_INTERVAL);
+        }
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut scenarios = Vec::new();
+    for first in 0..3 {
+        for second in 0..3 {
+            for reverse in [false, true] {
+                let dir = dir.path().to_owned();
+                let (sender, receiver) = mpsc::channel();
+                thread::spawn(move || {
+                    let tag = format!("{}-{}-{}", first, second, reverse);
+                    let first_pid = dir.join(format!("{}-first", tag));
+                    let second_pid = dir.join(format!("{}-second", tag));
+                    let mut streams = vec![open(first, &first_pid), open(second, &second_pid)];
+                    let pids = [read_pid(&first_pid), read_pid(&second_pid)];
+                    if reverse {
+                        streams.reverse();
+                    }
+                    for stream in streams {
+                        // Writable streams must be closed or abandoned before
+                        // they're dropped; abandoning them leaves their
+                        // children for teardown, as a panic would.
+                        match stream {
+                            Stream::Input(input) => drop(input),
+                            Stream::Output(mut output) => output.abandon(),
+                            Stream::Interactive(mut interactive) => interactive.abandon(),
+                        }
+                    }
+                    for pid in pids {
+                        assert_reaped(pid);
+                    }
+                    sender.send(()).unwrap();
+                });
+                scenarios.push(((first, second, reverse), receiver));
+            }
+        }
+    }
+
+    let timeout = 2 * CHILD_EXIT_GRACE + Duration::from_secs(20);
+    for (scenario, receiver) in scenarios {
+        receiver
+            .recv_timeout(timeout)
+            .unwrap_or_else(|_| panic!("teardown of {:?} hung or failed", scenario));
+    }
+}
@@ -0,0 +1,566 @@
+use layered_io::Status;
+use std::ffi::OsStr;
+use std::io;
+#[cfg(feature = "tracing")]
+use {
+    crate::end_status::EndState,
+    crate::open_input::Input,
+    crate::open_output::Output,
+    crate::{classify, EndStatus, MediaType, StreamKind, SyntaxKind},
+    std::sync::atomic::{AtomicU64, Ordering},
+    std::time::Instant,
+    tracing::{field::Empty, Level, Span},
+};
+
+/// The target of all of the spans and events nameless emits.
+#[cfg(feature = "tracing")]
+const TARGET: &str = "nameless";
+
+/// What an opened stream reports in its `nameless.open` span.
+#[cfg(feature = "tracing")]
+pub(crate) struct Opened<'a> {
+    pub(crate) kind: StreamKind,
+    pub(crate) media_type: &'a MediaType,
+    pub(crate) size_hint: Option<u64>,
+    pub(crate) end_state: Option<&'a EndState>,
+}
+
+/// Types which `traced_open` can describe.
+#[cfg(feature = "tracing")]
+pub(crate) trait Describe {
+    fn describe(&self) -> Opened<'_>;
+}
+
+#[cfg(feature = "tracing")]
+impl Describe for Input {
+    fn describe(&self) -> Opened<'_> {
+        Opened {
+            kind: self.kind,
+            media_type: &self.media_type,
+            size_hint: self.initial_size,
+            end_state: Some(&self.end_state),
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Describe for Output {
+    fn describe(&self) -> Opened<'_> {
+        Opened {
+            kind: self.kind,
+            media_type: &self.media_type,
+            size_hint: None,
+            end_state: None,
+        }
+    }
+}
+
+/// Open a stream with `open`, emitting a `nameless.open` span for it and
+/// counting it in the metrics, and return a `Telemetry` to track the rest
+/// of its life.
+#[cfg(feature = "tracing")]
+pub(crate) fn traced_open<T: Describe>(
+    os: &OsStr,
+    open: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<(T, Telemetry)> {
+    let start = Instant::now();
+    let span = tracing::info_span!(
+        target: TARGET,
+        "nameless.open",
+        kind = Empty,
+        scheme = Empty,
+        media_type = Empty,
+        size_hint = Empty,
+        outcome = Empty,
+    );
+    if !span.is_disabled() {
+        span.record("scheme", scheme_name(os));
+    }
+
+    let opened = span.in_scope(open);
+    match opened {
+        Ok(opened) => {
+            OPENED.fetch_add(1, Ordering::Relaxed);
+            let Opened {
+                kind,
+                media_type,
+                size_hint,
+                end_state,
+            } = opened.describe();
+            if !span.is_disabled() {
+                span.record("kind", kind_name(kind));
+                span.record("media_type", media_type.mime().essence_str());
+                if let Some(size_hint) = size_hint {
+                    span.record("size_hint", size_hint);
+                }
+                span.record("outcome", "ok");
+            }
+            let telemetry = Telemetry {
+                span,
+                start,
+                opened: Instant::now(),
+                bytes: 0,
+                reading: end_state.is_some(),
+                child_end: match kind {
+                    StreamKind::Child => end_state.cloned(),
+                    _ => None,
+                },
+                counted: true,
+            };
+            Ok((opened, telemetry))
+        }
+        Err(err) => {
+            OPEN_FAILED.fetch_add(1, Ordering::Relaxed);
+            span.record("outcome", "error");
+            Err(err)
+        }
+    }
+}
+
+/// Without the "tracing" feature, just open the stream.
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub(crate) fn traced_open<T>(
+    _os: &OsStr,
+    open: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<(T, Telemetry)> {
+    open().map(|opened| (opened, Telemetry::default()))
+}
+
+/// Tracks a stream's transfers, emitting `nameless.first_byte` on its first
+/// transfer and `nameless.close` when it's dropped.
+#[cfg(feature = "tracing")]
+pub(crate) struct Telemetry {
+    span: Span,
+    start: Instant,
+    opened: Instant,
+    bytes: u64,
+    reading: bool,
+    child_end: Option<EndState>,
+    /// Whether this stream was opened, and so counts in the metrics.
+    counted: bool,
+}
+
+/// Without the "tracing" feature, `Telemetry` does nothing.
+#[cfg(not(feature = "tracing"))]
+#[derive(Default)]
+pub(crate) struct Telemetry;
+
+impl Telemetry {
+    /// Observe the result of a read or write of bytes.
+    #[inline]
+    pub(crate) fn transfer(&mut self, result: io::Result<usize>) -> io::Result<usize> {
+        if let Ok(n) = result {
+            self.transferred(n);
+        }
+        result
+    }
+
+    /// Observe the result of a read which also reports a `Status`.
+    #[inline]
+    pub(crate) fn transfer_with_status(
+        &mut self,
+        result: io::Result<(usize, Status)>,
+    ) -> io::Result<(usize, Status)> {
+        if let Ok((n, _status)) = result {
+            self.transferred(n);
+        }
+        result
+    }
+
+    /// Observe the result of a `read_exact` or `write_all` of `len` bytes.
+    #[inline]
+    pub(crate) fn transfer_all<T>(&mut self, result: io::Result<T>, len: usize) -> io::Result<T> {
+        if result.is_ok() {
+            self.transferred(len);
+        }
+        result
+    }
+
+    /// Observe a successful transfer of `n` bytes.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn transferred(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        if self.bytes == 0 && tracing::enabled!(target: TARGET, Level::INFO) {
+            tracing::event!(
+                name: "nameless.first_byte",
+                target: TARGET,
+                parent: &self.span,
+                Level::INFO,
+                latency_us = self.opened.elapsed().as_micros() as u64,
+            );
+        }
+        self.bytes += n as u64;
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    #[inline(always)]
+    pub(crate) fn transferred(&mut self, _n: usize) {}
+
+    /// Return whether this stream's transfers need to be observed, for
+    /// callers which would otherwise skip computing their sizes.
+    #[cfg(feature = "tracing")]
+    #[inline]
+    pub(crate) fn is_counting(&self) -> bool {
+        self.counted
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    #[inline(always)]
+    pub(crate) fn is_counting(&self) -> bool {
+        false
+    }
+}
+
+/// For streams created without being opened, such as wrapped streams.
+#[cfg(feature = "tracing")]
+impl Default for Telemetry {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            span: Span::none(),
+            start: now,
+            opened: now,
+            bytes: 0,
+            reading: false,
+            child_end: None,
+            counted: false,
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if !self.counted {
+            return;
+        }
+        CLOSED.fetch_add(1, Ordering::Relaxed);
+        if self.reading {
+            BYTES_READ.fetch_add(self.bytes, Ordering::Relaxed);
+        } else {
+            BYTES_WRITTEN.fetch_add(self.bytes, Ordering::Relaxed);
+        }
+        if tracing::enabled!(target: TARGET, Level::INFO) {
+            let child_exit = self.child_end.as_ref().map(|end| match end.get() {
+                EndStatus::ProducerFailed(message) => message,
+                _ => "success".to_owned(),
+            });
+            tracing::event!(
+                name: "nameless.close",
+                target: TARGET,
+                parent: &self.span,
+                Level::INFO,
+                bytes = self.bytes,
+                duration_us = self.start.elapsed().as_micros() as u64,
+                child_exit = child_exit.as_deref(),
+            );
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn scheme_name(os: &OsStr) -> String {
+    match classify(os) {
+        SyntaxKind::Url(scheme) => scheme,
+        SyntaxKind::Stdio => "stdio".to_owned(),
+        SyntaxKind::Command => "child".to_owned(),
+        SyntaxKind::Pipeline => "pipeline".to_owned(),
+        SyntaxKind::Path => "path".to_owned(),
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn kind_name(kind: StreamKind) -> &'static str {
+    match kind {
+        StreamKind::File => "file",
+        StreamKind::Stdio => "stdio",
+        StreamKind::Http => "http",
+        StreamKind::Data => "data",
+        StreamKind::Child => "child",
+        StreamKind::Socket => "socket",
+        StreamKind::CharDevice => "char-device",
+        StreamKind::Scp => "scp",
+        StreamKind::Clipboard => "clipboard",
+        StreamKind::Unknown => "unknown",
+    }
+}
+
+#[cfg(feature = "tracing")]
+static OPENED: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "tracing")]
+static OPEN_FAILED: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "tracing")]
+static CLOSED: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "tracing")]
+static BYTES_READ: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "tracing")]
+static BYTES_WRITTEN: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "tracing")]
+static HTTP_REQUESTS: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "tracing")]
+static HTTP_CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Count an HTTP request, for the pool metrics.
+#[cfg(all(feature = "tracing", not(target_os = "wasi")))]
+pub(crate) fn count_http_request() {
+    HTTP_REQUESTS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[cfg(all(not(feature = "tracing"), not(target_os = "wasi")))]
+#[inline(always)]
+pub(crate) fn count_http_request() {}
+
+/// Count a new HTTP connection, for the pool metrics.
+#[cfg(all(feature = "tracing", not(target_os = "wasi")))]
+pub(crate) fn count_http_connection() {
+    HTTP_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[cfg(all(not(feature = "tracing"), not(target_os = "wasi")))]
+#[inline(always)]
+pub(crate) fn count_http_connection() {}
+
+/// Counts of the input and output streams this program has opened so far,
+/// returned by [`metrics_snapshot`].
+#[cfg(feature = "tracing")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MetricsSnapshot {
+    opened: u64,
+    open_failed: u64,
+    closed: u64,
+    bytes_read: u64,
+    bytes_written: u64,
+    http_requests: u64,
+    http_connections: u64,
+}
+
+#[cfg(feature = "tracing")]
+impl MetricsSnapshot {
+    /// Return the number of streams successfully opened.
+    #[inline]
+    pub fn opened(&self) -> u64 {
+        self.opened
+    }
+
+    /// Return the number of attempts to open a stream which failed.
+    #[inline]
+    pub fn open_failed(&self) -> u64 {
+        self.open_failed
+    }
+
+    /// Return the number of streams which have been closed.
+    #[inline]
+    pub fn closed(&self) -> u64 {
+        self.closed
+    }
+
+    /// Return the total number of bytes read from closed input streams.
+    #[inline]
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Return the total number of bytes written to closed output streams.
+    #[inline]
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Return the number of HTTP requests which reused a pooled
+    /// connection. See [`set_http_pool`].
+    ///
+    /// [`set_http_pool`]: crate::set_http_pool
+    #[inline]
+    pub fn http_pool_hits(&self) -> u64 {
+        self.http_requests.saturating_sub(self.http_connections)
+    }
+
+    /// Return the number of HTTP requests which opened a new connection.
+    #[inline]
+    pub fn http_pool_misses(&self) -> u64 {
+        self.http_connections
+    }
+}
+
+/// Return counts of the input and output streams this program has opened
+/// so far, for programs which want a summary without a tracing pipeline.
+///
+/// Byte counts are added when a stream is closed.
+#[cfg(feature = "tracing")]
+pub fn metrics_snapshot() -> MetricsSnapshot {
+    MetricsSnapshot {
+        opened: OPENED.load(Ordering::Relaxed),
+        open_failed: OPEN_FAILED.load(Ordering::Relaxed),
+        closed: CLOSED.load(Ordering::Relaxed),
+        bytes_read: BYTES_READ.load(Ordering::Relaxed),
+        bytes_written: BYTES_WRITTEN.load(Ordering::Relaxed),
+        http_requests: HTTP_REQUESTS.load(Ordering::Relaxed),
+        http_connections: HTTP_CONNECTIONS.load(Ordering::Relaxed),
+    }
+}
+
+/// Each span's name, and its fields and events.
+#[cfg(all(test, feature = "tracing"))]
+type Spans = Vec<(String, Vec<String>)>;
+
+/// A subscriber which records the spans and events it sees, as
+/// `name{field=value,...}` strings, with events nested under their parents.
+#[cfg(all(test, feature = "tracing"))]
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: std::sync::Arc<std::sync::Mutex<Spans>>,
+}
+
+#[cfg(all(test, feature = "tracing"))]
+struct Fields<'a>(&'a mut Vec<String>);
+
+#[cfg(all(test, feature = "tracing"))]
+impl tracing::field::Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0.push(format!("{}={:?}", field.name(), value));
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+impl tracing::Subscriber for Recorder {
+    fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut spans = self.spans.lock().unwrap();
+        let mut fields = Vec::new();
+        span.record(&mut Fields(&mut fields));
+        spans.push((span.metadata().name().to_owned(), fields));
+        tracing::span::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].1));
+    }
+
+    fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+    fn event(&self, event: &tracing::Event<'_>) {
+        let mut fields = Vec::new();
+        event.record(&mut Fields(&mut fields));
+        let line = format!("{}{{{}}}", event.metadata().name(), fields.join(","));
+        let mut spans = self.spans.lock().unwrap();
+        let parent = event.parent().expect("nameless events have parents");
+        spans[parent.into_u64() as usize - 1].1.push(line);
+    }
+
+    fn enter(&self, _span: &tracing::span::Id) {}
+
+    fn exit(&self, _span: &tracing::span::Id) {}
+}
+
+#[cfg(all(test, feature = "tracing"))]
+#[test]
+fn file_open_read_close() {
+    use crate::InputByteStream;
+    use clap::TryFromOsArg;
+    use std::io::Read;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("traced.txt");
+    std::fs::write(&path, "hello world").unwrap();
+
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let mut input =
+            InputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority())
+                .unwrap();
+        let mut buf = [0; 5];
+        input.read_exact(&mut buf).unwrap();
+        let mut rest = Vec::new();
+        input.read_to_end(&mut rest).unwrap();
+    });
+
+    let spans = recorder.spans.lock().unwrap();
+    assert_eq!(spans.len(), 1);
+    let (name, fields) = &spans[0];
+    assert_eq!(name, "nameless.open");
+    assert_eq!(
+        fields[..5],
+        [
+            "scheme=\"path\"",
+            "kind=\"file\"",
+            "media_type=\"text/plain\"",
+            "size_hint=11",
+            "outcome=\"ok\"",
+        ]
+    );
+    assert_eq!(fields.len(), 7);
+    assert!(fields[5].starts_with("nameless.first_byte{latency_us="));
+    assert!(fields[6].starts_with("nameless.close{bytes=11,duration_us="));
+    assert!(!fields[6].contains("child_exit"));
+}
+
+#[cfg(all(test, feature = "tracing", not(any(windows, target_os = "wasi"))))]
+#[test]
+fn child_close_and_failed_open() {
+    use crate::{InputByteStream, OutputByteStream};
+    use clap::TryFromOsArg;
+    use layered_io::WriteLayered;
+    use std::io::{Read, Write};
+
+    let before = metrics_snapshot();
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let mut input =
+            InputByteStream::try_from_os_str_arg("$(echo hi)".as_ref(), clap::ambient_authority())
+                .unwrap();
+        let mut buf = String::new();
+        input.read_to_string(&mut buf).unwrap();
+        drop(input);
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut output = OutputByteStream::try_from_os_str_arg(
+            dir.path().join("out.txt").as_os_str(),
+            clap::ambient_authority(),
+        )
+        .unwrap();
+        output.write_all(b"hello").unwrap();
+        output.close().unwrap();
+        drop(output);
+
+        InputByteStream::try_from_os_str_arg(
+            dir.path().join("missing").as_os_str(),
+            clap::ambient_authority(),
+        )
+        .unwrap_err();
+    });
+
+    let spans = recorder.spans.lock().unwrap();
+    assert_eq!(spans.len(), 3);
+    assert!(spans[0].1.contains(&"scheme=\"child\"".to_owned()));
+    assert!(spans[0]
+        .1
+        .last()
+        .unwrap()
+        .ends_with("child_exit=\"success\"}"));
+    assert!(spans[1].1.contains(&"kind=\"file\"".to_owned()));
+    assert!(spans[1]
+        .1
+        .last()
+        .unwrap()
+        .starts_with("nameless.close{bytes=5,"));
+    assert_eq!(
+        spans[2].1,
+        ["scheme=\"path\"".to_owned(), "outcome=\"error\"".to_owned()]
+    );
+
+    // Other tests may be opening streams concurrently, so only check lower
+    // bounds.
+    let after = metrics_snapshot();
+    assert!(after.opened() >= before.opened() + 2);
+    assert!(after.open_failed() > before.open_failed());
+    assert!(after.closed() >= before.closed() + 2);
+    assert!(after.bytes_read() >= before.bytes_read() + 3);
+    assert!(after.bytes_written() >= before.bytes_written() + 5);
+}
@@ -0,0 +1,195 @@
+//! A minimal HTTP/1.1 server for testing the HTTP paths against.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// A request received by the test server.
+#[derive(Clone)]
+pub(crate) struct Request {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) headers: Vec<(String, String)>,
+    /// The request body, decoded if it was sent with the chunked transfer
+    /// encoding.
+    pub(crate) body: Vec<u8>,
+}
+
+impl Request {
+    /// Look up a header, ignoring ASCII case in the name.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A server which answers requests on an ephemeral local port by calling a
+/// handler function, which returns the complete response bytes.
+pub(crate) struct TestServer {
+    addr: SocketAddr,
+    connections: Arc<AtomicUsize>,
+}
+
+impl TestServer {
+    pub(crate) fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> Vec<u8> + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(handler);
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let counter = Arc::clone(&connections);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                counter.fetch_add(1, Ordering::SeqCst);
+                let handler = Arc::clone(&handler);
+                thread::spawn(move || {
+                    // Errors just end the connection.
+                    let _ = serve_connection(stream, &*handler);
+                });
+            }
+        });
+
+        Self { addr, connections }
+    }
+
+    /// Return the number of connections accepted so far.
+    pub(crate) fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Return a URL for `path` on this server.
+    pub(crate) fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+}
+
+/// Format a response with the given status line, headers, and body. A
+/// `Content-Length` header is added unless one is given explicitly.
+pub(crate) fn response(status: &str, headers: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+    let mut result = format!("HTTP/1.1 {}\r\n", status).into_bytes();
+    for (key, value) in headers {
+        result.extend_from_slice(format!("{}: {}\r\n", key, value).as_bytes());
+    }
+    if !headers
+        .iter()
+        .any(|(key, _)| key.eq_ignore_ascii_case("Content-Length"))
+    {
+        result.extend_from_slice(format!("Content-Length: {}\r\n", body.len()).as_bytes());
+    }
+    result.extend_from_slice(b"\r\n");
+    result.extend_from_slice(body);
+    result
+}
+
+fn serve_connection(
+    stream: TcpStream,
+    handler: &(dyn Fn(&Request) -> Vec<u8> + Send + Sync),
+) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    while let Some(request) = read_request(&mut reader)? {
+        let response = handler(&request);
+        writer.write_all(&response)?;
+        writer.flush()?;
+        if closes_connection(&response) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Test whether a response has a `Connection: close` header, in which case
+/// the connection is closed after sending it, which lets tests send
+/// responses which are cut short.
+fn closes_connection(response: &[u8]) -> bool {
+    let response = String::from_utf8_lossy(response);
+    let head = response.split("\r\n\r\n").next().unwrap_or_default();
+    head.lines().any(|line| {
+        line.split_once(':').is_some_and(|(key, value)| {
+            key.trim().eq_ignore_ascii_case("Connection") && value.trim() == "close"
+        })
+    })
+}
+
+fn read_request(reader: &mut BufReader<TcpStream>) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_owned();
+    let path = parts.next().unwrap_or_default().to_owned();
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = trimmed.split_once(':') {
+            headers.push((key.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: Vec::new(),
+    };
+    if request
+        .header("Transfer-Encoding")
+        .is_some_and(|value| value.eq_ignore_ascii_case("chunked"))
+    {
+        request.body = read_chunked_body(reader)?;
+    } else if let Some(len) = request.header("Content-Length") {
+        let len = len
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Length"))?;
+        request.body = vec![0; len];
+        reader.read_exact(&mut request.body)?;
+    }
+    Ok(Some(request))
+}
+
+fn read_chunked_body(reader: &mut BufReader<TcpStream>) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let size = line.trim_end().split(';').next().unwrap_or_default();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size"))?;
+        if size == 0 {
+            // Skip any trailers, up to the final empty line.
+            loop {
+                line.clear();
+                reader.read_line(&mut line)?;
+                if line.trim_end().is_empty() {
+                    return Ok(body);
+                }
+            }
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        let mut crlf = [0; 2];
+        reader.read_exact(&mut crlf)?;
+    }
+}
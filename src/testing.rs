@@ -0,0 +1,425 @@
+//! Fault injection for testing code which uses nameless streams.
+//!
+//! [`FaultyInput`] and [`FaultyOutput`] wrap streams so that they fail on
+//! demand, following a [`FaultPlan`], while still presenting the ordinary
+//! [`InputByteStream`] and [`OutputByteStream`] types, so code under test
+//! doesn't need to be generic.
+//!
+//! ```rust
+//! use nameless::testing::{FaultPlan, FaultyInput};
+//! use nameless::InputByteStream;
+//! use std::io::{ErrorKind, Read};
+//! # use nameless::clap::TryFromOsArg;
+//! # fn main() -> anyhow::Result<()> {
+//! # let input = InputByteStream::try_from_os_str_arg(
+//! #     "data:,hello".as_ref(),
+//! #     nameless::clap::ambient_authority(),
+//! # )?;
+//! let mut input = FaultyInput::wrap(input, FaultPlan::reset_after(2));
+//! let mut buf = [0; 8];
+//! assert_eq!(input.read(&mut buf)?, 2);
+//! assert_eq!(input.read(&mut buf).unwrap_err().kind(), ErrorKind::ConnectionReset);
+//! assert_eq!(input.read(&mut buf)?, 3);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::output_byte_stream::WrappedOutput;
+use crate::{InputByteStream, OutputByteStream};
+#[cfg(test)]
+use std::io::Read;
+use std::io::{self, Write};
+
+/// A fault to inject, and how many times to inject it.
+#[derive(Clone, Debug)]
+pub struct Fault {
+    kind: FaultKind,
+    /// The number of times left to inject the fault, or `None` for every
+    /// time from when it's triggered on.
+    times: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+enum FaultKind {
+    Error(io::ErrorKind),
+    Short(usize),
+}
+
+impl Fault {
+    /// Fail once with an error of the given kind.
+    #[inline]
+    pub fn error(kind: io::ErrorKind) -> Self {
+        Self {
+            kind: FaultKind::Error(kind),
+            times: Some(1),
+        }
+    }
+
+    /// Transfer at most `max` bytes per call, on every call from the
+    /// trigger on.
+    #[inline]
+    pub fn short(max: usize) -> Self {
+        assert!(max != 0, "short transfers must transfer something");
+        Self {
+            kind: FaultKind::Short(max),
+            times: None,
+        }
+    }
+
+    /// Inject this fault on `n` consecutive calls.
+    #[inline]
+    pub fn times(mut self, n: usize) -> Self {
+        self.times = Some(n);
+        self
+    }
+
+    /// Inject this fault on every call from the trigger on.
+    #[inline]
+    pub fn forever(mut self) -> Self {
+        self.times = None;
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Trigger {
+    /// Once this many bytes have been transferred.
+    AfterBytes(u64),
+    /// From this read or write call on, counting from zero.
+    AtCall(u64),
+    /// From this flush on, counting from zero. Closing an output stream
+    /// flushes it, so this is also how close failures are injected.
+    AtFlush(u64),
+}
+
+/// A script of faults to inject, each triggered by a byte offset or a call
+/// count.
+///
+/// When several faults are triggered at once, they're applied in the order
+/// they were added, and the first error wins.
+#[derive(Clone, Debug, Default)]
+pub struct FaultPlan {
+    steps: Vec<(Trigger, Fault)>,
+}
+
+impl FaultPlan {
+    /// Return an empty plan, which injects nothing.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inject `fault` once `bytes` bytes have been transferred. Transfers
+    /// are shortened so that they stop exactly at `bytes`.
+    #[inline]
+    pub fn after_bytes(mut self, bytes: u64, fault: Fault) -> Self {
+        self.steps.push((Trigger::AfterBytes(bytes), fault));
+        self
+    }
+
+    /// Inject `fault` from the `call`th read or write on, counting from
+    /// zero.
+    #[inline]
+    pub fn at_call(mut self, call: u64, fault: Fault) -> Self {
+        self.steps.push((Trigger::AtCall(call), fault));
+        self
+    }
+
+    /// Inject `fault` from the `flush`th flush on, counting from zero.
+    /// Closing an output stream flushes it, so this also injects close
+    /// failures.
+    #[inline]
+    pub fn at_flush(mut self, flush: u64, fault: Fault) -> Self {
+        self.steps.push((Trigger::AtFlush(flush), fault));
+        self
+    }
+
+    /// Return a plan which fails once with `ConnectionReset` after `bytes`
+    /// bytes, and then continues normally.
+    #[inline]
+    pub fn reset_after(bytes: u64) -> Self {
+        Self::new().after_bytes(bytes, Fault::error(io::ErrorKind::ConnectionReset))
+    }
+
+    /// Return a plan which fails `n` times with `Interrupted` before the
+    /// first transfer.
+    #[inline]
+    pub fn interrupted_storm(n: usize) -> Self {
+        Self::new().at_call(0, Fault::error(io::ErrorKind::Interrupted).times(n))
+    }
+
+    /// Return a plan which fails `n` times with `WouldBlock` before the
+    /// first transfer.
+    #[inline]
+    pub fn would_block(n: usize) -> Self {
+        Self::new().at_call(0, Fault::error(io::ErrorKind::WouldBlock).times(n))
+    }
+
+    /// Return a plan which transfers one byte per call.
+    #[inline]
+    pub fn one_byte_at_a_time() -> Self {
+        Self::new().at_call(0, Fault::short(1))
+    }
+
+    /// Return a plan which fails every flush, and so also closing.
+    #[inline]
+    pub fn failing_flush() -> Self {
+        Self::new().at_flush(0, Fault::error(io::ErrorKind::Other).forever())
+    }
+}
+
+/// Applies a `FaultPlan` to a sequence of transfers.
+struct Injector {
+    plan: FaultPlan,
+    bytes: u64,
+    calls: u64,
+    flushes: u64,
+}
+
+impl Injector {
+    fn new(plan: FaultPlan) -> Self {
+        Self {
+            plan,
+            bytes: 0,
+            calls: 0,
+            flushes: 0,
+        }
+    }
+
+    /// Called before a read or write of `len` bytes, returning the number of
+    /// bytes to transfer, or an error to inject.
+    fn before_transfer(&mut self, mut len: usize) -> io::Result<usize> {
+        let call = self.calls;
+        self.calls += 1;
+        for (trigger, fault) in &mut self.plan.steps {
+            let triggered = match *trigger {
+                Trigger::AfterBytes(bytes) if self.bytes < bytes => {
+                    // Stop short of the trigger, so that it's hit exactly.
+                    len = len.min((bytes - self.bytes).try_into().unwrap_or(usize::MAX));
+                    false
+                }
+                Trigger::AfterBytes(_) => true,
+                Trigger::AtCall(at) => call >= at,
+                Trigger::AtFlush(_) => false,
+            };
+            if triggered {
+                if let Some(len) = apply(fault, len)? {
+                    return Ok(len);
+                }
+            }
+        }
+        Ok(len)
+    }
+
+    fn after_transfer(&mut self, n: usize) {
+        self.bytes += n as u64;
+    }
+
+    fn before_flush(&mut self) -> io::Result<()> {
+        let flush = self.flushes;
+        self.flushes += 1;
+        for (trigger, fault) in &mut self.plan.steps {
+            if matches!(*trigger, Trigger::AtFlush(at) if flush >= at) {
+                apply(fault, 0)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Apply `fault`, if it has any injections left, to a transfer of `len`
+/// bytes, returning an error, or a shortened length.
+fn apply(fault: &mut Fault, len: usize) -> io::Result<Option<usize>> {
+    match &mut fault.times {
+        Some(0) => return Ok(None),
+        Some(times) => *times -= 1,
+        None => {}
+    }
+    match fault.kind {
+        FaultKind::Error(kind) => Err(io::Error::new(kind, "injected fault")),
+        FaultKind::Short(max) => Ok(Some(len.min(max))),
+    }
+}
+
+/// Injects faults into an [`InputByteStream`]'s reads.
+///
+/// The faults are applied by the stream itself, above its buffering layers,
+/// so that it can keep reading after an injected error.
+pub struct FaultyInput {
+    injector: Injector,
+}
+
+impl FaultyInput {
+    /// Wrap `input` so that its reads follow `plan`.
+    pub fn wrap(mut input: InputByteStream, plan: FaultPlan) -> InputByteStream {
+        input.inject_faults(Self {
+            injector: Injector::new(plan),
+        });
+        input
+    }
+
+    /// Perform a read into `buf` with `read`, shortened or replaced by any
+    /// faults due.
+    pub(crate) fn read<T>(
+        &mut self,
+        buf: &mut [u8],
+        read: impl FnOnce(&mut [u8]) -> io::Result<(usize, T)>,
+    ) -> io::Result<(usize, T)> {
+        let len = self.injector.before_transfer(buf.len())?;
+        let (n, extra) = read(&mut buf[..len])?;
+        self.injector.after_transfer(n);
+        Ok((n, extra))
+    }
+}
+
+/// Wraps an [`OutputByteStream`] to inject faults into its writes and
+/// flushes.
+pub struct FaultyOutput {
+    inner: WrappedOutput,
+    injector: Injector,
+}
+
+impl FaultyOutput {
+    /// Wrap `output` so that its writes and flushes follow `plan`.
+    pub fn wrap(output: OutputByteStream, plan: FaultPlan) -> OutputByteStream {
+        output.wrap_boxed(|inner| {
+            Box::new(Self {
+                inner,
+                injector: Injector::new(plan),
+            })
+        })
+    }
+}
+
+impl Write for FaultyOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.injector.before_transfer(buf.len())?;
+        let n = self.inner.write(&buf[..len])?;
+        self.injector.after_transfer(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.injector.before_flush()?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+fn data_input(s: &str) -> InputByteStream {
+    use clap::TryFromOsArg;
+    InputByteStream::try_from_os_str_arg(format!("data:,{}", s).as_ref(), clap::ambient_authority())
+        .unwrap()
+}
+
+#[cfg(test)]
+fn file_output(dir: &tempfile::TempDir) -> (OutputByteStream, std::path::PathBuf) {
+    use clap::TryFromOsArg;
+    let path = dir.path().join("out.txt");
+    let output =
+        OutputByteStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    (output, path)
+}
+
+#[test]
+fn short_reads() {
+    let mut input = FaultyInput::wrap(data_input("hello"), FaultPlan::one_byte_at_a_time());
+    let mut buf = [0; 8];
+    assert_eq!(input.read(&mut buf).unwrap(), 1);
+    assert_eq!(&buf[..1], b"h");
+    let mut s = String::new();
+    input.read_to_string(&mut s).unwrap();
+    assert_eq!(s, "ello");
+}
+
+#[test]
+fn interrupted_storm() {
+    let mut input = FaultyInput::wrap(data_input("hello"), FaultPlan::interrupted_storm(100));
+    let mut buf = [0; 8];
+    for _ in 0..100 {
+        assert_eq!(
+            input.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::Interrupted
+        );
+    }
+    assert_eq!(input.read(&mut buf).unwrap(), 5);
+}
+
+#[test]
+fn would_block() {
+    let mut input = FaultyInput::wrap(data_input("hello"), FaultPlan::would_block(2));
+    let mut buf = [0; 8];
+    assert_eq!(
+        input.read(&mut buf).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+    assert_eq!(
+        input.read(&mut buf).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+    assert_eq!(input.read(&mut buf).unwrap(), 5);
+}
+
+#[test]
+fn reset_mid_stream() {
+    let mut input = FaultyInput::wrap(data_input("hello-world"), FaultPlan::reset_after(5));
+    let mut s = String::new();
+    let e = input.read_to_string(&mut s).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+    assert_eq!(s, "hello");
+}
+
+#[test]
+fn one_byte_writes() {
+    use layered_io::WriteLayered;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (output, path) = file_output(&dir);
+    let mut output = FaultyOutput::wrap(output, FaultPlan::one_byte_at_a_time());
+    assert_eq!(output.write(b"hello").unwrap(), 1);
+    output.write_all(b"ello").unwrap();
+    output.close().unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "hello");
+}
+
+#[test]
+fn flush_and_close_failures() {
+    use layered_io::WriteLayered;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (output, _path) = file_output(&dir);
+    let mut output = FaultyOutput::wrap(output, FaultPlan::failing_flush());
+    output.write_all(b"hello").unwrap();
+    assert!(output.flush().is_err());
+    assert!(output.close().is_err());
+}
+
+/// An example of testing a consumer's retry logic: the consumer reads
+/// everything, retrying after a reset.
+#[test]
+fn retry_after_reset() {
+    fn read_with_retries(input: &mut impl Read, retries: usize) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut retries_left = retries;
+        loop {
+            match input.read_to_end(&mut data) {
+                Ok(_) => return Ok(data),
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset && retries_left > 0 => {
+                    retries_left -= 1
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    let plan =
+        FaultPlan::reset_after(3).after_bytes(6, Fault::error(io::ErrorKind::ConnectionReset));
+    let mut input = FaultyInput::wrap(data_input("hello-world"), plan.clone());
+    assert_eq!(read_with_retries(&mut input, 2).unwrap(), b"hello-world");
+
+    let mut input = FaultyInput::wrap(data_input("hello-world"), plan);
+    assert_eq!(
+        read_with_retries(&mut input, 1).unwrap_err().kind(),
+        io::ErrorKind::ConnectionReset
+    );
+}
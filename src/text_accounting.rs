@@ -0,0 +1,127 @@
+use std::ops::Deref;
+
+/// Counts of the text which has passed through a text stream, as returned by
+/// `accounting` on [`InputTextStream`] and [`OutputTextStream`] once
+/// accounting has been enabled with `enable_accounting`.
+///
+/// [`InputTextStream`]: crate::InputTextStream
+/// [`OutputTextStream`]: crate::OutputTextStream
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct TextAccounting {
+    pub(crate) bytes: u64,
+    pub(crate) lines: u64,
+    pub(crate) max_line_len: u64,
+}
+
+impl TextAccounting {
+    /// Return the number of bytes.
+    #[inline]
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Return the number of newlines, as `wc -l` counts them.
+    #[inline]
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+
+    /// Return the length in bytes of the longest line, not including its
+    /// line ending, which may be `\n` or `\r\n`.
+    #[inline]
+    pub fn max_line_len(&self) -> u64 {
+        self.max_line_len
+    }
+}
+
+/// Incrementally maintains a `TextAccounting` as text is written or read in
+/// arbitrary pieces.
+#[derive(Default)]
+pub(crate) struct Accountant {
+    accounting: TextAccounting,
+    /// The length of the current line so far, including any `\r`.
+    line_len: u64,
+    /// Whether the last byte seen was a `\r`.
+    after_cr: bool,
+}
+
+impl Accountant {
+    pub(crate) fn accounting(&self) -> TextAccounting {
+        let mut accounting = self.accounting;
+        accounting.max_line_len = accounting.max_line_len.max(self.line_len);
+        accounting
+    }
+
+    pub(crate) fn account(&mut self, bytes: &[u8]) {
+        self.accounting.bytes += bytes.len() as u64;
+
+        let mut rest = bytes;
+        while let Some(pos) = rest.iter().position(|b| *b == b'\n') {
+            let (line, after) = rest.split_at(pos);
+            self.line_len += line.len() as u64;
+            let cr = match line.last() {
+                Some(last) => *last == b'\r',
+                None => self.after_cr,
+            };
+            let len = self.line_len - u64::from(cr);
+            self.accounting.max_line_len = self.accounting.max_line_len.max(len);
+            self.accounting.lines += 1;
+            self.line_len = 0;
+            self.after_cr = false;
+            rest = &after[1..];
+        }
+        self.line_len += rest.len() as u64;
+        if let Some(last) = rest.last() {
+            self.after_cr = *last == b'\r';
+        }
+    }
+
+    /// Account for the first `n` bytes of `bufs`, which are `IoSlice`s or
+    /// `IoSliceMut`s.
+    pub(crate) fn account_vectored<B: Deref<Target = [u8]>>(&mut self, bufs: &[B], mut n: usize) {
+        for buf in bufs {
+            let len = n.min(buf.len());
+            self.account(&buf[..len]);
+            n -= len;
+        }
+    }
+}
+
+#[test]
+fn account_pieces() {
+    let mut accountant = Accountant::default();
+    assert_eq!(accountant.accounting(), TextAccounting::default());
+
+    for piece in ["hello", " world\nbye", "\n", "\nlong line\r", "\nend"] {
+        accountant.account(piece.as_bytes());
+    }
+    assert_eq!(
+        accountant.accounting(),
+        TextAccounting {
+            bytes: 31,
+            lines: 4,
+            max_line_len: 11,
+        }
+    );
+
+    // The final line counts toward the maximum even without a newline.
+    accountant.account(b"abcdefghijklmnop");
+    assert_eq!(accountant.accounting().lines(), 4);
+    assert_eq!(accountant.accounting().max_line_len(), 19);
+}
+
+#[test]
+fn account_crlf() {
+    // A CRLF split across pieces isn't counted as part of the line.
+    let mut accountant = Accountant::default();
+    accountant.account(b"abc\r");
+    accountant.account(b"\n\r\nabcd\r\n");
+    assert_eq!(
+        accountant.accounting(),
+        TextAccounting {
+            bytes: 13,
+            lines: 3,
+            max_line_len: 4,
+        }
+    );
+}
@@ -0,0 +1,143 @@
+//! Round-trip checks for the text streams: text read by an
+//! `InputTextStream` is written through an `OutputTextStream` unchanged,
+//! and reading the output again gives the same text.
+
+use crate::{InputTextStream, OutputTextStream};
+use clap::TryFromOsArg;
+use layered_io::WriteLayered;
+use std::io::Read;
+use std::path::Path;
+use utf8_io::WriteStr;
+
+/// Read the file at `path` through an `InputTextStream`, with both the
+/// `Read` path and the bulk path, which must agree.
+fn read_text(path: &Path) -> String {
+    let open = || {
+        InputTextStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap()
+    };
+    let mut text = String::new();
+    open().read_to_string(&mut text).unwrap();
+    let mut bulk = Vec::new();
+    open().read_to_text_bytes(&mut bulk).unwrap();
+    assert_eq!(text.as_bytes(), &bulk[..]);
+    text
+}
+
+/// Write `text` to the file at `path` through an `OutputTextStream`, one
+/// line per write, and return the bytes written.
+fn write_text(path: &Path, text: &str) -> Vec<u8> {
+    let mut output =
+        OutputTextStream::try_from_os_str_arg(path.as_os_str(), clap::ambient_authority()).unwrap();
+    for line in text.split_inclusive('\n') {
+        output.write_str(line).unwrap();
+    }
+    output.close().unwrap();
+    std::fs::read(path).unwrap()
+}
+
+/// Check the round-trip properties for the plain text `bytes`, returning
+/// the text it reads as:
+///  - Reading normalizes: the text contains no CR or leading BOM, and
+///    non-empty text ends with a newline.
+///  - Writing preserves: writing the text produces exactly its bytes.
+///  - Reading is idempotent: reading the written text gives the same text.
+pub(crate) fn verify_roundtrip(bytes: &[u8]) -> String {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    let output = dir.path().join("output.txt");
+    std::fs::write(&input, bytes).unwrap();
+
+    let once = read_text(&input);
+    assert!(!once.contains('\r'), "{:?} read as {:?}", bytes, once);
+    assert!(
+        !once.starts_with('\u{feff}'),
+        "{:?} read as {:?}",
+        bytes,
+        once
+    );
+    assert!(once.is_empty() || once.ends_with('\n'), "{:?}", once);
+
+    let written = write_text(&output, &once);
+    assert_eq!(
+        written,
+        once.as_bytes(),
+        "{:?} was written differently",
+        once
+    );
+    let twice = read_text(&output);
+    assert_eq!(twice, once, "{:?} isn't stable when read again", once);
+    once
+}
+
+/// Pieces of text to build test strings from, with the scalars which text
+/// normalization treats specially.
+const PIECES: &[&str] = &[
+    "a",
+    "xyz",
+    " ",
+    "\t",
+    "\n",
+    "\r\n",
+    "\r",
+    "\r\r\n",
+    // NEL, LS, and PS, which are line breaks in some contexts.
+    "\u{85}",
+    "\u{2028}",
+    "\u{2029}",
+    // A byte order mark, which is only special at the start.
+    "\u{feff}",
+    // No-break spaces, which are ordinary text.
+    "\u{a0}",
+    "\u{2007}",
+    "\u{202f}",
+    "\u{2060}",
+    // Sequences which change under NFC.
+    "e\u{301}",
+    "\u{212b}",
+    "\u{1100}\u{1161}",
+    "é",
+];
+
+/// A small deterministic xorshift generator, so failures are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+}
+
+#[test]
+fn roundtrip_special_scalars() {
+    for piece in PIECES {
+        verify_roundtrip(piece.as_bytes());
+        verify_roundtrip(format!("{}\n", piece).as_bytes());
+        verify_roundtrip(format!("a{}b\n", piece).as_bytes());
+        verify_roundtrip(format!("{}{}", piece, piece).as_bytes());
+    }
+    verify_roundtrip(b"");
+}
+
+#[test]
+fn roundtrip_line_endings() {
+    assert_eq!(verify_roundtrip(b"a\r\nb\r\n"), "a\nb\n");
+    assert_eq!(verify_roundtrip(b"a\rb\r"), "a\nb\n");
+    assert_eq!(verify_roundtrip(b"a\nb"), "a\nb\n");
+    assert_eq!(verify_roundtrip("\u{feff}a\n".as_bytes()), "a\n");
+    assert_eq!(verify_roundtrip("e\u{301}\n".as_bytes()), "é\n");
+}
+
+#[test]
+fn roundtrip_generated() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..500 {
+        let len = rng.next() % 24;
+        let text = (0..len)
+            .map(|_| PIECES[rng.next() % PIECES.len()])
+            .collect::<String>();
+        verify_roundtrip(text.as_bytes());
+    }
+}
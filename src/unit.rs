@@ -0,0 +1,256 @@
+//! Reading whole units from streams which report where units end, with
+//! `InteractiveByteStream::read_unit` and
+//! `InteractiveTextStream::read_unit_to_string`.
+
+use layered_io::{Activity, ReadLayered, Status};
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// How a unit read with [`InteractiveByteStream::read_unit`] or
+/// [`InteractiveTextStream::read_unit_to_string`] ended.
+///
+/// [`InteractiveByteStream::read_unit`]: crate::InteractiveByteStream::read_unit
+/// [`InteractiveTextStream::read_unit_to_string`]: crate::InteractiveTextStream::read_unit_to_string
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnitEnd {
+    /// The stream reported a push, ending the unit, and remains open for
+    /// further units.
+    Unit,
+    /// The stream ended, which also ends the unit.
+    Stream,
+}
+
+/// The error for a unit which is larger than the limit passed to
+/// [`InteractiveByteStream::read_unit`] or
+/// [`InteractiveTextStream::read_unit_to_string`].
+///
+/// This is returned as an `io::Error` wrapping a `UnitTooLarge`, which can
+/// be obtained with [`UnitTooLarge::of`].
+///
+/// [`InteractiveByteStream::read_unit`]: crate::InteractiveByteStream::read_unit
+/// [`InteractiveTextStream::read_unit_to_string`]: crate::InteractiveTextStream::read_unit_to_string
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnitTooLarge {
+    /// The limit, in bytes.
+    pub max: usize,
+}
+
+impl UnitTooLarge {
+    fn into_io(self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, self)
+    }
+
+    /// If `e` was produced because a unit exceeded its limit, return the
+    /// details.
+    pub fn of(e: &io::Error) -> Option<&Self> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<Self>())
+    }
+}
+
+impl Error for UnitTooLarge {}
+
+impl fmt::Display for UnitTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unit exceeds the limit of {} bytes", self.max)
+    }
+}
+
+/// Read from `reader`, appending to `buf`, until it reports a push or the
+/// end of the stream.
+///
+/// A unit of more than `max` bytes is read and discarded, leaving `buf` as
+/// it was, and fails with [`UnitTooLarge`], so the next unit can still be
+/// read.
+pub(crate) fn read_unit<R: ReadLayered + ?Sized>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+) -> io::Result<UnitEnd> {
+    let start = buf.len();
+    let mut chunk = [0; 8192];
+    let mut overflowed = false;
+    loop {
+        let (n, status) = match reader.read_with_status(&mut chunk) {
+            Ok(result) => result,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                buf.truncate(start);
+                return Err(err);
+            }
+        };
+        if !overflowed {
+            if buf.len() - start + n > max {
+                overflowed = true;
+                buf.truncate(start);
+            } else {
+                buf.extend_from_slice(&chunk[..n]);
+            }
+        }
+        let end = match status {
+            Status::End => UnitEnd::Stream,
+            Status::Open(Activity::Push) => UnitEnd::Unit,
+            _ => continue,
+        };
+        return if overflowed {
+            Err(UnitTooLarge { max }.into_io())
+        } else {
+            Ok(end)
+        };
+    }
+}
+
+/// An in-memory `ReadLayered` which returns a sequence of reads, as a peer
+/// which pushes after each unit would.
+#[cfg(test)]
+struct Pushes {
+    reads: std::collections::VecDeque<(Vec<u8>, Status)>,
+}
+
+#[cfg(test)]
+impl Pushes {
+    fn new(reads: &[(&[u8], Status)]) -> Self {
+        Self {
+            reads: reads
+                .iter()
+                .map(|(data, status)| (data.to_vec(), *status))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl ReadLayered for Pushes {
+    fn read_with_status(&mut self, buf: &mut [u8]) -> io::Result<(usize, Status)> {
+        match self.reads.pop_front() {
+            Some((data, status)) => {
+                buf[..data.len()].copy_from_slice(&data);
+                Ok((data.len(), status))
+            }
+            None => Ok((0, Status::End)),
+        }
+    }
+
+    fn read_vectored_with_status(
+        &mut self,
+        bufs: &mut [io::IoSliceMut<'_>],
+    ) -> io::Result<(usize, Status)> {
+        match bufs.iter_mut().find(|buf| !buf.is_empty()) {
+            Some(buf) => self.read_with_status(buf),
+            None => Ok((0, Status::active())),
+        }
+    }
+}
+
+#[cfg(test)]
+impl io::Read for Pushes {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_with_status(buf).map(|(n, _status)| n)
+    }
+}
+
+#[cfg(test)]
+impl layered_io::Bufferable for Pushes {
+    fn abandon(&mut self) {
+        self.reads.clear();
+    }
+}
+
+#[test]
+fn read_units() {
+    let mut reader = Pushes::new(&[
+        (b"hel", Status::active()),
+        (b"lo", Status::push()),
+        (b"", Status::push()),
+        (b"world", Status::push()),
+        (b"!", Status::End),
+    ]);
+    let mut buf = Vec::new();
+    assert_eq!(read_unit(&mut reader, &mut buf, 16).unwrap(), UnitEnd::Unit);
+    assert_eq!(buf, b"hello");
+
+    // An empty unit.
+    buf.clear();
+    assert_eq!(read_unit(&mut reader, &mut buf, 16).unwrap(), UnitEnd::Unit);
+    assert_eq!(buf, b"");
+
+    // Units are appended.
+    assert_eq!(read_unit(&mut reader, &mut buf, 5).unwrap(), UnitEnd::Unit);
+    assert_eq!(
+        read_unit(&mut reader, &mut buf, 5).unwrap(),
+        UnitEnd::Stream
+    );
+    assert_eq!(buf, b"world!");
+
+    buf.clear();
+    assert_eq!(
+        read_unit(&mut reader, &mut buf, 5).unwrap(),
+        UnitEnd::Stream
+    );
+    assert_eq!(buf, b"");
+}
+
+#[test]
+fn read_unit_too_large() {
+    let mut reader = Pushes::new(&[
+        (b"abc", Status::active()),
+        (b"def", Status::active()),
+        (b"ghi", Status::push()),
+        (b"ok", Status::push()),
+    ]);
+    let mut buf = b"kept".to_vec();
+    let err = read_unit(&mut reader, &mut buf, 4).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(UnitTooLarge::of(&err), Some(&UnitTooLarge { max: 4 }));
+    assert_eq!(buf, b"kept");
+
+    // The rest of the oversized unit was discarded.
+    buf.clear();
+    assert_eq!(read_unit(&mut reader, &mut buf, 4).unwrap(), UnitEnd::Unit);
+    assert_eq!(buf, b"ok");
+}
+
+#[test]
+fn read_unit_interrupted() {
+    use layered_io::Bufferable;
+
+    struct Interrupting(bool, Pushes);
+
+    impl ReadLayered for Interrupting {
+        fn read_with_status(&mut self, buf: &mut [u8]) -> io::Result<(usize, Status)> {
+            self.0 = !self.0;
+            if self.0 {
+                Err(io::ErrorKind::Interrupted.into())
+            } else {
+                self.1.read_with_status(buf)
+            }
+        }
+
+        fn read_vectored_with_status(
+            &mut self,
+            bufs: &mut [io::IoSliceMut<'_>],
+        ) -> io::Result<(usize, Status)> {
+            self.1.read_vectored_with_status(bufs)
+        }
+    }
+
+    impl io::Read for Interrupting {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.read_with_status(buf).map(|(n, _status)| n)
+        }
+    }
+
+    impl Bufferable for Interrupting {
+        fn abandon(&mut self) {
+            self.1.abandon()
+        }
+    }
+
+    let mut reader = Interrupting(
+        false,
+        Pushes::new(&[(b"a", Status::active()), (b"b", Status::push())]),
+    );
+    let mut buf = Vec::new();
+    assert_eq!(read_unit(&mut reader, &mut buf, 2).unwrap(), UnitEnd::Unit);
+    assert_eq!(buf, b"ab");
+}
@@ -0,0 +1,58 @@
+//! Tests for implementing `TryFromOsArg` outside of nameless, using only the
+//! crate-root exports.
+
+use nameless::{ambient_authority, AmbientAuthority, InputByteStream, TryFromOsArg};
+use std::ffi::OsStr;
+use std::io::Read;
+
+/// A custom argument type which reads its input eagerly.
+struct Contents(String);
+
+impl TryFromOsArg for Contents {
+    type Error = anyhow::Error;
+
+    fn try_from_os_str_arg(
+        os: &OsStr,
+        ambient_authority: AmbientAuthority,
+    ) -> anyhow::Result<Self> {
+        let mut input = InputByteStream::try_from_os_str_arg(os, ambient_authority)?;
+        let mut s = String::new();
+        input.read_to_string(&mut s)?;
+        Ok(Self(s))
+    }
+}
+
+/// A program using the custom type as a `kommand` argument. This only
+/// needs to compile.
+#[allow(dead_code)]
+mod program {
+    use super::Contents;
+
+    /// # Arguments
+    ///
+    /// * `contents` - Input source
+    /// * `more` - More input sources
+    #[kommand::main]
+    fn main(contents: Contents, more: Vec<Contents>) {
+        print!("{}", contents.0);
+        for contents in more {
+            print!("{}", contents.0);
+        }
+    }
+}
+
+#[test]
+fn custom_arg() {
+    let contents =
+        Contents::try_from_os_str_arg("data:,hello".as_ref(), ambient_authority()).unwrap();
+    assert_eq!(contents.0, "hello");
+
+    assert!(Contents::try_from_os_str_arg("nosuchscheme:x".as_ref(), ambient_authority()).is_err());
+}
+
+#[test]
+#[allow(deprecated)]
+fn deprecated_single_argument_form() {
+    let contents: Contents = nameless::try_from_os_str_arg("data:,world".as_ref()).unwrap();
+    assert_eq!(contents.0, "world");
+}
@@ -0,0 +1,67 @@
+//! Tests for reading units from interactive streams, with a peer over TCP.
+
+#![cfg(not(target_os = "wasi"))]
+
+use nameless::{
+    ambient_authority, InteractiveByteStream, InteractiveTextStream, TryFromOsArg, UnitEnd,
+    UnitTooLarge,
+};
+use std::io::Write;
+use std::net::{Shutdown, TcpListener, TcpStream};
+
+/// Connect a stream of type `T` to a new listener, and return it with the
+/// peer's end.
+fn connect<T: TryFromOsArg>() -> (T, TcpStream)
+where
+    T::Error: std::fmt::Debug,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let name = format!("connect://{}", listener.local_addr().unwrap());
+    let stream = T::try_from_os_str_arg(name.as_ref(), ambient_authority()).unwrap();
+    let (peer, _addr) = listener.accept().unwrap();
+    (stream, peer)
+}
+
+#[test]
+fn byte_unit_ends_with_stream() {
+    let (mut stream, mut peer) = connect::<InteractiveByteStream>();
+
+    // Sockets don't carry pushes, so the unit is the rest of the stream.
+    peer.write_all(b"first\nsecond\n").unwrap();
+    peer.shutdown(Shutdown::Write).unwrap();
+
+    let mut buf = b"> ".to_vec();
+    assert_eq!(stream.read_unit(&mut buf, 64).unwrap(), UnitEnd::Stream);
+    assert_eq!(buf, b"> first\nsecond\n");
+
+    // After the end, units are empty.
+    buf.clear();
+    assert_eq!(stream.read_unit(&mut buf, 64).unwrap(), UnitEnd::Stream);
+    assert_eq!(buf, b"");
+}
+
+#[test]
+fn byte_unit_too_large() {
+    let (mut stream, mut peer) = connect::<InteractiveByteStream>();
+    peer.write_all(&[b'x'; 100]).unwrap();
+    peer.shutdown(Shutdown::Write).unwrap();
+
+    let mut buf = Vec::new();
+    let err = stream.read_unit(&mut buf, 99).unwrap_err();
+    assert_eq!(UnitTooLarge::of(&err), Some(&UnitTooLarge { max: 99 }));
+    assert_eq!(buf, b"");
+}
+
+#[test]
+fn text_unit() {
+    let (mut stream, mut peer) = connect::<InteractiveTextStream>();
+    peer.write_all("héllo\n".as_bytes()).unwrap();
+    peer.shutdown(Shutdown::Write).unwrap();
+
+    let mut s = String::new();
+    assert_eq!(
+        stream.read_unit_to_string(&mut s, 64).unwrap(),
+        UnitEnd::Stream
+    );
+    assert_eq!(s, "héllo\n");
+}
@@ -0,0 +1,142 @@
+//! Tests for `LineProtocolServer`, with clients connecting over TCP.
+
+#![cfg(not(target_os = "wasi"))]
+
+use nameless::{
+    ambient_authority, CancellationToken, InteractiveByteStream, LineProtocolServer, Response,
+    TryFromOsArg,
+};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+struct Client {
+    io: BufReader<InteractiveByteStream>,
+}
+
+impl Client {
+    fn connect(name: &str) -> Self {
+        let io =
+            InteractiveByteStream::try_from_os_str_arg(name.as_ref(), ambient_authority()).unwrap();
+        Self {
+            io: BufReader::new(io),
+        }
+    }
+
+    fn send(&mut self, line: &str) {
+        writeln!(self.io.get_mut(), "{}", line).unwrap();
+        self.io.get_mut().flush().unwrap();
+    }
+
+    /// Read a line, or `None` at the end of the stream.
+    fn recv(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match self.io.read_line(&mut line).unwrap() {
+            0 => None,
+            _ => Some(line.trim_end_matches('\n').to_owned()),
+        }
+    }
+}
+
+#[test]
+fn concurrent_clients() {
+    let barrier = Arc::new(Barrier::new(3));
+    let shutdown = CancellationToken::new();
+    let server = LineProtocolServer::new("accept://127.0.0.1:0")
+        .max_line_len(16)
+        .shutdown_token(shutdown.clone())
+        .on_line({
+            let barrier = Arc::clone(&barrier);
+            move |line, ctx| match line {
+                // Each client waits here until all three are connected, so
+                // this only finishes if they're handled concurrently.
+                "sync" => {
+                    barrier.wait();
+                    ctx.send("synced")?;
+                    Ok(Response::NoReply)
+                }
+                "quit" => Ok(Response::Close),
+                "fail" => Err(anyhow::anyhow!("failed on purpose")),
+                _ => Ok(Response::Reply(line.to_uppercase())),
+            }
+        })
+        .bind(ambient_authority())
+        .unwrap();
+    let name = server.connect_name().unwrap();
+    assert!(name.starts_with("connect://127.0.0.1:"));
+    let serving = thread::spawn(move || server.serve());
+
+    let clients = (0..3)
+        .map(|i| {
+            let name = name.clone();
+            thread::spawn(move || {
+                let mut client = Client::connect(&name);
+                client.send("sync");
+                assert_eq!(client.recv().unwrap(), "synced");
+
+                client.send(&format!("hello {}", i));
+                assert_eq!(client.recv().unwrap(), format!("HELLO {}", i));
+
+                // A line which is too long is rejected, and the connection
+                // continues.
+                client.send(&"x".repeat(32));
+                assert_eq!(
+                    client.recv().unwrap(),
+                    "error: line is longer than 16 bytes"
+                );
+                client.send("fail");
+                assert_eq!(client.recv().unwrap(), "error: failed on purpose");
+                client.send("still here");
+                assert_eq!(client.recv().unwrap(), "STILL HERE");
+                client
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut clients = clients
+        .into_iter()
+        .map(|client| client.join().unwrap())
+        .collect::<Vec<_>>();
+
+    // The handler can close a connection.
+    let mut closed = clients.pop().unwrap();
+    closed.send("quit");
+    assert_eq!(closed.recv(), None);
+
+    // Shutting down closes the remaining connections and stops the server.
+    shutdown.cancel();
+    serving.join().unwrap().unwrap();
+    for mut client in clients {
+        assert_eq!(client.recv(), None);
+    }
+}
+
+#[test]
+fn no_handler() {
+    let err = LineProtocolServer::new("accept://127.0.0.1:0")
+        .bind(ambient_authority())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "LineProtocolServer has no `on_line` handler"
+    );
+}
+
+#[cfg(not(windows))]
+#[test]
+fn single_connection_hangs_up() {
+    use std::sync::Mutex;
+
+    // The server finishes cleanly when a single-connection stream ends.
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    LineProtocolServer::new("$(printf 'hello\\nworld\\n')")
+        .on_line({
+            let lines = Arc::clone(&lines);
+            move |line, _ctx| {
+                lines.lock().unwrap().push(line.to_owned());
+                Ok(Response::NoReply)
+            }
+        })
+        .run(ambient_authority())
+        .unwrap();
+    assert_eq!(*lines.lock().unwrap(), ["hello", "world"]);
+}
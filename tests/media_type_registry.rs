@@ -0,0 +1,65 @@
+//! Tests for registering custom media types. Registrations are process-wide,
+//! so these run in their own test binary.
+
+use nameless::{ambient_authority, InputByteStream, MediaType, TryFromOsArg};
+use std::ffi::OsStr;
+use std::str::FromStr;
+
+fn open(path: &std::path::Path) -> InputByteStream {
+    InputByteStream::try_from_os_str_arg(path.as_os_str(), ambient_authority()).unwrap()
+}
+
+#[test]
+fn registered_extension() {
+    let parquet = mime::Mime::from_str("application/vnd.apache.parquet").unwrap();
+    MediaType::register_extension("parquet", parquet.clone());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.parquet");
+    std::fs::write(&path, b"PAR1").unwrap();
+    assert_eq!(open(&path).media_type().mime(), &parquet);
+
+    assert_eq!(
+        MediaType::from_mime(parquet).preferred_extension(),
+        Some("parquet")
+    );
+}
+
+#[test]
+fn registered_extension_overrides_mime_guess() {
+    // `mime_guess` knows `.vcf` as a vCard; register it as the Variant Call
+    // Format instead.
+    let vcard = MediaType::from_extension(Some(OsStr::new("vcf")));
+    let variants = mime::Mime::from_str("text/x-vcf").unwrap();
+    assert_ne!(vcard.mime(), &variants);
+
+    MediaType::register_from_pairs([("vcf", variants.clone())]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("calls.vcf");
+    std::fs::write(&path, b"##fileformat=VCFv4.3\n").unwrap();
+    assert_eq!(open(&path).media_type().mime(), &variants);
+
+    // Types constructed before the registration are unaffected.
+    assert_ne!(vcard.mime(), &variants);
+}
+
+#[test]
+fn registered_filename() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // Well-known names are recognized without registration.
+    let makefile = dir.path().join("Makefile");
+    std::fs::write(&makefile, b"all:\n").unwrap();
+    assert_eq!(
+        open(&makefile).media_type().mime().essence_str(),
+        "text/x-makefile"
+    );
+
+    let just = mime::Mime::from_str("text/x-just").unwrap();
+    MediaType::register_filename("justfile", just.clone());
+
+    let path = dir.path().join("Justfile");
+    std::fs::write(&path, b"build:\n").unwrap();
+    assert_eq!(open(&path).media_type().mime(), &just);
+}
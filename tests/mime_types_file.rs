@@ -0,0 +1,33 @@
+//! Tests for loading media types from the file named by
+//! `NAMELESS_MIME_TYPES`. The file is read on the first lookup in the
+//! process, so this runs in its own test binary with a single test.
+
+#![cfg(feature = "mime-types-file")]
+
+use nameless::MediaType;
+use std::ffi::OsStr;
+use std::str::FromStr;
+
+#[test]
+fn mime_types_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let types = dir.path().join("types.toml");
+    std::fs::write(
+        &types,
+        "parquet = \"application/vnd.apache.parquet\"\nfq = \"text/x-fastq\"\n",
+    )
+    .unwrap();
+    std::env::set_var("NAMELESS_MIME_TYPES", &types);
+
+    let ext = |s| MediaType::from_extension(Some(OsStr::new(s)));
+    let mime = |s| mime::Mime::from_str(s).unwrap();
+    assert_eq!(
+        ext("parquet").mime(),
+        &mime("application/vnd.apache.parquet")
+    );
+    assert_eq!(ext("fq").mime(), &mime("text/x-fastq"));
+
+    // Registrations take precedence over the file.
+    MediaType::register_extension("fq", mime("application/x-fastq"));
+    assert_eq!(ext("fq").mime(), &mime("application/x-fastq"));
+}
@@ -0,0 +1,91 @@
+//! Tests for the `nameless-cat` program, including its stderr summary format.
+
+#![cfg(feature = "bin")]
+
+use std::fs;
+use std::process::{Command, Output};
+
+fn nameless_cat(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nameless-cat"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn data_url_to_stdout() {
+    let output = nameless_cat(&["data:text/plain,hello"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"hello");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "input 0: kind=Data media-type=text/plain size=5 copied=5\n\
+         output: kind=Stdio media-type=*/* existence=- copied=5\n"
+    );
+}
+
+#[test]
+fn files_to_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("first.txt");
+    let second = dir.path().join("second.txt");
+    let out = dir.path().join("out.txt");
+    fs::write(&first, "hello\n").unwrap();
+    fs::write(&second, "world\n").unwrap();
+
+    let output = nameless_cat(&[
+        "-o",
+        out.to_str().unwrap(),
+        first.to_str().unwrap(),
+        second.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(fs::read_to_string(&out).unwrap(), "hello\nworld\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "input 0: kind=File media-type=text/plain size=6 copied=6\n\
+         input 1: kind=File media-type=text/plain size=6 copied=6\n\
+         output: kind=File media-type=text/plain existence=created copied=12\n"
+    );
+}
+
+#[test]
+fn exit_codes() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let missing = dir.path().join("missing.txt");
+    let output = nameless_cat(&[missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(66));
+
+    let unwritable = dir.path().join("missing").join("out.txt");
+    let output = nameless_cat(&["-o", unwritable.to_str().unwrap(), "data:,hello"]);
+    assert_eq!(output.status.code(), Some(73));
+}
+
+#[cfg(unix)]
+#[test]
+fn long_version() {
+    let output = nameless_cat(&["--version"]);
+    assert!(output.status.success());
+    // The optional syntaxes are listed only when their features are enabled.
+    let scp = if cfg!(feature = "ssh2") { "scp:, " } else { "" };
+    let clipboard = if cfg!(feature = "clipboard") {
+        ", clipboard:"
+    } else {
+        ""
+    };
+    // kommand names programs by their crate name, which uses underscores.
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "nameless_cat {}\n\
+             streams: https:, data:, file:, {}connect:, accept:, stdio, $(exec), pipeline, path, *.gz{}\n\
+             nameless {}\n",
+            env!("CARGO_PKG_VERSION"),
+            scp,
+            clipboard,
+            nameless::CRATE_VERSION
+        )
+    );
+}
@@ -0,0 +1,103 @@
+//! Tests for using the stream types from `structopt`, which parses
+//! arguments with `FromStr`.
+
+#![cfg(feature = "structopt-compat")]
+
+use layered_io::WriteLayered;
+use nameless::{InputByteStream, LazyOutput, MediaType, OutputByteStream};
+use std::io::{Read, Write};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+struct Opt {
+    /// Input source
+    input: InputByteStream,
+
+    /// Output sink
+    output: LazyOutput<OutputByteStream>,
+
+    /// Output sink which is only created if it's used
+    lazy: LazyOutput<OutputByteStream>,
+}
+
+#[test]
+fn input_and_outputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    let output = dir.path().join("output.txt");
+    let lazy = dir.path().join("lazy.txt");
+    std::fs::write(&input, "hello\n").unwrap();
+
+    let mut opt = Opt::from_iter_safe([
+        "test".as_ref(),
+        input.as_os_str(),
+        output.as_os_str(),
+        lazy.as_os_str(),
+    ])
+    .unwrap();
+    let mut s = String::new();
+    opt.input.read_to_string(&mut s).unwrap();
+    let mut output_stream = opt.output.materialize(MediaType::text()).unwrap();
+    output_stream.write_all(s.as_bytes()).unwrap();
+    output_stream.close().unwrap();
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), "hello\n");
+
+    // The lazy output isn't created until it's materialized.
+    assert!(!lazy.exists());
+    let mut lazy_output = opt.lazy.materialize(MediaType::text()).unwrap();
+    lazy_output.write_all(b"later\n").unwrap();
+    lazy_output.close().unwrap();
+    assert_eq!(std::fs::read_to_string(&lazy).unwrap(), "later\n");
+}
+
+#[test]
+fn output_from_str() {
+    // Outside of `structopt`, outputs can be parsed directly.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("output.txt");
+    let mut output = path.to_str().unwrap().parse::<OutputByteStream>().unwrap();
+    output.write_all(b"direct\n").unwrap();
+    output.close().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "direct\n");
+}
+
+#[test]
+fn open_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.txt");
+    let output = dir.path().join("output.txt");
+
+    let err = match Opt::from_iter_safe([
+        "test".as_ref(),
+        missing.as_os_str(),
+        output.as_os_str(),
+        output.as_os_str(),
+    ]) {
+        Ok(_) => panic!("opening a missing input should fail"),
+        Err(err) => err,
+    };
+    assert_eq!(err.kind, structopt::clap::ErrorKind::ValueValidation);
+    assert!(err.message.contains("<input>"));
+}
+
+#[cfg(not(any(windows, target_os = "wasi")))]
+#[test]
+fn interactive() {
+    use nameless::{InteractiveByteStream, LazyInteractive};
+    use std::io::{BufRead, BufReader};
+
+    #[derive(StructOpt)]
+    struct Opt {
+        /// Interactive stream
+        io: LazyInteractive<InteractiveByteStream>,
+    }
+
+    let opt = Opt::from_iter_safe(["test", "$(cat)"]).unwrap();
+    let mut io = BufReader::new(opt.io.materialize().unwrap());
+    io.get_mut().write_all(b"ping\n").unwrap();
+    io.get_mut().flush().unwrap();
+    let mut line = String::new();
+    io.read_line(&mut line).unwrap();
+    assert_eq!(line, "ping\n");
+    io.get_mut().close().unwrap();
+}